@@ -1,5 +1,3 @@
-use std::any::Any;
-
 pub trait AppData: Default {
     fn get<T: 'static>(&self, key: impl AsRef<str>) -> Option<&T>;
     fn get_mut<T: 'static>(&mut self, key: impl AsRef<str>) -> Option<&mut T>;
@@ -0,0 +1,37 @@
+use std::{error::Error, future::Future, io::Result, sync::Arc};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Mutex,
+};
+
+use crate::{
+    app_data::AppData,
+    receiver::{r#async::handler::AsyncConnectionHandler, slots::Closer},
+};
+
+/// Async counterpart of [`crate::receiver::sync::app::App`].
+pub trait App {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send;
+    type BufStream: AsyncRead + AsyncWrite + Unpin + Send;
+    type EStream: AsyncRead + AsyncWrite + Unpin + Send;
+    type AppData: AppData + Send;
+    type ConnectionHandler: AsyncConnectionHandler<Self::AppData, Self::EStream> + Send + Sync;
+    fn get_stream(this: Arc<Self>) -> impl AsyncFnMut() -> Option<Self::Stream>;
+    fn to_buffer_stream(this: &Arc<Self>, stream: Self::Stream) -> Self::BufStream;
+    fn log_error<E: Error>(_: &Arc<Self>, _error: E) {}
+    /// Something that closes `stream` from another task, see
+    /// [`crate::receiver::sync::app::App::closer`].
+    fn closer(_: &Arc<Self>, _stream: &Self::Stream) -> Option<Closer> {
+        None
+    }
+    /// See [`crate::receiver::sync::app::App::can_stop`].
+    fn can_stop(_: &Arc<Self>, _stream: &Self::EStream) -> bool {
+        true
+    }
+    fn encrypt_connection(
+        this: &Arc<Self>,
+        data: &Arc<Mutex<Self::AppData>>,
+        stream: Self::BufStream,
+    ) -> impl Future<Output = Result<Self::EStream>> + Send;
+}
@@ -0,0 +1,22 @@
+use std::{io, sync::Arc};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::Mutex,
+};
+
+use crate::receiver::r#async::BoxFuture;
+
+pub type AsyncHandle<Data, Stream> = Box<
+    dyn for<'a> Fn(&'a Arc<Mutex<Data>>, &'a mut Stream) -> BoxFuture<'a, io::Result<()>>
+        + Send
+        + Sync,
+>;
+
+pub trait AsyncConnectionHandler<Data, Stream: AsyncRead + AsyncWrite>: Default {
+    fn get(&self, id: impl AsRef<str>) -> Option<&AsyncHandle<Data, Stream>>;
+    /// Ids and versions of the tasks, advertised to senders.
+    fn tasks(&self) -> Vec<(&str, u32)> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,11 @@
+use std::{future::Future, pin::Pin};
+
+pub mod app;
+pub mod handler;
+pub mod server;
+
+/// Boxed future returned by handlers and auth hooks.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,395 @@
+use std::{io, sync::Arc, time::Duration};
+
+use ee_proto::{Capabilities, ErrorKind, Response};
+use ee_stream::e_stream::{CIPHER_SUITE, DEFAULT_MAX_FRAME_SIZE};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+    task::JoinSet,
+};
+
+use crate::receiver::{
+    r#async::{BoxFuture, app::App, handler::AsyncConnectionHandler},
+    slots::{BUSY, ConnectionStats, OverloadPolicy, ShutdownHandle, SlotGuard, Slots},
+};
+
+type Auth<A> = Arc<
+    dyn for<'a> Fn(
+            Arc<A>,
+            Arc<Mutex<<A as App>::AppData>>,
+            &'a mut <A as App>::BufStream,
+        ) -> BoxFuture<'a, io::Result<bool>>
+        + Send
+        + Sync
+        + 'static,
+>;
+
+/// Async counterpart of [`crate::receiver::sync::server::Server`].
+///
+/// Speaks the same wire protocol, so a sync sender can talk to it. Every
+/// connection runs in its own tokio task.
+pub struct Server<A>
+where
+    A: App + Send + Sync + 'static,
+{
+    version: (u32, u32, u32),
+    app_name: &'static str,
+    app: Arc<A>,
+    app_data: Arc<Mutex<A::AppData>>,
+    connection_handler: Arc<A::ConnectionHandler>,
+    auth: Auth<A>,
+    slots: Arc<Slots>,
+    overload_policy: OverloadPolicy,
+    shutdown_timeout: Duration,
+    remote_stop: bool,
+    capabilities: Capabilities,
+}
+
+/// Where a new connection stands when it is accepted.
+enum Slot {
+    Ready(SlotGuard),
+    Queued(Duration),
+    Busy,
+}
+
+/// What every connection of a running server needs.
+struct Shared<A: App> {
+    app: Arc<A>,
+    data: Arc<Mutex<A::AppData>>,
+    handler: Arc<A::ConnectionHandler>,
+    auth: Auth<A>,
+    app_name: &'static str,
+    version: (u32, u32, u32),
+    capabilities: Capabilities,
+    remote_stop: bool,
+    retry_after: Duration,
+    slots: Arc<Slots>,
+}
+
+impl<A: App + Send + Sync + 'static> Server<A>
+where
+    A::Stream: 'static,
+    A::AppData: 'static,
+    A::ConnectionHandler: 'static,
+{
+    /// Serve connections until `get_stream` ends or the server is shut down.
+    ///
+    /// Same shutdown steps as the sync server, except that sessions which
+    /// outlive the deadline are aborted.
+    pub async fn run(self) {
+        let shared = Arc::new(self.shared());
+        let mut get_stream = A::get_stream(self.app.clone());
+        let mut sessions = JoinSet::new();
+        loop {
+            let stream = tokio::select! {
+                v = get_stream() => v,
+                _ = self.slots.stopped() => None,
+            };
+            let Some(stream) = stream else {
+                break;
+            };
+            while sessions.try_join_next().is_some() {}
+            let shared = shared.clone();
+            let closer = A::closer(&shared.app, &stream);
+            let slot = self.slot();
+            sessions.spawn(async move {
+                let app = &shared.app;
+                let mut stream = App::to_buffer_stream(app, stream);
+                let guard = match slot {
+                    Slot::Ready(guard) => Some(guard),
+                    Slot::Queued(timeout) => shared.slots.wait_async(timeout).await,
+                    Slot::Busy => None,
+                };
+                let Some(guard) = guard else {
+                    if let Err(err) = Self::reject(&shared, &mut stream).await {
+                        App::log_error(app, err);
+                    }
+                    return;
+                };
+                guard.set_closer(closer);
+                if let Err(err) = Self::handle(&shared, &guard, stream).await
+                    && !guard.is_closed()
+                {
+                    App::log_error(app, err);
+                }
+            });
+        }
+        drop(get_stream);
+        self.slots.shutdown();
+        let drain = async { while sessions.join_next().await.is_some() {} };
+        if tokio::time::timeout(self.shutdown_timeout, drain)
+            .await
+            .is_err()
+        {
+            sessions.shutdown().await;
+        }
+    }
+
+    fn shared(&self) -> Shared<A> {
+        let capabilities = self
+            .connection_handler
+            .tasks()
+            .into_iter()
+            .fold(self.capabilities.clone(), |v, (id, version)| {
+                v.task(id, version)
+            });
+        Shared {
+            app: self.app.clone(),
+            data: self.app_data.clone(),
+            handler: self.connection_handler.clone(),
+            auth: self.auth.clone(),
+            app_name: self.app_name,
+            version: self.version,
+            capabilities,
+            remote_stop: self.remote_stop,
+            retry_after: self.overload_policy.retry_after(),
+            slots: self.slots.clone(),
+        }
+    }
+
+    /// Take a slot for a new connection, or decide how it waits for one.
+    fn slot(&self) -> Slot {
+        if let Some(guard) = self.slots.try_acquire() {
+            return Slot::Ready(guard);
+        }
+        match self.overload_policy {
+            OverloadPolicy::Queue {
+                max_queued,
+                timeout,
+            } if self.slots.enqueue(max_queued) => Slot::Queued(timeout),
+            OverloadPolicy::EvictIdle { timeout }
+                if self.slots.evict_idle() && self.slots.enqueue(usize::MAX) =>
+            {
+                Slot::Queued(timeout)
+            }
+            _ => Slot::Busy,
+        }
+    }
+
+    async fn handle(
+        shared: &Shared<A>,
+        guard: &SlotGuard,
+        mut stream: A::BufStream,
+    ) -> io::Result<()> {
+        let Shared { app, data, .. } = shared;
+        if !Self::connect(shared.app_name, shared.version, &mut stream).await? {
+            return Ok(());
+        }
+        if !(shared.auth)(app.clone(), data.clone(), &mut stream).await? {
+            return Ok(());
+        }
+        let mut e_stream = App::encrypt_connection(app, data, stream).await?;
+        let mut buf = Vec::new();
+        shared.capabilities.write_to(&mut buf)?;
+        e_stream.write_all(&buf).await?;
+        e_stream.flush().await?;
+        while guard.enter_idle() {
+            let id = tokio::select! {
+                v = Self::read_task_id(&mut e_stream) => v?,
+                _ = guard.stopped() => break,
+            };
+            guard.leave_idle();
+            if id.is_empty() || &id == ":break:" {
+                break;
+            }
+            if &id == ":stop-server:" {
+                let allowed = shared.remote_stop && App::can_stop(app, &e_stream);
+                let res = if allowed {
+                    Response::ok()
+                } else {
+                    Response::error(ErrorKind::PermissionDenied, "remote stop is not allowed")
+                };
+                Self::respond(&mut e_stream, &res).await?;
+                if allowed {
+                    guard.shutdown();
+                    break;
+                }
+                continue;
+            }
+            if let Some(f) = shared.handler.get(&id) {
+                Self::respond(&mut e_stream, &Response::ok()).await?;
+                if let Err(err) = f(data, &mut e_stream).await {
+                    App::log_error(app, err);
+                }
+            } else {
+                Self::respond(&mut e_stream, &Response::unknown_task(&id)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the hello of a connection that did not get a slot and answer
+    /// `:busy:<retry-after ms><active><queued>`.
+    async fn reject(shared: &Shared<A>, stream: &mut A::BufStream) -> io::Result<()> {
+        if Self::read_hello(shared.app_name, stream).await?.is_none() {
+            return Ok(());
+        }
+        let (active, queued) = {
+            let state = shared.slots.lock();
+            (state.active, state.queued)
+        };
+        stream.write_all(BUSY).await?;
+        stream
+            .write_u32(u32::try_from(shared.retry_after.as_millis()).unwrap_or(u32::MAX))
+            .await?;
+        stream.write_u32(active as u32).await?;
+        stream.write_u32(queued as u32).await?;
+        stream.flush().await
+    }
+
+    async fn connect(
+        app_name: &str,
+        app_version: (u32, u32, u32),
+        stream: &mut A::BufStream,
+    ) -> io::Result<bool> {
+        let Some((major, _, _)) = Self::read_hello(app_name, stream).await? else {
+            return Ok(false);
+        };
+        let accepted = major == app_version.0;
+        if accepted {
+            stream.write_all(b":ok:").await?;
+        } else {
+            stream.write_all(b":version_not_accepted:").await?;
+        }
+        stream.write_u32(app_version.0).await?;
+        stream.write_u32(app_version.1).await?;
+        stream.write_u32(app_version.2).await?;
+        stream.flush().await?;
+        Ok(accepted)
+    }
+    /// Read `<app-name><version>`, `None` if the app name does not match.
+    async fn read_hello(
+        app_name: &str,
+        stream: &mut A::BufStream,
+    ) -> io::Result<Option<(u32, u32, u32)>> {
+        // sender send
+        // <app-name><version>
+        let mut buf = [0u8; 4];
+        let buf_len = buf.len();
+        let mut app_name = app_name.as_bytes();
+        // read prefix (app name)
+        while !app_name.is_empty() {
+            let n = stream
+                .read(&mut buf[0..std::cmp::min(app_name.len(), buf_len)])
+                .await?;
+            if n == 0 || buf[0..n] != app_name[0..n] {
+                return Ok(None);
+            }
+            app_name = &app_name[n..];
+        }
+        // read version
+        let major = stream.read_u32().await?;
+        let minor = stream.read_u32().await?;
+        let patch = stream.read_u32().await?;
+        Ok(Some((major, minor, patch)))
+    }
+    async fn respond(stream: &mut A::EStream, res: &Response) -> io::Result<()> {
+        let mut buf = Vec::new();
+        res.write_to(&mut buf)?;
+        stream.write_all(&buf).await?;
+        stream.flush().await
+    }
+    async fn read_task_id(stream: &mut A::EStream) -> io::Result<String> {
+        let mut buf = [0; 1];
+        let mut result = String::new();
+        loop {
+            if result.len() > 100 {
+                return Err(io::Error::other("Tast ID can not be greater then 100"));
+            }
+            let n = stream.read(&mut buf).await?;
+            if n == 0 || buf[0] == b'\n' {
+                break;
+            }
+            result.push(buf[0] as char);
+        }
+        Ok(result)
+    }
+    pub fn new<F: FnOnce() -> A + Send + Sync + 'static>(f: F) -> Self {
+        let app = f();
+        let data = Arc::new(Mutex::new(A::AppData::default()));
+        let handler = Arc::new(A::ConnectionHandler::default());
+        Self {
+            version: (0, 0, 0),
+            app_name: "eagle-eye",
+            app: Arc::new(app),
+            app_data: data,
+            connection_handler: handler,
+            auth: Arc::new(|_, _, _| Box::pin(async { Ok(true) })),
+            slots: Arc::new(Slots::new(4)),
+            overload_policy: OverloadPolicy::default(),
+            shutdown_timeout: Duration::from_secs(10),
+            remote_stop: false,
+            capabilities: Capabilities::new()
+                .max_frame_size(DEFAULT_MAX_FRAME_SIZE as u32)
+                .cipher(CIPHER_SUITE),
+        }
+    }
+    pub fn auth(
+        &mut self,
+        f: impl for<'a> Fn(
+            Arc<A>,
+            Arc<Mutex<A::AppData>>,
+            &'a mut A::BufStream,
+        ) -> BoxFuture<'a, io::Result<bool>>
+        + Send
+        + Sync
+        + 'static,
+    ) -> &mut Self {
+        self.auth = Arc::new(f);
+        self
+    }
+    pub fn version(&mut self, version: (u32, u32, u32)) -> &mut Self {
+        self.version = version;
+        self
+    }
+    pub fn app_name(&mut self, name: &'static str) -> &mut Self {
+        self.app_name = name;
+        self
+    }
+    pub fn app(&mut self, app: A) -> &mut Self {
+        self.app = Arc::new(app);
+        self
+    }
+    pub fn app_data(&mut self, data: A::AppData) -> &mut Self {
+        self.app_data = Arc::new(Mutex::new(data));
+        self
+    }
+    pub fn handler(&mut self, handler: A::ConnectionHandler) -> &mut Self {
+        self.connection_handler = Arc::new(handler);
+        self
+    }
+    pub fn max_connection(&mut self, n: usize) -> &mut Self {
+        assert!(n != 0, "max connection can not be zero");
+        self.slots.set_max(n);
+        self
+    }
+    /// What to do with a connection while `max_connection` sessions run.
+    pub fn overload_policy(&mut self, policy: OverloadPolicy) -> &mut Self {
+        self.overload_policy = policy;
+        self
+    }
+    /// Active and queued connection counts, live while the server runs.
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats(self.slots.clone())
+    }
+    /// Handle to stop [`Server::run`] from another task or thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.slots.clone())
+    }
+    /// How long running tasks may take to finish once the server shuts down.
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+    /// Capabilities sent to every sender, see
+    /// [`crate::receiver::sync::server::Server::capabilities`].
+    pub fn capabilities(&mut self, capabilities: Capabilities) -> &mut Self {
+        self.capabilities = capabilities;
+        self
+    }
+    /// Let senders stop the server with `:stop-server:`.
+    pub fn remote_stop(&mut self, allow: bool) -> &mut Self {
+        self.remote_stop = allow;
+        self
+    }
+}
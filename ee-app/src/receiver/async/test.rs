@@ -0,0 +1,265 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream as StdTcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use ee_device::{Busy, ClientSync};
+use ee_proto::{Response, Status};
+use ee_stream::{e_stream::EStreamAsync, handshake};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufStream},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+use crate::{
+    app_data::AppData,
+    receiver::{
+        OverloadPolicy,
+        r#async::{
+            app::App,
+            handler::{AsyncConnectionHandler, AsyncHandle},
+            server::Server,
+        },
+    },
+};
+
+const KEY: [u8; 32] = [9; 32];
+
+struct TestApp {
+    listener: std::sync::Mutex<Option<std::net::TcpListener>>,
+}
+
+/// Number of pings served, shared with the test.
+#[derive(Default)]
+struct Counter(Arc<AtomicU32>);
+
+impl AppData for Counter {
+    fn get<T: 'static>(&self, _key: impl AsRef<str>) -> Option<&T> {
+        None
+    }
+    fn get_mut<T: 'static>(&mut self, _key: impl AsRef<str>) -> Option<&mut T> {
+        None
+    }
+    fn set<T: 'static>(&mut self, _key: impl AsRef<str>) {}
+}
+
+struct Handler(Vec<(&'static str, AsyncHandle<Counter, EStreamAsync<TcpStream>>)>);
+
+impl Default for Handler {
+    fn default() -> Self {
+        let ping: AsyncHandle<Counter, EStreamAsync<TcpStream>> = Box::new(|data, stream| {
+            Box::pin(async move {
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).await?;
+                if &buf == b"ping" {
+                    data.lock().await.0.fetch_add(1, Ordering::Relaxed);
+                    stream.write_all(b"pong").await?;
+                }
+                stream.flush().await
+            })
+        });
+        Self(vec![("ping", ping)])
+    }
+}
+
+impl AsyncConnectionHandler<Counter, EStreamAsync<TcpStream>> for Handler {
+    fn get(&self, id: impl AsRef<str>) -> Option<&AsyncHandle<Counter, EStreamAsync<TcpStream>>> {
+        self.0.iter().find(|v| v.0 == id.as_ref()).map(|v| &v.1)
+    }
+    fn tasks(&self) -> Vec<(&str, u32)> {
+        vec![("ping", 1)]
+    }
+}
+
+impl App for TestApp {
+    type Stream = TcpStream;
+    type BufStream = BufStream<TcpStream>;
+    type EStream = EStreamAsync<TcpStream>;
+    type AppData = Counter;
+    type ConnectionHandler = Handler;
+    fn get_stream(this: Arc<Self>) -> impl AsyncFnMut() -> Option<Self::Stream> {
+        let listener = this.listener.lock().unwrap().take().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener = TcpListener::from_std(listener).unwrap();
+        async move || listener.accept().await.ok().map(|v| v.0)
+    }
+    fn to_buffer_stream(_this: &Arc<Self>, stream: Self::Stream) -> Self::BufStream {
+        BufStream::new(stream)
+    }
+    async fn encrypt_connection(
+        _this: &Arc<Self>,
+        _data: &Arc<Mutex<Self::AppData>>,
+        mut stream: Self::BufStream,
+    ) -> io::Result<Self::EStream> {
+        let keys = handshake::respond_async(&mut stream, &KEY).await?;
+        Ok(EStreamAsync::builder()
+            .keys(keys)
+            .inner(stream.into_inner())
+            .build()
+            .unwrap())
+    }
+}
+
+fn server() -> (SocketAddr, Server<TestApp>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(move || TestApp {
+        listener: std::sync::Mutex::new(Some(listener)),
+    });
+    server.app_name("eagle-eye").version((2, 0, 0));
+    (addr, server)
+}
+
+/// Run a blocking client next to the server until the client is done.
+async fn with_client<T: Send + 'static>(
+    server: Server<TestApp>,
+    client: impl FnOnce() -> T + Send + 'static,
+) -> T {
+    let client = tokio::task::spawn_blocking(client);
+    tokio::select! {
+        _ = server.run() => unreachable!(),
+        v = client => v.unwrap(),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_with_sync_sender() {
+    let (addr, mut server) = server();
+    let pings = Arc::new(AtomicU32::new(0));
+    server.app_data(Counter(pings.clone()));
+    let r = with_client(server, move || -> io::Result<()> {
+        let stream = StdTcpStream::connect(addr)?;
+        let mut sender = ClientSync::new().version((2, 1, 3)).connect(KEY, stream)?;
+        assert_eq!(sender.capabilities().task_version("ping"), Some(1));
+        let mut buf = [0; 4];
+        for _ in 0..2 {
+            sender.write_all(b"ping\n")?;
+            sender.flush()?;
+            assert!(Response::read_from(&mut sender)?.is_ok());
+            sender.write_all(b"ping")?;
+            sender.flush()?;
+            sender.read_exact(&mut buf)?;
+            assert_eq!(&buf, b"pong");
+        }
+        sender.write_all(b"unknown\n")?;
+        sender.flush()?;
+        let res = Response::read_from(&mut sender)?;
+        assert_eq!(res.get_status(), Status::UnknownTask);
+        sender.write_all(b":break:\n")?;
+        sender.flush()?;
+        // the server closes the connection
+        assert_eq!(sender.read(&mut buf)?, 0);
+        Ok(())
+    })
+    .await;
+    r.unwrap();
+    assert_eq!(pings.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_rejects_wrong_key() {
+    let (addr, server) = server();
+    let err = with_client(server, move || {
+        let stream = StdTcpStream::connect(addr).unwrap();
+        ClientSync::new().connect([1; 32], stream).err().unwrap()
+    })
+    .await;
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_rejects_other_version() {
+    let (addr, server) = server();
+    let err = with_client(server, move || {
+        let stream = StdTcpStream::connect(addr).unwrap();
+        ClientSync::new()
+            .version((1, 0, 0))
+            .connect(KEY, stream)
+            .err()
+            .unwrap()
+    })
+    .await;
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_queues_then_rejects() {
+    let (addr, mut server) = server();
+    server
+        .max_connection(1)
+        .overload_policy(OverloadPolicy::Queue {
+            max_queued: 1,
+            timeout: std::time::Duration::from_millis(100),
+        });
+    let stats = server.stats();
+    let busy = with_client(server, move || {
+        let _first = ClientSync::new()
+            .connect(KEY, StdTcpStream::connect(addr).unwrap())
+            .unwrap();
+        let err = ClientSync::new()
+            .connect(KEY, StdTcpStream::connect(addr).unwrap())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+        *err.get_ref().unwrap().downcast_ref::<Busy>().unwrap()
+    })
+    .await;
+    assert_eq!(
+        busy,
+        Busy {
+            retry_after: std::time::Duration::from_millis(100),
+            active: 1,
+            queued: 0,
+        }
+    );
+    assert_eq!(stats.queued(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_shutdown_handle() {
+    let (addr, server) = server();
+    let shutdown = server.shutdown_handle();
+    let run = server.run();
+    tokio::pin!(run);
+    let client = tokio::task::spawn_blocking(move || {
+        ClientSync::new()
+            .connect(KEY, StdTcpStream::connect(addr).unwrap())
+            .unwrap()
+    });
+    let mut sender = tokio::select! {
+        _ = &mut run => unreachable!(),
+        v = client => v.unwrap(),
+    };
+    shutdown.shutdown();
+    tokio::time::timeout(std::time::Duration::from_secs(5), run)
+        .await
+        .unwrap();
+    // the idle session was closed
+    let n = tokio::task::spawn_blocking(move || sender.read(&mut [0; 1]).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(n, 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_server_remote_stop() {
+    let (addr, mut server) = server();
+    server.remote_stop(true);
+    let client = tokio::task::spawn_blocking(move || {
+        ClientSync::new()
+            .connect(KEY, StdTcpStream::connect(addr)?)?
+            .stop_server()
+    });
+    // `run` returns on its own once the sender stopped it
+    let (_, r) = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        tokio::join!(server.run(), client)
+    })
+    .await
+    .unwrap();
+    r.unwrap().unwrap();
+}
@@ -1,2 +1,5 @@
 pub mod r#async;
+pub mod slots;
 pub mod sync;
+
+pub use slots::{Closer, ConnectionStats, OverloadPolicy, ShutdownHandle};
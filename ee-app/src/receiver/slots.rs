@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+/// Closes a session from another thread, used to evict idle sessions and
+/// to close sessions on shutdown.
+pub type Closer = Box<dyn Fn() + Send + Sync>;
+
+/// Reply sent instead of `:ok:` when the receiver has no room for a
+/// connection, followed by `<retry-after ms><active><queued>` as u32 BE.
+pub const BUSY: &[u8] = b":busy:";
+
+/// What the server does with a connection while every slot is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Wait up to `timeout` for a free slot, with at most `max_queued`
+    /// connections waiting. A connection that does not get a slot in time
+    /// is rejected.
+    Queue {
+        max_queued: usize,
+        timeout: Duration,
+    },
+    /// Reply `:busy:` and ask the sender to retry after `retry_after`.
+    Reject { retry_after: Duration },
+    /// Close the least recently used idle session and give its slot to the
+    /// new connection. Rejects when no session is idle, or when the app
+    /// can not close its streams (see `App::closer`).
+    EvictIdle { timeout: Duration },
+}
+
+impl Default for OverloadPolicy {
+    fn default() -> Self {
+        Self::Reject {
+            retry_after: Duration::from_secs(1),
+        }
+    }
+}
+
+impl OverloadPolicy {
+    /// Hint sent with `:busy:` when a connection is rejected.
+    pub(crate) fn retry_after(&self) -> Duration {
+        match *self {
+            Self::Queue { timeout, .. } => timeout,
+            Self::Reject { retry_after } => retry_after,
+            Self::EvictIdle { timeout } => timeout,
+        }
+    }
+}
+
+/// Live view of the connections of a server.
+#[derive(Clone)]
+pub struct ConnectionStats(pub(crate) Arc<Slots>);
+
+impl ConnectionStats {
+    /// Connections being served.
+    pub fn active(&self) -> usize {
+        self.0.lock().active
+    }
+    /// Connections waiting for a free slot.
+    pub fn queued(&self) -> usize {
+        self.0.lock().queued
+    }
+    /// Upper bound of [`ConnectionStats::active`].
+    pub fn max(&self) -> usize {
+        self.0.lock().max
+    }
+}
+
+/// Stops a running server, see `Server::shutdown_handle`.
+#[derive(Clone)]
+pub struct ShutdownHandle(pub(crate) Arc<Slots>);
+
+impl ShutdownHandle {
+    /// Stop accepting connections, close idle sessions and let the running
+    /// tasks finish. Does not wait, `Server::run` returns once it is done.
+    pub fn shutdown(&self) {
+        self.0.shutdown();
+    }
+    pub fn is_shutdown(&self) -> bool {
+        self.0.lock().stopping
+    }
+}
+
+struct Session {
+    last_active: Instant,
+    idle: bool,
+    closed: bool,
+    closer: Option<Closer>,
+}
+
+impl Session {
+    fn close(&mut self) {
+        if let Some(close) = &self.closer {
+            self.closed = true;
+            close();
+        }
+    }
+}
+
+pub(crate) struct State {
+    max: usize,
+    pub(crate) active: usize,
+    pub(crate) queued: usize,
+    next_id: u64,
+    sessions: HashMap<u64, Session>,
+    stopping: bool,
+    on_stop: Option<Closer>,
+}
+
+/// Book keeping of the connection slots, shared by the sync and the async
+/// server.
+pub(crate) struct Slots {
+    state: Mutex<State>,
+    freed: Condvar,
+    notify: Notify,
+}
+
+impl Slots {
+    pub(crate) fn new(max: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                max,
+                active: 0,
+                queued: 0,
+                next_id: 0,
+                sessions: HashMap::new(),
+                stopping: false,
+                on_stop: None,
+            }),
+            freed: Condvar::new(),
+            notify: Notify::new(),
+        }
+    }
+    pub(crate) fn set_max(&self, max: usize) {
+        self.lock().max = max;
+    }
+    pub(crate) fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn take(self: &Arc<Self>, state: &mut State) -> SlotGuard {
+        state.active += 1;
+        let id = state.next_id;
+        state.next_id += 1;
+        state.sessions.insert(
+            id,
+            Session {
+                last_active: Instant::now(),
+                idle: false,
+                closed: false,
+                closer: None,
+            },
+        );
+        SlotGuard {
+            slots: self.clone(),
+            id,
+        }
+    }
+    /// Take a slot if one is free.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<SlotGuard> {
+        let mut state = self.lock();
+        (!state.stopping && state.active < state.max).then(|| self.take(&mut state))
+    }
+    /// Join the queue, `false` if `max_queued` connections already wait.
+    pub(crate) fn enqueue(&self, max_queued: usize) -> bool {
+        let mut state = self.lock();
+        if state.stopping || state.queued >= max_queued {
+            return false;
+        }
+        state.queued += 1;
+        true
+    }
+    /// Leave the queue with a slot, or `None` if there is no free slot.
+    ///
+    /// A connection gives up on its own after its timeout, or when the
+    /// server stops.
+    fn dequeue(self: &Arc<Self>, state: &mut State, give_up: &mut bool) -> Option<SlotGuard> {
+        *give_up |= state.stopping;
+        if !*give_up && state.active < state.max {
+            state.queued -= 1;
+            return Some(self.take(state));
+        }
+        if *give_up {
+            state.queued -= 1;
+        }
+        None
+    }
+    /// Block a queued connection until a slot is free or `timeout` passes.
+    pub(crate) fn wait_sync(self: &Arc<Self>, timeout: Duration) -> Option<SlotGuard> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.lock();
+        loop {
+            let now = Instant::now();
+            let mut give_up = now >= deadline;
+            if let Some(guard) = self.dequeue(&mut state, &mut give_up) {
+                return Some(guard);
+            }
+            if give_up {
+                return None;
+            }
+            state = self
+                .freed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+    /// Async counterpart of [`Slots::wait_sync`].
+    pub(crate) async fn wait_async(self: &Arc<Self>, timeout: Duration) -> Option<SlotGuard> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            // register before checking, so a release in between is not lost
+            notified.as_mut().enable();
+            let mut give_up = tokio::time::Instant::now() >= deadline;
+            if let Some(guard) = self.dequeue(&mut self.lock(), &mut give_up) {
+                return Some(guard);
+            }
+            if give_up {
+                return None;
+            }
+            let _ = tokio::time::timeout_at(deadline, notified).await;
+        }
+    }
+    /// Close the least recently used idle session.
+    ///
+    /// Returns `false` if no idle session can be closed.
+    pub(crate) fn evict_idle(&self) -> bool {
+        let mut state = self.lock();
+        let session = state
+            .sessions
+            .values_mut()
+            .filter(|v| v.idle && !v.closed && v.closer.is_some())
+            .min_by_key(|v| v.last_active);
+        match session {
+            Some(session) => {
+                session.close();
+                true
+            }
+            None => false,
+        }
+    }
+    /// Run `f` once the server stops, right away if it already did.
+    pub(crate) fn on_stop(&self, f: Closer) {
+        let mut state = self.lock();
+        if state.stopping {
+            drop(state);
+            f();
+        } else {
+            state.on_stop = Some(f);
+        }
+    }
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.lock().stopping
+    }
+    /// Stop handing out slots and close every idle session.
+    pub(crate) fn shutdown(&self) {
+        let mut state = self.lock();
+        if state.stopping {
+            return;
+        }
+        state.stopping = true;
+        state
+            .sessions
+            .values_mut()
+            .filter(|v| v.idle)
+            .for_each(Session::close);
+        let on_stop = state.on_stop.take();
+        drop(state);
+        if let Some(f) = on_stop {
+            f();
+        }
+        self.freed.notify_all();
+        self.notify.notify_waiters();
+    }
+    /// Wait until the server stops.
+    pub(crate) async fn stopped(&self) {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.is_stopping() {
+                return;
+            }
+            notified.await;
+        }
+    }
+    /// Close every session that can be closed, busy or not.
+    pub(crate) fn close_all(&self) {
+        self.lock()
+            .sessions
+            .values_mut()
+            .filter(|v| !v.closed)
+            .for_each(Session::close);
+    }
+    /// Block until no session is left, `false` if `deadline` passes first.
+    pub(crate) fn wait_empty_sync(&self, deadline: Instant) -> bool {
+        let mut state = self.lock();
+        loop {
+            if state.active == 0 {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .freed
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+    fn release(&self, id: u64) {
+        let mut state = self.lock();
+        state.sessions.remove(&id);
+        state.active -= 1;
+        drop(state);
+        self.freed.notify_all();
+        self.notify.notify_waiters();
+    }
+}
+
+/// A taken slot, freed on drop.
+pub(crate) struct SlotGuard {
+    slots: Arc<Slots>,
+    id: u64,
+}
+
+impl SlotGuard {
+    fn with<T>(&self, f: impl FnOnce(&mut Session) -> T) -> Option<T> {
+        self.slots.lock().sessions.get_mut(&self.id).map(f)
+    }
+    pub(crate) fn set_closer(&self, closer: Option<Closer>) {
+        self.with(|v| v.closer = closer);
+    }
+    /// Mark the session idle while it waits for the next task.
+    ///
+    /// Returns `false` instead if the server stops, the session should end.
+    pub(crate) fn enter_idle(&self) -> bool {
+        let mut state = self.slots.lock();
+        if state.stopping {
+            return false;
+        }
+        if let Some(v) = state.sessions.get_mut(&self.id) {
+            v.idle = true;
+            v.last_active = Instant::now();
+        }
+        true
+    }
+    pub(crate) fn leave_idle(&self) {
+        self.with(|v| {
+            v.idle = false;
+            v.last_active = Instant::now();
+        });
+    }
+    /// Closed by the server (evicted or shut down), a failed read is expected.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.with(|v| v.closed).unwrap_or(false)
+    }
+    /// Stop the server this session belongs to.
+    pub(crate) fn shutdown(&self) {
+        self.slots.shutdown();
+    }
+    pub(crate) async fn stopped(&self) {
+        self.slots.stopped().await
+    }
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        self.slots.release(self.id);
+    }
+}
@@ -4,7 +4,10 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use crate::{app_data::AppData, receiver::sync::handler::ConnectionHandler};
+use crate::{
+    app_data::AppData,
+    receiver::{slots::Closer, sync::handler::ConnectionHandler},
+};
 
 pub trait App {
     type Stream: Read + Write + Send + Sync;
@@ -15,6 +18,21 @@ pub trait App {
     fn get_stream(this: Arc<Self>) -> impl FnMut() -> Option<Self::Stream>;
     fn to_buffer_stream(this: &Arc<Self>, stream: Self::Stream) -> Self::BufStream;
     fn log_error<E: Error>(_: &Arc<Self>, _error: E) {}
+    /// Something that closes `stream` from another thread.
+    ///
+    /// Needed by [`OverloadPolicy::EvictIdle`](crate::receiver::OverloadPolicy::EvictIdle),
+    /// sessions without a closer are never evicted.
+    fn closer(_: &Arc<Self>, _stream: &Self::Stream) -> Option<Closer> {
+        None
+    }
+    /// Called once when the server shuts down, the function returned by
+    /// `get_stream` should return `None` soon after.
+    fn stop(_: &Arc<Self>) {}
+    /// Whether the sender on `stream` may stop the server with
+    /// `:stop-server:`, only asked when remote stop is on.
+    fn can_stop(_: &Arc<Self>, _stream: &Self::EStream) -> bool {
+        true
+    }
     fn encrypt_connection(
         this: &Arc<Self>,
         data: &Arc<Mutex<Self::AppData>>,
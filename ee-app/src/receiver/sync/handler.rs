@@ -1,12 +1,15 @@
 use std::{
     io::{self, Read, Write},
-    marker::PhantomData,
     sync::{Arc, Mutex},
 };
 
+pub type Handle<Data, Stream> =
+    Box<dyn Fn(&Arc<Mutex<Data>>, &mut Stream) -> io::Result<()> + Send + Sync>;
+
 pub trait ConnectionHandler<Data, Stream: Read + Write>: Default {
-    fn get(
-        &self,
-        id: impl AsRef<str>,
-    ) -> Option<&Box<dyn Fn(&Arc<Mutex<Data>>, &mut Stream) -> io::Result<()>>>;
+    fn get(&self, id: impl AsRef<str>) -> Option<&Handle<Data, Stream>>;
+    /// Ids and versions of the tasks, advertised to senders.
+    fn tasks(&self) -> Vec<(&str, u32)> {
+        Vec::new()
+    }
 }
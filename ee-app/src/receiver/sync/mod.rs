@@ -1,3 +1,6 @@
 pub mod app;
 pub mod handler;
 pub mod server;
+
+#[cfg(test)]
+mod test;
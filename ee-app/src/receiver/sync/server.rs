@@ -1,11 +1,30 @@
 use std::{
     io::{self, Read, Write},
-    num::NonZeroUsize,
-    sync::{Arc, Mutex, atomic::AtomicUsize},
+    sync::{Arc, Mutex},
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use crate::receiver::sync::{app::App, handler::ConnectionHandler};
+use ee_proto::{Capabilities, ErrorKind, Response};
+use ee_stream::e_stream::{CIPHER_SUITE, DEFAULT_MAX_FRAME_SIZE};
+
+use crate::receiver::{
+    slots::{BUSY, ConnectionStats, OverloadPolicy, ShutdownHandle, SlotGuard, Slots},
+    sync::{app::App, handler::ConnectionHandler},
+};
+
+type Auth<A> = Arc<
+    Box<
+        dyn Fn(
+                Arc<A>,
+                Arc<Mutex<<A as App>::AppData>>,
+                &mut <A as App>::BufStream,
+            ) -> io::Result<bool>
+            + Send
+            + Sync
+            + 'static,
+    >,
+>;
 
 pub struct Server<A>
 where
@@ -16,84 +35,201 @@ where
     app: Arc<A>,
     app_data: Arc<Mutex<A::AppData>>,
     connection_handler: Arc<A::ConnectionHandler>,
-    auth: Arc<
-        Box<
-            dyn Fn(Arc<A>, Arc<Mutex<A::AppData>>, &mut A::BufStream) -> io::Result<bool>
-                + Send
-                + Sync
-                + 'static,
-        >,
-    >,
-    max_connection: NonZeroUsize,
-    active_connection: Arc<AtomicUsize>,
+    auth: Auth<A>,
+    slots: Arc<Slots>,
+    overload_policy: OverloadPolicy,
+    shutdown_timeout: Duration,
+    remote_stop: bool,
+    capabilities: Capabilities,
+}
+
+/// How long closed sessions get to end after the shutdown deadline.
+const CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Where a new connection stands when it is accepted.
+enum Slot {
+    Ready(SlotGuard),
+    Queued(Duration),
+    Busy,
+}
+
+/// What every connection of a running server needs.
+struct Shared<A: App> {
+    app: Arc<A>,
+    data: Arc<Mutex<A::AppData>>,
+    handler: Arc<A::ConnectionHandler>,
+    auth: Auth<A>,
+    app_name: &'static str,
+    version: (u32, u32, u32),
+    capabilities: Capabilities,
+    remote_stop: bool,
+    retry_after: Duration,
+    slots: Arc<Slots>,
 }
 
 impl<A: App + Send + Sync + 'static> Server<A> {
+    /// Serve connections until `get_stream` ends or the server is shut down.
+    ///
+    /// On shutdown, idle sessions are closed right away and running tasks
+    /// get [`Server::shutdown_timeout`] to finish before their sessions are
+    /// closed too. Sessions the app can not close (see `App::closer`) are
+    /// left behind.
     pub fn run(self) {
-        let auth = self.auth.clone();
+        let app = self.app.clone();
+        self.slots.on_stop(Box::new(move || A::stop(&app)));
+        let shared = Arc::new(self.shared());
         let mut get_stream = A::get_stream(self.app.clone());
+        let mut workers: Vec<JoinHandle<()>> = Vec::new();
         while let Some(stream) = get_stream() {
-            let app = self.app.clone();
-            let data = self.app_data.clone();
-            let handler = self.connection_handler.clone();
-
-            let app_name = self.app_name;
-            let version = self.version;
-            let auth = auth.clone();
-
-            self.spawn(move || {
-                let mut stream = App::to_buffer_stream(&app, stream);
-                let r = Self::connect(app_name, version, &mut stream);
-                if let Err(err) = r {
-                    App::log_error(&app, err);
-                    return;
-                } else {
-                    if !r.unwrap() {
-                        return;
+            if self.slots.is_stopping() {
+                break;
+            }
+            workers.retain(|v| !v.is_finished());
+            let shared = shared.clone();
+            let closer = A::closer(&shared.app, &stream);
+            let slot = self.slot();
+            workers.push(std::thread::spawn(move || {
+                let app = &shared.app;
+                let mut stream = App::to_buffer_stream(app, stream);
+                let guard = match slot {
+                    Slot::Ready(guard) => Some(guard),
+                    Slot::Queued(timeout) => shared.slots.wait_sync(timeout),
+                    Slot::Busy => None,
+                };
+                let Some(guard) = guard else {
+                    if let Err(err) = Self::reject(&shared, &mut stream) {
+                        App::log_error(app, err);
                     }
-                }
-                let r = auth(app.clone(), data.clone(), &mut stream);
-                if let Err(err) = r {
-                    App::log_error(&app, err);
                     return;
-                } else {
-                    if !r.unwrap() {
-                        return;
-                    }
-                }
-                let e_stream = App::encrypt_connection(&app, &data, stream);
-                if let Err(ref err) = e_stream {
-                    App::log_error(&app, err);
-                }
-                let mut e_stream = e_stream.unwrap();
-                loop {
-                    let id = Self::read_task_id(&mut e_stream);
-                    if id.is_err() {
-                        let err = id.unwrap_err();
-                        App::log_error(&app, err);
-                        return;
-                    }
-                    let id = id.unwrap();
-                    if id.is_empty() || &id == ":break:" {
-                        break;
-                    }
-                    if let Some(f) = handler.get(id) {
-                        if let Err(err) = e_stream.write_all(&[0, 1, 0]) {
-                            App::log_error(&app, err);
-                            return;
-                        };
-                        if let Err(err) = f(&data, &mut e_stream) {
-                            App::log_error(&app, err);
-                        };
-                    } else {
-                        if let Err(err) = e_stream.write_all(&[1, 0, 1]) {
-                            App::log_error(&app, err);
-                            return;
-                        };
-                    }
+                };
+                guard.set_closer(closer);
+                // a session closed by the server fails its read, nothing to report
+                if let Err(err) = Self::handle(&shared, &guard, stream)
+                    && !guard.is_closed()
+                {
+                    App::log_error(app, err);
                 }
+            }));
+        }
+        drop(get_stream);
+        self.slots.shutdown();
+        if !self
+            .slots
+            .wait_empty_sync(Instant::now() + self.shutdown_timeout)
+        {
+            self.slots.close_all();
+            self.slots.wait_empty_sync(Instant::now() + CLOSE_GRACE);
+        }
+        for worker in workers {
+            if worker.is_finished() {
+                let _ = worker.join();
+            }
+        }
+    }
+
+    fn shared(&self) -> Shared<A> {
+        let capabilities = self
+            .connection_handler
+            .tasks()
+            .into_iter()
+            .fold(self.capabilities.clone(), |v, (id, version)| {
+                v.task(id, version)
             });
+        Shared {
+            app: self.app.clone(),
+            data: self.app_data.clone(),
+            handler: self.connection_handler.clone(),
+            auth: self.auth.clone(),
+            app_name: self.app_name,
+            version: self.version,
+            capabilities,
+            remote_stop: self.remote_stop,
+            retry_after: self.overload_policy.retry_after(),
+            slots: self.slots.clone(),
+        }
+    }
+
+    /// Take a slot for a new connection, or decide how it waits for one.
+    fn slot(&self) -> Slot {
+        if let Some(guard) = self.slots.try_acquire() {
+            return Slot::Ready(guard);
+        }
+        match self.overload_policy {
+            OverloadPolicy::Queue {
+                max_queued,
+                timeout,
+            } if self.slots.enqueue(max_queued) => Slot::Queued(timeout),
+            OverloadPolicy::EvictIdle { timeout }
+                if self.slots.evict_idle() && self.slots.enqueue(usize::MAX) =>
+            {
+                Slot::Queued(timeout)
+            }
+            _ => Slot::Busy,
+        }
+    }
+
+    fn handle(shared: &Shared<A>, guard: &SlotGuard, mut stream: A::BufStream) -> io::Result<()> {
+        let Shared { app, data, .. } = shared;
+        if !Self::connect(shared.app_name, shared.version, &mut stream)? {
+            return Ok(());
+        }
+        if !(shared.auth)(app.clone(), data.clone(), &mut stream)? {
+            return Ok(());
+        }
+        let mut e_stream = App::encrypt_connection(app, data, stream)?;
+        shared.capabilities.write_to(&mut e_stream)?;
+        e_stream.flush()?;
+        while guard.enter_idle() {
+            let id = Self::read_task_id(&mut e_stream)?;
+            guard.leave_idle();
+            if id.is_empty() || &id == ":break:" {
+                break;
+            }
+            if &id == ":stop-server:" {
+                let allowed = shared.remote_stop && App::can_stop(app, &e_stream);
+                let res = if allowed {
+                    Response::ok()
+                } else {
+                    Response::error(ErrorKind::PermissionDenied, "remote stop is not allowed")
+                };
+                res.write_to(&mut e_stream)?;
+                e_stream.flush()?;
+                if allowed {
+                    guard.shutdown();
+                    break;
+                }
+                continue;
+            }
+            if let Some(f) = shared.handler.get(&id) {
+                Response::ok().write_to(&mut e_stream)?;
+                e_stream.flush()?;
+                if let Err(err) = f(data, &mut e_stream) {
+                    App::log_error(app, err);
+                };
+            } else {
+                Response::unknown_task(&id).write_to(&mut e_stream)?;
+                e_stream.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the hello of a connection that did not get a slot and answer
+    /// `:busy:<retry-after ms><active><queued>`.
+    fn reject(shared: &Shared<A>, stream: &mut A::BufStream) -> io::Result<()> {
+        if Self::read_hello(shared.app_name, stream)?.is_none() {
+            return Ok(());
         }
+        let (active, queued) = {
+            let state = shared.slots.lock();
+            (state.active, state.queued)
+        };
+        let retry_after = u32::try_from(shared.retry_after.as_millis()).unwrap_or(u32::MAX);
+        stream.write_all(BUSY)?;
+        stream.write_all(&retry_after.to_be_bytes())?;
+        stream.write_all(&(active as u32).to_be_bytes())?;
+        stream.write_all(&(queued as u32).to_be_bytes())?;
+        stream.flush()
     }
 
     fn connect(
@@ -101,6 +237,30 @@ impl<A: App + Send + Sync + 'static> Server<A> {
         app_version: (u32, u32, u32),
         stream: &mut A::BufStream,
     ) -> io::Result<bool> {
+        let Some(version) = Self::read_hello(app_name, stream)? else {
+            return Ok(false);
+        };
+        if version.0 == app_version.0 {
+            stream.write_all(b":ok:")?;
+            stream.write_all(&app_version.0.to_be_bytes())?;
+            stream.write_all(&app_version.1.to_be_bytes())?;
+            stream.write_all(&app_version.2.to_be_bytes())?;
+            stream.flush()?;
+            Ok(true)
+        } else {
+            stream.write_all(b":version_not_accepted:")?;
+            stream.write_all(&app_version.0.to_be_bytes())?;
+            stream.write_all(&app_version.1.to_be_bytes())?;
+            stream.write_all(&app_version.2.to_be_bytes())?;
+            stream.flush()?;
+            Ok(false)
+        }
+    }
+    /// Read `<app-name><version>`, `None` if the app name does not match.
+    fn read_hello(
+        app_name: &str,
+        stream: &mut A::BufStream,
+    ) -> io::Result<Option<(u32, u32, u32)>> {
         // sender send
         // <app-name><version>
         let mut buf = [0u8; 4];
@@ -112,8 +272,8 @@ impl<A: App + Send + Sync + 'static> Server<A> {
                 break;
             }
             let n = stream.read(&mut buf[0..std::cmp::min(app_name.len(), buf_len)])?;
-            if &buf[0..n] != &app_name[0..n] {
-                return Ok(false);
+            if n == 0 || buf[0..n] != app_name[0..n] {
+                return Ok(None);
             }
             app_name = &app_name[n..];
         }
@@ -124,22 +284,7 @@ impl<A: App + Send + Sync + 'static> Server<A> {
         let minor = u32::from_be_bytes(buf);
         stream.read_exact(&mut buf)?;
         let patch = u32::from_be_bytes(buf);
-        let version = (major, minor, patch);
-        if version.0 == app_version.0 {
-            stream.write_all(b":ok:")?;
-            stream.write_all(&app_version.0.to_be_bytes())?;
-            stream.write_all(&app_version.1.to_be_bytes())?;
-            stream.write_all(&app_version.2.to_be_bytes())?;
-            stream.flush()?;
-            Ok(true)
-        } else {
-            stream.write_all(b":version_not_accepted:")?;
-            stream.write_all(&app_version.0.to_be_bytes())?;
-            stream.write_all(&app_version.1.to_be_bytes())?;
-            stream.write_all(&app_version.2.to_be_bytes())?;
-            stream.flush()?;
-            Ok(false)
-        }
+        Ok(Some((major, minor, patch)))
     }
     fn read_task_id(stream: &mut A::EStream) -> io::Result<String> {
         let mut buf = [0; 1];
@@ -167,8 +312,13 @@ impl<A: App + Send + Sync + 'static> Server<A> {
             app_data: data,
             connection_handler: handler,
             auth: Arc::new(Box::new(|_, _, _| Ok(true))),
-            max_connection: unsafe { NonZeroUsize::new_unchecked(4) },
-            active_connection: Arc::new(AtomicUsize::new(0)),
+            slots: Arc::new(Slots::new(4)),
+            overload_policy: OverloadPolicy::default(),
+            shutdown_timeout: Duration::from_secs(10),
+            remote_stop: false,
+            capabilities: Capabilities::new()
+                .max_frame_size(DEFAULT_MAX_FRAME_SIZE as u32)
+                .cipher(CIPHER_SUITE),
         }
     }
     pub fn auth(
@@ -202,30 +352,37 @@ impl<A: App + Send + Sync + 'static> Server<A> {
         self
     }
     pub fn max_connection(&mut self, n: usize) -> &mut Self {
-        self.max_connection = NonZeroUsize::new(n).expect("max connection can not be zero");
+        assert!(n != 0, "max connection can not be zero");
+        self.slots.set_max(n);
         self
     }
-    fn spawn<F, T>(&self, f: F) -> Option<JoinHandle<T>>
-    where
-        F: FnOnce() -> T,
-        F: Send + 'static,
-        T: Send + 'static,
-    {
-        if self
-            .active_connection
-            .load(std::sync::atomic::Ordering::SeqCst)
-            < self.max_connection.get()
-        {
-            self.active_connection
-                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            let count = self.active_connection.clone();
-            Some(std::thread::spawn(move || {
-                let v = f();
-                count.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
-                v
-            }))
-        } else {
-            None
-        }
+    /// What to do with a connection while `max_connection` sessions run.
+    pub fn overload_policy(&mut self, policy: OverloadPolicy) -> &mut Self {
+        self.overload_policy = policy;
+        self
+    }
+    /// Active and queued connection counts, live while the server runs.
+    pub fn stats(&self) -> ConnectionStats {
+        ConnectionStats(self.slots.clone())
+    }
+    /// Handle to stop [`Server::run`] from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.slots.clone())
+    }
+    /// How long running tasks may take to finish once the server shuts down.
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+    /// Capabilities sent to every sender. The tasks of the handler are
+    /// added on top, so this sets the frame size, compression and ciphers.
+    pub fn capabilities(&mut self, capabilities: Capabilities) -> &mut Self {
+        self.capabilities = capabilities;
+        self
+    }
+    /// Let senders stop the server with `:stop-server:`.
+    pub fn remote_stop(&mut self, allow: bool) -> &mut Self {
+        self.remote_stop = allow;
+        self
     }
 }
@@ -0,0 +1,404 @@
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use ee_device::{Busy, ClientSync, TaskSenderSync};
+use ee_proto::{Response, Status};
+use ee_stream::{
+    buffer::BufReadWriter,
+    e_stream::{CIPHER_SUITE, DEFAULT_MAX_FRAME_SIZE, EStreamSync},
+    handshake,
+};
+use ee_task::{GetId, ping_pong::Ping};
+
+use crate::{
+    app_data::AppData,
+    receiver::{
+        Closer, OverloadPolicy,
+        sync::{
+            app::App,
+            handler::{ConnectionHandler, Handle},
+            server::Server,
+        },
+    },
+};
+
+const KEY: [u8; 32] = [7; 32];
+
+struct TestApp {
+    addr: SocketAddr,
+    listener: Mutex<Option<TcpListener>>,
+    stopped: AtomicBool,
+    can_stop: bool,
+}
+
+#[derive(Default)]
+struct Data;
+
+impl AppData for Data {
+    fn get<T: 'static>(&self, _key: impl AsRef<str>) -> Option<&T> {
+        None
+    }
+    fn get_mut<T: 'static>(&mut self, _key: impl AsRef<str>) -> Option<&mut T> {
+        None
+    }
+    fn set<T: 'static>(&mut self, _key: impl AsRef<str>) {}
+}
+
+struct Handler(Vec<(&'static str, Handle<Data, EStreamSync<TcpStream>>)>);
+
+impl Default for Handler {
+    fn default() -> Self {
+        let echo: Handle<Data, EStreamSync<TcpStream>> = Box::new(|_, stream| {
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf)?;
+            stream.write_all(&buf)?;
+            stream.flush()
+        });
+        let slow: Handle<Data, EStreamSync<TcpStream>> = Box::new(|_, stream| {
+            std::thread::sleep(Duration::from_millis(200));
+            stream.write_all(b"done")?;
+            stream.flush()
+        });
+        Self(vec![("echo", echo), ("slow", slow)])
+    }
+}
+
+impl ConnectionHandler<Data, EStreamSync<TcpStream>> for Handler {
+    fn get(&self, id: impl AsRef<str>) -> Option<&Handle<Data, EStreamSync<TcpStream>>> {
+        self.0.iter().find(|v| v.0 == id.as_ref()).map(|v| &v.1)
+    }
+    fn tasks(&self) -> Vec<(&str, u32)> {
+        vec![("echo", 1), ("slow", 2)]
+    }
+}
+
+impl App for TestApp {
+    type Stream = TcpStream;
+    type BufStream = BufReadWriter<TcpStream>;
+    type EStream = EStreamSync<TcpStream>;
+    type AppData = Data;
+    type ConnectionHandler = Handler;
+    fn get_stream(this: Arc<Self>) -> impl FnMut() -> Option<Self::Stream> {
+        let listener = this.listener.lock().unwrap().take().unwrap();
+        move || {
+            let stream = listener.accept().ok()?.0;
+            (!this.stopped.load(Ordering::SeqCst)).then_some(stream)
+        }
+    }
+    fn stop(this: &Arc<Self>) {
+        this.stopped.store(true, Ordering::SeqCst);
+        // wake up `accept`
+        let _ = TcpStream::connect(this.addr);
+    }
+    fn can_stop(this: &Arc<Self>, _stream: &Self::EStream) -> bool {
+        this.can_stop
+    }
+    fn to_buffer_stream(_this: &Arc<Self>, stream: Self::Stream) -> Self::BufStream {
+        BufReadWriter::new(stream)
+    }
+    fn closer(_: &Arc<Self>, stream: &Self::Stream) -> Option<Closer> {
+        let stream = stream.try_clone().ok()?;
+        Some(Box::new(move || {
+            let _ = stream.shutdown(Shutdown::Both);
+        }))
+    }
+    fn encrypt_connection(
+        _this: &Arc<Self>,
+        _data: &Arc<Mutex<Self::AppData>>,
+        mut stream: Self::BufStream,
+    ) -> io::Result<Self::EStream> {
+        let keys = handshake::respond_sync(&mut stream, &KEY)?;
+        Ok(EStreamSync::builder()
+            .keys(keys)
+            .inner(stream.inner())
+            .build()
+            .unwrap())
+    }
+}
+
+/// Server allowing one connection at a time.
+fn server(policy: OverloadPolicy) -> (SocketAddr, Server<TestApp>) {
+    server_with(policy, true)
+}
+
+/// [`server`] whose app lets senders stop it only if `can_stop`.
+fn server_with(policy: OverloadPolicy, can_stop: bool) -> (SocketAddr, Server<TestApp>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(move || TestApp {
+        addr,
+        listener: Mutex::new(Some(listener)),
+        stopped: AtomicBool::new(false),
+        can_stop,
+    });
+    server
+        .app_name("eagle-eye")
+        .version((2, 0, 0))
+        .max_connection(1)
+        .overload_policy(policy);
+    (addr, server)
+}
+
+fn spawn(server: Server<TestApp>) -> JoinHandle<()> {
+    std::thread::spawn(move || server.run())
+}
+
+fn join(server: JoinHandle<()>) {
+    wait_until(|| server.is_finished());
+    server.join().unwrap();
+}
+
+fn connect(addr: SocketAddr) -> io::Result<TaskSenderSync> {
+    ClientSync::new().connect(KEY, TcpStream::connect(addr)?)
+}
+
+fn busy(err: io::Error) -> Busy {
+    assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+    *err.get_ref().unwrap().downcast_ref::<Busy>().unwrap()
+}
+
+fn echo(sender: &mut TaskSenderSync) -> io::Result<()> {
+    let mut buf = [0; 4];
+    sender.write_all(b"echo\n")?;
+    sender.flush()?;
+    assert!(Response::read_from(&mut *sender)?.is_ok());
+    sender.write_all(b"data")?;
+    sender.flush()?;
+    sender.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"data");
+    Ok(())
+}
+
+fn close(mut sender: TaskSenderSync) {
+    sender.write_all(b":break:\n").unwrap();
+    sender.flush().unwrap();
+}
+
+fn wait_until(f: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !f() {
+        assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_sync_server_rejects_when_busy() {
+    let (addr, server) = server(OverloadPolicy::Reject {
+        retry_after: Duration::from_millis(250),
+    });
+    let stats = server.stats();
+    spawn(server);
+    let mut first = connect(addr).unwrap();
+    let err = connect(addr).err().unwrap();
+    assert_eq!(
+        busy(err),
+        Busy {
+            retry_after: Duration::from_millis(250),
+            active: 1,
+            queued: 0,
+        }
+    );
+    // the first session is not affected
+    echo(&mut first).unwrap();
+    close(first);
+    wait_until(|| stats.active() == 0);
+    connect(addr).unwrap();
+}
+
+#[test]
+fn test_sync_server_queues_until_slot_is_free() {
+    let (addr, server) = server(OverloadPolicy::Queue {
+        max_queued: 1,
+        timeout: Duration::from_secs(5),
+    });
+    let stats = server.stats();
+    spawn(server);
+    let first = connect(addr).unwrap();
+    let second = std::thread::spawn(move || {
+        let mut sender = connect(addr)?;
+        echo(&mut sender)
+    });
+    wait_until(|| stats.queued() == 1);
+    assert_eq!(stats.active(), 1);
+    // the queue is full
+    let err = connect(addr).err().unwrap();
+    assert_eq!(busy(err).queued, 1);
+    close(first);
+    second.join().unwrap().unwrap();
+    assert_eq!(stats.queued(), 0);
+}
+
+#[test]
+fn test_sync_server_queue_times_out() {
+    let (addr, server) = server(OverloadPolicy::Queue {
+        max_queued: 1,
+        timeout: Duration::from_millis(100),
+    });
+    let stats = server.stats();
+    spawn(server);
+    let _first = connect(addr).unwrap();
+    let err = connect(addr).err().unwrap();
+    assert_eq!(busy(err).retry_after, Duration::from_millis(100));
+    assert_eq!(stats.queued(), 0);
+}
+
+#[test]
+fn test_sync_server_evicts_idle_session() {
+    let (addr, server) = server(OverloadPolicy::EvictIdle {
+        timeout: Duration::from_secs(5),
+    });
+    let stats = server.stats();
+    spawn(server);
+    let mut first = connect(addr).unwrap();
+    echo(&mut first).unwrap();
+    // let the session go back to waiting for the next task
+    std::thread::sleep(Duration::from_millis(100));
+    let mut second = connect(addr).unwrap();
+    echo(&mut second).unwrap();
+    assert_eq!(stats.active(), 1);
+    let mut buf = [0; 1];
+    assert!(!matches!(first.read(&mut buf), Ok(1)));
+}
+
+#[test]
+fn test_sync_server_shutdown_closes_idle_sessions() {
+    let (addr, server) = server(OverloadPolicy::default());
+    let shutdown = server.shutdown_handle();
+    let server = spawn(server);
+    let mut sender = connect(addr).unwrap();
+    echo(&mut sender).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    shutdown.shutdown();
+    join(server);
+    assert!(shutdown.is_shutdown());
+    let mut buf = [0; 1];
+    assert_eq!(sender.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_sync_server_shutdown_waits_for_running_task() {
+    let (addr, server) = server(OverloadPolicy::default());
+    let shutdown = server.shutdown_handle();
+    let stats = server.stats();
+    let server = spawn(server);
+    let mut sender = connect(addr).unwrap();
+    let mut buf = [0; 4];
+    sender.write_all(b"slow\n").unwrap();
+    sender.flush().unwrap();
+    assert!(Response::read_from(&mut sender).unwrap().is_ok());
+    shutdown.shutdown();
+    // no new connections while stopping
+    assert!(connect(addr).is_err());
+    sender.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"done");
+    join(server);
+    assert_eq!(stats.active(), 0);
+}
+
+#[test]
+fn test_sync_server_shutdown_closes_busy_sessions_after_deadline() {
+    let (addr, mut server) = server(OverloadPolicy::default());
+    server.shutdown_timeout(Duration::from_millis(100));
+    let shutdown = server.shutdown_handle();
+    let server = spawn(server);
+    let mut sender = connect(addr).unwrap();
+    let mut buf = [0; 4];
+    // echo waits for data that never comes
+    sender.write_all(b"echo\n").unwrap();
+    sender.flush().unwrap();
+    assert!(Response::read_from(&mut sender).unwrap().is_ok());
+    let start = Instant::now();
+    shutdown.shutdown();
+    join(server);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert!(!matches!(sender.read(&mut buf), Ok(1..)));
+}
+
+#[test]
+fn test_sync_server_remote_stop() {
+    let (addr, mut server) = server(OverloadPolicy::default());
+    server.remote_stop(true);
+    let server = spawn(server);
+    connect(addr).unwrap().stop_server().unwrap();
+    join(server);
+}
+
+#[test]
+fn test_sync_server_refuses_remote_stop() {
+    let (addr, server) = server(OverloadPolicy::default());
+    let shutdown = server.shutdown_handle();
+    spawn(server);
+    let mut sender = connect(addr).unwrap();
+    let err = sender.stop_server().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(!shutdown.is_shutdown());
+    // the session goes on
+    echo(&mut sender).unwrap();
+}
+
+#[test]
+fn test_sync_server_app_refuses_remote_stop() {
+    let (addr, mut server) = server_with(OverloadPolicy::default(), false);
+    server.remote_stop(true);
+    let shutdown = server.shutdown_handle();
+    spawn(server);
+    let mut sender = connect(addr).unwrap();
+    let err = sender.stop_server().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert!(!shutdown.is_shutdown());
+    echo(&mut sender).unwrap();
+}
+
+#[test]
+fn test_sync_server_unknown_task() {
+    let (addr, server) = server(OverloadPolicy::default());
+    spawn(server);
+    let mut sender = connect(addr).unwrap();
+    sender.write_all(b"unknown\n").unwrap();
+    sender.flush().unwrap();
+    let res = Response::read_from(&mut sender).unwrap();
+    assert_eq!(res.get_status(), Status::UnknownTask);
+    assert_eq!(res.get_message(), Some("unknown task `unknown`"));
+    let err = res.into_result().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    // the session goes on
+    echo(&mut sender).unwrap();
+}
+
+#[test]
+fn test_sync_server_sends_capabilities() {
+    let (addr, server) = server(OverloadPolicy::default());
+    spawn(server);
+    let sender = connect(addr).unwrap();
+    let caps = sender.capabilities();
+    assert_eq!(caps.task_version("echo"), Some(1));
+    assert_eq!(caps.task_version("slow"), Some(2));
+    assert!(!caps.supports(Ping::id()));
+    assert_eq!(caps.get_max_frame_size() as usize, DEFAULT_MAX_FRAME_SIZE);
+    assert_eq!(caps.get_ciphers(), [CIPHER_SUITE]);
+    // unknown tasks fail before anything is sent
+    let err = sender.require::<Ping>().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn test_sync_server_rejects_older_sender() {
+    let (addr, server) = server(OverloadPolicy::default());
+    spawn(server);
+    let err = ClientSync::new()
+        .version((1, 0, 0))
+        .connect(KEY, TcpStream::connect(addr).unwrap())
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    assert!(err.to_string().contains("receiver version: 2.0.0"));
+}
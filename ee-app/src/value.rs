@@ -41,11 +41,17 @@ impl Value {
         let val = unsafe { &mut *(self.ptr as *mut T) };
         Some(val)
     }
+    /// # Safety
+    ///
+    /// `T` must be the type this value was created with.
     pub unsafe fn get_unchecked<T>(&self) -> &T {
         unsafe { &*(self.ptr as *const T) }
     }
 
-    pub unsafe fn get_mut_unchecked<T>(&self) -> &mut T {
+    /// # Safety
+    ///
+    /// `T` must be the type this value was created with.
+    pub unsafe fn get_mut_unchecked<T>(&mut self) -> &mut T {
         unsafe { &mut *(self.ptr as *mut T) }
     }
     pub fn as_ptr(&self) -> *const u8 {
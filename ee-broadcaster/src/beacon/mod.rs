@@ -0,0 +1,243 @@
+//! Authenticated discovery beacon.
+//!
+//! The sender broadcasts `<prefix><beacon>` until the receiver with the
+//! matching id dials back to `port` and answers with [`Beacon::answer`].
+//!
+//! ```text
+//! <version: 1><id: 16><port: 2><timestamp: 8><nonce: 16><mac: 32>
+//! ```
+//!
+//! The mac is HMAC-SHA256 over everything before it, keyed with the device
+//! key. A receiver only dials out for a beacon that verifies, is fresh,
+//! was not seen before and whose source is within its rate limit (see
+//! [`BeaconFilter`]).
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Prefix in front of every beacon datagram.
+pub const PREFIX: &str = ":eagle-eye:";
+
+/// UDP port the receiver listens on for beacons.
+pub const PORT: u16 = 7766;
+
+pub const NONCE_LEN: usize = 16;
+pub const MAC_LEN: usize = 32;
+pub const BEACON_LEN: usize = 1 + 16 + 2 + 8 + NONCE_LEN + MAC_LEN;
+
+/// Size of the answer the receiver sends after dialing back.
+pub const ANSWER_LEN: usize = 32;
+
+const VERSION: u8 = 1;
+const BEACON_LABEL: &[u8] = b"eagle-eye beacon v1";
+const ANSWER_LABEL: &[u8] = b"eagle-eye beacon answer v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Beacon {
+    id: u128,
+    port: u16,
+    timestamp: u64,
+    nonce: [u8; NONCE_LEN],
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or(0)
+}
+
+fn mac(key: &[u8; 32], label: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(label);
+    mac
+}
+
+impl Beacon {
+    /// Beacon for device `id`, stamped with the current time and a random nonce.
+    pub fn new(id: u128, port: u16) -> Self {
+        Self {
+            id,
+            port,
+            timestamp: unix_now(),
+            nonce: rand::random(),
+        }
+    }
+    pub fn timestamp(mut self, secs: u64) -> Self {
+        self.timestamp = secs;
+        self
+    }
+    pub fn get_id(&self) -> u128 {
+        self.id
+    }
+    pub fn get_port(&self) -> u16 {
+        self.port
+    }
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+    pub fn get_nonce(&self) -> &[u8; NONCE_LEN] {
+        &self.nonce
+    }
+    pub fn encode(&self, key: &[u8; 32]) -> [u8; BEACON_LEN] {
+        let mut v = [0u8; BEACON_LEN];
+        v[0] = VERSION;
+        v[1..17].copy_from_slice(&self.id.to_be_bytes());
+        v[17..19].copy_from_slice(&self.port.to_be_bytes());
+        v[19..27].copy_from_slice(&self.timestamp.to_be_bytes());
+        v[27..43].copy_from_slice(&self.nonce);
+        let tag = mac(key, BEACON_LABEL)
+            .chain_update(&v[..BEACON_LEN - MAC_LEN])
+            .finalize()
+            .into_bytes();
+        v[BEACON_LEN - MAC_LEN..].copy_from_slice(&tag);
+        v
+    }
+    /// Parse `data` (without the prefix), `None` unless the mac verifies
+    /// under `key`.
+    pub fn decode(data: &[u8], key: &[u8; 32]) -> Option<Self> {
+        if data.len() != BEACON_LEN || data[0] != VERSION {
+            return None;
+        }
+        let (body, tag) = data.split_at(BEACON_LEN - MAC_LEN);
+        mac(key, BEACON_LABEL)
+            .chain_update(body)
+            .verify_slice(tag)
+            .ok()?;
+        Some(Self {
+            id: u128::from_be_bytes(body[1..17].try_into().unwrap()),
+            port: u16::from_be_bytes(body[17..19].try_into().unwrap()),
+            timestamp: u64::from_be_bytes(body[19..27].try_into().unwrap()),
+            nonce: body[27..43].try_into().unwrap(),
+        })
+    }
+    /// Proof sent by the receiver after dialing back, so the sender knows
+    /// the connection comes from the device and not from someone who saw
+    /// the beacon.
+    pub fn answer(&self, key: &[u8; 32]) -> [u8; ANSWER_LEN] {
+        mac(key, ANSWER_LABEL)
+            .chain_update(self.id.to_be_bytes())
+            .chain_update(self.nonce)
+            .finalize()
+            .into_bytes()
+            .into()
+    }
+    /// Check an answer (in constant time).
+    pub fn verify_answer(&self, key: &[u8; 32], answer: &[u8]) -> bool {
+        mac(key, ANSWER_LABEL)
+            .chain_update(self.id.to_be_bytes())
+            .chain_update(self.nonce)
+            .verify_slice(answer)
+            .is_ok()
+    }
+}
+
+/// Why [`BeaconFilter::check`] turned a beacon down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejected {
+    /// The timestamp is further away than the maximum age.
+    Stale,
+    /// The nonce was already seen.
+    Duplicate,
+    /// The source sent too many beacons in the current window.
+    RateLimited,
+    /// The nonce cache is full of fresh entries.
+    CacheFull,
+}
+
+/// Receiver side replay and rate limit state.
+pub struct BeaconFilter {
+    max_age: Duration,
+    max_per_source: u32,
+    rate_window: Duration,
+    cache_size: usize,
+    seen: HashMap<[u8; NONCE_LEN], u64>,
+    sources: HashMap<IpAddr, (Instant, u32)>,
+}
+
+impl Default for BeaconFilter {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(30),
+            max_per_source: 4,
+            rate_window: Duration::from_secs(10),
+            cache_size: 4096,
+            seen: HashMap::new(),
+            sources: HashMap::new(),
+        }
+    }
+}
+
+impl BeaconFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Largest accepted difference between the beacon timestamp and the
+    /// local clock.
+    pub fn max_age(mut self, d: Duration) -> Self {
+        self.max_age = d;
+        self
+    }
+    /// Accept at most `max` beacons from one address per `per`.
+    pub fn rate_limit(mut self, max: u32, per: Duration) -> Self {
+        self.max_per_source = max;
+        self.rate_window = per;
+        self
+    }
+    pub fn cache_size(mut self, size: usize) -> Self {
+        self.cache_size = size;
+        self
+    }
+    /// Decide if the receiver may dial back for `beacon`, received from
+    /// `source`. An accepted beacon is remembered and never accepted again.
+    pub fn check(&mut self, source: IpAddr, beacon: &Beacon) -> Result<(), Rejected> {
+        self.check_at(source, beacon, unix_now(), Instant::now())
+    }
+    pub(crate) fn check_at(
+        &mut self,
+        source: IpAddr,
+        beacon: &Beacon,
+        now: u64,
+        instant: Instant,
+    ) -> Result<(), Rejected> {
+        let max_age = self.max_age.as_secs();
+        if now.abs_diff(beacon.timestamp) > max_age {
+            return Err(Rejected::Stale);
+        }
+        if self.seen.contains_key(&beacon.nonce) {
+            return Err(Rejected::Duplicate);
+        }
+        if self.seen.len() >= self.cache_size {
+            // stale entries can not be replayed anyway
+            self.seen.retain(|_, t| now.abs_diff(*t) <= max_age);
+            if self.seen.len() >= self.cache_size {
+                return Err(Rejected::CacheFull);
+            }
+        }
+        if self.sources.len() >= self.cache_size {
+            let window = self.rate_window;
+            self.sources
+                .retain(|_, (start, _)| instant.duration_since(*start) < window);
+        }
+        let (start, count) = self.sources.entry(source).or_insert((instant, 0));
+        if instant.duration_since(*start) >= self.rate_window {
+            *start = instant;
+            *count = 0;
+        }
+        if *count >= self.max_per_source {
+            return Err(Rejected::RateLimited);
+        }
+        *count += 1;
+        self.seen.insert(beacon.nonce, beacon.timestamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test;
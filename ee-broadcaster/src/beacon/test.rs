@@ -0,0 +1,125 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use crate::beacon::{BEACON_LEN, Beacon, BeaconFilter, Rejected};
+
+const KEY: [u8; 32] = [7; 32];
+const NOW: u64 = 1_700_000_000;
+
+fn ip(v: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(192, 168, 1, v))
+}
+
+#[test]
+fn test_beacon_round_trip() {
+    let beacon = Beacon::new(123, 4567);
+    let data = beacon.encode(&KEY);
+    assert_eq!(data.len(), BEACON_LEN);
+    assert_eq!(Beacon::decode(&data, &KEY), Some(beacon));
+    assert_eq!(beacon.get_id(), 123);
+    assert_eq!(beacon.get_port(), 4567);
+    assert_ne!(Beacon::new(123, 4567).get_nonce(), beacon.get_nonce());
+}
+
+#[test]
+fn test_beacon_wrong_key_or_tampered() {
+    let data = Beacon::new(123, 4567).encode(&KEY);
+    assert_eq!(Beacon::decode(&data, &[8; 32]), None);
+    for i in 0..BEACON_LEN {
+        let mut v = data;
+        v[i] ^= 1;
+        assert_eq!(Beacon::decode(&v, &KEY), None);
+    }
+    assert_eq!(Beacon::decode(&data[..BEACON_LEN - 1], &KEY), None);
+    assert_eq!(Beacon::decode(&[data.as_slice(), &[0]].concat(), &KEY), None);
+}
+
+#[test]
+fn test_beacon_answer() {
+    let beacon = Beacon::new(123, 4567);
+    let answer = beacon.answer(&KEY);
+    assert!(beacon.verify_answer(&KEY, &answer));
+    assert!(!beacon.verify_answer(&[8; 32], &answer));
+    assert!(!Beacon::new(123, 4567).verify_answer(&KEY, &answer));
+    assert!(!beacon.verify_answer(&KEY, &answer[..31]));
+}
+
+#[test]
+fn test_beacon_filter_stale() {
+    let mut f = BeaconFilter::new().max_age(Duration::from_secs(30));
+    let t = Instant::now();
+    let old = Beacon::new(1, 1).timestamp(NOW - 31);
+    let future = Beacon::new(1, 1).timestamp(NOW + 31);
+    assert_eq!(f.check_at(ip(1), &old, NOW, t), Err(Rejected::Stale));
+    assert_eq!(f.check_at(ip(1), &future, NOW, t), Err(Rejected::Stale));
+    let edge = Beacon::new(1, 1).timestamp(NOW - 30);
+    assert_eq!(f.check_at(ip(1), &edge, NOW, t), Ok(()));
+}
+
+#[test]
+fn test_beacon_filter_duplicate() {
+    let mut f = BeaconFilter::new();
+    let t = Instant::now();
+    let beacon = Beacon::new(1, 1).timestamp(NOW);
+    assert_eq!(f.check_at(ip(1), &beacon, NOW, t), Ok(()));
+    assert_eq!(
+        f.check_at(ip(1), &beacon, NOW + 1, t),
+        Err(Rejected::Duplicate)
+    );
+    // from another host as well
+    assert_eq!(
+        f.check_at(ip(2), &beacon, NOW + 1, t),
+        Err(Rejected::Duplicate)
+    );
+}
+
+#[test]
+fn test_beacon_filter_rate_limit() {
+    let mut f = BeaconFilter::new().rate_limit(2, Duration::from_secs(10));
+    let t = Instant::now();
+    let mut check = |source, after| {
+        f.check_at(
+            source,
+            &Beacon::new(1, 1).timestamp(NOW),
+            NOW,
+            t + Duration::from_secs(after),
+        )
+    };
+    assert_eq!(check(ip(1), 0), Ok(()));
+    assert_eq!(check(ip(1), 1), Ok(()));
+    assert_eq!(check(ip(1), 2), Err(Rejected::RateLimited));
+    // other hosts are not affected
+    assert_eq!(check(ip(2), 2), Ok(()));
+    // next window
+    assert_eq!(check(ip(1), 10), Ok(()));
+}
+
+#[test]
+fn test_beacon_filter_rate_limited_beacon_not_remembered() {
+    let mut f = BeaconFilter::new().rate_limit(1, Duration::from_secs(10));
+    let t = Instant::now();
+    let a = Beacon::new(1, 1).timestamp(NOW);
+    let b = Beacon::new(1, 1).timestamp(NOW);
+    assert_eq!(f.check_at(ip(1), &a, NOW, t), Ok(()));
+    assert_eq!(f.check_at(ip(1), &b, NOW, t), Err(Rejected::RateLimited));
+    // a retransmission of `b` is still good once the window is over
+    let later = t + Duration::from_secs(10);
+    assert_eq!(f.check_at(ip(1), &b, NOW, later), Ok(()));
+}
+
+#[test]
+fn test_beacon_filter_cache_full() {
+    let mut f = BeaconFilter::new()
+        .cache_size(2)
+        .max_age(Duration::from_secs(30))
+        .rate_limit(100, Duration::from_secs(10));
+    let t = Instant::now();
+    let mut check = |now| f.check_at(ip(1), &Beacon::new(1, 1).timestamp(now), now, t);
+    assert_eq!(check(NOW), Ok(()));
+    assert_eq!(check(NOW), Ok(()));
+    assert_eq!(check(NOW), Err(Rejected::CacheFull));
+    // the old entries expire and make room
+    assert_eq!(check(NOW + 31), Ok(()));
+}
@@ -1,5 +1,7 @@
+pub mod beacon;
 mod receiver;
 mod sender;
 
+pub use beacon::{Beacon, BeaconFilter};
 pub use receiver::{ReceiverInfo, ReceiverInfoBuilder};
 pub use sender::{SenderInfo, SenderInfoBuilder};
@@ -19,6 +19,10 @@ impl ReceiverInfo {
     pub fn builder() -> ReceiverInfoBuilder {
         ReceiverInfoBuilder::default()
     }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> io::Result<Option<(SocketAddr, &mut [u8], usize)>> {
         let socket = &self.socket;
         loop {
@@ -26,10 +30,10 @@ impl ReceiverInfo {
                 return Ok(None);
             }
             let v = socket.recv_from(&mut self.buf);
-            dbg!("data received ( remove me )");
             match v {
-                Ok((total, addr)) if self.buf.starts_with(&self.prefix) => {
-                    return Ok(Some((addr, &mut self.buf[self.prefix.len()..], total)));
+                Ok((total, addr)) if self.buf[..total].starts_with(&self.prefix) => {
+                    let n = self.prefix.len();
+                    return Ok(Some((addr, &mut self.buf[n..total], total - n)));
                 }
                 Ok(_) => {
                     if !self.is_running.load(Ordering::Relaxed) {
@@ -80,6 +84,12 @@ impl ReceiverInfoBuilder {
         self.socket_addr = addr;
         self
     }
+    /// Read timeout of the socket, `next` fails with
+    /// [`io::ErrorKind::WouldBlock`] (or `TimedOut`) when it expires.
+    pub fn time_out(mut self, t: Duration) -> Self {
+        self.time_out = Some(t);
+        self
+    }
     pub fn build(self) -> io::Result<ReceiverInfo> {
         let buf_size = self.buf_size.expect("Buffer size can not be zero...").get();
         let v = Box::<[u8]>::new_uninit_slice(buf_size + self.prefix.len());
@@ -1 +1,52 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
 
+use ee_broadcaster::{
+    Beacon, BeaconFilter, ReceiverInfo, SenderInfo,
+    beacon::{PREFIX, Rejected},
+};
+
+#[test]
+fn test_send_recv_beacon() {
+    let key = [5; 32];
+    let mut receiver = ReceiverInfo::builder()
+        .prefix(PREFIX)
+        .socket_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .build()
+        .unwrap();
+    let port = receiver.local_addr().unwrap().port();
+
+    // junk without the prefix is skipped
+    let junk = UdpSocket::bind("127.0.0.1:0").unwrap();
+    junk.send_to(b"hello", ("127.0.0.1", port)).unwrap();
+
+    let beacon = Beacon::new(99, 4321);
+    let is_running = Arc::new(AtomicBool::new(true));
+    let sender = SenderInfo::builder()
+        .is_running(is_running.clone())
+        .prefix(PREFIX)
+        .data(beacon.encode(&key))
+        .socket_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .broadcast_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port))
+        .build();
+    let t = std::thread::spawn(move || sender.send());
+
+    let mut filter = BeaconFilter::new();
+    let (addr, data, len) = receiver.next().unwrap().unwrap();
+    assert_eq!(data.len(), len);
+    let got = Beacon::decode(data, &key).unwrap();
+    assert_eq!(got, beacon);
+    assert_eq!(filter.check(addr.ip(), &got), Ok(()));
+
+    // the sender repeats the beacon, the receiver must not dial twice
+    let (addr, data, _) = receiver.next().unwrap().unwrap();
+    let again = Beacon::decode(data, &key).unwrap();
+    assert_eq!(filter.check(addr.ip(), &again), Err(Rejected::Duplicate));
+
+    is_running.store(false, std::sync::atomic::Ordering::Relaxed);
+    std::thread::sleep(Duration::from_millis(10));
+    t.join().unwrap().unwrap();
+}
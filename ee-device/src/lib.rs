@@ -3,21 +3,27 @@ mod utils;
 
 use ee_http::HttpRequest;
 pub use task_sender::TaskSenderSync;
+pub use utils::Busy;
 
 use std::{
     collections::HashMap,
-    io::{self, BufWriter, Read, Write},
+    io::{self, Read, Write},
     net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream},
     path::PathBuf,
     sync::{Arc, atomic::AtomicBool},
     time::Duration,
 };
 
-use aes::cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
-use ee_broadcaster::SenderInfo;
+use ee_broadcaster::{Beacon, SenderInfo, beacon};
+use ee_proto::Capabilities;
+use ee_stream::{
+    e_stream::{EStreamSync, TAG_LEN},
+    handshake,
+    pairing::{self, PairingCode},
+};
 use ee_task::{ExeSenderSync, ExecuteResult, ping_pong::Ping};
 
-use crate::utils::handle_auth_on_sender_sync;
+use crate::utils::hello_sync;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Device {
@@ -98,9 +104,9 @@ impl Device {
         let mut buf = [0; 16];
         let mut small = [0; 2];
         reader.read_exact(&mut small)?;
-        let mut os_len = u16::from_be_bytes([small[0], small[1]]) as usize;
+        let os_len = u16::from_be_bytes([small[0], small[1]]) as usize;
         reader.read_exact(&mut small)?;
-        let mut user_len = u16::from_be_bytes([small[0], small[1]]) as usize;
+        let user_len = u16::from_be_bytes([small[0], small[1]]) as usize;
         if os_len + user_len > 128 {
             return Err(io::Error::other(
                 "ERROR: length of (user + os) can not be greater then 128",
@@ -110,26 +116,45 @@ impl Device {
         let id = u128::from_be_bytes(buf);
         let mut key = [0; 32];
         reader.read_exact(&mut key)?;
-        reader.read_exact(&mut buf[0..2])?;
-        let mut os = String::new();
-        loop {
-            if os_len == 0 {
-                break;
-            }
-            let r = reader.read(&mut buf[0..std::cmp::min(16, os_len)])?;
-            os.push_str(unsafe { std::str::from_utf8_unchecked(&buf[0..r]) });
-            os_len -= r;
-        }
-        let mut user = String::new();
-        loop {
-            if user_len == 0 {
-                break;
-            }
-            let r = reader.read(&mut buf[0..std::cmp::min(16, user_len)])?;
-            user.push_str(unsafe { std::str::from_utf8_unchecked(&buf[0..r]) });
-            user_len -= r;
+        let mut os = vec![0; os_len];
+        reader.read_exact(&mut os)?;
+        let mut user = vec![0; user_len];
+        reader.read_exact(&mut user)?;
+        reader.read_exact(&mut small)?;
+        if small != [111, 0] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ERROR: invalid device record",
+            ));
         }
-        Ok(Self { id, key, os, user })
+        let to_string = |v| {
+            String::from_utf8(v).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "ERROR: invalid utf-8")
+            })
+        };
+        Ok(Self {
+            id,
+            key,
+            os: to_string(os)?,
+            user: to_string(user)?,
+        })
+    }
+    /// Pair with the receiver on the other end of `stream` using the code
+    /// it shows, and return it with its freshly exchanged id and key.
+    pub fn pair_sync<S: io::Read + io::Write>(
+        mut stream: S,
+        code: &PairingCode,
+    ) -> io::Result<Self> {
+        let keys = pairing::initiate_sync(&mut stream, code)?;
+        let mut stream = EStreamSync::builder()
+            .keys(keys)
+            .inner(stream)
+            .build()
+            .unwrap();
+        let device = Self::from_reader(&mut stream)?;
+        stream.write_all(b":ok:")?;
+        stream.flush()?;
+        Ok(device)
     }
     pub fn get_id(&self) -> &u128 {
         &self.id
@@ -144,68 +169,55 @@ impl Device {
         self.user.as_str()
     }
     pub fn connect_sync(&self, timeout: Duration) -> io::Result<Option<TcpStream>> {
-        type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
-        let mut buffer = [0; 256];
         let is_running = Arc::new(AtomicBool::new(true));
         let listener = TcpListener::bind(SocketAddr::new(
             std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
             0,
         ))?;
         let addr = listener.local_addr()?;
-        let listener_port = addr.port();
-        let iv = rand::random::<[u8; 16]>();
-        let secret = rand::random::<[u8; 16]>();
-        let ct = Aes256CbcEnc::new(self.get_key().into(), &iv.into());
-        let id_bytes = &self.get_id().to_be_bytes(); // 16 bytes = 128 bit
-        buffer[..16].copy_from_slice(id_bytes);
-        buffer[16..32].copy_from_slice(&secret);
-        buffer[32..34].copy_from_slice(&listener_port.to_be_bytes());
-        let encrypted = ct.encrypt_padded_mut::<Pkcs7>(&mut buffer, 34).unwrap();
-        let data_len = encrypted.len() as u16 + iv.len() as u16;
+        let beacon = Beacon::new(self.id, addr.port());
+        let key = self.key;
 
-        is_running.store(true, std::sync::atomic::Ordering::Relaxed);
         let v = SenderInfo::builder()
             .is_running(is_running.clone())
-            .prefix(":eagle-eye:")
-            .data(data_len.to_be_bytes())
-            .data(iv)
-            .data(encrypted)
+            .prefix(beacon::PREFIX)
+            .data(beacon.encode(&key))
             .broadcast_addr(SocketAddr::new(
                 IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)),
-                6923,
+                beacon::PORT,
             ))
             .socket_addr(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0))
             .build();
         let (send, recv) = std::sync::mpsc::channel::<TcpStream>();
         let t1 = std::thread::spawn(move || v.send());
-        let t2 = std::thread::spawn(move || {
-            let mut buf = [0; 16];
-            for stream in listener.incoming() {
-                if stream.is_err() {
-                    break;
-                }
-                let mut stream = stream.unwrap();
-                if stream
-                    .set_read_timeout(Some(Duration::from_secs(1)))
-                    .is_err()
-                {
-                    break;
-                }
-                if stream.read_exact(&mut buf[0..1]).is_err() {
-                    continue;
+        let t2 = {
+            let is_running = is_running.clone();
+            std::thread::spawn(move || {
+                let mut buf = [0; beacon::ANSWER_LEN];
+                for stream in listener.incoming() {
+                    if !is_running.load(std::sync::atomic::Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(mut stream) = stream else {
+                        break;
+                    };
+                    if stream
+                        .set_read_timeout(Some(Duration::from_secs(1)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                    if stream.read_exact(&mut buf).is_err() {
+                        continue;
+                    }
+                    if beacon.verify_answer(&key, &buf) {
+                        let _ = stream.set_read_timeout(None);
+                        send.send(stream).unwrap();
+                        break;
+                    }
                 }
-                if buf[0] == 111 {
-                    break;
-                }
-                if stream.read_exact(&mut buf).is_err() {
-                    continue;
-                }
-                if buf == secret {
-                    send.send(stream).unwrap();
-                    break;
-                }
-            }
-        });
+            })
+        };
 
         let now = std::time::Instant::now();
         loop {
@@ -217,8 +229,7 @@ impl Device {
             if now.elapsed() > timeout {
                 // stop t1 and t2 thread
                 is_running.store(false, std::sync::atomic::Ordering::Relaxed);
-                let mut v = TcpStream::connect(addr)?;
-                v.write_all(&[111])?;
+                TcpStream::connect(addr)?;
                 break;
             }
         }
@@ -230,6 +241,8 @@ impl Device {
 
 pub struct ClientSync {
     id: u128,
+    app_name: &'static str,
+    version: (u32, u32, u32),
     device_connect_time_out: Duration,
     // devices: Vec<Device>,
     log: Option<PathBuf>,
@@ -245,10 +258,27 @@ impl ClientSync {
     pub fn new() -> Self {
         Self {
             id: 0,
+            app_name: "eagle-eye",
+            version: (2, 0, 0),
             device_connect_time_out: Duration::from_secs(3),
             log: None,
         }
     }
+    pub fn id(mut self, id: u128) -> Self {
+        self.id = id;
+        self
+    }
+    pub fn get_id(&self) -> &u128 {
+        &self.id
+    }
+    pub fn app_name(mut self, name: &'static str) -> Self {
+        self.app_name = name;
+        self
+    }
+    pub fn version(mut self, version: (u32, u32, u32)) -> Self {
+        self.version = version;
+        self
+    }
     pub fn log<T: Into<PathBuf>>(mut self, path: T) -> Self {
         self.log = Some(path.into());
         self
@@ -257,63 +287,71 @@ impl ClientSync {
         self.device_connect_time_out = t;
         self
     }
-    pub fn connect(&self, key: [u8; 32], stream: TcpStream) -> io::Result<TaskSenderSync> {
-        let stream1 = stream;
-        let stream2 = stream1.try_clone()?;
-        let e_stream = match handle_auth_on_sender_sync(key, stream1, stream2)? {
-            Some(v) => v,
-            None => {
-                return Err(io::Error::new(
-                    io::ErrorKind::PermissionDenied,
-                    "Wrong Password",
-                ));
-            }
-        };
-        Ok(TaskSenderSync::new(e_stream))
+    pub fn connect(&self, key: [u8; 32], mut stream: TcpStream) -> io::Result<TaskSenderSync> {
+        hello_sync(&mut stream, self.app_name, self.version)?;
+        let keys = handshake::initiate_sync(&mut stream, &key)?;
+        let mut e_stream = EStreamSync::builder()
+            .keys(keys)
+            .inner(stream)
+            .build()
+            .unwrap();
+        let capabilities = Capabilities::read_from(&mut e_stream)?;
+        let max_frame_size = capabilities.get_max_frame_size() as usize;
+        if max_frame_size < e_stream.write_buffer_size() + TAG_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("receiver accepts frames of at most {max_frame_size} bytes"),
+            ));
+        }
+        Ok(TaskSenderSync::new(e_stream, capabilities))
+    }
+    /// Find `device` on the network and open a new session with it.
+    pub fn connect_device(&self, device: &Device) -> io::Result<TaskSenderSync> {
+        let stream = device
+            .connect_sync(self.device_connect_time_out)?
+            .ok_or_else(|| io::Error::other("Device is offline"))?;
+        self.connect(device.key, stream)
     }
 }
 
-pub struct DeviceManager<'a> {
+pub struct DeviceManager {
     // online devices
-    online: HashMap<u128, TaskSenderSync<'a>>,
+    online: HashMap<u128, TaskSenderSync>,
     // u128: device id
     // [u8; 32]: password
     all: Vec<Device>,
 }
 
-impl Default for DeviceManager<'_> {
+impl Default for DeviceManager {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl DeviceManager<'_> {
+impl DeviceManager {
     pub fn new() -> Self {
         Self {
             online: HashMap::new(),
             all: Vec::new(),
         }
     }
-    pub fn send<
-        T: for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync<'static>, &'b mut BufWriter<TcpStream>>,
-    >(
+    pub fn send<W, T>(
         &mut self,
         client: &ClientSync,
         id: &u128,
         req: &mut HttpRequest,
-        http: &mut BufWriter<TcpStream>,
+        http: &mut W,
         task: T,
-    ) -> io::Result<ExecuteResult> {
+    ) -> io::Result<ExecuteResult>
+    where
+        W: io::Write,
+        T: for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut W>,
+    {
         if let Some(t) = self.online.get_mut(id) {
             t.send(task, req, http)
         } else {
             if let Some(device) = self.all.iter().find(|&v| v.get_id() == id) {
-                let v = device.connect_sync(client.device_connect_time_out)?;
-                if v.is_none() {
-                    return Err(io::Error::other("Device is offline"));
-                }
-                let stream = v.unwrap();
-                let mut sender = client.connect(device.key, stream)?;
+                let mut sender = client.connect_device(device)?;
                 let r = sender.send(task, req, http);
                 self.online.insert(*id, sender);
                 return r;
@@ -360,6 +398,18 @@ impl DeviceManager<'_> {
             self.all.push(device);
         }
     }
+    /// Pair with a new receiver (see [`Device::pair_sync`]) and remember
+    /// it. Call [`DeviceManager::save`] to persist it.
+    pub fn pair_sync<S: io::Read + io::Write>(
+        &mut self,
+        stream: S,
+        code: &PairingCode,
+    ) -> io::Result<&Device> {
+        let device = Device::pair_sync(stream, code)?;
+        let id = device.id;
+        self.push_device(device);
+        Ok(self.get_device(&id).unwrap())
+    }
     pub fn get_device(&self, id: &u128) -> Option<&Device> {
         self.all.iter().find(|&v| &v.id == id)
     }
@@ -388,10 +438,7 @@ impl DeviceManager<'_> {
     pub fn get_online_device(&self) -> Vec<&Device> {
         self.online
             .iter()
-            .map(|v| v.0)
-            .map(|v| self.get_device(v))
-            .filter(|v| v.is_some())
-            .map(|v| v.unwrap())
+            .filter_map(|v| self.get_device(v.0))
             .collect()
     }
     pub fn scan(&mut self, client: &ClientSync) -> io::Result<()> {
@@ -411,3 +458,6 @@ impl DeviceManager<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -1,20 +1,25 @@
-use std::io::{self, Read, Write};
+use std::{
+    io::{self, Write},
+    net::TcpStream,
+};
 
 use ee_http::HttpRequest;
-use ee_stream::{EStreamSync, FlowControl};
-use ee_task::{ExeSenderSync, ExecuteResult};
+use ee_proto::{Capabilities, Response, Status};
+use ee_stream::e_stream::EStreamSync;
+use ee_task::{ExeSenderSync, ExecuteResult, GetId};
 
-pub struct TaskSenderSync<'a> {
-    stream: EStreamSync<'a>,
+pub struct TaskSenderSync {
+    stream: EStreamSync<TcpStream>,
+    capabilities: Capabilities,
 }
 
-impl io::Read for TaskSenderSync<'_> {
+impl io::Read for TaskSenderSync {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.stream.read(buf)
     }
 }
 
-impl io::Write for TaskSenderSync<'_> {
+impl io::Write for TaskSenderSync {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.stream.write(buf)
     }
@@ -23,30 +28,50 @@ impl io::Write for TaskSenderSync<'_> {
     }
 }
 
-impl<'a> TaskSenderSync<'a> {
-    pub fn new(value: EStreamSync<'a>) -> Self {
-        Self { stream: value }
+impl TaskSenderSync {
+    pub fn new(value: EStreamSync<TcpStream>, capabilities: Capabilities) -> Self {
+        Self {
+            stream: value,
+            capabilities,
+        }
+    }
+    /// What the receiver announced when the session started.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+    /// Fails with [`io::ErrorKind::Unsupported`] unless the receiver runs
+    /// the same version of task `T`.
+    pub fn require<T: GetId>(&self) -> io::Result<()> {
+        match self.capabilities.task_version(T::id()) {
+            Some(v) if v == T::version() => Ok(()),
+            Some(v) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "receiver runs version {v} of task `{}`, sender runs version {}",
+                    T::id(),
+                    T::version()
+                ),
+            )),
+            None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("receiver does not support task `{}`", T::id()),
+            )),
+        }
     }
-}
-
-impl TaskSenderSync<'_> {
     pub fn send<U: io::Write, T: for<'a> ExeSenderSync<&'a mut Self, U>>(
         &mut self,
         task: T,
         req: &mut HttpRequest,
         http: U,
     ) -> io::Result<ExecuteResult> {
-        let mut buf = [0; 1];
+        self.require::<T>()?;
         writeln!(self, "{}", T::id())?;
         self.flush()?;
-        self.read_exact(&mut buf)?;
-        let flow = FlowControl::try_from(buf);
-        if flow.is_err() {
-            return Err(io::Error::other("Invalid Flow"));
-        }
-        match flow.unwrap() {
-            FlowControl::Close | FlowControl::StopServer => Ok(ExecuteResult::UnknownTask),
-            FlowControl::Continue => task.execute_on_sender(self, req, http),
+        let res = Response::read_from(&mut *self)?;
+        match res.get_status() {
+            Status::Ok => task.execute_on_sender(self, req, http),
+            Status::UnknownTask => Ok(ExecuteResult::UnknownTask),
+            Status::Failed => res.into_result().map(|_| ExecuteResult::Faild),
         }
     }
 
@@ -55,8 +80,11 @@ impl TaskSenderSync<'_> {
         self.flush()
     }
 
+    /// Ask the receiver to shut down, fails with
+    /// [`io::ErrorKind::PermissionDenied`] if it does not allow remote stops.
     pub fn stop_server(&mut self) -> io::Result<()> {
         self.write_all(b":stop-server:\n")?;
-        self.flush()
+        self.flush()?;
+        Response::read_from(self)?.into_result().map(|_| ())
     }
 }
@@ -0,0 +1,98 @@
+use std::{
+    io::{self, Read},
+    net::{TcpListener, TcpStream},
+    str::FromStr,
+    thread,
+};
+
+use ee_stream::{
+    e_stream::EStreamSync,
+    pairing::{self, PairingCode},
+};
+
+use crate::{Device, DeviceManager};
+
+#[test]
+fn test_device_save_from_reader() {
+    let device = Device::new()
+        .id(42)
+        .key([9; 32])
+        .os("Linux")
+        .user("eagle");
+    let mut buf = Vec::new();
+    device.save(&mut buf).unwrap();
+    assert_eq!(Device::from_reader(buf.as_slice()).unwrap(), device);
+
+    // the record trailer is checked
+    let last = buf.len() - 2;
+    buf[last] = 0;
+    assert_eq!(
+        Device::from_reader(buf.as_slice()).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_device_manager_save_from_reader() {
+    let mut manager = DeviceManager::new();
+    manager.push_device(Device::new().id(1).key([1; 32]).user("a"));
+    manager.push_device(Device::new().id(2).key([2; 32]).os("Android"));
+    manager.push_device(Device::new().id(1).key([3; 32]).user("b"));
+    assert_eq!(manager.total_device(), 2);
+    let mut buf = Vec::new();
+    manager.save(&mut buf).unwrap();
+    let loaded = DeviceManager::from_reader(buf.as_slice()).unwrap();
+    assert_eq!(loaded.total_device(), 2);
+    assert_eq!(loaded.get_device(&1), manager.get_device(&1));
+    assert_eq!(loaded.get_device(&1).unwrap().get_key(), &[3; 32]);
+    assert_eq!(loaded.get_device(&2), manager.get_device(&2));
+}
+
+/// Plays the receiver side of a pairing, sends `device` and waits for the ack.
+fn receiver(code: PairingCode, device: Device) -> (u16, thread::JoinHandle<io::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let t = thread::spawn(move || {
+        let (mut stream, _) = listener.accept()?;
+        let keys = pairing::respond_sync(&mut stream, &code)?;
+        let mut stream = EStreamSync::builder()
+            .keys(keys)
+            .inner(stream)
+            .build()
+            .unwrap();
+        device.save(&mut stream)?;
+        let mut ack = [0; 4];
+        stream.read_exact(&mut ack)?;
+        assert_eq!(&ack, b":ok:");
+        Ok(())
+    });
+    (port, t)
+}
+
+#[test]
+fn test_device_manager_pair_sync() {
+    let code = PairingCode::generate();
+    let device = Device::new().id(77).key([5; 32]).user("eagle");
+    let (port, t) = receiver(code.clone(), device.clone());
+    let mut manager = DeviceManager::new();
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let paired = manager.pair_sync(stream, &code).unwrap();
+    assert_eq!(paired, &device);
+    t.join().unwrap().unwrap();
+    assert_eq!(manager.get_device(&77), Some(&device));
+}
+
+#[test]
+fn test_device_manager_pair_sync_wrong_code() {
+    let code = PairingCode::from_str("1234-5678").unwrap();
+    let (port, t) = receiver(code, Device::new().id(77));
+    let mut manager = DeviceManager::new();
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let err = manager
+        .pair_sync(&mut stream, &PairingCode::from_str("1234-5679").unwrap())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    stream.shutdown(std::net::Shutdown::Both).unwrap();
+    assert!(t.join().unwrap().is_err());
+    assert_eq!(manager.total_device(), 0);
+}
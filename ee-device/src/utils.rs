@@ -1,32 +1,85 @@
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::{fmt, io, time::Duration};
 
-use aes::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
-use ee_stream::EStreamSync;
+/// Error payload of [`io::ErrorKind::ResourceBusy`] returned when the
+/// receiver has no free connection slot, get it with
+/// `err.get_ref().and_then(|e| e.downcast_ref::<Busy>())`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Busy {
+    /// How long the receiver asks the sender to wait before trying again.
+    pub retry_after: Duration,
+    /// Connections the receiver is serving.
+    pub active: u32,
+    /// Connections waiting for a slot on the receiver.
+    pub queued: u32,
+}
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "receiver is busy ({} active, {} queued), retry after {} ms",
+            self.active,
+            self.queued,
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for Busy {}
+
+fn read_u32<S: io::Read>(mut stream: S) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
 
-pub(crate) fn handle_auth_on_sender_sync<'a>(
-    key: [u8; 32],
-    reader: impl io::Read + 'a,
-    writer: impl io::Write + 'a,
-) -> io::Result<Option<EStreamSync<'a>>> {
-    let mut reader = BufReader::new(reader);
-    let mut writer = BufWriter::new(writer);
-    let mut iv = [0; 16];
-    let mut buf = [0u8; 32];
-    reader.read_exact(&mut iv)?;
-    reader.read_exact(&mut buf)?;
-    let mut cipher = ctr::Ctr64LE::<aes::Aes256>::new(&key.into(), &iv.into());
-    cipher.apply_keystream(&mut buf);
-    writer.write_all(&buf)?;
-    writer.flush()?;
-    reader.read_exact(&mut buf[0..3])?;
-    if buf[0..3] != *b":0:" {
-        return Ok(None);
+/// Send `<app-name><major><minor><patch>` and wait for the receiver to accept it.
+pub(crate) fn hello_sync<S: io::Read + io::Write>(
+    mut stream: S,
+    app_name: &str,
+    version: (u32, u32, u32),
+) -> io::Result<(u32, u32, u32)> {
+    stream.write_all(app_name.as_bytes())?;
+    stream.write_all(&version.0.to_be_bytes())?;
+    stream.write_all(&version.1.to_be_bytes())?;
+    stream.write_all(&version.2.to_be_bytes())?;
+    stream.flush()?;
+    let mut status = [0u8; 22];
+    stream.read_exact(&mut status[0..4])?;
+    let accepted = match &status[0..4] {
+        b":ok:" => true,
+        b":ver" => {
+            stream.read_exact(&mut status[4..])?;
+            if &status != b":version_not_accepted:" {
+                return Err(io::Error::other("Invalid Response"));
+            }
+            false
+        }
+        b":bus" => {
+            stream.read_exact(&mut status[4..6])?;
+            if &status[..6] != b":busy:" {
+                return Err(io::Error::other("Invalid Response"));
+            }
+            let busy = Busy {
+                retry_after: Duration::from_millis(read_u32(&mut stream)?.into()),
+                active: read_u32(&mut stream)?,
+                queued: read_u32(&mut stream)?,
+            };
+            return Err(io::Error::new(io::ErrorKind::ResourceBusy, busy));
+        }
+        _ => return Err(io::Error::other("Invalid Response")),
+    };
+    let major = read_u32(&mut stream)?;
+    let minor = read_u32(&mut stream)?;
+    let patch = read_u32(&mut stream)?;
+    if !accepted {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "version not accepted, receiver version: {major}.{minor}.{patch}, sender version: {}.{}.{}",
+                version.0, version.1, version.2
+            ),
+        ));
     }
-    cipher.seek(0);
-    let e_stream = EStreamSync::builder()
-        .cipher(cipher)
-        .reader(reader)
-        .writer(writer)
-        .build();
-    Ok(e_stream)
+    Ok((major, minor, patch))
 }
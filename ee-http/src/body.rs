@@ -0,0 +1,42 @@
+use std::io::{self, Read};
+
+/// The body of a request with a `Content-Length`, read straight from the
+/// connection.
+///
+/// It ends after `len` bytes, and fails if the connection ends before
+/// them, so a cut off body never reads like a whole one.
+pub struct Body<R: Read> {
+    inner: R,
+    left: u64,
+}
+
+impl<R: Read> Body<R> {
+    pub fn new(inner: R, len: u64) -> Self {
+        Self { inner, left: len }
+    }
+    /// Bytes of the body not read yet.
+    pub fn left(&self) -> u64 {
+        self.left
+    }
+}
+
+impl<R: Read> Read for Body<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.left == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf
+            .len()
+            .min(usize::try_from(self.left).unwrap_or(usize::MAX));
+        match self.inner.read(&mut buf[..len])? {
+            0 => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed before the end of the body",
+            )),
+            n => {
+                self.left -= n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::{self, Write};
+
+/// Writes a body with `Transfer-Encoding: chunked`, for responses whose
+/// length is not known up front. Every write is sent as one chunk.
+///
+/// [`ChunkedWriter::finish`] ends the body, without it the client sees a
+/// cut off response.
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Write the body to `inner`, after a response sent with the header
+    /// `Transfer-Encoding: chunked`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+    /// Send the last, empty chunk and return the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // an empty chunk would end the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
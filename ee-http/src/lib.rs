@@ -1,11 +1,20 @@
+mod body;
+mod chunked;
+pub mod multipart;
 mod request;
 mod response;
 mod status;
+pub mod websocket;
+
+#[cfg(test)]
+mod test;
 
 use std::{fmt, str::FromStr};
 
+pub use body::Body;
+pub use chunked::ChunkedWriter;
 pub use request::HttpRequest;
-pub use response::HttpResponse;
+pub use response::{HttpResponse, content_type};
 pub use status::Status;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -0,0 +1,176 @@
+use std::io::{self, Read};
+
+/// Headers of one part of a `multipart/form-data` body.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+/// Boundary of a `multipart/form-data` content type, `None` for any other
+/// content type.
+pub fn boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|v| v.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v.trim().trim_matches('"'))
+        .filter(|v| !v.is_empty())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|v| v == needle)
+}
+
+/// Most bytes taken from the body at once.
+const READ_LEN: usize = 64 * 1024;
+
+/// Longest headers of a part.
+const MAX_HEAD: usize = 8 * 1024;
+
+/// A `multipart/form-data` body read one part after the other, it never
+/// holds more of the body than one read.
+///
+/// [`Multipart::next_part`] moves to the next part and returns its headers,
+/// reading then gives the data of that part up to its end.
+pub struct Multipart<R: Read> {
+    inner: R,
+    /// `\r\n--<boundary>`, the end of every part.
+    delimiter: Vec<u8>,
+    /// Read from `inner`, not handed out yet.
+    buf: Vec<u8>,
+    /// In the data of a part, or in the preamble.
+    in_part: bool,
+    done: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(inner: R, boundary: &str) -> Self {
+        Self {
+            inner,
+            delimiter: format!("\r\n--{boundary}").into_bytes(),
+            // the first boundary starts the body, without a line break
+            buf: b"\r\n".to_vec(),
+            in_part: true,
+            done: false,
+        }
+    }
+    /// Skip the rest of the current part and return the headers of the next
+    /// one, `None` after the last part.
+    pub fn next_part(&mut self) -> io::Result<Option<Part>> {
+        if self.done {
+            return Ok(None);
+        }
+        io::copy(self, &mut io::sink())?;
+        self.fill(2)?;
+        if self.buf.starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+        if !self.buf.starts_with(b"\r\n") {
+            return Err(invalid("invalid boundary line"));
+        }
+        self.buf.drain(..2);
+        let (end, skip) = loop {
+            if self.buf.starts_with(b"\r\n") {
+                break (0, 2);
+            }
+            if let Some(i) = find(&self.buf, b"\r\n\r\n") {
+                break (i, 4);
+            }
+            if self.buf.len() > MAX_HEAD {
+                return Err(invalid("part headers are too long"));
+            }
+            self.read_more()?;
+        };
+        let head = std::str::from_utf8(&self.buf[..end]).map_err(|_| invalid("invalid utf-8"))?;
+        let part = parse_head(head)?;
+        self.buf.drain(..end + skip);
+        self.in_part = true;
+        Ok(Some(part))
+    }
+    /// Read until `buf` holds at least `len` bytes.
+    fn fill(&mut self, len: usize) -> io::Result<()> {
+        while self.buf.len() < len {
+            self.read_more()?;
+        }
+        Ok(())
+    }
+    /// Append the next read of `inner` to `buf`, the body always ends with
+    /// the last boundary.
+    fn read_more(&mut self) -> io::Result<()> {
+        let len = self.buf.len();
+        self.buf.resize(len + READ_LEN, 0);
+        let n = loop {
+            match self.inner.read(&mut self.buf[len..]) {
+                Ok(n) => break n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(err);
+                }
+            }
+        };
+        self.buf.truncate(len + n);
+        if n == 0 {
+            return Err(invalid("unterminated multipart body"));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Multipart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.in_part || buf.is_empty() {
+            return Ok(0);
+        }
+        self.fill(self.delimiter.len())?;
+        let len = match find(&self.buf, &self.delimiter) {
+            Some(0) => {
+                self.buf.drain(..self.delimiter.len());
+                self.in_part = false;
+                return Ok(0);
+            }
+            Some(i) => i,
+            // the end may be the start of the delimiter
+            None => self.buf.len() + 1 - self.delimiter.len(),
+        };
+        let n = len.min(buf.len());
+        buf[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        Ok(n)
+    }
+}
+
+fn parse_head(head: &str) -> io::Result<Part> {
+    let mut part = Part::default();
+    for line in head.split("\r\n").filter(|v| !v.is_empty()) {
+        let Some((key, value)) = line.split_once(':') else {
+            return Err(invalid("invalid part header"));
+        };
+        if key.trim().eq_ignore_ascii_case("Content-Type") {
+            part.content_type = Some(value.trim().to_owned());
+        } else if key.trim().eq_ignore_ascii_case("Content-Disposition") {
+            for param in value.split(';').skip(1) {
+                let Some((k, v)) = param.split_once('=') else {
+                    continue;
+                };
+                let v = v.trim().trim_matches('"').to_owned();
+                match k.trim() {
+                    "name" => part.name = v,
+                    "filename" => part.filename = Some(v),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(part)
+}
@@ -130,22 +130,22 @@ impl HttpResponse {
                 .map(|u| Cow::Borrowed(u.0.as_str())),
         }
     }
+    /// Send the status line and the headers, the body is up to the caller.
     pub fn send<W: io::Write>(self, req: &HttpRequest, mut stream: W) -> io::Result<()> {
         write!(stream, "{} {}\r\n", self.protocol_version, self.status)?;
-        if self.content_type.is_some() {
-            write!(
-                stream,
-                "Content-Type: {}",
-                self.content_type.as_ref().unwrap()
-            )?;
+        if let Some(content_type) = self.content_type.as_ref() {
+            write!(stream, "Content-Type: {content_type}\r\n")?;
+        }
+        if let Some(len) = self.content_length {
+            write!(stream, "Content-Length: {len}\r\n")?;
         }
         for (key, value) in self.headers.iter() {
             write!(stream, "{key}: {value}\r\n")?;
         }
-        if self.get_header("Connection").is_none() {
-            if let Some(v) = req.get_header("Connection") {
-                write!(stream, "Connection: {v}\r\n")?;
-            }
+        if self.get_header("Connection").is_none()
+            && let Some(v) = req.get_header("Connection")
+        {
+            write!(stream, "Connection: {v}\r\n")?;
         }
         write!(stream, "\r\n")?;
         stream.flush()
@@ -192,54 +192,11 @@ impl HttpResponse {
     }
     pub fn send_file<W: io::Write, P: AsRef<Path>>(self, mut stream: W, path: P) -> io::Result<()> {
         let path = path.as_ref();
-        let ext = path.extension();
         let file = std::fs::File::open(path)?;
         let n = file.metadata()?.len();
         let mut file_reader = BufReader::new(file);
         write!(stream, "{} {}\r\n", self.protocol_version, self.status)?;
-        write!(
-            stream,
-            "Content-Type: {}\r\n",
-            match ext {
-                // text
-                Some(v) if v == OsStr::new("html") => "text/html",
-                Some(v) if v == OsStr::new("htm") => "text/html",
-                Some(v) if v == OsStr::new("css") => "text/css",
-                Some(v) if v == OsStr::new("js") => "application/javascript",
-                Some(v) if v == OsStr::new("json") => "application/json",
-                Some(v) if v == OsStr::new("txt") => "text/plain",
-                Some(v) if v == OsStr::new("csv") => "text/csv",
-                Some(v) if v == OsStr::new("xml") => "application/xml",
-
-                // image
-                Some(v) if v == OsStr::new("jpg") => "image/jpeg",
-                Some(v) if v == OsStr::new("jpeg") => "image/jpeg",
-                Some(v) if v == OsStr::new("png") => "image/png",
-                Some(v) if v == OsStr::new("gif") => "image/gif",
-                Some(v) if v == OsStr::new("webp") => "image/webp",
-                Some(v) if v == OsStr::new("svg") => "image/svg+xml",
-                Some(v) if v == OsStr::new("ico") => "image/x-icon",
-
-                // audio
-                Some(v) if v == OsStr::new("mp3") => "audio/mpeg",
-                Some(v) if v == OsStr::new("wav") => "audio/wav",
-                Some(v) if v == OsStr::new("ogg") => "audio/ogg",
-                Some(v) if v == OsStr::new("m4a") => "audio/mp4",
-
-                // video
-                Some(v) if v == OsStr::new("mp4") => "video/mp4",
-                Some(v) if v == OsStr::new("webm") => "video/webm",
-                Some(v) if v == OsStr::new("ogg") => "video/ogg",
-                Some(v) if v == OsStr::new("mov") => "video/quicktime",
-
-                // executable
-                Some(v) if v == OsStr::new("wasm") => "application/wasm",
-                Some(v) if v == OsStr::new("sh") => "application/x-sh",
-
-                // unknown
-                _ => "application/octet-stream",
-            }
-        )?;
+        write!(stream, "Content-Type: {}\r\n", content_type(path))?;
         write!(stream, "Content-Length: {n}\r\n")?;
         for (key, value) in self.headers {
             write!(stream, "{key}: {value}\r\n")?;
@@ -250,3 +207,47 @@ impl HttpResponse {
         Ok(())
     }
 }
+
+/// Content type of a file, guessed from the extension of `path`.
+pub fn content_type<P: AsRef<Path>>(path: P) -> &'static str {
+    let ext = path.as_ref().extension();
+    match ext {
+        // text
+        Some(v) if v == OsStr::new("html") => "text/html",
+        Some(v) if v == OsStr::new("htm") => "text/html",
+        Some(v) if v == OsStr::new("css") => "text/css",
+        Some(v) if v == OsStr::new("js") => "application/javascript",
+        Some(v) if v == OsStr::new("json") => "application/json",
+        Some(v) if v == OsStr::new("txt") => "text/plain",
+        Some(v) if v == OsStr::new("csv") => "text/csv",
+        Some(v) if v == OsStr::new("xml") => "application/xml",
+
+        // image
+        Some(v) if v == OsStr::new("jpg") => "image/jpeg",
+        Some(v) if v == OsStr::new("jpeg") => "image/jpeg",
+        Some(v) if v == OsStr::new("png") => "image/png",
+        Some(v) if v == OsStr::new("gif") => "image/gif",
+        Some(v) if v == OsStr::new("webp") => "image/webp",
+        Some(v) if v == OsStr::new("svg") => "image/svg+xml",
+        Some(v) if v == OsStr::new("ico") => "image/x-icon",
+
+        // audio
+        Some(v) if v == OsStr::new("mp3") => "audio/mpeg",
+        Some(v) if v == OsStr::new("wav") => "audio/wav",
+        Some(v) if v == OsStr::new("ogg") => "audio/ogg",
+        Some(v) if v == OsStr::new("m4a") => "audio/mp4",
+
+        // video
+        Some(v) if v == OsStr::new("mp4") => "video/mp4",
+        Some(v) if v == OsStr::new("webm") => "video/webm",
+        Some(v) if v == OsStr::new("ogg") => "video/ogg",
+        Some(v) if v == OsStr::new("mov") => "video/quicktime",
+
+        // executable
+        Some(v) if v == OsStr::new("wasm") => "application/wasm",
+        Some(v) if v == OsStr::new("sh") => "application/x-sh",
+
+        // unknown
+        _ => "application/octet-stream",
+    }
+}
@@ -137,11 +137,69 @@ impl From<Status> for u32 {
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let value: u32 = (*self).into();
-        match self {
-            Self::Ok => write!(f, "{value} OK"),
-            Self::NotFound => write!(f, "{value} Not Found"),
-            Self::Created => write!(f, "{value} Created"),
-            _ => todo!(),
-        }
+        let reason = match self {
+            Self::Continue => "Continue",
+            Self::SwitchingProtocols => "Switching Protocols",
+            Self::Processing => "Processing",
+            Self::EarlyHints => "Early Hints",
+            Self::Ok => "OK",
+            Self::Created => "Created",
+            Self::Accepted => "Accepted",
+            Self::NonAuthoritativeInformation => "Non-Authoritative Information",
+            Self::NoContent => "No Content",
+            Self::ResetContent => "Reset Content",
+            Self::PartialContent => "Partial Content",
+            Self::MultiStatus => "Multi-Status",
+            Self::AlreadyReported => "Already Reported",
+            Self::IMUsed => "IM Used",
+            Self::MultipleChoices => "Multiple Choices",
+            Self::MovedPermanently => "Moved Permanently",
+            Self::Found => "Found",
+            Self::SeeOther => "See Other",
+            Self::NotModified => "Not Modified",
+            Self::TemporaryRedirect => "Temporary Redirect",
+            Self::PermanentRedirect => "Permanent Redirect",
+            Self::BadRequest => "Bad Request",
+            Self::Unauthorized => "Unauthorized",
+            Self::PaymentRequired => "Payment Required",
+            Self::Forbidden => "Forbidden",
+            Self::NotFound => "Not Found",
+            Self::MethodNotAllowed => "Method Not Allowed",
+            Self::NotAcceptable => "Not Acceptable",
+            Self::ProxyAuthenticationRequired => "Proxy Authentication Required",
+            Self::RequestTimeout => "Request Timeout",
+            Self::Conflict => "Conflict",
+            Self::Gone => "Gone",
+            Self::LengthRequired => "Length Required",
+            Self::PreconditionFailed => "Precondition Failed",
+            Self::ContentTooLarge => "Content Too Large",
+            Self::URITooLong => "URI Too Long",
+            Self::UnsupportedMediaType => "Unsupported Media Type",
+            Self::RangeNotSatisfiable => "Range Not Satisfiable",
+            Self::ExpectationFailed => "Expectation Failed",
+            Self::ImATeapot => "I'm a teapot",
+            Self::MisdirectedRequest => "Misdirected Request",
+            Self::UnprocessableContent => "Unprocessable Content",
+            Self::Locked => "Locked",
+            Self::FailedDependency => "Failed Dependency",
+            Self::TooEarly => "Too Early",
+            Self::UpgradeRequired => "Upgrade Required",
+            Self::PreconditionRequired => "Precondition Required",
+            Self::TooManyRequests => "Too Many Requests",
+            Self::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            Self::UnavailableForLegalReasons => "Unavailable For Legal Reasons",
+            Self::InternalServerError => "Internal Server Error",
+            Self::NotImplemented => "Not Implemented",
+            Self::BadGateway => "Bad Gateway",
+            Self::ServiceUnavailable => "Service Unavailable",
+            Self::GatewayTimeout => "Gateway Timeout",
+            Self::HTTPVersionNotSupported => "HTTP Version Not Supported",
+            Self::VariantAlsoNegotiates => "Variant Also Negotiates",
+            Self::InsufficientStorage => "Insufficient Storage",
+            Self::LoopDetected => "Loop Detected",
+            Self::NotExtended => "Not Extended",
+            Self::NetworkAuthenticationRequired => "Network Authentication Required",
+        };
+        write!(f, "{value} {reason}")
     }
 }
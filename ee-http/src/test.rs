@@ -0,0 +1,231 @@
+use std::io::{self, Cursor, Read, Write};
+
+use crate::{
+    Body, ChunkedWriter, HttpRequest,
+    multipart::{Multipart, Part, boundary},
+    websocket::{self, Message, WebSocket},
+};
+
+const BODY: &[u8] = b"preamble\r\n\
+--xyz\r\n\
+Content-Disposition: form-data; name=\"path\"\r\n\
+\r\n\
+/tmp/a.txt\r\n\
+--xyz\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+line 1\r\n--xy\r\nline 2\r\n\
+--xyz--\r\n";
+
+#[test]
+fn test_multipart_boundary() {
+    assert_eq!(boundary("multipart/form-data; boundary=xyz"), Some("xyz"));
+    assert_eq!(
+        boundary("Multipart/Form-Data;charset=utf-8; boundary=\"a b\""),
+        Some("a b")
+    );
+    assert_eq!(boundary("multipart/form-data"), None);
+    assert_eq!(boundary("application/octet-stream; boundary=xyz"), None);
+}
+
+/// Hands out `input` one byte at a time.
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(self.0.len()).min(1);
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+/// Every part of a body with its data.
+fn parts<R: Read>(body: R, boundary: &str) -> io::Result<Vec<(Part, Vec<u8>)>> {
+    let mut form = Multipart::new(body, boundary);
+    let mut parts = Vec::new();
+    while let Some(part) = form.next_part()? {
+        let mut data = Vec::new();
+        form.read_to_end(&mut data)?;
+        parts.push((part, data));
+    }
+    Ok(parts)
+}
+
+#[test]
+fn test_multipart_parse() {
+    let expected = [
+        (
+            Part {
+                name: "path".to_owned(),
+                filename: None,
+                content_type: None,
+            },
+            b"/tmp/a.txt".to_vec(),
+        ),
+        (
+            Part {
+                name: "file".to_owned(),
+                filename: Some("a.txt".to_owned()),
+                content_type: Some("text/plain".to_owned()),
+            },
+            b"line 1\r\n--xy\r\nline 2".to_vec(),
+        ),
+    ];
+    assert_eq!(parts(BODY, "xyz").unwrap(), expected);
+    // delimiters split across reads
+    assert_eq!(parts(Trickle(BODY), "xyz").unwrap(), expected);
+
+    // a part left unread is skipped
+    let mut form = Multipart::new(BODY, "xyz");
+    assert_eq!(form.next_part().unwrap().unwrap().name, "path");
+    assert_eq!(form.next_part().unwrap().unwrap().name, "file");
+    let mut data = [0; 4];
+    form.read_exact(&mut data).unwrap();
+    assert_eq!(&data, b"line");
+    assert_eq!(form.next_part().unwrap(), None);
+    assert_eq!(form.next_part().unwrap(), None);
+}
+
+#[test]
+fn test_multipart_invalid() {
+    assert!(parts(BODY, "abc").is_err());
+    // cut inside the file part
+    assert!(parts(&BODY[..BODY.len() - 12], "xyz").is_err());
+    assert!(parts(&b"--xyz\r\nno headers end"[..], "xyz").is_err());
+    assert!(parts(&b"--xyz--"[..], "xyz").unwrap().is_empty());
+}
+
+#[test]
+fn test_body() {
+    let mut body = Body::new(&b"hello, world"[..], 5);
+    let mut data = Vec::new();
+    body.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello");
+    assert_eq!(body.left(), 0);
+
+    let mut body = Body::new(&b"hel"[..], 5);
+    let err = body.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert_eq!(body.left(), 2);
+}
+
+#[test]
+fn test_chunked_writer() {
+    let mut w = ChunkedWriter::new(Vec::new());
+    w.write_all(b"hello").unwrap();
+    w.write_all(b"").unwrap();
+    w.write_all(&[b'a'; 26]).unwrap();
+    let body = w.finish().unwrap();
+    let mut expected = b"5\r\nhello\r\n1a\r\n".to_vec();
+    expected.extend_from_slice(&[b'a'; 26]);
+    expected.extend_from_slice(b"\r\n0\r\n\r\n");
+    assert_eq!(body, expected);
+}
+
+#[test]
+fn test_websocket_accept() {
+    // the example of RFC 6455
+    assert_eq!(
+        websocket::accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+        "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+    );
+    let req = HttpRequest::default()
+        .header("Upgrade", "WebSocket")
+        .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+    assert!(websocket::is_upgrade(&req));
+    let mut out = Vec::new();
+    websocket::accept(&req, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+    assert!(out.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    assert!(websocket::accept(&HttpRequest::default(), Vec::new()).is_err());
+}
+
+/// A frame the way a client sends it, masked.
+fn client_frame(fin: bool, opcode: u8, data: &[u8]) -> Vec<u8> {
+    let mask = [1, 2, 3, 4];
+    let mut v = vec![(fin as u8) << 7 | opcode];
+    match data.len() {
+        n if n < 126 => v.push(0x80 | n as u8),
+        n => {
+            v.push(0x80 | 126);
+            v.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+    }
+    v.extend_from_slice(&mask);
+    v.extend(data.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    v
+}
+
+/// Hands out `input` a few bytes at a time, then times out.
+struct Slow {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Slow {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(3);
+        match self.input.read(&mut buf[..len])? {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for Slow {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_websocket_messages() {
+    let long = vec![7; 300];
+    let mut input = client_frame(true, 0x1, b"hello");
+    input.extend(client_frame(false, 0x2, b"ab"));
+    input.extend(client_frame(true, 0x9, b"ping"));
+    input.extend(client_frame(true, 0x0, b"cd"));
+    input.extend(client_frame(true, 0x2, &long));
+    input.extend(client_frame(true, 0x8, &1000u16.to_be_bytes()));
+    let mut ws = WebSocket::new(Slow {
+        input: Cursor::new(input),
+        output: Vec::new(),
+    });
+    assert_eq!(ws.read().unwrap(), Some(Message::Text("hello".into())));
+    // the ping in between is answered, the fragments put together
+    assert_eq!(ws.read().unwrap(), Some(Message::Binary(b"abcd".to_vec())));
+    assert_eq!(ws.read().unwrap(), Some(Message::Binary(long)));
+    assert_eq!(ws.read().unwrap(), Some(Message::Close));
+    assert_eq!(ws.read().unwrap(), None);
+    assert_eq!(
+        ws.get_ref().output,
+        [&[0x8a, 4][..], b"ping", &[0x88, 2, 0x03, 0xe8]].concat()
+    );
+
+    let mut ws = WebSocket::new(Slow {
+        input: Cursor::new(vec![0x81, 5, b'h', b'e', b'l', b'l', b'o']),
+        output: Vec::new(),
+    });
+    // unmasked frames of a client are an error
+    assert_eq!(ws.read().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_websocket_send() {
+    let mut ws = WebSocket::new(Slow {
+        input: Cursor::new(Vec::new()),
+        output: Vec::new(),
+    });
+    ws.send_text("hi").unwrap();
+    ws.send_binary(&[0; 200]).unwrap();
+    let out = &ws.get_ref().output;
+    assert_eq!(&out[..4], &[0x81, 2, b'h', b'i']);
+    assert_eq!(&out[4..8], &[0x82, 126, 0, 200]);
+    assert_eq!(out.len(), 8 + 200);
+}
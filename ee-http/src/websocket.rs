@@ -0,0 +1,225 @@
+use std::io::{self, Read, Write};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use sha1::{Digest, Sha1};
+
+use crate::{HttpRequest, HttpResponse, Status};
+
+/// Appended to the key of the client to prove the server speaks WebSocket
+/// (RFC 6455).
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message a [`WebSocket`] reads.
+pub const MAX_MESSAGE_LEN: usize = 16 << 20;
+
+/// `Sec-WebSocket-Accept` for the `Sec-WebSocket-Key` of a client.
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// Whether `req` asks to switch to WebSocket.
+pub fn is_upgrade(req: &HttpRequest) -> bool {
+    req.get_header("Upgrade")
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+        && req.get_header("Sec-WebSocket-Key").is_some()
+}
+
+/// Answer the WebSocket upgrade of `req`, `stream` speaks WebSocket after it.
+pub fn accept<W: Write>(req: &HttpRequest, mut stream: W) -> io::Result<()> {
+    let Some(key) = req
+        .get_header("Sec-WebSocket-Key")
+        .filter(|_| is_upgrade(req))
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a websocket upgrade",
+        ));
+    };
+    let res = HttpResponse::new()
+        .status(Status::SwitchingProtocols)
+        .push_header("Upgrade", "websocket")
+        .push_header("Connection", "Upgrade")
+        .push_header("Sec-WebSocket-Accept", accept_key(key));
+    res.send(req, &mut stream)
+}
+
+/// A message of a [`WebSocket`], fragments put together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// The peer closes the connection.
+    Close,
+}
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+/// The server end of a WebSocket connection.
+///
+/// Reading keeps partial frames, so the stream can have a read timeout:
+/// [`WebSocket::read`] returns `None` when it runs out, and the next call
+/// goes on where it stopped. Pings are answered while reading.
+pub struct WebSocket<S> {
+    inner: S,
+    buf: Vec<u8>,
+    /// Opcode and data of a fragmented message.
+    fragments: Option<(u8, Vec<u8>)>,
+    closed: bool,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            fragments: None,
+            closed: false,
+        }
+    }
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+    /// The next message, `None` if the read timed out first.
+    pub fn read(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            while let Some((fin, opcode, data)) = self.frame()? {
+                if let Some(v) = self.message(fin, opcode, data)? {
+                    return Ok(Some(v));
+                }
+            }
+            let mut buf = [0; 4096];
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buf.extend_from_slice(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Ok(None);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    /// Take the first frame out of the buffer, if it is all there.
+    fn frame(&mut self) -> io::Result<Option<(bool, u8, Vec<u8>)>> {
+        let buf = &self.buf;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buf[0] & 0x80 != 0;
+        let opcode = buf[0] & 0x0f;
+        if buf[1] & 0x80 == 0 {
+            return Err(invalid("client frames have to be masked"));
+        }
+        let (len, mut at) = match buf[1] & 0x7f {
+            126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+            127 if buf.len() >= 10 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+            126 | 127 => return Ok(None),
+            v => (v as u64, 2),
+        };
+        if len > MAX_MESSAGE_LEN as u64 {
+            return Err(invalid("message too long"));
+        }
+        let len = len as usize;
+        if buf.len() < at + 4 + len {
+            return Ok(None);
+        }
+        let mask: [u8; 4] = buf[at..at + 4].try_into().unwrap();
+        at += 4;
+        let data = buf[at..at + len]
+            .iter()
+            .enumerate()
+            .map(|(i, v)| v ^ mask[i % 4])
+            .collect();
+        self.buf.drain(..at + len);
+        Ok(Some((fin, opcode, data)))
+    }
+    fn message(&mut self, fin: bool, opcode: u8, data: Vec<u8>) -> io::Result<Option<Message>> {
+        let (opcode, data) = match opcode {
+            PING => {
+                self.send(PONG, &data)?;
+                return Ok(None);
+            }
+            PONG => return Ok(None),
+            CLOSE => {
+                if !self.closed {
+                    self.close()?;
+                }
+                return Ok(Some(Message::Close));
+            }
+            TEXT | BINARY if self.fragments.is_none() => {
+                if !fin {
+                    self.fragments = Some((opcode, data));
+                    return Ok(None);
+                }
+                (opcode, data)
+            }
+            CONTINUATION => {
+                let Some((first, mut v)) = self.fragments.take() else {
+                    return Err(invalid("continuation without a message"));
+                };
+                v.extend_from_slice(&data);
+                if v.len() > MAX_MESSAGE_LEN {
+                    return Err(invalid("message too long"));
+                }
+                if !fin {
+                    self.fragments = Some((first, v));
+                    return Ok(None);
+                }
+                (first, v)
+            }
+            _ => return Err(invalid("unexpected opcode")),
+        };
+        Ok(Some(if opcode == TEXT {
+            Message::Text(String::from_utf8(data).map_err(|_| invalid("invalid utf-8"))?)
+        } else {
+            Message::Binary(data)
+        }))
+    }
+    /// Send one unfragmented frame.
+    fn send(&mut self, opcode: u8, data: &[u8]) -> io::Result<()> {
+        let mut head = vec![0x80 | opcode];
+        match data.len() {
+            n if n < 126 => head.push(n as u8),
+            n if n <= u16::MAX as usize => {
+                head.push(126);
+                head.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                head.push(127);
+                head.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        self.inner.write_all(&head)?;
+        self.inner.write_all(data)?;
+        self.inner.flush()
+    }
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        self.send(TEXT, text.as_bytes())
+    }
+    pub fn send_binary(&mut self, data: &[u8]) -> io::Result<()> {
+        self.send(BINARY, data)
+    }
+    /// Send a close frame, nothing can be sent after it.
+    pub fn close(&mut self) -> io::Result<()> {
+        self.closed = true;
+        // 1000, normal closure
+        self.send(CLOSE, &1000u16.to_be_bytes())
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,346 @@
+//! `#[derive(Message)]`, implements `ee_proto::Encode` and
+//! `ee_proto::Decode` for structs and enums.
+//!
+//! Fields are encoded one after the other in declaration order. An enum
+//! starts with a `u8` tag, the index of the variant unless set with
+//! `#[message(tag = N)]`.
+//!
+//! Field attributes:
+//! - `#[message(max_len = N)]` limits a string, path, byte blob or list to
+//!   `N`, an integer or a `usize` constant (see `Encode::encode_max` and
+//!   `Decode::decode_max`).
+//! - `#[message(since = N)]` marks a field added in version `N` of the
+//!   message. Structs with such fields are versioned (see
+//!   `ee_proto::codec::write_versioned`): readers of an older version skip
+//!   the field, readers of a newer version get its `Default` (usually an
+//!   `Option`). The version of the struct is the highest `since`, or
+//!   `#[message(version = N)]` on the struct.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    Attribute, Data, DeriveInput, Error, Expr, Fields, Generics, Ident, LitInt, Type,
+    parse_macro_input, parse_quote,
+};
+
+#[proc_macro_derive(Message, attributes(message))]
+pub fn derive_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Attrs {
+    max_len: Option<Expr>,
+    since: Option<u16>,
+    version: Option<u16>,
+    tag: Option<u8>,
+}
+
+impl Attrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut v = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("message")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("max_len") {
+                    v.max_len = Some(meta.value()?.parse()?);
+                    return Ok(());
+                }
+                let lit: LitInt = meta.value()?.parse()?;
+                if meta.path.is_ident("since") {
+                    v.since = Some(version(&lit)?);
+                } else if meta.path.is_ident("version") {
+                    v.version = Some(version(&lit)?);
+                } else if meta.path.is_ident("tag") {
+                    v.tag = Some(lit.base10_parse()?);
+                } else {
+                    return Err(meta.error("expected `max_len`, `since`, `version` or `tag`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(v)
+    }
+    /// Reject the attributes that do not belong at `place`.
+    fn only(&self, place: &str, allowed: &[&str]) -> syn::Result<()> {
+        let set = [
+            ("max_len", self.max_len.is_some()),
+            ("since", self.since.is_some()),
+            ("version", self.version.is_some()),
+            ("tag", self.tag.is_some()),
+        ];
+        match set.iter().find(|v| v.1 && !allowed.contains(&v.0)) {
+            Some((name, _)) => Err(Error::new(
+                Span::call_site(),
+                format!("`{name}` can not be used on {place}"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+fn version(lit: &LitInt) -> syn::Result<u16> {
+    match lit.base10_parse()? {
+        0 => Err(Error::new(lit.span(), "versions start at 1")),
+        v => Ok(v),
+    }
+}
+
+struct Field {
+    /// `self.<member>` for structs, the binding for enum variants.
+    member: TokenStream2,
+    binding: Ident,
+    ty: Type,
+    attrs: Attrs,
+}
+
+fn fields(fields: &Fields, place: &str, allowed: &[&str]) -> syn::Result<Vec<Field>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let attrs = Attrs::parse(&f.attrs)?;
+            attrs.only(place, allowed)?;
+            let (member, binding) = match &f.ident {
+                Some(ident) => (quote!(#ident), ident.clone()),
+                None => {
+                    let index = syn::Index::from(i);
+                    (quote!(#index), format_ident!("__f{}", i))
+                }
+            };
+            Ok(Field {
+                member,
+                binding,
+                ty: f.ty.clone(),
+                attrs,
+            })
+        })
+        .collect()
+}
+
+fn encode_field(value: TokenStream2, f: &Field) -> TokenStream2 {
+    match &f.attrs.max_len {
+        Some(max) => quote!(::ee_proto::Encode::encode_max(#value, __w, #max)?;),
+        None => quote!(::ee_proto::Encode::encode(#value, __w)?;),
+    }
+}
+
+fn decode_field(f: &Field) -> TokenStream2 {
+    let ty = &f.ty;
+    match &f.attrs.max_len {
+        Some(max) => quote!(<#ty as ::ee_proto::Decode>::decode_max(__r, #max)?),
+        None => quote!(<#ty as ::ee_proto::Decode>::decode(__r)?),
+    }
+}
+
+/// `Self { a, b }`, `Self(__f0, __f1)` or `Self` out of the bindings.
+fn construct(path: TokenStream2, shape: &Fields, fields: &[Field]) -> TokenStream2 {
+    let bindings = fields.iter().map(|f| &f.binding);
+    match shape {
+        Fields::Named(_) => quote!(#path { #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(#path ( #(#bindings),* )),
+        Fields::Unit => path,
+    }
+}
+
+/// `Encode + Decode` on every type parameter, `Default` on the types of
+/// versioned fields.
+fn add_bounds(generics: &mut Generics, defaults: &[Type]) {
+    let params: Vec<Ident> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let clause = generics.make_where_clause();
+    for p in params {
+        clause
+            .predicates
+            .push(parse_quote!(#p: ::ee_proto::Encode + ::ee_proto::Decode));
+    }
+    for ty in defaults {
+        clause
+            .predicates
+            .push(parse_quote!(#ty: ::core::default::Default));
+    }
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let attrs = Attrs::parse(&input.attrs)?;
+    let (encode, decode, defaults) = match &input.data {
+        Data::Struct(data) => {
+            attrs.only("a struct", &["version"])?;
+            let fields = fields(&data.fields, "a struct field", &["max_len", "since"])?;
+            let (encode, decode) = expand_struct(&data.fields, &fields, attrs.version)?;
+            let defaults = fields
+                .iter()
+                .filter(|f| f.attrs.since.is_some())
+                .map(|f| f.ty.clone())
+                .collect::<Vec<_>>();
+            (encode, decode, defaults)
+        }
+        Data::Enum(data) => {
+            attrs.only("an enum", &[])?;
+            let (encode, decode) = expand_enum(name, data)?;
+            (encode, decode, Vec::new())
+        }
+        Data::Union(_) => {
+            return Err(Error::new(
+                Span::call_site(),
+                "`Message` can not be derived for unions",
+            ));
+        }
+    };
+    add_bounds(&mut input.generics, &defaults);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ee_proto::Encode for #name #ty_generics #where_clause {
+            fn encode<__W: ::std::io::Write + ?Sized>(
+                &self,
+                __w: &mut __W,
+            ) -> ::std::io::Result<()> {
+                #encode
+            }
+        }
+
+        impl #impl_generics ::ee_proto::Decode for #name #ty_generics #where_clause {
+            fn decode<__R: ::std::io::Read + ?Sized>(
+                __r: &mut __R,
+            ) -> ::std::io::Result<Self> {
+                #decode
+            }
+        }
+    })
+}
+
+fn expand_struct(
+    shape: &Fields,
+    fields: &[Field],
+    version: Option<u16>,
+) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let since = fields.iter().filter_map(|f| f.attrs.since).max();
+    let version = match (version, since) {
+        (Some(v), Some(since)) if v < since => {
+            return Err(Error::new(
+                Span::call_site(),
+                format!("a field is added in version {since}, after the struct version {v}"),
+            ));
+        }
+        (v, since) => v.or(since),
+    };
+    let encode = fields.iter().map(|f| {
+        let member = &f.member;
+        encode_field(quote!(&self.#member), f)
+    });
+    let decode = fields.iter().map(|f| {
+        let binding = &f.binding;
+        let value = decode_field(f);
+        match f.attrs.since {
+            Some(since) => quote! {
+                let #binding = if __version >= #since {
+                    #value
+                } else {
+                    ::core::default::Default::default()
+                };
+            },
+            None => quote!(let #binding = #value;),
+        }
+    });
+    let value = construct(quote!(Self), shape, fields);
+    Ok(match version {
+        Some(version) => (
+            quote! {
+                ::ee_proto::codec::write_versioned(__w, #version, |__w| {
+                    #(#encode)*
+                    ::core::result::Result::Ok(())
+                })
+            },
+            quote! {
+                ::ee_proto::codec::read_versioned(__r, |__r, __version| {
+                    #(#decode)*
+                    ::core::result::Result::Ok(#value)
+                })
+            },
+        ),
+        None => (
+            quote! {
+                #(#encode)*
+                ::core::result::Result::Ok(())
+            },
+            quote! {
+                #(#decode)*
+                ::core::result::Result::Ok(#value)
+            },
+        ),
+    })
+}
+
+fn expand_enum(name: &Ident, data: &syn::DataEnum) -> syn::Result<(TokenStream2, TokenStream2)> {
+    let mut encode = Vec::new();
+    let mut decode = Vec::new();
+    let mut tags: Vec<u8> = Vec::new();
+    let mut next: u16 = 0;
+    for variant in &data.variants {
+        let attrs = Attrs::parse(&variant.attrs)?;
+        attrs.only("an enum variant", &["tag"])?;
+        let tag = match attrs.tag {
+            Some(tag) => tag,
+            None => u8::try_from(next).map_err(|_| {
+                Error::new_spanned(variant, "an enum can have at most 256 variants")
+            })?,
+        };
+        if tags.contains(&tag) {
+            return Err(Error::new_spanned(
+                variant,
+                format!("tag {tag} is used twice"),
+            ));
+        }
+        tags.push(tag);
+        next = tag as u16 + 1;
+
+        let ident = &variant.ident;
+        let fields = fields(&variant.fields, "an enum field", &["max_len"])?;
+        let pattern = construct(quote!(Self::#ident), &variant.fields, &fields);
+        let encode_fields = fields.iter().map(|f| {
+            let binding = &f.binding;
+            encode_field(quote!(#binding), f)
+        });
+        encode.push(quote! {
+            #pattern => {
+                ::ee_proto::Encode::encode(&#tag, __w)?;
+                #(#encode_fields)*
+            }
+        });
+        let decode_fields = fields.iter().map(|f| {
+            let binding = &f.binding;
+            let value = decode_field(f);
+            quote!(let #binding = #value;)
+        });
+        decode.push(quote! {
+            #tag => {
+                #(#decode_fields)*
+                ::core::result::Result::Ok(#pattern)
+            }
+        });
+    }
+    let unknown = format!("unknown variant of `{name}`");
+    let encode = if encode.is_empty() {
+        quote!(match *self {})
+    } else {
+        quote! {
+            match self {
+                #(#encode)*
+            }
+            ::core::result::Result::Ok(())
+        }
+    };
+    let decode = quote! {
+        match <u8 as ::ee_proto::Decode>::decode(__r)? {
+            #(#decode)*
+            _ => ::core::result::Result::Err(::std::io::Error::new(
+                ::std::io::ErrorKind::InvalidData,
+                #unknown,
+            )),
+        }
+    };
+    Ok((encode, decode))
+}
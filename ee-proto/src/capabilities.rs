@@ -0,0 +1,161 @@
+use std::io::{self, Read, Write};
+
+use crate::codec::{Decode, Encode};
+
+/// Most entries of a single list the reader accepts, so a peer can not make
+/// us allocate without bound.
+pub const MAX_ENTRIES: usize = 1024;
+
+/// What a receiver supports, sent once right after the encrypted stream is
+/// set up.
+///
+/// Layout (all integers big-endian):
+///
+/// ```text
+/// <u16 n> n * (<u8 len><task id><u32 task version>)
+/// <u32 max frame size>
+/// <u8 n> n * (<u8 len><compression algorithm>)
+/// <u8 n> n * (<u8 len><cipher suite>)
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    // sorted by id
+    tasks: Vec<(String, u32)>,
+    max_frame_size: u32,
+    compression: Vec<String>,
+    ciphers: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add (or replace) task `id` with its `version`.
+    pub fn task(mut self, id: impl Into<String>, version: u32) -> Self {
+        let id = id.into();
+        match self.tasks.binary_search_by(|v| v.0.as_str().cmp(&id)) {
+            Ok(i) => self.tasks[i].1 = version,
+            Err(i) => self.tasks.insert(i, (id, version)),
+        }
+        self
+    }
+    pub fn max_frame_size(mut self, size: u32) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+    pub fn compression(mut self, name: impl Into<String>) -> Self {
+        self.compression.push(name.into());
+        self
+    }
+    pub fn cipher(mut self, name: impl Into<String>) -> Self {
+        self.ciphers.push(name.into());
+        self
+    }
+    /// Supported tasks and their versions, sorted by id.
+    pub fn get_tasks(&self) -> &[(String, u32)] {
+        &self.tasks
+    }
+    /// Version of task `id`, `None` if it is not supported.
+    pub fn task_version(&self, id: &str) -> Option<u32> {
+        self.tasks
+            .binary_search_by(|v| v.0.as_str().cmp(id))
+            .ok()
+            .map(|i| self.tasks[i].1)
+    }
+    pub fn supports(&self, id: &str) -> bool {
+        self.task_version(id).is_some()
+    }
+    pub fn get_max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+    pub fn get_compression(&self) -> &[String] {
+        &self.compression
+    }
+    pub fn get_ciphers(&self) -> &[String] {
+        &self.ciphers
+    }
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        if self.tasks.len() > MAX_ENTRIES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many tasks",
+            ));
+        }
+        w.write_all(&(self.tasks.len() as u16).to_be_bytes())?;
+        for (id, version) in &self.tasks {
+            write_str(&mut w, id)?;
+            w.write_all(&version.to_be_bytes())?;
+        }
+        w.write_all(&self.max_frame_size.to_be_bytes())?;
+        write_list(&mut w, &self.compression)?;
+        write_list(&mut w, &self.ciphers)
+    }
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut buf = [0; 2];
+        r.read_exact(&mut buf)?;
+        let n = u16::from_be_bytes(buf) as usize;
+        if n > MAX_ENTRIES {
+            return Err(invalid_data("too many tasks"));
+        }
+        let mut v = Self::new();
+        for _ in 0..n {
+            let id = read_str(&mut r)?;
+            let version = read_u32(&mut r)?;
+            v = v.task(id, version);
+        }
+        v.max_frame_size = read_u32(&mut r)?;
+        v.compression = read_list(&mut r)?;
+        v.ciphers = read_list(&mut r)?;
+        Ok(v)
+    }
+}
+
+impl Encode for Capabilities {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.write_to(w)
+    }
+}
+
+impl Decode for Capabilities {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::read_from(r)
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32<R: Read>(mut r: R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn write_str<W: Write>(mut w: W, v: &str) -> io::Result<()> {
+    let len = u8::try_from(v.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "name is too long"))?;
+    w.write_all(&[len])?;
+    w.write_all(v.as_bytes())
+}
+
+fn read_str<R: Read>(mut r: R) -> io::Result<String> {
+    let mut len = [0; 1];
+    r.read_exact(&mut len)?;
+    let mut buf = vec![0; len[0] as usize];
+    r.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| invalid_data("invalid utf-8"))
+}
+
+fn write_list<W: Write>(mut w: W, v: &[String]) -> io::Result<()> {
+    let len = u8::try_from(v.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "list is too long"))?;
+    w.write_all(&[len])?;
+    v.iter().try_for_each(|v| write_str(&mut w, v))
+}
+
+fn read_list<R: Read>(mut r: R) -> io::Result<Vec<String>> {
+    let mut len = [0; 1];
+    r.read_exact(&mut len)?;
+    (0..len[0]).map(|_| read_str(&mut r)).collect()
+}
@@ -0,0 +1,391 @@
+//! Binary encoding shared by the tasks.
+//!
+//! Integers are big-endian, floats their IEEE 754 bits big-endian. Strings,
+//! paths, byte blobs and lists are a `u32` length followed by their content,
+//! an `Option` is a `u8` tag (`0` none, `1` some) followed by the value, a
+//! `bool` is one byte. Decoding checks every length against a limit before
+//! allocating, [`MAX_LEN`] unless a `*_max` method is used.
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Default limit of a string, path, byte blob or list (in elements).
+pub const MAX_LEN: usize = 1 << 20;
+
+/// Default limit of a frame written by [`write_frame`].
+pub const MAX_FRAME_LEN: usize = 16 << 20;
+
+pub trait Encode {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()>;
+    /// Like [`Encode::encode`], failing with [`io::ErrorKind::InvalidInput`]
+    /// if the value is longer than `max`. Only values with a length check
+    /// it.
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        let _ = max;
+        self.encode(w)
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self>;
+    /// Like [`Decode::decode`], failing with [`io::ErrorKind::InvalidData`]
+    /// if the value is longer than `max`. Only values with a length check
+    /// it.
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        let _ = max;
+        Self::decode(r)
+    }
+}
+
+pub(crate) fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Write `len` as `u32`, it has to be at most `max`.
+pub fn write_len<W: Write + ?Sized>(w: &mut W, len: usize, max: usize) -> io::Result<()> {
+    if len > max || len > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "value is too long",
+        ));
+    }
+    w.write_all(&(len as u32).to_be_bytes())
+}
+
+/// Read a `u32` length, it has to be at most `max`.
+pub fn read_len<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<usize> {
+    let len = u32::decode(r)? as usize;
+    if len > max {
+        return Err(invalid_data("value is too long"));
+    }
+    Ok(len)
+}
+
+fn read_vec<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Vec<u8>> {
+    let len = read_len(r, max)?;
+    let mut buf = vec![0; len];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Encode `v` into a `<u32 len><message>` frame, failing if the message
+/// is longer than `max`.
+pub fn write_frame<W: Write + ?Sized, T: Encode + ?Sized>(
+    w: &mut W,
+    v: &T,
+    max: usize,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    v.encode(&mut buf)?;
+    write_len(w, buf.len(), max)?;
+    w.write_all(&buf)
+}
+
+/// Decode a frame written by [`write_frame`]. The message has to fill the
+/// frame exactly.
+pub fn read_frame<R: Read + ?Sized, T: Decode>(r: &mut R, max: usize) -> io::Result<T> {
+    let len = read_len(r, max)?;
+    let mut frame = r.take(len as u64);
+    let v = T::decode(&mut frame)?;
+    if frame.limit() != 0 {
+        return Err(invalid_data("trailing bytes in frame"));
+    }
+    Ok(v)
+}
+
+/// Write a message as `<u16 version><u32 len><body>`, so readers of other
+/// versions can tell which fields it has and where it ends.
+pub fn write_versioned<W: Write + ?Sized>(
+    w: &mut W,
+    version: u16,
+    body: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buf = Vec::new();
+    body(&mut buf)?;
+    version.encode(w)?;
+    write_len(w, buf.len(), MAX_FRAME_LEN)?;
+    w.write_all(&buf)
+}
+
+/// Read a message written by [`write_versioned`]. `body` gets the version
+/// of the writer and reads the fields it knows of, what is left (fields of
+/// newer versions) is skipped.
+pub fn read_versioned<R: Read + ?Sized, T>(
+    r: &mut R,
+    body: impl FnOnce(&mut io::Take<&mut R>, u16) -> io::Result<T>,
+) -> io::Result<T> {
+    let version = u16::decode(r)?;
+    if version == 0 {
+        return Err(invalid_data("invalid message version"));
+    }
+    let len = read_len(r, MAX_FRAME_LEN)?;
+    let mut r = r.take(len as u64);
+    let v = body(&mut r, version)?;
+    io::copy(&mut r, &mut io::sink())?;
+    if r.limit() != 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(v)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_be_bytes())
+            }
+        }
+
+        impl Decode for $t {
+            fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+                let mut buf = [0; size_of::<$t>()];
+                r.read_exact(&mut buf)?;
+                Ok(<$t>::from_be_bytes(buf))
+            }
+        }
+    )*};
+}
+
+impl_int!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Encode for bool {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).encode(w)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool")),
+        }
+    }
+}
+
+impl Encode for () {
+    fn encode<W: Write + ?Sized>(&self, _: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Decode for () {
+    fn decode<R: Read + ?Sized>(_: &mut R) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl Encode for str {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.encode_max(w, MAX_LEN)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        write_len(w, self.len(), max)?;
+        w.write_all(self.as_bytes())
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.as_str().encode(w)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        self.as_str().encode_max(w, max)
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::decode_max(r, MAX_LEN)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        String::from_utf8(read_vec(r, max)?).map_err(|_| invalid_data("invalid utf-8"))
+    }
+}
+
+/// Paths travel as UTF-8 so both ends can read them whatever their
+/// platform, a path that is not valid UTF-8 can not be encoded.
+impl Encode for Path {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.encode_max(w, MAX_LEN)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        self.to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not valid utf-8"))?
+            .encode_max(w, max)
+    }
+}
+
+impl Encode for PathBuf {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.as_path().encode(w)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        self.as_path().encode_max(w, max)
+    }
+}
+
+impl Decode for PathBuf {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::decode_max(r, MAX_LEN)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        String::decode_max(r, max).map(PathBuf::from)
+    }
+}
+
+/// A byte blob, read and written in one go instead of byte by byte like
+/// a `Vec<u8>`. Both have the same encoding.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Bytes(pub Vec<u8>);
+
+impl From<Vec<u8>> for Bytes {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(value: Bytes) -> Self {
+        value.0
+    }
+}
+
+impl Encode for Bytes {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.encode_max(w, MAX_LEN)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        write_len(w, self.0.len(), max)?;
+        w.write_all(&self.0)
+    }
+}
+
+impl Decode for Bytes {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::decode_max(r, MAX_LEN)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        read_vec(r, max).map(Self)
+    }
+}
+
+/// `max` limits the value inside.
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.encode_max(w, MAX_LEN)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        match self {
+            Some(v) => {
+                true.encode(w)?;
+                v.encode_max(w, max)
+            }
+            None => false.encode(w),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::decode_max(r, MAX_LEN)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        match bool::decode(r) {
+            Ok(true) => T::decode_max(r, max).map(Some),
+            Ok(false) => Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Err(invalid_data("invalid option tag"))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// `max` limits the number of elements.
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.encode_max(w, MAX_LEN)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        write_len(w, self.len(), max)?;
+        self.iter().try_for_each(|v| v.encode(w))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.as_slice().encode(w)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        self.as_slice().encode_max(w, max)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::decode_max(r, MAX_LEN)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        let len = read_len(r, max)?;
+        // the length is not trusted until the elements arrive
+        let mut v = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            v.push(T::decode(r)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (**self).encode(w)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        (**self).encode_max(w, max)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        (**self).encode(w)
+    }
+    fn encode_max<W: Write + ?Sized>(&self, w: &mut W, max: usize) -> io::Result<()> {
+        (**self).encode_max(w, max)
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        T::decode(r).map(Box::new)
+    }
+    fn decode_max<R: Read + ?Sized>(r: &mut R, max: usize) -> io::Result<Self> {
+        T::decode_max(r, max).map(Box::new)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t:ident),*) => {
+        impl<$($t: Encode),*> Encode for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+                let ($($t,)*) = self;
+                $($t.encode(w)?;)*
+                Ok(())
+            }
+        }
+
+        impl<$($t: Decode),*> Decode for ($($t,)*) {
+            fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+                Ok(($($t::decode(r)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
@@ -1,12 +1,14 @@
-use std::{
-    any::TypeId,
-    collections::HashMap,
-    fmt::Write,
-    io::{self, Read},
-};
+// lets `#[derive(Message)]` name `::ee_proto` inside this crate too
+extern crate self as ee_proto;
 
-trait AppReceiver {
-    fn get_handle<T, U>(id: impl AsRef<str>) -> Option<impl Fn(U, T) -> io::Result<()>>
-    where
-        T: Read + Write;
-}
+pub mod capabilities;
+pub mod codec;
+pub mod response;
+
+pub use capabilities::Capabilities;
+pub use codec::{Bytes, Decode, Encode};
+pub use ee_proto_derive::Message;
+pub use response::{ErrorKind, Response, Status};
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,346 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use crate::codec::{Decode, Encode};
+
+/// Longest message carried by a [`Response`], longer ones are cut.
+pub const MAX_MESSAGE_LEN: usize = u16::MAX as usize;
+
+const HAS_MESSAGE: u8 = 1;
+const HAS_PAYLOAD: u8 = 1 << 1;
+
+/// Outcome of a request.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    /// The receiver has no task with the requested id.
+    UnknownTask = 1,
+    /// The request was understood but failed, see [`Response::get_kind`].
+    Failed = 2,
+}
+
+impl TryFrom<u8> for Status {
+    type Error = io::Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Ok),
+            1 => Ok(Self::UnknownTask),
+            2 => Ok(Self::Failed),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid response status",
+            )),
+        }
+    }
+}
+
+/// Why a request failed, the part of [`io::ErrorKind`] that makes sense on
+/// the other side of the connection.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    None = 0,
+    NotFound = 1,
+    PermissionDenied = 2,
+    AlreadyExists = 3,
+    InvalidInput = 4,
+    InvalidData = 5,
+    Unsupported = 6,
+    TimedOut = 7,
+    Busy = 8,
+    UnexpectedEof = 9,
+    /// A directory that has to be empty is not.
+    NotEmpty = 10,
+    IsADirectory = 11,
+    NotADirectory = 12,
+    /// Outside of what the receiver lets the sender touch, like a path
+    /// outside of its shared roots.
+    Forbidden = 13,
+    Other = 255,
+}
+
+impl ErrorKind {
+    /// Name of the kind in snake case, as the http api shows it.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::NotFound => "not_found",
+            Self::PermissionDenied => "permission_denied",
+            Self::AlreadyExists => "already_exists",
+            Self::InvalidInput => "invalid_input",
+            Self::InvalidData => "invalid_data",
+            Self::Unsupported => "unsupported",
+            Self::TimedOut => "timed_out",
+            Self::Busy => "busy",
+            Self::UnexpectedEof => "unexpected_eof",
+            Self::NotEmpty => "not_empty",
+            Self::IsADirectory => "is_a_directory",
+            Self::NotADirectory => "not_a_directory",
+            Self::Forbidden => "forbidden",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl From<u8> for ErrorKind {
+    /// Kinds added by newer peers read as [`ErrorKind::Other`].
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::None,
+            1 => Self::NotFound,
+            2 => Self::PermissionDenied,
+            3 => Self::AlreadyExists,
+            4 => Self::InvalidInput,
+            5 => Self::InvalidData,
+            6 => Self::Unsupported,
+            7 => Self::TimedOut,
+            8 => Self::Busy,
+            9 => Self::UnexpectedEof,
+            10 => Self::NotEmpty,
+            11 => Self::IsADirectory,
+            12 => Self::NotADirectory,
+            13 => Self::Forbidden,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(value: io::ErrorKind) -> Self {
+        match value {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            io::ErrorKind::InvalidInput => Self::InvalidInput,
+            io::ErrorKind::InvalidData => Self::InvalidData,
+            io::ErrorKind::Unsupported => Self::Unsupported,
+            io::ErrorKind::TimedOut => Self::TimedOut,
+            io::ErrorKind::ResourceBusy => Self::Busy,
+            io::ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            io::ErrorKind::DirectoryNotEmpty => Self::NotEmpty,
+            io::ErrorKind::IsADirectory => Self::IsADirectory,
+            io::ErrorKind::NotADirectory => Self::NotADirectory,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<ErrorKind> for io::ErrorKind {
+    fn from(value: ErrorKind) -> Self {
+        match value {
+            ErrorKind::NotFound => Self::NotFound,
+            ErrorKind::PermissionDenied => Self::PermissionDenied,
+            ErrorKind::AlreadyExists => Self::AlreadyExists,
+            ErrorKind::InvalidInput => Self::InvalidInput,
+            ErrorKind::InvalidData => Self::InvalidData,
+            ErrorKind::Unsupported => Self::Unsupported,
+            ErrorKind::TimedOut => Self::TimedOut,
+            ErrorKind::Busy => Self::ResourceBusy,
+            ErrorKind::UnexpectedEof => Self::UnexpectedEof,
+            ErrorKind::NotEmpty => Self::DirectoryNotEmpty,
+            ErrorKind::IsADirectory => Self::IsADirectory,
+            ErrorKind::NotADirectory => Self::NotADirectory,
+            ErrorKind::Forbidden => Self::PermissionDenied,
+            ErrorKind::None | ErrorKind::Other => Self::Other,
+        }
+    }
+}
+
+/// An error with a kind [`io::ErrorKind`] has no match for, carried inside
+/// an [`io::Error`] so [`Response`] keeps the kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+    pub fn get_kind(&self) -> ErrorKind {
+        self.kind
+    }
+    /// Kind of `err`, the one of an [`Error`] inside it if there is one.
+    pub fn kind_of(err: &io::Error) -> ErrorKind {
+        match err.get_ref().and_then(|v| v.downcast_ref::<Self>()) {
+            Some(v) => v.kind,
+            None => err.kind().into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(value: Error) -> Self {
+        io::Error::new(value.kind.into(), value)
+    }
+}
+
+/// Status the receiver sends in front of everything it answers.
+///
+/// Layout (all integers big-endian):
+///
+/// ```text
+/// <u8 status><u8 error kind><u8 flags>
+/// [<u16 len><utf-8 message>]  if flags & 1
+/// [<u64 payload len>]         if flags & 2
+/// ```
+///
+/// The payload itself is not part of the response, it follows it on the
+/// stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    status: Status,
+    kind: ErrorKind,
+    message: Option<String>,
+    payload_len: Option<u64>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            status: Status::Ok,
+            kind: ErrorKind::None,
+            message: None,
+            payload_len: None,
+        }
+    }
+    pub fn unknown_task(id: &str) -> Self {
+        Self {
+            status: Status::UnknownTask,
+            kind: ErrorKind::Unsupported,
+            message: Some(format!("unknown task `{id}`")),
+            payload_len: None,
+        }
+    }
+    pub fn error(kind: impl Into<ErrorKind>, message: impl Into<String>) -> Self {
+        Self {
+            status: Status::Failed,
+            kind: kind.into(),
+            message: Some(message.into()),
+            payload_len: None,
+        }
+    }
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+    /// Length of the payload that follows the response.
+    pub fn payload_len(mut self, len: u64) -> Self {
+        self.payload_len = Some(len);
+        self
+    }
+    pub fn get_status(&self) -> Status {
+        self.status
+    }
+    pub fn get_kind(&self) -> ErrorKind {
+        self.kind
+    }
+    pub fn get_message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+    pub fn get_payload_len(&self) -> Option<u64> {
+        self.payload_len
+    }
+    pub fn is_ok(&self) -> bool {
+        self.status == Status::Ok
+    }
+    /// `Ok(self)` for [`Status::Ok`], the failure as an [`io::Error`]
+    /// otherwise.
+    pub fn into_result(self) -> io::Result<Self> {
+        if self.is_ok() {
+            return Ok(self);
+        }
+        let message = self.message.unwrap_or_else(|| "request failed".to_owned());
+        Err(Error::new(self.kind, message).into())
+    }
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut flags = 0;
+        if self.message.is_some() {
+            flags |= HAS_MESSAGE;
+        }
+        if self.payload_len.is_some() {
+            flags |= HAS_PAYLOAD;
+        }
+        w.write_all(&[self.status as u8, self.kind as u8, flags])?;
+        if let Some(message) = &self.message {
+            let message = truncate(message, MAX_MESSAGE_LEN);
+            w.write_all(&(message.len() as u16).to_be_bytes())?;
+            w.write_all(message.as_bytes())?;
+        }
+        if let Some(len) = self.payload_len {
+            w.write_all(&len.to_be_bytes())?;
+        }
+        Ok(())
+    }
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut head = [0; 3];
+        r.read_exact(&mut head)?;
+        let [status, kind, flags] = head;
+        let mut v = Self {
+            status: Status::try_from(status)?,
+            kind: ErrorKind::from(kind),
+            message: None,
+            payload_len: None,
+        };
+        if flags & HAS_MESSAGE != 0 {
+            let mut len = [0; 2];
+            r.read_exact(&mut len)?;
+            let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+            r.read_exact(&mut buf)?;
+            v.message = Some(
+                String::from_utf8(buf)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid utf-8"))?,
+            );
+        }
+        if flags & HAS_PAYLOAD != 0 {
+            let mut len = [0; 8];
+            r.read_exact(&mut len)?;
+            v.payload_len = Some(u64::from_be_bytes(len));
+        }
+        Ok(v)
+    }
+}
+
+impl Encode for Response {
+    fn encode<W: Write + ?Sized>(&self, w: &mut W) -> io::Result<()> {
+        self.write_to(w)
+    }
+}
+
+impl Decode for Response {
+    fn decode<R: Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        Self::read_from(r)
+    }
+}
+
+impl From<&io::Error> for Response {
+    fn from(value: &io::Error) -> Self {
+        Self::error(Error::kind_of(value), value.to_string())
+    }
+}
+
+fn truncate(v: &str, max: usize) -> &str {
+    if v.len() <= max {
+        return v;
+    }
+    let mut end = max;
+    while !v.is_char_boundary(end) {
+        end -= 1;
+    }
+    &v[..end]
+}
@@ -0,0 +1,280 @@
+use std::{fmt::Debug, io, path::PathBuf};
+
+use crate::{
+    Bytes, Capabilities, Decode, Encode, ErrorKind, Response, Status,
+    capabilities::MAX_ENTRIES,
+    codec::{self, MAX_LEN},
+    response::{self, MAX_MESSAGE_LEN},
+};
+
+fn sample() -> Capabilities {
+    Capabilities::new()
+        .task("ping", 1)
+        .task("device-info", 2)
+        .task("ping", 3)
+        .max_frame_size(64 * 1024)
+        .cipher("x25519-aes-256-gcm")
+}
+
+#[test]
+fn test_capabilities_round_trip() {
+    let caps = sample();
+    let mut buf = Vec::new();
+    caps.write_to(&mut buf).unwrap();
+    let read = Capabilities::read_from(buf.as_slice()).unwrap();
+    assert_eq!(read, caps);
+    assert_eq!(read.task_version("ping"), Some(3));
+    assert_eq!(read.task_version("device-info"), Some(2));
+    assert!(!read.supports("ls"));
+    assert_eq!(read.get_max_frame_size(), 64 * 1024);
+    assert!(read.get_compression().is_empty());
+    assert_eq!(read.get_ciphers(), ["x25519-aes-256-gcm"]);
+}
+
+#[test]
+fn test_capabilities_tasks_sorted() {
+    let ids: Vec<_> = sample().get_tasks().iter().map(|v| v.0.clone()).collect();
+    assert_eq!(ids, ["device-info", "ping"]);
+}
+
+#[test]
+fn test_capabilities_truncated() {
+    let mut buf = Vec::new();
+    sample().write_to(&mut buf).unwrap();
+    for n in 0..buf.len() {
+        let err = Capabilities::read_from(&buf[..n]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
+
+#[test]
+fn test_capabilities_too_many_tasks() {
+    let mut buf = ((MAX_ENTRIES + 1) as u16).to_be_bytes().to_vec();
+    buf.extend_from_slice(&[0; 64]);
+    let err = Capabilities::read_from(buf.as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+fn round_trip(v: &Response) -> Response {
+    let mut buf = Vec::new();
+    v.write_to(&mut buf).unwrap();
+    Response::read_from(buf.as_slice()).unwrap()
+}
+
+#[test]
+fn test_response_round_trip() {
+    for v in [
+        Response::ok(),
+        Response::ok().payload_len(u64::MAX),
+        Response::ok().message("done").payload_len(0),
+        Response::unknown_task("ls"),
+        Response::error(io::ErrorKind::NotFound, "no such file"),
+    ] {
+        assert_eq!(round_trip(&v), v);
+    }
+}
+
+#[test]
+fn test_response_layout() {
+    let mut buf = Vec::new();
+    Response::ok().write_to(&mut buf).unwrap();
+    assert_eq!(buf, [0, 0, 0]);
+    buf.clear();
+    Response::error(ErrorKind::NotFound, "x")
+        .payload_len(2)
+        .write_to(&mut buf)
+        .unwrap();
+    assert_eq!(buf, [2, 1, 3, 0, 1, b'x', 0, 0, 0, 0, 0, 0, 0, 2]);
+}
+
+#[test]
+fn test_response_into_result() {
+    assert!(Response::ok().into_result().is_ok());
+    let err = Response::error(io::ErrorKind::PermissionDenied, "nope")
+        .into_result()
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(err.to_string(), "nope");
+    let v = Response::unknown_task("ls");
+    assert_eq!(v.get_status(), Status::UnknownTask);
+    assert_eq!(
+        v.into_result().unwrap_err().kind(),
+        io::ErrorKind::Unsupported
+    );
+}
+
+#[test]
+fn test_response_error_kind() {
+    let v = Response::from(&io::Error::new(io::ErrorKind::AlreadyExists, "exists"));
+    assert_eq!(v.get_kind(), ErrorKind::AlreadyExists);
+    assert_eq!(v.get_message(), Some("exists"));
+    let v = Response::from(&io::Error::from(io::ErrorKind::DirectoryNotEmpty));
+    assert_eq!(v.get_kind(), ErrorKind::NotEmpty);
+    assert_eq!(
+        v.into_result().unwrap_err().kind(),
+        io::ErrorKind::DirectoryNotEmpty
+    );
+    // kinds without a code are sent as `Other`
+    let v = Response::error(io::ErrorKind::BrokenPipe, "pipe");
+    assert_eq!(v.get_kind(), ErrorKind::Other);
+    // kinds from newer peers read as `Other`
+    let v = Response::read_from([2, 42, 0].as_slice()).unwrap();
+    assert_eq!(v.get_kind(), ErrorKind::Other);
+}
+
+#[test]
+fn test_response_typed_error() {
+    // kinds `io::ErrorKind` has no match for survive the round trip
+    let err: io::Error = response::Error::new(ErrorKind::Forbidden, "outside of the roots").into();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    let v = Response::from(&err);
+    assert_eq!(v.get_kind(), ErrorKind::Forbidden);
+    assert_eq!(v.get_message(), Some("outside of the roots"));
+    let mut buf = Vec::new();
+    v.write_to(&mut buf).unwrap();
+    let err = Response::read_from(buf.as_slice())
+        .unwrap()
+        .into_result()
+        .unwrap_err();
+    assert_eq!(response::Error::kind_of(&err), ErrorKind::Forbidden);
+    assert_eq!(ErrorKind::Forbidden.as_str(), "forbidden");
+}
+
+#[test]
+fn test_response_invalid() {
+    let err = Response::read_from([9, 0, 0].as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = Response::read_from([2, 0, 1, 0, 2, 0xff, 0xfe].as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = Response::read_from([2, 0, 2, 0].as_slice()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_response_long_message() {
+    // a multi-byte char across the limit is not split
+    let message = format!("a{}", "é".repeat(MAX_MESSAGE_LEN));
+    let v = round_trip(&Response::error(ErrorKind::Other, message));
+    let read = v.get_message().unwrap();
+    assert_eq!(read.len(), MAX_MESSAGE_LEN);
+    assert!(read.ends_with('é'));
+}
+
+fn encoded<T: Encode + ?Sized>(v: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    v.encode(&mut buf).unwrap();
+    buf
+}
+
+fn assert_round_trip<T: Encode + Decode + PartialEq + Debug>(v: T) {
+    let buf = encoded(&v);
+    let mut r = buf.as_slice();
+    assert_eq!(T::decode(&mut r).unwrap(), v);
+    assert!(r.is_empty(), "{v:?} left bytes behind");
+}
+
+#[test]
+fn test_codec_round_trip() {
+    assert_round_trip(0xabu8);
+    assert_round_trip(u16::MAX);
+    assert_round_trip(0xdead_beefu32);
+    assert_round_trip(u64::MAX - 1);
+    assert_round_trip(-1i32);
+    assert_round_trip(i64::MIN);
+    assert_round_trip(-0.25f32);
+    assert_round_trip(f64::MAX);
+    assert_round_trip(true);
+    assert_round_trip(String::new());
+    assert_round_trip("héllo".to_owned());
+    assert_round_trip(PathBuf::from("/tmp/a b/ç.txt"));
+    assert_round_trip(Bytes(vec![0, 1, 255]));
+    assert_round_trip(Some(7u32));
+    assert_round_trip(None::<String>);
+    assert_round_trip(vec!["a".to_owned(), "bc".to_owned()]);
+    assert_round_trip(vec![Some(1u8), None]);
+    assert_round_trip(("id".to_owned(), 3u32, false));
+    assert_round_trip(Response::error(ErrorKind::NotFound, "gone"));
+    assert_round_trip(sample());
+}
+
+#[test]
+fn test_codec_layout() {
+    assert_eq!(encoded(&0x0102u16), [1, 2]);
+    assert_eq!(encoded(&1.0f64), [0x3f, 0xf0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(encoded("ab"), [0, 0, 0, 2, b'a', b'b']);
+    assert_eq!(encoded(&None::<u8>), [0]);
+    assert_eq!(encoded(&Some(5u8)), [1, 5]);
+    assert_eq!(encoded(&vec![1u16, 2]), [0, 0, 0, 2, 0, 1, 0, 2]);
+    // a blob and a list of bytes are the same on the wire
+    assert_eq!(encoded(&Bytes(vec![1, 2])), encoded(&vec![1u8, 2]));
+}
+
+#[test]
+fn test_codec_invalid() {
+    let kind = |buf: &[u8], f: fn(&mut &[u8]) -> io::Result<()>| {
+        let mut r = buf;
+        f(&mut r).unwrap_err().kind()
+    };
+    let invalid = io::ErrorKind::InvalidData;
+    assert_eq!(kind(&[2], |r| bool::decode(r).map(drop)), invalid);
+    assert_eq!(kind(&[2], |r| Option::<u8>::decode(r).map(drop)), invalid);
+    assert_eq!(
+        kind(&[0, 0, 0, 2, 0xff, 0xfe], |r| String::decode(r).map(drop)),
+        invalid
+    );
+    assert_eq!(
+        kind(&[0, 0, 0, 3, b'a'], |r| String::decode(r).map(drop)),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn test_codec_limits() {
+    // the length is checked before anything is allocated
+    let mut r: &[u8] = &[0xff, 0xff, 0xff, 0xff];
+    let err = Bytes::decode(&mut r).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let mut r: &[u8] = &(MAX_LEN as u32 + 1).to_be_bytes();
+    let err = Vec::<u8>::decode(&mut r).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let buf = encoded("abcd");
+    let err = String::decode_max(&mut buf.as_slice(), 3).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(String::decode_max(&mut buf.as_slice(), 4).unwrap(), "abcd");
+    // the limit of an option applies to its value
+    let buf = encoded(&Some("abcd".to_owned()));
+    assert!(Option::<String>::decode_max(&mut buf.as_slice(), 3).is_err());
+
+    let err = "abcd".encode_max(&mut Vec::new(), 3).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = vec![1u8, 2].encode_max(&mut Vec::new(), 1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    // values without a length ignore it
+    assert!(7u64.encode_max(&mut Vec::new(), 0).is_ok());
+}
+
+#[test]
+fn test_codec_frame() {
+    let v = ("ls".to_owned(), Some(PathBuf::from("/home")));
+    let mut buf = Vec::new();
+    codec::write_frame(&mut buf, &v, 64).unwrap();
+    assert_eq!(&buf[..4], &(buf.len() as u32 - 4).to_be_bytes());
+    let mut r = buf.as_slice();
+    let read: (String, Option<PathBuf>) = codec::read_frame(&mut r, 64).unwrap();
+    assert_eq!(read, v);
+    assert!(r.is_empty());
+
+    let err = codec::write_frame(&mut Vec::new(), &v, 4).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err =
+        codec::read_frame::<_, (String, Option<PathBuf>)>(&mut buf.as_slice(), 4).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // the message has to fill the frame
+    let err = codec::read_frame::<_, u8>(&mut buf.as_slice(), 64).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // and can not read past it
+    let err = codec::read_frame::<_, u64>(&mut [0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0].as_slice(), 64)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,260 @@
+use std::{
+    fmt::Debug,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use ee_proto::{Bytes, Decode, Encode, Message, codec};
+use ee_stream::e_stream::{EStreamSync, Role, SessionKeys};
+use ee_test_util::Pipe;
+use proptest::{collection::vec, option, prelude::*};
+
+#[derive(Debug, Clone, PartialEq, Message)]
+struct Entry {
+    name: String,
+    #[message(max_len = 4)]
+    tags: Vec<String>,
+    size: u64,
+    hidden: bool,
+    target: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Message)]
+struct Pair<T>(T, i32);
+
+#[derive(Debug, Clone, PartialEq, Message)]
+struct Unit;
+
+#[derive(Debug, Clone, PartialEq, Message)]
+enum Request {
+    Ping,
+    Read {
+        path: PathBuf,
+        offset: u64,
+    },
+    Write(PathBuf, #[message(max_len = 64)] Bytes),
+    List(Vec<Entry>),
+    #[message(tag = 10)]
+    Stop,
+}
+
+/// The same message as a sender and a receiver of different versions see it.
+mod v1 {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Message)]
+    #[message(version = 1)]
+    pub struct Stat {
+        pub path: PathBuf,
+        pub size: u64,
+    }
+}
+
+mod v3 {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Message)]
+    pub struct Stat {
+        pub path: PathBuf,
+        pub size: u64,
+        #[message(since = 2)]
+        pub mode: Option<u32>,
+        #[message(since = 3, max_len = 8)]
+        pub owner: Option<String>,
+    }
+}
+
+fn path() -> impl Strategy<Value = PathBuf> {
+    "[a-zA-Z0-9 ./_-]{0,24}".prop_map(PathBuf::from)
+}
+
+fn entry() -> impl Strategy<Value = Entry> {
+    (
+        any::<String>(),
+        vec("[a-z]{0,6}", 0..=4),
+        any::<u64>(),
+        any::<bool>(),
+        option::of(path()),
+    )
+        .prop_map(|(name, tags, size, hidden, target)| Entry {
+            name,
+            tags,
+            size,
+            hidden,
+            target,
+        })
+}
+
+fn request() -> impl Strategy<Value = Request> {
+    prop_oneof![
+        Just(Request::Ping),
+        (path(), any::<u64>()).prop_map(|(path, offset)| Request::Read { path, offset }),
+        (path(), vec(any::<u8>(), 0..=64)).prop_map(|(p, v)| Request::Write(p, Bytes(v))),
+        vec(entry(), 0..4).prop_map(Request::List),
+        Just(Request::Stop),
+    ]
+}
+
+fn stat() -> impl Strategy<Value = v3::Stat> {
+    (
+        path(),
+        any::<u64>(),
+        option::of(any::<u32>()),
+        option::of("[a-z]{0,8}"),
+    )
+        .prop_map(|(path, size, mode, owner)| v3::Stat {
+            path,
+            size,
+            mode,
+            owner,
+        })
+}
+
+fn encoded<T: Encode>(v: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    v.encode(&mut buf).unwrap();
+    buf
+}
+
+/// Decode `T` out of `buf`, which it has to use up.
+fn decoded<T: Decode>(buf: &[u8]) -> io::Result<T> {
+    let mut r = buf;
+    let v = T::decode(&mut r)?;
+    assert!(r.is_empty(), "{} bytes left", r.len());
+    Ok(v)
+}
+
+fn assert_round_trip<T: Encode + Decode + PartialEq + Debug>(v: &T) {
+    assert_eq!(&decoded::<T>(&encoded(v)).unwrap(), v);
+}
+
+fn e_stream(role: Role, input: Vec<u8>, write_size: usize) -> EStreamSync<Pipe> {
+    EStreamSync::builder()
+        .keys(SessionKeys::derive(&[7; 32], b"test-message", role))
+        .write_buffer_size(write_size)
+        .inner(Pipe::new(input))
+        .build()
+        .unwrap()
+}
+
+proptest! {
+    #[test]
+    fn test_message_struct_round_trip(v in entry()) {
+        assert_round_trip(&v);
+    }
+
+    #[test]
+    fn test_message_enum_round_trip(v in request()) {
+        assert_round_trip(&v);
+    }
+
+    #[test]
+    fn test_message_generic_round_trip(a in any::<String>(), b in any::<i32>()) {
+        assert_round_trip(&Pair(a, b));
+        assert_round_trip(&Pair(Pair(b as u16, b), b));
+    }
+
+    #[test]
+    fn test_message_versioned_round_trip(v in stat()) {
+        assert_round_trip(&v);
+    }
+
+    #[test]
+    fn test_message_newer_sender(v in stat()) {
+        // an older receiver skips the fields it does not know
+        let old: v1::Stat = decoded(&encoded(&v)).unwrap();
+        prop_assert_eq!(old, v1::Stat { path: v.path, size: v.size });
+    }
+
+    #[test]
+    fn test_message_older_sender(path in path(), size in any::<u64>()) {
+        // a newer receiver gets the default of the fields the sender does not know
+        let old = v1::Stat { path: path.clone(), size };
+        let new: v3::Stat = decoded(&encoded(&old)).unwrap();
+        prop_assert_eq!(new, v3::Stat { path, size, mode: None, owner: None });
+    }
+
+    #[test]
+    fn test_message_truncated(v in request()) {
+        let buf = encoded(&v);
+        for len in 0..buf.len() {
+            prop_assert!(Request::decode(&mut &buf[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_message_over_e_stream(
+        v in vec(request(), 1..8),
+        write_size in 16usize..512,
+    ) {
+        let mut sender = e_stream(Role::Initiator, Vec::new(), write_size);
+        for v in &v {
+            codec::write_frame(&mut sender, v, codec::MAX_FRAME_LEN).unwrap();
+        }
+        sender.flush().unwrap();
+        let wire = std::mem::take(&mut sender.inner_ref_mut().output);
+        let mut receiver = e_stream(Role::Responder, wire, write_size);
+        for v in &v {
+            let read: Request = codec::read_frame(&mut receiver, codec::MAX_FRAME_LEN).unwrap();
+            prop_assert_eq!(&read, v);
+        }
+    }
+}
+
+#[test]
+fn test_message_layout() {
+    assert!(encoded(&Unit).is_empty());
+    assert_eq!(encoded(&Request::Ping), [0]);
+    assert_eq!(encoded(&Request::Stop), [10]);
+    assert_eq!(
+        encoded(&Request::Read {
+            path: "/a".into(),
+            offset: 1
+        }),
+        [1, 0, 0, 0, 2, b'/', b'a', 0, 0, 0, 0, 0, 0, 0, 1]
+    );
+    let v = v1::Stat {
+        path: "a".into(),
+        size: 2,
+    };
+    assert_eq!(
+        encoded(&v),
+        [0, 1, 0, 0, 0, 13, 0, 0, 0, 1, b'a', 0, 0, 0, 0, 0, 0, 0, 2]
+    );
+}
+
+#[test]
+fn test_message_unknown_variant() {
+    let err = decoded::<Request>(&[5]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "unknown variant of `Request`");
+}
+
+#[test]
+fn test_message_max_len() {
+    let mut v = Entry {
+        name: String::new(),
+        tags: vec![String::new(); 5],
+        size: 0,
+        hidden: false,
+        target: None,
+    };
+    let err = v.encode(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    // a peer that does not check
+    v.tags.pop();
+    let mut buf = encoded(&v);
+    buf[7] = 5;
+    buf.splice(8..8, [0, 0, 0, 0]);
+    let err = decoded::<Entry>(&buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let v = v3::Stat {
+        path: "a".into(),
+        size: 0,
+        mode: None,
+        owner: Some("too long owner".into()),
+    };
+    assert!(v.encode(&mut Vec::new()).is_err());
+}
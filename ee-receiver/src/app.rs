@@ -1,47 +1,89 @@
 use std::{
     io::{self, Read, Write},
-    net::{SocketAddr, TcpStream},
-    sync::Arc,
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
 
-use aes::cipher::{
-    BlockDecryptMut, KeyIvInit, StreamCipher, StreamCipherSeek, block_padding::Pkcs7,
+use ee_app::receiver::{Closer, sync::app::App as SenderApp};
+use ee_broadcaster::{
+    Beacon, BeaconFilter, ReceiverInfo,
+    beacon::{self, Rejected},
 };
-use ee_app::receiver::sync::app::App as SenderApp;
-use ee_broadcaster::ReceiverInfo;
-use ee_stream::{buffer::BufReadWriter, e_stream::EStreamSync};
-use rand::Rng;
+use ee_stream::{buffer::BufReadWriter, e_stream::EStreamSync, handshake};
+use ee_task::sandbox::Sandbox;
 
-use crate::{data::AppData, handler::ConnectionHandler};
+use crate::{
+    data::AppData,
+    handler::ConnectionHandler,
+    identity::{Identity, Sender},
+    sandbox::{Policy, Session},
+};
+
+/// A sender connected over tcp, with the key it paired with and its
+/// sandbox.
+pub struct Connection {
+    stream: TcpStream,
+    key: [u8; 32],
+    sandbox: Arc<Sandbox>,
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Session for EStreamSync<Connection> {
+    fn sandbox(&self) -> Arc<Sandbox> {
+        self.inner_ref().sandbox.clone()
+    }
+}
 
 pub struct App {
     id: u128,
-    key: [u8; 32],
+    /// Key of every paired sender with its sandbox.
+    keys: Vec<([u8; 32], Arc<Sandbox>)>,
     socket_addr: SocketAddr,
     broadcast_buf_size: usize,
     broadcast_data_prefix: &'static str,
+    is_running: Arc<AtomicBool>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(identity: &Identity, senders: &[Sender], policy: &Policy) -> Self {
+        let keys = senders
+            .iter()
+            .map(|v| (*v.key(), Arc::new(policy.sandbox(v.id()))))
+            .collect();
         Self {
-            id: 0,
-            key: [0; 32],
-            socket_addr: SocketAddr::from(([0, 0, 0, 0], 7766)),
-            broadcast_buf_size: 8 * 1024,
-            broadcast_data_prefix: "",
+            id: identity.id(),
+            keys,
+            socket_addr: SocketAddr::from(([0, 0, 0, 0], beacon::PORT)),
+            broadcast_buf_size: 1024,
+            broadcast_data_prefix: beacon::PREFIX,
+            is_running: Arc::new(AtomicBool::new(true)),
         }
     }
-    pub fn key(&self) -> &[u8; 32] {
-        &self.key
-    }
     pub fn id(&self) -> u128 {
         self.id
     }
 }
 
 impl SenderApp for App {
-    type Stream = TcpStream;
+    type Stream = Connection;
     type BufStream = BufReadWriter<Self::Stream>;
     type EStream = EStreamSync<Self::Stream>;
     type AppData = AppData;
@@ -52,30 +94,28 @@ impl SenderApp for App {
     fn log_error<E: std::error::Error>(_: &Arc<Self>, error: E) {
         eprintln!("{}\n", error);
     }
+    fn stop(this: &Arc<Self>) {
+        this.is_running.store(false, Ordering::Relaxed);
+    }
+    /// Only senders that reach everything may stop the receiver.
+    fn can_stop(_: &Arc<Self>, stream: &Self::EStream) -> bool {
+        stream.sandbox().is_unrestricted()
+    }
+    fn closer(_: &Arc<Self>, stream: &Self::Stream) -> Option<Closer> {
+        let stream = stream.stream.try_clone().ok()?;
+        Some(Box::new(move || {
+            let _ = stream.shutdown(Shutdown::Both);
+        }))
+    }
     fn encrypt_connection(
-        this: &Arc<Self>,
+        _this: &Arc<Self>,
         _data: &Arc<std::sync::Mutex<Self::AppData>>,
         mut stream: Self::BufStream,
     ) -> std::io::Result<Self::EStream> {
-        let iv = rand::rng().random::<[u8; 16]>();
-        let data = rand::rng().random::<[u8; 32]>();
-        let mut buf = [0u8; 32];
-        let mut cipher = ctr::Ctr64LE::<aes::Aes256>::new(this.key().into(), &iv.into());
-        cipher.apply_keystream_b2b(&data, &mut buf).unwrap();
-        stream.write_all(&iv)?;
-        stream.write_all(&buf)?;
-        stream.flush()?;
-        stream.read_exact(&mut buf)?;
-        if data != buf {
-            stream.write_all(b":1:")?;
-            stream.flush()?;
-            return Err(io::Error::other("error"));
-        }
-        stream.write_all(b":0:")?;
-        stream.flush()?;
-        cipher.seek(0);
-        let e_stream: EStreamSync<TcpStream> = EStreamSync::builder()
-            .cipher(cipher)
+        let key = stream.inner_ref().key;
+        let keys = handshake::respond_sync(&mut stream, &key)?;
+        let e_stream: EStreamSync<Connection> = EStreamSync::builder()
+            .keys(keys)
             .read_buffer_size(8 * 1024)
             .write_buffer_size(8 * 1024)
             .inner(stream.inner())
@@ -88,55 +128,58 @@ impl SenderApp for App {
             .prefix(this.broadcast_data_prefix)
             .buffer_size(this.broadcast_buf_size)
             .socket_addr(this.socket_addr)
+            .is_running(this.is_running.clone())
+            // wake up now and then to notice a shutdown
+            .time_out(Duration::from_millis(500))
             .build()
             .unwrap();
-        let v = move || {
+        let mut filter = BeaconFilter::new();
+        move || {
             loop {
-                if let Ok(Some((addr, buf, len))) = receiver.next() {
-                    type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
-                    if len < 52 {
-                        continue;
-                    }
-                    let data_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
-                    if data_len != len - 2 {
+                let (addr, data, _) = match receiver.next() {
+                    Ok(Some(v)) => v,
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
                         continue;
                     }
-                    let mut iv = [0; 16];
-                    iv.copy_from_slice(&buf[2..18]);
-                    let data = &mut buf[18..len];
-                    // TODO: remove me
-                    println!("Before Decrypt: {data:?}");
-                    let pt = Aes256CbcDec::new(this.key().into(), &iv.into());
-                    let data = pt.decrypt_padded_mut::<Pkcs7>(data).ok()?;
-                    // TODO: remove me
-                    println!("After Decrypt: {data:?}");
-                    let mut got_id = [0u8; 16];
-                    got_id.copy_from_slice(&data[..16]);
-                    let got_id = u128::from_be_bytes(got_id);
-                    if got_id != this.id() {
-                        continue;
-                    }
-                    let secret = &data[16..32];
-                    let port = u16::from_be_bytes([data[32], data[33]]);
-                    let addr = SocketAddr::new(addr.ip(), port);
-
-                    let v = TcpStream::connect(addr);
-                    if v.is_err() {
-                        continue;
-                    }
-                    let mut v = v.unwrap();
-                    if v.write_all(secret).is_err() {
-                        continue;
-                    }
-                    if v.flush().is_err() {
-                        continue;
+                    _ => return None,
+                };
+                let Some((beacon, key, sandbox)) = this
+                    .keys
+                    .iter()
+                    .find_map(|(key, sandbox)| Some((Beacon::decode(data, key)?, key, sandbox)))
+                else {
+                    continue;
+                };
+                if beacon.get_id() != this.id() {
+                    continue;
+                }
+                if let Err(reason) = filter.check(addr.ip(), &beacon) {
+                    if reason != Rejected::Duplicate {
+                        eprintln!("beacon from {} rejected: {reason:?}", addr.ip());
                     }
-                    return Some(v);
-                } else {
-                    return None;
+                    continue;
+                }
+                let addr = SocketAddr::new(addr.ip(), beacon.get_port());
+                let Ok(mut v) = TcpStream::connect_timeout(&addr, Duration::from_secs(3)) else {
+                    continue;
+                };
+                if v.write_all(&beacon.answer(key)).is_err() {
+                    continue;
                 }
+                if v.flush().is_err() {
+                    continue;
+                }
+                return Some(Connection {
+                    stream: v,
+                    key: *key,
+                    sandbox: sandbox.clone(),
+                });
             }
-        };
-        v
+        }
     }
 }
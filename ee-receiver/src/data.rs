@@ -2,33 +2,19 @@ use std::any::Any;
 
 use ee_app::app_data::AppData as Data;
 
+#[derive(Default)]
 pub struct AppData {
     inner: Vec<(String, Box<dyn Any + Send + Sync>)>,
 }
 
-impl Default for AppData {
-    fn default() -> Self {
-        Self { inner: Vec::new() }
-    }
-}
-
 impl AppData {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn with_capacity(cap: usize) -> Self {
-        Self {
-            inner: Vec::with_capacity(cap),
-        }
-    }
-    pub fn insert<T: Send + Sync + 'static>(&mut self, key: impl Into<String>, value: T) {
-        self.inner.push((key.into(), Box::new(value)));
-        self.inner.sort_by(|a, b| (&a.0).cmp(&b.0));
-    }
     pub fn find_index(&self, key: impl AsRef<str>) -> Option<usize> {
         let new_key = key.as_ref();
         self.inner
-            .binary_search_by(|v| (&v.0).as_str().cmp(new_key))
+            .binary_search_by(|v| v.0.as_str().cmp(new_key))
             .ok()
     }
 }
@@ -55,7 +41,7 @@ impl Data for AppData {
         let val = unsafe { &mut self.inner.get_unchecked_mut(index).1 };
         Some(unsafe { val.get_mut_unchecked() })
     }*/
-    fn set<T: 'static>(&mut self, key: impl AsRef<str>) {
+    fn set<T: 'static>(&mut self, _key: impl AsRef<str>) {
         todo!()
     }
 }
@@ -1,32 +1,119 @@
 use std::{
     io::{self, Read, Write},
-    marker::PhantomData,
     sync::{Arc, Mutex},
 };
 
-use ee_app::receiver::sync::handler::ConnectionHandler as Handler;
+use ee_app::receiver::sync::handler::{ConnectionHandler as Handler, Handle};
+use ee_task::{
+    ExeReceiverSync,
+    archive::ArchiveSync,
+    exec::ExecSync,
+    file::{
+        CopySync, DownloadFileSync, LsSync, MkdirSync, RemoveDirSync, RemoveFileSync, RenameSync,
+        StatSync, UploadFileSync,
+    },
+    find::FindSync,
+    metrics::MetricsSync,
+    ping_pong::{DeviceInfo, Ping},
+    process::{KillProcessSync, ProcessListSync},
+    shell::ShellSync,
+    sync::SyncDirSync,
+    system::SystemInfoSync,
+};
+
+use crate::sandbox::Session;
 
+/// Task handlers of the receiver with their versions, sorted by id.
 pub struct ConnectionHandler<Data, T: Read + Write> {
-    inner: Vec<T>,
-    marker: PhantomData<Data>,
+    inner: Vec<(&'static str, u32, Handle<Data, T>)>,
 }
 
 impl<Data, T: Read + Write> Default for ConnectionHandler<Data, T> {
     fn default() -> Self {
-        Self {
-            inner: Vec::new(),
-            marker: PhantomData,
+        Self { inner: Vec::new() }
+    }
+}
+
+impl<Data, T: Read + Write> ConnectionHandler<Data, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers a closure under `id` with the version senders see.
+    ///
+    /// # Panics
+    ///
+    /// Panics if:
+    /// - the ID breaks the rules of [`ee_task::validate_id`]
+    /// - a task with the same ID already exists
+    pub fn register(
+        &mut self,
+        id: &'static str,
+        version: u32,
+        f: impl Fn(&Arc<Mutex<Data>>, &mut T) -> io::Result<()> + Send + Sync + 'static,
+    ) -> &mut Self {
+        if let Err(err) = ee_task::validate_id(id) {
+            panic!("{err}");
         }
+        match self.inner.binary_search_by_key(&id, |&(v, ..)| v) {
+            Ok(_) => panic!("already exists with this ID..."),
+            Err(index) => self.inner.insert(index, (id, version, Box::new(f))),
+        }
+        self
+    }
+}
+
+impl<Data, T: Read + Write + Session> ConnectionHandler<Data, T> {
+    /// Handler with every task the receiver ships with.
+    pub fn with_default_tasks() -> Self {
+        let mut v = Self::new();
+        v.register_task::<Ping>()
+            .register_task::<DeviceInfo>()
+            .register_task::<RemoveFileSync>()
+            .register_task::<LsSync>()
+            .register_task::<DownloadFileSync>()
+            .register_task::<UploadFileSync>()
+            .register_task::<StatSync>()
+            .register_task::<MkdirSync>()
+            .register_task::<RenameSync>()
+            .register_task::<CopySync>()
+            .register_task::<RemoveDirSync>()
+            .register_task::<ExecSync>()
+            .register_task::<ShellSync>()
+            .register_task::<ProcessListSync>()
+            .register_task::<KillProcessSync>()
+            .register_task::<SystemInfoSync>()
+            .register_task::<MetricsSync>()
+            .register_task::<SyncDirSync>()
+            .register_task::<ArchiveSync>()
+            .register_task::<FindSync>();
+        v
+    }
+    /// Registers the receiver side of task `E` under [`ee_task::GetId::id`]
+    /// and [`ee_task::GetId::version`], limited to the sandbox of the
+    /// connection.
+    ///
+    /// # Panics
+    ///
+    /// Same as [`ConnectionHandler::register`].
+    pub fn register_task<E: ExeReceiverSync>(&mut self) -> &mut Self {
+        self.register(E::id(), E::version(), |_, stream| {
+            let sandbox = stream.sandbox();
+            E::execute_sandboxed(stream, &sandbox).map(|_| ())
+        })
     }
 }
 
 impl<Data, Stream: Read + Write + Send + Sync> Handler<Data, Stream>
     for ConnectionHandler<Data, Stream>
 {
-    fn get(
-        &self,
-        id: impl AsRef<str>,
-    ) -> Option<&Box<dyn Fn(&Arc<Mutex<Data>>, &mut Stream) -> io::Result<()>>> {
-        todo!()
+    fn get(&self, id: impl AsRef<str>) -> Option<&Handle<Data, Stream>> {
+        let id = id.as_ref();
+        self.inner
+            .binary_search_by_key(&id, |&(v, ..)| v)
+            .ok()
+            .map(|i| &self.inner[i].2)
+    }
+    fn tasks(&self) -> Vec<(&str, u32)> {
+        self.inner.iter().map(|&(id, version, _)| (id, version)).collect()
     }
 }
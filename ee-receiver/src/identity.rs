@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ee_stream::{
+    e_stream::EStreamSync,
+    pairing::{self, PairingCode},
+};
+
+/// Id of this receiver.
+///
+/// Created on first use. Senders get the id during pairing together with
+/// a key of their own, see [`Sender`].
+#[derive(Clone)]
+pub struct Identity {
+    id: u128,
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        Self { id: rand::random() }
+    }
+    pub fn id(&self) -> u128 {
+        self.id
+    }
+    pub fn from_reader<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut id = [0; 16];
+        reader.read_exact(&mut id)?;
+        Ok(Self {
+            id: u128::from_be_bytes(id),
+        })
+    }
+    pub fn save<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.id.to_be_bytes())?;
+        writer.flush()
+    }
+    /// Load the identity stored at `path`, or create and store a new one.
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        match fs::File::open(path) {
+            Ok(f) => return Self::from_reader(f),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            Err(_) => {}
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let identity = Self::generate();
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        identity.save(options.open(path)?)?;
+        Ok(identity)
+    }
+}
+
+/// A sender paired with this receiver, with the key only it was given.
+#[derive(Clone)]
+pub struct Sender {
+    id: u128,
+    key: [u8; 32],
+    /// Address it paired from, to tell senders apart.
+    name: String,
+}
+
+impl Sender {
+    pub fn generate(name: impl Into<String>) -> Self {
+        Self {
+            id: rand::random(),
+            key: rand::random(),
+            name: name.into(),
+        }
+    }
+    pub fn id(&self) -> u128 {
+        self.id
+    }
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn from_reader<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut id = [0; 16];
+        let mut key = [0; 32];
+        let mut len = [0; 2];
+        reader.read_exact(&mut id)?;
+        reader.read_exact(&mut key)?;
+        reader.read_exact(&mut len)?;
+        let mut name = vec![0; u16::from_be_bytes(len) as usize];
+        reader.read_exact(&mut name)?;
+        Ok(Self {
+            id: u128::from_be_bytes(id),
+            key,
+            name: String::from_utf8(name)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid sender name"))?,
+        })
+    }
+    pub fn save<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        let name = &self.name.as_bytes()[..self.name.len().min(u16::MAX as usize)];
+        writer.write_all(&self.id.to_be_bytes())?;
+        writer.write_all(&self.key)?;
+        writer.write_all(&(name.len() as u16).to_be_bytes())?;
+        writer.write_all(name)?;
+        writer.flush()
+    }
+}
+
+/// The senders stored at `path`, none if there is no such file.
+pub fn load_senders<P: AsRef<Path>>(path: P) -> io::Result<Vec<Sender>> {
+    let data = match fs::read(path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut reader = data.as_slice();
+    let mut senders = Vec::new();
+    while !reader.is_empty() {
+        senders.push(Sender::from_reader(&mut reader)?);
+    }
+    Ok(senders)
+}
+
+/// Append `sender` to the senders stored at `path`.
+pub fn add_sender<P: AsRef<Path>>(path: P, sender: &Sender) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut buf = Vec::new();
+    sender.save(&mut buf)?;
+    // one write, a crash never leaves half a sender behind
+    options.open(path)?.write_all(&buf)
+}
+
+/// `~/.eagle-eye`, or the current directory if there is no home directory.
+pub fn default_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".eagle-eye"))
+        .unwrap_or_default()
+}
+
+/// `~/.eagle-eye/receiver.identity`, or `./receiver.identity` if there is
+/// no home directory.
+pub fn default_path() -> PathBuf {
+    default_dir().join("receiver.identity")
+}
+
+/// `~/.eagle-eye/senders`, see [`default_path`].
+pub fn senders_path() -> PathBuf {
+    default_dir().join("senders")
+}
+
+/// Show a one-time code on the terminal and pair with the first sender
+/// that connects to `addr`.
+///
+/// The sender gets a key of its own, stored with the other senders at
+/// `senders`, so the receiver can tell it apart.
+///
+/// The code is only good for one attempt, a wrong code ends the pairing.
+pub fn pair_sync(identity: &Identity, senders: &Path, addr: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let code = PairingCode::generate();
+    println!("Pairing code: {code}");
+    println!(
+        "Waiting for a sender on port {} ...",
+        listener.local_addr()?.port()
+    );
+    let (mut stream, peer) = listener.accept()?;
+    stream.set_read_timeout(Some(Duration::from_secs(60)))?;
+    let keys = pairing::respond_sync(&mut stream, &code)?;
+    let mut stream = EStreamSync::builder()
+        .keys(keys)
+        .inner(stream)
+        .build()
+        .unwrap();
+    // same layout as `ee_device::Device::save`
+    let os = std::env::consts::OS.as_bytes();
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|v| v.len() <= 64)
+        .unwrap_or_else(|| "Unknown".to_owned());
+    let user = user.as_bytes();
+    stream.write_all(&(os.len() as u16).to_be_bytes())?;
+    stream.write_all(&(user.len() as u16).to_be_bytes())?;
+    let sender = Sender::generate(peer.ip().to_string());
+    stream.write_all(&identity.id.to_be_bytes())?;
+    stream.write_all(sender.key())?;
+    stream.write_all(os)?;
+    stream.write_all(user)?;
+    stream.write_all(&[111, 0])?;
+    stream.flush()?;
+    let mut ack = [0; 4];
+    stream.read_exact(&mut ack)?;
+    if &ack != b":ok:" {
+        return Err(io::Error::other("sender did not confirm the pairing"));
+    }
+    add_sender(senders, &sender)?;
+    println!("Paired with {} as sender {:032x}", peer.ip(), sender.id());
+    Ok(())
+}
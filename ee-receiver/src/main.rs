@@ -2,25 +2,67 @@ mod app;
 mod config;
 mod data;
 mod handler;
+mod identity;
+mod sandbox;
 mod utils;
 
-use std::io;
+use std::{io, net::SocketAddr, time::Duration};
 
-use ee_app::receiver::sync::server::Server;
+use ee_app::receiver::{OverloadPolicy, sync::server::Server};
 
-use crate::{app::App, data::AppData, handler::ConnectionHandler, utils::auth};
+use crate::{
+    app::App, data::AppData, handler::ConnectionHandler, identity::Identity, sandbox::Policy,
+};
 
 fn main() -> io::Result<()> {
-    let mut server = Server::new(move || App::new());
+    let identity = Identity::load_or_create(identity::default_path())?;
+    let mut args = std::env::args().skip(1);
+    let remote_stop = match args.next().as_deref() {
+        Some("pair") => {
+            let addr = match args.next() {
+                Some(v) => v
+                    .parse()
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid address"))?,
+                None => SocketAddr::from(([0, 0, 0, 0], 7767)),
+            };
+            return identity::pair_sync(&identity, &identity::senders_path(), addr);
+        }
+        // the ids to give directories in `sandbox.json`
+        Some("senders") => {
+            for v in identity::load_senders(identity::senders_path())? {
+                println!("{:032x}  {}", v.id(), v.name());
+            }
+            return Ok(());
+        }
+        // off unless asked for, even then only unrestricted senders may stop it
+        Some("--allow-remote-stop") => true,
+        None => false,
+        Some(v) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown argument `{v}`"),
+            ));
+        }
+    };
+    let senders = identity::load_senders(identity::senders_path())?;
+    let policy = Policy::load(sandbox::default_path())?;
+    let mut server = Server::new(move || App::new(&identity, &senders, &policy));
 
     server.app_name("eagle-eye");
-    server.version((1, 0, 0));
-    server.handler(ConnectionHandler::default());
+    server.version((2, 0, 0));
+    server.handler(ConnectionHandler::with_default_tasks());
 
     server.max_connection(8);
-    server.auth(auth);
+    server.overload_policy(OverloadPolicy::Queue {
+        max_queued: 8,
+        timeout: Duration::from_secs(5),
+    });
+    server.remote_stop(remote_stop);
     server.app_data(AppData::new());
 
     server.run();
     Ok(())
 }
+
+#[cfg(test)]
+mod test;
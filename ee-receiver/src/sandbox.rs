@@ -0,0 +1,100 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+    sync::Arc,
+};
+
+use ee_task::sandbox::{Root, Sandbox};
+use serde::Deserialize;
+
+/// The directories each paired sender can reach, from a file like
+///
+/// ```json
+/// {
+///     "default": [{ "path": "/home/me/Public", "access": "read-only" }],
+///     "senders": {
+///         "<sender id>": [{ "path": "/home/me/share", "access": "read-write" }]
+///     },
+///     "unrestricted": ["<sender id>"]
+/// }
+/// ```
+///
+/// A sender in `unrestricted` reaches everything, one with an entry in
+/// `senders` its directories, any other `default`. Without the file no
+/// sender reaches anything.
+#[derive(Debug, Default)]
+pub struct Policy {
+    default: Vec<Root>,
+    senders: HashMap<u128, Vec<Root>>,
+    unrestricted: HashSet<u128>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    default: Vec<Root>,
+    /// Sender ids as 32 hex digits.
+    #[serde(default)]
+    senders: HashMap<String, Vec<Root>>,
+    #[serde(default)]
+    unrestricted: Vec<String>,
+}
+
+/// `id` as 32 hex digits.
+fn sender_id(id: &str) -> io::Result<u128> {
+    u128::from_str_radix(id, 16).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid sender id `{id}`"),
+        )
+    })
+}
+
+impl Policy {
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let file: PolicyFile = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut senders = HashMap::new();
+        for (id, v) in file.senders {
+            senders.insert(sender_id(&id)?, v);
+        }
+        Ok(Self {
+            default: file.default,
+            senders,
+            unrestricted: file
+                .unrestricted
+                .iter()
+                .map(|v| sender_id(v))
+                .collect::<io::Result<_>>()?,
+        })
+    }
+    /// Read the policy at `path`, every path forbidden if there is no such
+    /// file.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(v) => Self::from_json(&v),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+    /// Sandbox of the sender with id `sender`.
+    pub fn sandbox(&self, sender: u128) -> Sandbox {
+        if self.unrestricted.contains(&sender) {
+            return Sandbox::unrestricted();
+        }
+        let roots = self.senders.get(&sender).unwrap_or(&self.default);
+        Sandbox::new().roots(roots.iter().cloned())
+    }
+}
+
+/// `~/.eagle-eye/sandbox.json`, see [`crate::identity::default_dir`].
+pub fn default_path() -> std::path::PathBuf {
+    crate::identity::default_dir().join("sandbox.json")
+}
+
+/// A connection that knows the sandbox of the sender on the other end.
+pub trait Session {
+    fn sandbox(&self) -> Arc<Sandbox>;
+}
@@ -0,0 +1,212 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use ee_app::receiver::sync::handler::ConnectionHandler as _;
+use ee_proto::{Encode, ErrorKind, Response};
+use ee_task::{
+    GetId,
+    archive::ArchiveSync,
+    exec::ExecSync,
+    file::{
+        CopySync, DownloadFileSync, LsSync, MkdirSync, RemoveDirSync, RemoveFileSync, RenameSync,
+        StatSync, UploadFileSync,
+    },
+    find::FindSync,
+    metrics::MetricsSync,
+    ping_pong::{DeviceInfo, Ping},
+    process::{KillProcessSync, ProcessListSync},
+    sandbox::{Access, Root, Sandbox},
+    shell::ShellSync,
+    sync::SyncDirSync,
+    system::SystemInfoSync,
+};
+use ee_test_util::{Pipe, TempDir};
+
+use crate::{
+    data::AppData,
+    handler::ConnectionHandler,
+    identity::{self, Sender},
+    sandbox::{Policy, Session},
+};
+
+impl Session for Pipe<Arc<Sandbox>> {
+    fn sandbox(&self) -> Arc<Sandbox> {
+        self.session.clone()
+    }
+}
+
+/// A pipe of an unrestricted session.
+fn pipe(input: &[u8]) -> Pipe<Arc<Sandbox>> {
+    Pipe::new(input).session(Arc::new(Sandbox::unrestricted()))
+}
+
+type Handler = ConnectionHandler<AppData, Pipe<Arc<Sandbox>>>;
+
+fn data() -> Arc<Mutex<AppData>> {
+    Arc::new(Mutex::new(AppData::new()))
+}
+
+#[test]
+fn test_handler_default_tasks() {
+    let handler = Handler::with_default_tasks();
+    for id in [
+        Ping::id(),
+        DeviceInfo::id(),
+        RemoveFileSync::id(),
+        LsSync::id(),
+        DownloadFileSync::id(),
+        UploadFileSync::id(),
+        StatSync::id(),
+        MkdirSync::id(),
+        RenameSync::id(),
+        CopySync::id(),
+        RemoveDirSync::id(),
+        ExecSync::id(),
+        ShellSync::id(),
+        ProcessListSync::id(),
+        KillProcessSync::id(),
+        SystemInfoSync::id(),
+        MetricsSync::id(),
+        SyncDirSync::id(),
+        ArchiveSync::id(),
+        FindSync::id(),
+    ] {
+        assert!(handler.get(id).is_some(), "{id} is not registered");
+    }
+    assert!(handler.get("unknown").is_none());
+    assert!(handler.get("").is_none());
+
+    let mut pipe = pipe(b"ping");
+    handler.get(Ping::id()).unwrap()(&data(), &mut pipe).unwrap();
+    assert_eq!(pipe.output, b"pong");
+}
+
+#[test]
+fn test_handler_register_closure() {
+    let mut handler = Handler::new();
+    handler
+        .register("b", 1, |_, s| s.write_all(b"b"))
+        .register("a", 1, |_, s| s.write_all(b"a"))
+        .register("c", 1, |_, s| s.write_all(b"c"));
+    for id in ["a", "b", "c"] {
+        let mut pipe = pipe(b"");
+        handler.get(id).unwrap()(&data(), &mut pipe).unwrap();
+        assert_eq!(pipe.output, id.as_bytes());
+    }
+}
+
+#[test]
+#[should_panic(expected = "already exists")]
+fn test_handler_duplicate_id() {
+    Handler::with_default_tasks().register(Ping::id(), 1, |_, _| Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "start and end with `:`")]
+fn test_handler_reserved_id() {
+    Handler::new().register(":break:", 1, |_, _| Ok(()));
+}
+
+#[test]
+#[should_panic(expected = "new line")]
+fn test_handler_new_line_in_id() {
+    Handler::new().register("a\nb", 1, |_, _| Ok(()));
+}
+
+#[test]
+fn test_handler_task_versions() {
+    let mut handler = Handler::with_default_tasks();
+    handler.register("custom", 3, |_, _| Ok(()));
+    let tasks = handler.tasks();
+    assert!(tasks.contains(&("custom", 3)));
+    assert!(tasks.contains(&(Ping::id(), Ping::version())));
+    assert!(tasks.is_sorted());
+}
+
+#[test]
+fn test_policy_per_sender() {
+    let sender = Sender::generate("192.168.0.2");
+    let json = format!(
+        r#"{{
+            "default": [{{ "path": "/srv/public" }}],
+            "senders": {{
+                "{:032X}": [{{ "path": "/srv/share", "access": "read-write" }}]
+            }}
+        }}"#,
+        sender.id()
+    );
+    let policy = Policy::from_json(&json).unwrap();
+    assert_eq!(
+        policy.sandbox(sender.id()),
+        Sandbox::new().root("/srv/share", Access::ReadWrite)
+    );
+    let public = Sandbox::new().roots([Root {
+        path: "/srv/public".into(),
+        access: Access::ReadOnly,
+    }]);
+    assert_eq!(policy.sandbox(sender.id() ^ 1), public);
+
+    // a sender with neither an entry nor a default reaches nothing
+    let policy = Policy::from_json(r#"{ "senders": {} }"#).unwrap();
+    assert_eq!(policy.sandbox(sender.id()), Sandbox::new());
+    // nor without the file
+    let dir = TempDir::new("receiver-policy");
+    let policy = Policy::load(dir.path().join("sandbox.json")).unwrap();
+    assert_eq!(policy.sandbox(sender.id()), Sandbox::new());
+
+    // only the senders listed reach everything
+    let json = format!(r#"{{ "unrestricted": ["{:032x}"] }}"#, sender.id());
+    let policy = Policy::from_json(&json).unwrap();
+    assert!(policy.sandbox(sender.id()).is_unrestricted());
+    assert_eq!(policy.sandbox(sender.id() ^ 1), Sandbox::new());
+
+    assert!(Policy::from_json(r#"{ "senders": { "not hex": [] } }"#).is_err());
+    assert!(Policy::from_json(r#"{ "unrestricted": ["not hex"] }"#).is_err());
+    assert!(Policy::from_json(r#"{ "default": [{ "path": "/", "access": "all" }] }"#).is_err());
+}
+
+#[test]
+fn test_handler_sandboxed() {
+    let dir = TempDir::new("receiver-sandbox")
+        .dir("shared")
+        .file("secret.txt", b"secret");
+    let file = dir.path().join("secret.txt");
+
+    let handler = Handler::with_default_tasks();
+    let mut input = Vec::new();
+    file.encode(&mut input).unwrap();
+    let shared = Sandbox::new().root(dir.path().join("shared"), Access::ReadWrite);
+    let mut sandboxed = Pipe::new(input.as_slice()).session(Arc::new(shared));
+    handler.get(RemoveFileSync::id()).unwrap()(&data(), &mut sandboxed).unwrap();
+    let res = Response::read_from(sandboxed.output.as_slice()).unwrap();
+    assert_eq!(res.get_kind(), ErrorKind::Forbidden);
+    assert!(file.exists());
+
+    // unrestricted connections keep working as before
+    let mut unrestricted = pipe(&input);
+    handler.get(RemoveFileSync::id()).unwrap()(&data(), &mut unrestricted).unwrap();
+    assert!(
+        Response::read_from(unrestricted.output.as_slice())
+            .unwrap()
+            .is_ok()
+    );
+    assert!(!file.exists());
+}
+
+#[test]
+fn test_senders_file() {
+    let dir = TempDir::new("receiver-senders");
+    let path = dir.path().join("senders");
+    assert!(identity::load_senders(&path).unwrap().is_empty());
+    let a = Sender::generate("10.0.0.1");
+    let b = Sender::generate("10.0.0.2");
+    identity::add_sender(&path, &a).unwrap();
+    identity::add_sender(&path, &b).unwrap();
+    let senders = identity::load_senders(&path).unwrap();
+    assert_eq!(senders.len(), 2);
+    assert_eq!(senders[0].id(), a.id());
+    assert_eq!(senders[1].key(), b.key());
+    assert_eq!(senders[1].name(), "10.0.0.2");
+}
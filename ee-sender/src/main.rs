@@ -33,45 +33,120 @@ async fn hello(req: Request<hyper::body::Incoming>) -> Result<Response<Full<Byte
 */
 use std::{
     collections::HashMap,
-    io::{self, BufRead, BufReader, BufWriter, Write},
-    mem::ManuallyDrop,
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
     net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
     str::FromStr,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
-use ee_device::{ClientSync, Device, DeviceManager};
-use ee_http::{HttpRequest, HttpResponse, Method, Status};
-use ee_task::{GetId, file::RemoveFileSync, prelude::Ping};
+use ee_device::{ClientSync, Device, DeviceManager, TaskSenderSync};
+use ee_http::{Body, HttpRequest, HttpResponse, Method, Status, websocket};
+use ee_stream::pairing::PairingCode;
+use ee_task::{
+    ExeSenderSync, ExecuteResult, GetId,
+    archive::ArchiveSync,
+    exec::ExecSync,
+    file::{
+        CopySync, DownloadFileSync, LsSync, MkdirSync, RemoveDirSync, RemoveFileSync, RenameSync,
+        StatSync, UploadFileSync,
+    },
+    find::FindSync,
+    metrics::MetricsSync,
+    ping_pong::DeviceInfo,
+    prelude::Ping,
+    process::{KillProcessSync, ProcessListSync},
+    shell::ShellSync,
+    sync::SyncDirSync,
+    system::SystemInfoSync,
+};
+use serde::de::DeserializeOwned;
 
 fn main() -> io::Result<()> {
     let client = ClientSync::new().device_connect_time_out(Duration::from_secs(3));
-    let mut my_devices = DeviceManager::new();
-    my_devices.push_device(Device::new().id(123).key([33; 32]));
-    my_devices.push_device(Device::new().id(3).key([0; 32]));
-    my_devices.push_device(Device::new().id(10).key([17; 32]));
-    my_devices.push_device(Device::new().id(15).key([123; 32]));
+    let devices_path = devices_path();
+    let mut my_devices = load_devices(&devices_path)?;
+
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().map(String::as_str) == Some("pair") {
+        let (Some(addr), Some(code)) = (args.get(1), args.get(2)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "usage: ee-sender pair <receiver-addr:port> <code>",
+            ));
+        };
+        let code = PairingCode::from_str(code)?;
+        let stream = TcpStream::connect(addr.as_str())?;
+        stream.set_read_timeout(Some(Duration::from_secs(60)))?;
+        let id = *my_devices.pair_sync(stream, &code)?.get_id();
+        save_devices(&my_devices, &devices_path)?;
+        my_print(format!("Paired with device {id}"));
+        return Ok(());
+    }
 
+    let client = Arc::new(client);
+    let my_devices = Arc::new(Mutex::new(my_devices));
     let listener = TcpListener::bind("0.0.0.0:8080")?;
     for stream in listener.incoming() {
         let stream = stream?;
-        stream.set_read_timeout(Some(Duration::from_secs(3)))?;
-        let mut reader = BufReader::new(stream.try_clone().unwrap());
-        let mut writer = BufWriter::new(stream);
-        loop {
-            match handle(&client, &mut reader, &mut writer, &mut my_devices) {
-                Ok(true) => continue,
-                Ok(false) => break,
-                Err(err) => {
-                    eprintln!("{err}\n");
-                    break;
-                }
+        let client = client.clone();
+        let my_devices = my_devices.clone();
+        // event streams and shells stay open, every connection gets its own
+        // thread so they do not hold up the others
+        std::thread::spawn(move || {
+            if let Err(err) = serve(&client, stream, &my_devices) {
+                eprintln!("{err}\n");
             }
-        }
+        });
     }
     Ok(())
 }
 
+/// Answer the requests on one http connection until it closes.
+fn serve(client: &ClientSync, stream: TcpStream, manager: &Mutex<DeviceManager>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(3)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    while handle(client, &mut reader, &mut writer, manager)? {}
+    Ok(())
+}
+
+/// `~/.eagle-eye/devices`, or `./devices` if there is no home directory.
+fn devices_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".eagle-eye"))
+        .unwrap_or_default()
+        .join("devices")
+}
+
+fn load_devices(path: &Path) -> io::Result<DeviceManager> {
+    match fs::File::open(path) {
+        Ok(f) => DeviceManager::from_reader(BufReader::new(f)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DeviceManager::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write to a temporary file first, so a crash never leaves a truncated list.
+fn save_devices(manager: &DeviceManager, path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut writer = BufWriter::new(options.open(&tmp)?);
+    manager.save(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(tmp, path)
+}
+
 fn my_print<T: std::fmt::Display>(v: T) {
     let mut stdout = std::io::stdout();
     let _ = writeln!(stdout, "{v}");
@@ -82,13 +157,34 @@ fn handle(
     client: &ClientSync,
     reader: &mut BufReader<TcpStream>,
     writer: &mut BufWriter<TcpStream>,
-    manager: &mut DeviceManager,
+    manager: &Mutex<DeviceManager>,
 ) -> io::Result<bool> {
-    let mut req = get_http_request(reader)?;
+    let (mut req, len) = match get_http_request(reader) {
+        Ok(Some(v)) => v,
+        Ok(None) => return Ok(false),
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            HttpResponse::new()
+                .status(Status::BadRequest)
+                .send_str(writer, err.to_string())?;
+            return Ok(false);
+        }
+        Err(err) => return Err(err),
+    };
+    // the body is not read yet, uploads stay out of the log
     my_print(format!("{:?}", &req));
+    // an upload goes on to the device as it comes in, any other body is
+    // read here
+    let upload = matches!(req.get_path(), "/api" | "/api/")
+        && req.get_header("TaskId") == Some(UploadFileSync::id());
+    if !upload && req.get_header("Content-Length").is_some() {
+        let mut body = Vec::new();
+        Body::new(&mut *reader, len).read_to_end(&mut body)?;
+        req.set_body(body);
+    }
     match req.get_path() {
         "/" => HttpResponse::new().send_file(writer, "web/index.html")?,
         "/api/scan-devices" => {
+            let mut manager = manager.lock().unwrap();
             manager.scan(client)?;
             let online = manager
                 .get_online_device()
@@ -104,7 +200,18 @@ fn handle(
             HttpResponse::new().send_json_str(writer, serde_json::to_string(&online).unwrap())?;
         }
         "/api" | "/api/" => {
-            handle_api(client, &mut req, writer, manager)?;
+            let mut body = Body::new(&mut *reader, if upload { len } else { 0 });
+            handle_api(client, &mut req, &mut body, writer, manager)?;
+            // the next request starts after what the upload left of the body
+            io::copy(&mut body, &mut io::sink())?;
+        }
+        v if v == "/api/metrics" || v.starts_with("/api/metrics?") => {
+            handle_metrics(client, &mut req, writer, manager)?;
+        }
+        v if v == "/api/shell" || v.starts_with("/api/shell?") => {
+            // the connection is a WebSocket now, or failed
+            handle_shell(client, &mut req, reader, writer, manager)?;
+            return Ok(false);
         }
         _ => HttpResponse::default()
             .status(Status::NotFound)
@@ -113,31 +220,130 @@ fn handle(
     Ok(req.get_header("Connection") == Some("keep-alive"))
 }
 
-fn read_n<R: io::Read>(mut reader: R, mut n: usize) -> io::Result<Vec<u8>> {
-    const LEN: usize = 32;
-    let mut buf = [0u8; LEN];
-    let mut v = Vec::new();
-    loop {
-        if n == 0 {
-            break;
-        }
-        let r = reader.read(&mut buf[0..std::cmp::min(n, LEN)])?;
-        if r == 0 {
-            break;
-        }
-        v.extend_from_slice(&buf[0..r]);
-        n -= r;
+/// `/api/shell?id=<device>&cols=<cols>&rows=<rows>`, a WebSocket to a
+/// shell on the device. Browsers can not set headers on a WebSocket, so it
+/// all comes in the query.
+///
+/// Like every task, the shell gets a session with the device of its own for
+/// as long as it runs.
+fn handle_shell(
+    client: &ClientSync,
+    req: &mut HttpRequest,
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut BufWriter<TcpStream>,
+    manager: &Mutex<DeviceManager>,
+) -> io::Result<()> {
+    if !websocket::is_upgrade(req) {
+        return HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(writer, "WebSocket upgrade expected.");
+    }
+    let query = query(req);
+    let Some(device_id) = query.get("id").and_then(|v| v.parse::<u128>().ok()) else {
+        return HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(writer, "Id not found.");
+    };
+    let mut task = ShellSync::new();
+    let cols = query.get("cols").and_then(|v| v.parse().ok());
+    let rows = query.get("rows").and_then(|v| v.parse().ok());
+    if let (Some(cols), Some(rows)) = (cols, rows) {
+        task = task.size(cols, rows);
+    }
+    // polled for input between the turns of the shell
+    reader
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(10)))?;
+    let Some(device) = manager.lock().unwrap().get_device(&device_id).cloned() else {
+        return HttpResponse::new()
+            .status(Status::NotFound)
+            .send_str(writer, "Device not found.");
+    };
+    let mut http = Duplex { reader, writer };
+    client.connect_device(&device)?.send(task, req, &mut http)?;
+    Ok(())
+}
+
+/// `/api/metrics?id=<device>&interval=<milliseconds>`, the live resource
+/// usage of the device as Server-Sent Events. `EventSource` can not set
+/// headers either.
+///
+/// The events go on until the page closes, on a session with the device of
+/// their own like every task.
+fn handle_metrics(
+    client: &ClientSync,
+    req: &mut HttpRequest,
+    writer: &mut BufWriter<TcpStream>,
+    manager: &Mutex<DeviceManager>,
+) -> io::Result<()> {
+    let query = query(req);
+    let Some(device_id) = query.get("id").and_then(|v| v.parse::<u128>().ok()) else {
+        return HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(writer, "Id not found.");
+    };
+    let mut task = MetricsSync::new();
+    if let Some(v) = query.get("interval").and_then(|v| v.parse().ok()) {
+        task = task.interval(Duration::from_millis(v));
+    }
+    let Some(device) = manager.lock().unwrap().get_device(&device_id).cloned() else {
+        return HttpResponse::new()
+            .status(Status::NotFound)
+            .send_str(writer, "Device not found.");
+    };
+    // the events may be streaming already, close the connection instead of
+    // answering twice
+    client.connect_device(&device)?.send(task, req, writer)?;
+    Ok(())
+}
+
+/// The query of the path of `req`, without any decoding.
+fn query(req: &HttpRequest) -> HashMap<String, String> {
+    req.get_path()
+        .split_once('?')
+        .map(|(_, v)| v)
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|v| v.split_once('='))
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect()
+}
+
+/// The two halves of an http connection as one stream.
+struct Duplex<'a, R> {
+    reader: R,
+    writer: &'a mut BufWriter<TcpStream>,
+}
+
+impl<R: io::Read> io::Read for Duplex<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
     }
-    Ok(v)
 }
 
-fn get_http_request(reader: &mut BufReader<TcpStream>) -> io::Result<HttpRequest> {
+impl<R> io::Write for Duplex<'_, R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The head of the next request and the length of its body, `None` once
+/// the client closed the connection. A malformed head is an
+/// [`io::ErrorKind::InvalidData`] error.
+fn get_http_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<(HttpRequest, u64)>> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
     let mut iter = reader.lines();
-    let first_line = iter.next().unwrap()?;
+    let Some(first_line) = iter.next().transpose()? else {
+        return Ok(None);
+    };
     let t: Vec<&str> = first_line.split_whitespace().collect();
-    let method = Method::from_str(t.first().unwrap()).unwrap();
-    let path = *t.get(1).unwrap();
-    let version = *t.get(2).unwrap();
+    let [method, path, version] = t[..] else {
+        return Err(invalid("Invalid request line."));
+    };
+    let method = Method::from_str(method).map_err(|_| invalid("Unknown method."))?;
     let mut headers = HashMap::new();
     for line in iter {
         let line = line?;
@@ -148,52 +354,177 @@ fn get_http_request(reader: &mut BufReader<TcpStream>) -> io::Result<HttpRequest
         let v = line
             .split_once(':')
             .map(|(x, y)| (x.trim(), y.trim()))
-            .unwrap();
+            .ok_or_else(|| invalid("Invalid header line."))?;
         headers.insert(v.0.to_owned(), v.1.to_owned());
     }
-    let body;
-    if let Some(v) = headers.get("Content-Length") {
-        let len: usize = v.parse().unwrap();
-        let u = read_n(reader, len)?;
-        body = Some(u);
-    } else {
-        body = None;
-    }
-    Ok(HttpRequest::default()
+    let len = match headers.get("Content-Length") {
+        Some(v) => v.parse().map_err(|_| invalid("Invalid Content-Length."))?,
+        None => 0,
+    };
+    let req = HttpRequest::default()
         .method(method)
         .path(path)
         .version(version)
-        .headers(headers)
-        .body(body))
+        .headers(headers);
+    Ok(Some((req, len)))
 }
 
-fn handle_api(
+/// Like the shells and the metrics, every task runs on a session with the
+/// device of its own, `manager` is only locked to find the device.
+fn handle_api<R: io::Read>(
     client: &ClientSync,
     req: &mut HttpRequest,
+    body: R,
     writer: &mut BufWriter<TcpStream>,
-    manager: &mut DeviceManager,
+    manager: &Mutex<DeviceManager>,
 ) -> io::Result<()> {
-    let task_id = req.get_header("TaskId");
-    if task_id.is_none() {
-        return HttpResponse::new().send_str(writer, "TaskId not found.");
-    }
-    let device_id = match req.get_header("Id") {
-        Some(id) => id.parse::<u128>().unwrap(),
-        None => return HttpResponse::new().send_str(writer, "Id not found."),
+    let Some(task_id) = req.get_header("TaskId").map(str::to_owned) else {
+        return HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(writer, "TaskId not found.");
     };
-    match task_id.unwrap() {
+    let Some(device_id) = req.get_header("Id").and_then(|v| v.parse::<u128>().ok()) else {
+        return HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(writer, "Id not found.");
+    };
+    let Some(device) = manager.lock().unwrap().get_device(&device_id).cloned() else {
+        return HttpResponse::new()
+            .status(Status::NotFound)
+            .send_str(writer, "Device not found.");
+    };
+    let mut api = Api {
+        client,
+        device,
+        req,
+        writer,
+    };
+    match task_id.as_str() {
         v if v == Ping::id() => {
-            if manager
-                .send(client, &device_id, req, writer, Ping::new())
-                .is_ok()
-            {
-                return HttpResponse::new().send_str(writer, "pong");
+            if api.send(Ping::new()).is_ok() {
+                HttpResponse::new().send_str(api.writer, "pong")
             } else {
-                return HttpResponse::new().send_str(writer, "Device is not online.");
+                HttpResponse::new().send_str(api.writer, "Device is not online.")
             }
         }
-        v if v == RemoveFileSync::id() => {}
-        _ => {}
+        v if v == LsSync::id() => api.send_json(Some(LsSync::home())),
+        // the headers may be out already, close the connection instead of
+        // answering twice
+        v if v == DownloadFileSync::id() => api.stream_json::<DownloadFileSync>(),
+        v if v == ArchiveSync::id() => api.stream_json::<ArchiveSync>(),
+        v if v == FindSync::id() => api.stream_json::<FindSync>(),
+        v if v == ExecSync::id() => match ExecSync::from_http(api.req) {
+            Ok(task) => api.stream(task),
+            Err(err) => api.bad_request(err),
+        },
+        v if v == UploadFileSync::id() => api.upload(body),
+        v if v == RemoveFileSync::id() => api.send_json::<RemoveFileSync>(None),
+        v if v == StatSync::id() => api.send_json::<StatSync>(None),
+        v if v == MkdirSync::id() => api.send_json::<MkdirSync>(None),
+        v if v == RenameSync::id() => api.send_json::<RenameSync>(None),
+        v if v == CopySync::id() => api.send_json::<CopySync>(None),
+        v if v == RemoveDirSync::id() => api.send_json::<RemoveDirSync>(None),
+        v if v == SyncDirSync::id() => api.send_json::<SyncDirSync>(None),
+        v if v == ProcessListSync::id() => api.send_json(Some(ProcessListSync::new())),
+        v if v == KillProcessSync::id() => api.send_json::<KillProcessSync>(None),
+        v if v == DeviceInfo::id() => api.send(DeviceInfo::new()),
+        v if v == SystemInfoSync::id() => api.send(SystemInfoSync::new()),
+        _ => HttpResponse::new()
+            .status(Status::NotFound)
+            .send_str(api.writer, format!("Unknown task `{task_id}`.")),
+    }
+}
+
+/// A request to `/api` for one device.
+struct Api<'a> {
+    client: &'a ClientSync,
+    device: Device,
+    req: &'a mut HttpRequest,
+    writer: &'a mut BufWriter<TcpStream>,
+}
+
+impl Api<'_> {
+    fn bad_request<E: std::fmt::Display>(&mut self, err: E) -> io::Result<()> {
+        HttpResponse::new()
+            .status(Status::BadRequest)
+            .send_str(&mut *self.writer, err.to_string())
+    }
+    /// The task in the JSON body, `default` if there is no body.
+    fn json_body<T: DeserializeOwned>(&self, default: Option<T>) -> Result<T, String> {
+        match self.req.get_body().map(serde_json::from_slice::<T>) {
+            Some(Ok(v)) => Ok(v),
+            Some(Err(err)) => Err(err.to_string()),
+            None => default.ok_or_else(|| "body not found.".to_owned()),
+        }
+    }
+    /// Send `task` and answer with the error if it fails.
+    fn send<T>(&mut self, task: T) -> io::Result<()>
+    where
+        T: for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut BufWriter<TcpStream>>,
+    {
+        if let Err(err) = self.run(task) {
+            return HttpResponse::new().send_str(&mut *self.writer, err.to_string());
+        }
+        Ok(())
+    }
+    /// Send `task`, which streams its answer. A failure closes the
+    /// connection, the answer may have started already.
+    fn stream<T>(&mut self, task: T) -> io::Result<()>
+    where
+        T: for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut BufWriter<TcpStream>>,
+    {
+        self.run(task)?;
+        Ok(())
+    }
+    /// Stream the file in `body` to the device, the answer comes once it is
+    /// in place.
+    fn upload<R: io::Read>(&mut self, body: R) -> io::Result<()> {
+        let (task, file) = match UploadFileSync::from_http(self.req, body) {
+            Ok(v) => v,
+            Err(err) => return self.bad_request(err),
+        };
+        let mut http = Duplex {
+            reader: file,
+            writer: &mut *self.writer,
+        };
+        let res = self
+            .client
+            .connect_device(&self.device)
+            .and_then(|mut v| v.send(task, self.req, &mut http));
+        if let Err(err) = res {
+            return HttpResponse::new().send_str(&mut *self.writer, err.to_string());
+        }
+        Ok(())
+    }
+    /// `task` on a new session with the device.
+    fn run<T>(&mut self, task: T) -> io::Result<ExecuteResult>
+    where
+        T: for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut BufWriter<TcpStream>>,
+    {
+        self.client
+            .connect_device(&self.device)?
+            .send(task, self.req, &mut *self.writer)
+    }
+    /// [`Api::send`] the task in the JSON body, `default` without a body.
+    fn send_json<T>(&mut self, default: Option<T>) -> io::Result<()>
+    where
+        T: DeserializeOwned
+            + for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut BufWriter<TcpStream>>,
+    {
+        match self.json_body(default) {
+            Ok(task) => self.send(task),
+            Err(err) => self.bad_request(err),
+        }
+    }
+    /// [`Api::stream`] the task in the JSON body.
+    fn stream_json<T>(&mut self) -> io::Result<()>
+    where
+        T: DeserializeOwned
+            + for<'a, 'b> ExeSenderSync<&'a mut TaskSenderSync, &'b mut BufWriter<TcpStream>>,
+    {
+        match self.json_body::<T>(None) {
+            Ok(task) => self.stream(task),
+            Err(err) => self.bad_request(err),
+        }
     }
-    todo!()
 }
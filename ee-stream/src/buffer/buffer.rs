@@ -1,56 +1,56 @@
-use std::io::{self, Write};
+use std::io;
 
 use crate::buffer::{r_buf::ReaderBuff, w_buf::WriterBuff};
 
 pub struct BufReadWriter<T: io::Write> {
     read_buf: ReaderBuff,
     write_buf: WriterBuff,
-    inner: T,
+    // `None` only after `inner()` has moved the stream out
+    inner: Option<T>,
 }
 
-/*
 impl<T: io::Write> Drop for BufReadWriter<T> {
     fn drop(&mut self) {
-        if !self.write_buf.buffer().is_empty() {
-            let _ = self.flush();
+        if self.inner.is_some() && !self.write_buf.buffer().is_empty() {
+            let _ = io::Write::flush(self);
         }
     }
 }
-*/
+
 impl<T: io::Write> BufReadWriter<T> {
     pub fn new(inner: T) -> Self {
         Self {
             read_buf: ReaderBuff::new(),
             write_buf: WriterBuff::new(),
-            inner,
+            inner: Some(inner),
         }
     }
     pub fn with_capacity(cap: usize, inner: T) -> Self {
         Self {
             read_buf: ReaderBuff::with_capacity(cap),
             write_buf: WriterBuff::with_capacity(cap),
-            inner,
+            inner: Some(inner),
         }
     }
     pub fn with_read_write_capacity(r: usize, w: usize, inner: T) -> Self {
         Self {
             read_buf: ReaderBuff::with_capacity(r),
             write_buf: WriterBuff::with_capacity(w),
-            inner,
+            inner: Some(inner),
         }
     }
     pub fn with_read_capacity(cap: usize, inner: T) -> Self {
         Self {
             read_buf: ReaderBuff::with_capacity(cap),
             write_buf: WriterBuff::new(),
-            inner,
+            inner: Some(inner),
         }
     }
     pub fn with_write_capacity(cap: usize, inner: T) -> Self {
         Self {
             read_buf: ReaderBuff::new(),
             write_buf: WriterBuff::with_capacity(cap),
-            inner,
+            inner: Some(inner),
         }
     }
     pub fn read_buffer(&self) -> &[u8] {
@@ -66,13 +66,16 @@ impl<T: io::Write> BufReadWriter<T> {
         self.write_buf.buffer_mut()
     }
     pub fn inner_ref(&self) -> &T {
-        &self.inner
+        self.inner.as_ref().expect("inner stream taken")
     }
-    pub fn inner(self) -> T {
-        self.inner
+    /// Unwraps this `BufReadWriter`, returning the underlying stream.
+    ///
+    /// Buffered data is discarded, call `flush` first to keep it.
+    pub fn inner(mut self) -> T {
+        self.inner.take().expect("inner stream taken")
     }
     pub fn inner_ref_mut(&mut self) -> &mut T {
-        &mut self.inner
+        self.inner.as_mut().expect("inner stream taken")
     }
 }
 
@@ -80,7 +83,7 @@ impl<T: io::Read + io::Write> io::Read for BufReadWriter<T> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if self.read_buf.pos == self.read_buf.filled && buf.len() >= self.read_buf.buf.len() {
             self.read_buf.discard_buffer();
-            return self.inner.read(buf);
+            return self.inner_ref_mut().read(buf);
         }
         let rem = std::io::BufRead::fill_buf(self)?;
         let amt = std::cmp::min(rem.len(), buf.len());
@@ -96,7 +99,8 @@ impl<T: io::Read + io::Write> io::Read for BufReadWriter<T> {
 
 impl<T: io::Read + io::Write> std::io::BufRead for BufReadWriter<T> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        self.read_buf.fill_buf(&mut self.inner)
+        let inner = self.inner.as_mut().expect("inner stream taken");
+        self.read_buf.fill_buf(inner)
     }
     fn consume(&mut self, amount: usize) {
         self.read_buf.consume(amount)
@@ -112,7 +116,8 @@ impl<T: io::Write> io::Write for BufReadWriter<T> {
 
             Ok(buf.len())
         } else {
-            self.write_buf.write_cold(buf, &mut self.inner)
+            let inner = self.inner.as_mut().expect("inner stream taken");
+            self.write_buf.write_cold(buf, inner)
         }
     }
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
@@ -123,12 +128,14 @@ impl<T: io::Write> io::Write for BufReadWriter<T> {
 
             Ok(())
         } else {
-            self.write_buf.write_all_cold(buf, &mut self.inner)
+            let inner = self.inner.as_mut().expect("inner stream taken");
+            self.write_buf.write_all_cold(buf, inner)
         }
     }
     fn flush(&mut self) -> io::Result<()> {
+        let inner = self.inner.as_mut().expect("inner stream taken");
         self.write_buf
-            .flush_buf(&mut self.inner)
-            .and_then(|()| self.inner.flush())
+            .flush_buf(&mut *inner)
+            .and_then(|()| inner.flush())
     }
 }
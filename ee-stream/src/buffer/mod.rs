@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 mod buffer;
 mod r_buf;
 mod w_buf;
@@ -55,7 +55,7 @@ fn test_buf_read_writer_read_1() {
     assert_eq!(reader.read_buffer(), b"o");
 
     assert_eq!(reader.read(&mut buf).unwrap(), 1);
-    assert_eq!(&buf[0..1], &*b"o");
+    assert_eq!(&buf[0..1], b"o");
     assert_eq!(reader.read_buffer(), b"");
 
     assert_eq!(reader.read(&mut buf).unwrap(), 2);
@@ -75,7 +75,7 @@ fn test_buf_read_writer_read_1() {
     assert_eq!(reader.read_buffer(), b"");
 
     assert_eq!(reader.read(&mut buf).unwrap(), 1);
-    assert_eq!(&buf[0..1], &*b"z");
+    assert_eq!(&buf[0..1], b"z");
     assert_eq!(reader.read_buffer(), b"");
 
     assert_eq!(reader.read(&mut buf).unwrap(), 0);
@@ -83,15 +83,6 @@ impl WriterBuff {
             self.buf.set_len(old_len + buf_len);
         }
     }
-    pub(super) fn write_to_buf(&mut self, buf: &[u8]) -> usize {
-        let available = self.spare_capacity();
-        let amt_to_buffer = available.min(buf.len());
-
-        unsafe {
-            self.write_to_buffer_unchecked(&buf[..amt_to_buffer]);
-        }
-        amt_to_buffer
-    }
     #[cold]
     #[inline(never)]
     pub(super) fn write_cold(
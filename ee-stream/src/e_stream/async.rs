@@ -0,0 +1,247 @@
+use std::{
+    io,
+    num::NonZero,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::e_stream::{
+    DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN, SessionKeys, TAG_LEN,
+    cipher::{FrameCipher, auth_error},
+};
+
+/// Async counterpart of [`EStreamSync`](crate::e_stream::EStreamSync).
+///
+/// Uses the same frames, keys and nonces, so one side can be sync and the
+/// other async.
+pub struct EStreamAsync<T: AsyncRead + AsyncWrite + Unpin> {
+    inner: T,
+    cipher: FrameCipher,
+    // frame being read: header, then ciphertext + tag
+    header: [u8; FRAME_HEADER_LEN],
+    header_filled: usize,
+    read_buff: Vec<u8>,
+    frame_filled: usize,
+    // plaintext of the last opened frame
+    read_pos: usize,
+    read_filled: usize,
+    // plaintext waiting to be sealed
+    write_buff: Vec<u8>,
+    // sealed frame waiting to be written to `inner`
+    out: Vec<u8>,
+    out_pos: usize,
+    max_frame_size: usize,
+    poisoned: bool,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> EStreamAsync<T> {
+    #[inline]
+    pub fn builder() -> EStreamBuilderAsync<T> {
+        EStreamBuilderAsync {
+            keys: None,
+            write_buf_size: NonZero::new(8 * 1024),
+            max_frame_size: NonZero::new(DEFAULT_MAX_FRAME_SIZE),
+            inner: None,
+        }
+    }
+    pub fn inner_ref(&self) -> &T {
+        &self.inner
+    }
+    pub fn inner_ref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+    fn poison(&mut self, err: io::Error) -> io::Error {
+        self.poisoned = true;
+        err
+    }
+    /// Read and open the next frame.
+    ///
+    /// Returns `false` on a clean end of stream.
+    fn poll_read_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<bool>> {
+        while self.header_filled < FRAME_HEADER_LEN {
+            let mut buf = ReadBuf::new(&mut self.header[self.header_filled..]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                if self.header_filled == 0 {
+                    return Poll::Ready(Ok(false));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.header_filled += n;
+            if self.header_filled == FRAME_HEADER_LEN {
+                let len = FrameCipher::frame_len(&self.header, self.max_frame_size)
+                    .map_err(|e| self.poison(e))?;
+                self.read_buff.resize(len, 0);
+                self.frame_filled = 0;
+            }
+        }
+        while self.frame_filled < self.read_buff.len() {
+            let mut buf = ReadBuf::new(&mut self.read_buff[self.frame_filled..]);
+            ready!(Pin::new(&mut self.inner).poll_read(cx, &mut buf))?;
+            let n = buf.filled().len();
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.frame_filled += n;
+        }
+        let data_len = self
+            .cipher
+            .open(&self.header, &mut self.read_buff)
+            .map_err(|e| self.poison(e))?;
+        self.header_filled = 0;
+        self.read_pos = 0;
+        self.read_filled = data_len;
+        Poll::Ready(Ok(true))
+    }
+    /// Seal the write buffer (if there is no sealed frame pending) and
+    /// write the sealed frame to `inner`.
+    fn poll_write_frame(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.out.is_empty() {
+            if self.write_buff.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let (header, tag) = self.cipher.seal(&mut self.write_buff)?;
+            self.out.extend_from_slice(&header);
+            self.out.extend_from_slice(&self.write_buff);
+            self.out.extend_from_slice(&tag);
+            self.write_buff.clear();
+            self.out_pos = 0;
+        }
+        while self.out_pos < self.out.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.out[self.out_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.out_pos += n;
+        }
+        self.out.clear();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for EStreamAsync<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let rem = ready!(Pin::new(&mut *this).poll_fill_buf(cx))?;
+        let amt = std::cmp::min(rem.len(), buf.remaining());
+        buf.put_slice(&rem[..amt]);
+        Pin::new(this).consume(amt);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncBufRead for EStreamAsync<T> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.poisoned {
+            return Poll::Ready(Err(auth_error()));
+        }
+        while this.read_pos >= this.read_filled {
+            if !ready!(this.poll_read_frame(cx))? {
+                return Poll::Ready(Ok(&[]));
+            }
+        }
+        Poll::Ready(Ok(&this.read_buff[this.read_pos..this.read_filled]))
+    }
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.read_pos = std::cmp::min(this.read_pos + amt, this.read_filled);
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for EStreamAsync<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.poisoned {
+            return Poll::Ready(Err(auth_error()));
+        }
+        if !this.out.is_empty() {
+            ready!(this.poll_write_frame(cx))?;
+        }
+        if this.write_buff.len() == this.write_buff.capacity() {
+            ready!(this.poll_write_frame(cx))?;
+        }
+        let r = std::cmp::min(buf.len(), this.write_buff.capacity() - this.write_buff.len());
+        this.write_buff.extend_from_slice(&buf[..r]);
+        Poll::Ready(Ok(r))
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.poisoned {
+            return Poll::Ready(Err(auth_error()));
+        }
+        // a pending frame goes out first, then whatever is left in the buffer
+        ready!(this.poll_write_frame(cx))?;
+        ready!(this.poll_write_frame(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+pub struct EStreamBuilderAsync<T: AsyncRead + AsyncWrite + Unpin> {
+    keys: Option<SessionKeys>,
+    write_buf_size: Option<NonZero<usize>>,
+    max_frame_size: Option<NonZero<usize>>,
+    inner: Option<T>,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> EStreamBuilderAsync<T> {
+    pub fn keys(mut self, keys: SessionKeys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+    pub fn inner(mut self, v: T) -> Self {
+        self.inner = Some(v);
+        self
+    }
+    /// Largest plaintext carried by a single frame.
+    pub fn write_buffer_size(mut self, size: usize) -> Self {
+        self.write_buf_size = NonZero::new(size);
+        self
+    }
+    /// Largest frame (ciphertext + tag) accepted from the peer.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = NonZero::new(size);
+        self
+    }
+    pub fn build(self) -> Option<EStreamAsync<T>> {
+        let write_buffer_size = self.write_buf_size?.get();
+        let max_frame_size = self.max_frame_size?.get();
+        if write_buffer_size + TAG_LEN > max_frame_size {
+            return None;
+        }
+        let keys = self.keys?;
+        Some(EStreamAsync {
+            inner: self.inner?,
+            cipher: FrameCipher::new(&keys),
+            header: [0; FRAME_HEADER_LEN],
+            header_filled: 0,
+            read_buff: Vec::new(),
+            frame_filled: 0,
+            read_pos: 0,
+            read_filled: 0,
+            write_buff: Vec::with_capacity(write_buffer_size),
+            out: Vec::with_capacity(write_buffer_size + TAG_LEN + FRAME_HEADER_LEN),
+            out_pos: 0,
+            max_frame_size,
+            poisoned: false,
+        })
+    }
+}
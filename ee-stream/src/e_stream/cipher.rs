@@ -0,0 +1,100 @@
+use std::io;
+
+use aes_gcm::{AeadInPlace, Aes256Gcm, KeyInit, Nonce, Tag};
+
+use crate::e_stream::SessionKeys;
+
+/// Size of the authentication tag appended to every frame.
+pub const TAG_LEN: usize = 16;
+
+/// Size of the big-endian length prefix in front of every frame.
+pub const FRAME_HEADER_LEN: usize = 4;
+
+/// Name of the key exchange and frame cipher, advertised to the peer.
+pub const CIPHER_SUITE: &str = "x25519-hkdf-sha256-aes-256-gcm";
+
+/// Default upper bound of a single frame (ciphertext + tag).
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// Frame sealing and opening shared by the sync and async streams.
+pub(crate) struct FrameCipher {
+    read_cipher: Aes256Gcm,
+    write_cipher: Aes256Gcm,
+    read_nonce: u64,
+    write_nonce: u64,
+}
+
+fn nonce(counter: u64) -> Nonce<<Aes256Gcm as aes_gcm::AeadCore>::NonceSize> {
+    let mut v = [0u8; 12];
+    v[4..].copy_from_slice(&counter.to_be_bytes());
+    v.into()
+}
+
+fn next(counter: &mut u64) -> io::Result<u64> {
+    let n = *counter;
+    *counter = n
+        .checked_add(1)
+        .ok_or_else(|| io::Error::other("nonce exhausted, start a new session"))?;
+    Ok(n)
+}
+
+pub(crate) fn auth_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "frame authentication failed")
+}
+
+impl FrameCipher {
+    pub(crate) fn new(keys: &SessionKeys) -> Self {
+        Self {
+            read_cipher: Aes256Gcm::new(&keys.read.into()),
+            write_cipher: Aes256Gcm::new(&keys.write.into()),
+            read_nonce: 0,
+            write_nonce: 0,
+        }
+    }
+    /// Encrypt `data` in place and return the frame header and tag.
+    pub(crate) fn seal(
+        &mut self,
+        data: &mut [u8],
+    ) -> io::Result<([u8; FRAME_HEADER_LEN], [u8; TAG_LEN])> {
+        let n = nonce(next(&mut self.write_nonce)?);
+        let header = ((data.len() + TAG_LEN) as u32).to_be_bytes();
+        let tag = self
+            .write_cipher
+            .encrypt_in_place_detached(&n, &header, data)
+            .map_err(|_| io::Error::other("failed to encrypt frame"))?;
+        Ok((header, tag.into()))
+    }
+    /// Length of the frame announced by `header`.
+    pub(crate) fn frame_len(
+        header: &[u8; FRAME_HEADER_LEN],
+        max_frame_size: usize,
+    ) -> io::Result<usize> {
+        let len = u32::from_be_bytes(*header) as usize;
+        if len < TAG_LEN {
+            return Err(auth_error());
+        }
+        if len > max_frame_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame exceeds the maximum frame size",
+            ));
+        }
+        Ok(len)
+    }
+    /// Decrypt `frame` (ciphertext + tag) in place and return the length
+    /// of the plaintext at its start.
+    pub(crate) fn open(
+        &mut self,
+        header: &[u8; FRAME_HEADER_LEN],
+        frame: &mut [u8],
+    ) -> io::Result<usize> {
+        let data_len = frame.len() - TAG_LEN;
+        let tag = Tag::clone_from_slice(&frame[data_len..]);
+        let n = nonce(self.read_nonce);
+        self.read_cipher
+            .decrypt_in_place_detached(&n, header, &mut frame[..data_len], &tag)
+            .map_err(|_| auth_error())?;
+        next(&mut self.read_nonce)?;
+        Ok(data_len)
+    }
+}
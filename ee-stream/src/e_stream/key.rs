@@ -0,0 +1,61 @@
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+const INITIATOR_TO_RESPONDER: &[u8] = b"eagle-eye e-stream initiator->responder";
+const RESPONDER_TO_INITIATOR: &[u8] = b"eagle-eye e-stream responder->initiator";
+
+/// Which end of the connection a [`SessionKeys`] belongs to.
+///
+/// The side that starts the conversation (ee-sender) is the initiator,
+/// the side that answers (ee-receiver) is the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+/// Per direction AES-256-GCM keys of a single session.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    pub(crate) read: [u8; 32],
+    pub(crate) write: [u8; 32],
+}
+
+impl std::fmt::Debug for SessionKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKeys { .. }")
+    }
+}
+
+impl SessionKeys {
+    pub fn new(read: [u8; 32], write: [u8; 32]) -> Self {
+        Self { read, write }
+    }
+    /// Derive both direction keys from `secret` with HKDF-SHA256.
+    ///
+    /// `salt` must be unique per session, otherwise both peers end up
+    /// with the same keys (and nonce counters) as an earlier session.
+    pub fn derive(secret: &[u8], salt: &[u8], role: Role) -> Self {
+        let hk = Hkdf::<Sha256>::new(Some(salt), secret);
+        let mut i2r = [0u8; 32];
+        let mut r2i = [0u8; 32];
+        hk.expand(INITIATOR_TO_RESPONDER, &mut i2r).unwrap();
+        hk.expand(RESPONDER_TO_INITIATOR, &mut r2i).unwrap();
+        match role {
+            Role::Initiator => Self {
+                read: r2i,
+                write: i2r,
+            },
+            Role::Responder => Self {
+                read: i2r,
+                write: r2i,
+            },
+        }
+    }
+    pub fn read_key(&self) -> &[u8; 32] {
+        &self.read
+    }
+    pub fn write_key(&self) -> &[u8; 32] {
+        &self.write
+    }
+}
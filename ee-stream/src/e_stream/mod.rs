@@ -1,3 +1,14 @@
+mod cipher;
+mod key;
+#[cfg(feature = "async")]
+mod r#async;
 mod sync;
 
+pub use cipher::{CIPHER_SUITE, DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN, TAG_LEN};
+pub use key::{Role, SessionKeys};
+#[cfg(feature = "async")]
+pub use r#async::{EStreamAsync, EStreamBuilderAsync};
 pub use sync::{EStreamBuilderSync, EStreamSync};
+
+#[cfg(test)]
+mod test;
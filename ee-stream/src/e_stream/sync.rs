@@ -1,45 +1,127 @@
-use std::{io, num::NonZero};
+use std::{
+    io::{self, Read, Write},
+    num::NonZero,
+};
 
-use aes::cipher::StreamCipher;
-
-use crate::buffer::BufReadWriter;
+use crate::{
+    buffer::BufReadWriter,
+    e_stream::{
+        DEFAULT_MAX_FRAME_SIZE, FRAME_HEADER_LEN, SessionKeys, TAG_LEN,
+        cipher::{FrameCipher, auth_error},
+    },
+};
 
+/// Encrypted and authenticated stream.
+///
+/// Data is sent as frames of `<len: u32><ciphertext><tag>`, sealed with
+/// AES-256-GCM. Each direction has its own key and its own nonce counter,
+/// so a frame that is modified, dropped, reordered or replayed fails to
+/// open and the read returns [`io::ErrorKind::InvalidData`]. After such an
+/// error the stream is unusable.
 pub struct EStreamSync<T: io::Read + io::Write> {
     inner: BufReadWriter<T>,
-    read_cipher: ctr::Ctr64LE<aes::Aes256>,
-    write_cipher: ctr::Ctr64LE<aes::Aes256>,
-    write_buff: Box<[u8]>,
+    cipher: FrameCipher,
+    read_buff: Vec<u8>,
+    read_pos: usize,
+    read_filled: usize,
+    write_buff: Vec<u8>,
+    max_frame_size: usize,
+    poisoned: bool,
 }
 
-// TODO: remove me
-#[cfg(debug_assertions)]
-impl<T: io::Read + io::Write> Drop for EStreamSync<T> {
-    fn drop(&mut self) {
-        println!("....EStream Droped......");
+impl<T: io::Read + io::Write> EStreamSync<T> {
+    #[inline]
+    pub fn builder() -> EStreamBuilderSync<T> {
+        EStreamBuilderSync {
+            keys: None,
+            read_buf_size: NonZero::new(8 * 1024),
+            write_buf_size: NonZero::new(8 * 1024),
+            max_frame_size: NonZero::new(DEFAULT_MAX_FRAME_SIZE),
+            inner: None,
+        }
+    }
+    pub fn inner_ref(&self) -> &T {
+        self.inner.inner_ref()
+    }
+    pub fn inner_ref_mut(&mut self) -> &mut T {
+        self.inner.inner_ref_mut()
+    }
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+    /// Largest plaintext sent in a single frame.
+    pub fn write_buffer_size(&self) -> usize {
+        self.write_buff.capacity()
+    }
+    /// Seal everything in the write buffer as one frame.
+    fn write_frame(&mut self) -> io::Result<()> {
+        if self.write_buff.is_empty() {
+            return Ok(());
+        }
+        let (header, tag) = self.cipher.seal(&mut self.write_buff)?;
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&self.write_buff)?;
+        self.inner.write_all(&tag)?;
+        self.write_buff.clear();
+        Ok(())
+    }
+    /// Read and open the next frame.
+    ///
+    /// Returns `false` on a clean end of stream.
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        let n = self.inner.read(&mut header)?;
+        if n == 0 {
+            return Ok(false);
+        }
+        self.inner.read_exact(&mut header[n..])?;
+        let frame_len = FrameCipher::frame_len(&header, self.max_frame_size).inspect_err(|_| {
+            self.poisoned = true;
+        })?;
+        self.read_buff.resize(frame_len, 0);
+        self.inner.read_exact(&mut self.read_buff)?;
+        let data_len = self
+            .cipher
+            .open(&header, &mut self.read_buff)
+            .inspect_err(|_| {
+                self.poisoned = true;
+            })?;
+        self.read_pos = 0;
+        self.read_filled = data_len;
+        Ok(true)
     }
 }
 
 impl<T: io::Read + io::Write> io::Read for EStreamSync<T> {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let n = self.inner.read(buf)?;
-        self.read_cipher.apply_keystream(&mut buf[0..n]);
-        Ok(n)
+        let rem = io::BufRead::fill_buf(self)?;
+        let amt = std::cmp::min(rem.len(), buf.len());
+        buf[..amt].copy_from_slice(&rem[..amt]);
+        io::BufRead::consume(self, amt);
+        Ok(amt)
     }
 }
 
 impl<T: io::Read + io::Write> io::Write for EStreamSync<T> {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let r = std::cmp::min(buf.len(), self.write_buff.len());
-        let src = unsafe { buf.get_unchecked(0..r) };
-        let dest = unsafe { self.write_buff.get_unchecked_mut(0..r) };
-        self.write_cipher.apply_keystream_b2b(src, dest).unwrap();
-        self.inner.write_all(dest)?;
+        if self.poisoned {
+            return Err(auth_error());
+        }
+        if self.write_buff.len() == self.write_buff.capacity() {
+            self.write_frame()?;
+        }
+        let r = std::cmp::min(buf.len(), self.write_buff.capacity() - self.write_buff.len());
+        self.write_buff.extend_from_slice(&buf[..r]);
         Ok(r)
     }
     #[inline]
     fn flush(&mut self) -> io::Result<()> {
+        if self.poisoned {
+            return Err(auth_error());
+        }
+        self.write_frame()?;
         self.inner.flush()
     }
 }
@@ -47,36 +129,33 @@ impl<T: io::Read + io::Write> io::Write for EStreamSync<T> {
 impl<T: io::Read + io::Write> io::BufRead for EStreamSync<T> {
     #[inline]
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        self.inner.fill_buf()
+        if self.poisoned {
+            return Err(auth_error());
+        }
+        while self.read_pos >= self.read_filled {
+            if !self.read_frame()? {
+                return Ok(&[]);
+            }
+        }
+        Ok(&self.read_buff[self.read_pos..self.read_filled])
     }
     #[inline]
     fn consume(&mut self, amt: usize) {
-        self.inner.consume(amt);
-    }
-}
-
-impl<T: io::Read + io::Write> EStreamSync<T> {
-    #[inline]
-    pub fn builder() -> EStreamBuilderSync<T> {
-        EStreamBuilderSync {
-            cipher: None,
-            read_buf_size: NonZero::new(8 * 1024),
-            write_buf_size: NonZero::new(8 * 1024),
-            inner: None,
-        }
+        self.read_pos = std::cmp::min(self.read_pos + amt, self.read_filled);
     }
 }
 
 pub struct EStreamBuilderSync<T: io::Read + io::Write> {
-    cipher: Option<ctr::Ctr64LE<aes::Aes256>>,
+    keys: Option<SessionKeys>,
     read_buf_size: Option<NonZero<usize>>,
     write_buf_size: Option<NonZero<usize>>,
+    max_frame_size: Option<NonZero<usize>>,
     inner: Option<T>,
 }
 
 impl<T: io::Read + io::Write> EStreamBuilderSync<T> {
-    pub fn cipher(mut self, cipher: ctr::Ctr64LE<aes::Aes256>) -> Self {
-        self.cipher = Some(cipher);
+    pub fn keys(mut self, keys: SessionKeys) -> Self {
+        self.keys = Some(keys);
         self
     }
     pub fn inner(mut self, v: T) -> Self {
@@ -87,31 +166,44 @@ impl<T: io::Read + io::Write> EStreamBuilderSync<T> {
         self.read_buf_size = NonZero::new(size);
         self
     }
+    /// Largest plaintext carried by a single frame.
     pub fn write_buffer_size(mut self, size: usize) -> Self {
         self.write_buf_size = NonZero::new(size);
         self
     }
+    /// Largest frame (ciphertext + tag) accepted from the peer.
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = NonZero::new(size);
+        self
+    }
     pub fn build(self) -> Option<EStreamSync<T>> {
         let Self {
-            cipher,
+            keys,
             read_buf_size,
             write_buf_size,
+            max_frame_size,
             inner,
         } = self;
         let read_buffer_size = read_buf_size?;
         let write_buffer_size = write_buf_size?;
-        let v = Box::<[u8]>::new_uninit_slice(write_buffer_size.get());
-        let buffer = unsafe { v.assume_init() };
-        let cipher = cipher?;
+        let max_frame_size = max_frame_size?.get();
+        if write_buffer_size.get() + TAG_LEN > max_frame_size {
+            return None;
+        }
+        let keys = keys?;
         Some(EStreamSync {
             inner: BufReadWriter::with_read_write_capacity(
                 read_buffer_size.get(),
-                write_buffer_size.get(),
+                write_buffer_size.get() + TAG_LEN + FRAME_HEADER_LEN,
                 inner?,
             ),
-            read_cipher: cipher.clone(),
-            write_cipher: cipher,
-            write_buff: buffer,
+            cipher: FrameCipher::new(&keys),
+            read_buff: Vec::new(),
+            read_pos: 0,
+            read_filled: 0,
+            write_buff: Vec::with_capacity(write_buffer_size.get()),
+            max_frame_size,
+            poisoned: false,
         })
     }
 }
@@ -0,0 +1,243 @@
+use std::io::{self, BufRead, Read, Write};
+
+use ee_test_util::Pipe;
+
+use crate::e_stream::{EStreamSync, FRAME_HEADER_LEN, Role, SessionKeys};
+
+const SECRET: [u8; 32] = [7; 32];
+const SALT: &[u8] = b"test-session";
+
+fn stream(role: Role, input: Vec<u8>, write_size: usize) -> EStreamSync<Pipe> {
+    EStreamSync::builder()
+        .keys(SessionKeys::derive(&SECRET, SALT, role))
+        .write_buffer_size(write_size)
+        .inner(Pipe::new(input))
+        .build()
+        .unwrap()
+}
+
+/// Everything `f` writes through an initiator stream, as it is on the wire.
+fn seal(write_size: usize, f: impl FnOnce(&mut EStreamSync<Pipe>)) -> Vec<u8> {
+    let mut s = stream(Role::Initiator, Vec::new(), write_size);
+    f(&mut s);
+    s.flush().unwrap();
+    std::mem::take(&mut s.inner_ref_mut().output)
+}
+
+/// Split raw wire data into frames (header included).
+fn frames(mut wire: &[u8]) -> Vec<Vec<u8>> {
+    let mut v = Vec::new();
+    while !wire.is_empty() {
+        let len = u32::from_be_bytes(wire[..FRAME_HEADER_LEN].try_into().unwrap()) as usize;
+        v.push(wire[..FRAME_HEADER_LEN + len].to_vec());
+        wire = &wire[FRAME_HEADER_LEN + len..];
+    }
+    v
+}
+
+#[test]
+fn test_e_stream_round_trip() {
+    let wire = seal(8, |s| {
+        s.write_all(b"hello world, this is eagle eye").unwrap();
+    });
+    assert_eq!(frames(&wire).len(), 4);
+    assert!(!wire.windows(5).any(|v| v == b"hello"));
+
+    let mut r = stream(Role::Responder, wire, 8);
+    let mut got = String::new();
+    r.read_to_string(&mut got).unwrap();
+    assert_eq!(got, "hello world, this is eagle eye");
+}
+
+#[test]
+fn test_e_stream_buf_read_is_plaintext() {
+    let wire = seal(1024, |s| {
+        s.write_all(b"line one\nline two\n").unwrap();
+    });
+    let mut r = stream(Role::Responder, wire, 1024);
+    assert_eq!(r.fill_buf().unwrap(), b"line one\nline two\n");
+    let mut line = String::new();
+    r.read_line(&mut line).unwrap();
+    assert_eq!(line, "line one\n");
+    line.clear();
+    r.read_line(&mut line).unwrap();
+    assert_eq!(line, "line two\n");
+    assert_eq!(r.fill_buf().unwrap(), b"");
+}
+
+#[test]
+fn test_e_stream_direction_keys_differ() {
+    let i = SessionKeys::derive(&SECRET, SALT, Role::Initiator);
+    let r = SessionKeys::derive(&SECRET, SALT, Role::Responder);
+    assert_ne!(i.write_key(), i.read_key());
+    assert_eq!(i.write_key(), r.read_key());
+    assert_eq!(i.read_key(), r.write_key());
+
+    let other = SessionKeys::derive(&SECRET, b"other-session", Role::Initiator);
+    assert_ne!(i.write_key(), other.write_key());
+
+    // the same plaintext written in both directions gives different frames
+    let mut a = stream(Role::Initiator, Vec::new(), 64);
+    let mut b = stream(Role::Responder, Vec::new(), 64);
+    a.write_all(b"same data").unwrap();
+    b.write_all(b"same data").unwrap();
+    a.flush().unwrap();
+    b.flush().unwrap();
+    assert_ne!(a.inner_ref().output, b.inner_ref().output);
+}
+
+#[test]
+fn test_e_stream_wrong_direction_rejected() {
+    // initiator can not read its own frames back
+    let wire = seal(64, |s| s.write_all(b"hello").unwrap());
+    let mut s = stream(Role::Initiator, wire, 64);
+    let err = s.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_e_stream_tampered_frame_rejected() {
+    let mut wire = seal(64, |s| s.write_all(b"remove-file /tmp/a").unwrap());
+    wire[FRAME_HEADER_LEN + 3] ^= 1;
+    let mut r = stream(Role::Responder, wire, 64);
+    let err = r.read(&mut [0; 64]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // the stream stays unusable
+    assert_eq!(
+        r.read(&mut [0; 64]).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(r.write_all(b"x").is_err());
+}
+
+#[test]
+fn test_e_stream_tampered_length_rejected() {
+    let mut wire = seal(64, |s| s.write_all(b"hello world").unwrap());
+    wire.push(0);
+    wire[FRAME_HEADER_LEN - 1] += 1;
+    let mut r = stream(Role::Responder, wire, 64);
+    let err = r.read(&mut [0; 64]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_e_stream_reordered_frames_rejected() {
+    let wire = seal(4, |s| s.write_all(b"aaaabbbb").unwrap());
+    let v = frames(&wire);
+    assert_eq!(v.len(), 2);
+    let reordered = [v[1].clone(), v[0].clone()].concat();
+    let mut r = stream(Role::Responder, reordered, 4);
+    let err = r.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_e_stream_replayed_frame_rejected() {
+    let wire = seal(4, |s| s.write_all(b"aaaa").unwrap());
+    let replayed = [wire.clone(), wire].concat();
+    let mut r = stream(Role::Responder, replayed, 4);
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"aaaa");
+    let err = r.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_e_stream_oversized_frame_rejected() {
+    let wire = seal(1024, |s| s.write_all(&[1; 1024]).unwrap());
+    let mut r = EStreamSync::builder()
+        .keys(SessionKeys::derive(&SECRET, SALT, Role::Responder))
+        .write_buffer_size(64)
+        .max_frame_size(128)
+        .inner(Pipe::new(wire))
+        .build()
+        .unwrap();
+    let err = r.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // the stream is poisoned, nothing more is read or written
+    let err = r.read(&mut [0; 8]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(r.write_all(b"x").and_then(|()| r.flush()).is_err());
+}
+
+#[test]
+fn test_e_stream_truncated_frame() {
+    let mut wire = seal(64, |s| s.write_all(b"hello world").unwrap());
+    wire.pop();
+    let mut r = stream(Role::Responder, wire, 64);
+    let err = r.read(&mut [0; 64]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[cfg(feature = "async")]
+mod r#async {
+    use std::io::{self, Read};
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    use super::{Pipe, SALT, SECRET, seal};
+    use crate::e_stream::{EStreamAsync, FRAME_HEADER_LEN, Role, SessionKeys};
+
+    fn stream<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+        role: Role,
+        inner: T,
+        write_size: usize,
+    ) -> EStreamAsync<T> {
+        EStreamAsync::builder()
+            .keys(SessionKeys::derive(&SECRET, SALT, role))
+            .write_buffer_size(write_size)
+            .inner(inner)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_e_stream_async_reads_sync_frames() {
+        let wire = seal(8, |s| {
+            io::Write::write_all(s, b"line one\nline two\n").unwrap();
+        });
+        let (mut a, b) = tokio::io::duplex(3);
+        let t = tokio::spawn(async move {
+            a.write_all(&wire).await.unwrap();
+        });
+        let mut r = stream(Role::Responder, b, 8);
+        let mut line = String::new();
+        r.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "line one\n");
+        let mut rest = String::new();
+        r.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "line two\n");
+        t.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_e_stream_async_writes_sync_frames() {
+        let (a, mut b) = tokio::io::duplex(5);
+        let t = tokio::spawn(async move {
+            let mut w = stream(Role::Initiator, a, 4);
+            w.write_all(b"hello world").await.unwrap();
+            w.shutdown().await.unwrap();
+        });
+        let mut wire = Vec::new();
+        b.read_to_end(&mut wire).await.unwrap();
+        t.await.unwrap();
+        // 4 + 4 + 3 bytes of plaintext
+        assert_eq!(wire.len(), 3 * (FRAME_HEADER_LEN + 16) + 11);
+
+        let mut r = super::stream(Role::Responder, wire, 4);
+        let mut got = String::new();
+        r.read_to_string(&mut got).unwrap();
+        assert_eq!(got, "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_e_stream_async_tampered_frame_rejected() {
+        let mut wire = seal(64, |s| io::Write::write_all(s, b"hello").unwrap());
+        wire[FRAME_HEADER_LEN] ^= 1;
+        let mut r = stream(Role::Responder, Pipe::new(wire), 64);
+        let err = r.read(&mut [0; 8]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(r.write_all(b"x").await.is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    e_stream::{Role, SessionKeys},
+    handshake::{
+        ACCEPTED, Ephemeral, MAC_LEN, PUBLIC_KEY_LEN, REJECTED, Transcript, auth_error,
+    },
+};
+
+/// Async version of [`initiate_sync`](crate::handshake::initiate_sync).
+pub async fn initiate_async<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    psk: &[u8; 32],
+) -> io::Result<SessionKeys> {
+    let own = Ephemeral::generate();
+    stream.write_all(&own.public()).await?;
+    stream.flush().await?;
+    let mut peer = [0u8; PUBLIC_KEY_LEN];
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut peer).await?;
+    stream.read_exact(&mut tag).await?;
+    let transcript = Transcript::new(psk, own, peer, Role::Initiator)?;
+    if !transcript.verify(Role::Responder, &tag) {
+        return Err(auth_error("peer failed to prove the device key"));
+    }
+    stream.write_all(&transcript.confirm(Role::Initiator)).await?;
+    stream.flush().await?;
+    let mut status = [0u8; 3];
+    stream.read_exact(&mut status).await?;
+    if &status != ACCEPTED {
+        return Err(auth_error("Wrong Password"));
+    }
+    Ok(transcript.session_keys(Role::Initiator))
+}
+
+/// Async version of [`respond_sync`](crate::handshake::respond_sync).
+pub async fn respond_async<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    psk: &[u8; 32],
+) -> io::Result<SessionKeys> {
+    let own = Ephemeral::generate();
+    let public = own.public();
+    let mut peer = [0u8; PUBLIC_KEY_LEN];
+    stream.read_exact(&mut peer).await?;
+    let transcript = Transcript::new(psk, own, peer, Role::Responder)?;
+    stream.write_all(&public).await?;
+    stream
+        .write_all(&transcript.confirm(Role::Responder))
+        .await?;
+    stream.flush().await?;
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut tag).await?;
+    if !transcript.verify(Role::Initiator, &tag) {
+        stream.write_all(REJECTED).await?;
+        stream.flush().await?;
+        return Err(auth_error("Wrong Password"));
+    }
+    stream.write_all(ACCEPTED).await?;
+    stream.flush().await?;
+    Ok(transcript.session_keys(Role::Responder))
+}
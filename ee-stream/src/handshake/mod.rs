@@ -0,0 +1,135 @@
+//! Ephemeral X25519 handshake authenticated by the pre-shared device key.
+//!
+//! ```text
+//! initiator                                  responder
+//!     <e_i: 32>                  ------->
+//!                                <-------    <e_r: 32><mac_r: 32>
+//!     <mac_i: 32>                ------->
+//!                                <-------    :0: | :1:
+//! ```
+//!
+//! Both sides compute `dh = X25519(e, E)` and run HKDF-SHA256 with the
+//! pre-shared key as salt. The pre-shared key only proves that the peer is
+//! paired (`mac_r`, `mac_i`); the session keys come from the ephemeral
+//! `dh`, so a recorded session can not be decrypted by stealing the device
+//! key afterwards.
+
+#[cfg(feature = "async")]
+mod r#async;
+mod sync;
+
+use std::io;
+
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::e_stream::{Role, SessionKeys};
+
+#[cfg(feature = "async")]
+pub use r#async::{initiate_async, respond_async};
+pub use sync::{initiate_sync, respond_sync};
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const MAC_LEN: usize = 32;
+
+const PROTOCOL: &[u8] = b"eagle-eye handshake v1";
+
+pub(crate) const ACCEPTED: &[u8; 3] = b":0:";
+pub(crate) const REJECTED: &[u8; 3] = b":1:";
+
+/// Ephemeral key pair, used for exactly one handshake.
+pub(crate) struct Ephemeral {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl Ephemeral {
+    pub(crate) fn generate() -> Self {
+        let secret = StaticSecret::from(rand::random::<[u8; 32]>());
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+    pub(crate) fn public(&self) -> [u8; PUBLIC_KEY_LEN] {
+        self.public.to_bytes()
+    }
+}
+
+/// Key material of a handshake after the Diffie-Hellman step.
+pub(crate) struct Transcript {
+    hash: [u8; 32],
+    confirm_initiator: [u8; 32],
+    confirm_responder: [u8; 32],
+    session: [u8; 32],
+}
+
+impl Transcript {
+    pub(crate) fn new(
+        psk: &[u8; 32],
+        own: Ephemeral,
+        peer: [u8; PUBLIC_KEY_LEN],
+        role: Role,
+    ) -> io::Result<Self> {
+        let (e_i, e_r) = match role {
+            Role::Initiator => (own.public(), peer),
+            Role::Responder => (peer, own.public()),
+        };
+        let dh = own.secret.diffie_hellman(&PublicKey::from(peer));
+        if !dh.was_contributory() {
+            return Err(auth_error("invalid ephemeral key"));
+        }
+        let hash: [u8; 32] = Sha256::new()
+            .chain_update(PROTOCOL)
+            .chain_update(e_i)
+            .chain_update(e_r)
+            .finalize()
+            .into();
+        Ok(Self::derive(psk, dh.as_bytes(), hash))
+    }
+    /// Expand the shared secret `ikm` into confirmation and session keys,
+    /// all bound to the transcript `hash`.
+    pub(crate) fn derive(salt: &[u8], ikm: &[u8], hash: [u8; 32]) -> Self {
+        let hk = Hkdf::<Sha256>::new(Some(salt), ikm);
+        let mut v = Self {
+            hash,
+            confirm_initiator: [0; 32],
+            confirm_responder: [0; 32],
+            session: [0; 32],
+        };
+        hk.expand_multi_info(&[b"confirm initiator", &hash], &mut v.confirm_initiator)
+            .unwrap();
+        hk.expand_multi_info(&[b"confirm responder", &hash], &mut v.confirm_responder)
+            .unwrap();
+        hk.expand_multi_info(&[b"session", &hash], &mut v.session)
+            .unwrap();
+        v
+    }
+    fn mac(&self, role: Role) -> Hmac<Sha256> {
+        let key = match role {
+            Role::Initiator => &self.confirm_initiator,
+            Role::Responder => &self.confirm_responder,
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(&self.hash);
+        mac
+    }
+    /// Proof that `role` knows the pre-shared key.
+    pub(crate) fn confirm(&self, role: Role) -> [u8; MAC_LEN] {
+        self.mac(role).finalize().into_bytes().into()
+    }
+    /// Check the proof sent by `role` (in constant time).
+    pub(crate) fn verify(&self, role: Role, tag: &[u8; MAC_LEN]) -> bool {
+        self.mac(role).verify_slice(tag).is_ok()
+    }
+    pub(crate) fn session_keys(&self, role: Role) -> SessionKeys {
+        SessionKeys::derive(&self.session, &self.hash, role)
+    }
+}
+
+pub(crate) fn auth_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, msg)
+}
+
+#[cfg(test)]
+mod test;
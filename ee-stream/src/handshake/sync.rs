@@ -0,0 +1,65 @@
+use std::io;
+
+use crate::{
+    e_stream::{Role, SessionKeys},
+    handshake::{
+        ACCEPTED, Ephemeral, MAC_LEN, PUBLIC_KEY_LEN, REJECTED, Transcript, auth_error,
+    },
+};
+
+/// Run the handshake as the side that speaks first (ee-sender).
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the peer does not
+/// know `psk`.
+pub fn initiate_sync<S: io::Read + io::Write>(
+    mut stream: S,
+    psk: &[u8; 32],
+) -> io::Result<SessionKeys> {
+    let own = Ephemeral::generate();
+    stream.write_all(&own.public())?;
+    stream.flush()?;
+    let mut peer = [0u8; PUBLIC_KEY_LEN];
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut peer)?;
+    stream.read_exact(&mut tag)?;
+    let transcript = Transcript::new(psk, own, peer, Role::Initiator)?;
+    if !transcript.verify(Role::Responder, &tag) {
+        return Err(auth_error("peer failed to prove the device key"));
+    }
+    stream.write_all(&transcript.confirm(Role::Initiator))?;
+    stream.flush()?;
+    let mut status = [0u8; 3];
+    stream.read_exact(&mut status)?;
+    if &status != ACCEPTED {
+        return Err(auth_error("Wrong Password"));
+    }
+    Ok(transcript.session_keys(Role::Initiator))
+}
+
+/// Run the handshake as the side that answers (ee-receiver).
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the peer does not
+/// know `psk`.
+pub fn respond_sync<S: io::Read + io::Write>(
+    mut stream: S,
+    psk: &[u8; 32],
+) -> io::Result<SessionKeys> {
+    let own = Ephemeral::generate();
+    let public = own.public();
+    let mut peer = [0u8; PUBLIC_KEY_LEN];
+    stream.read_exact(&mut peer)?;
+    let transcript = Transcript::new(psk, own, peer, Role::Responder)?;
+    stream.write_all(&public)?;
+    stream.write_all(&transcript.confirm(Role::Responder))?;
+    stream.flush()?;
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut tag)?;
+    if !transcript.verify(Role::Initiator, &tag) {
+        stream.write_all(REJECTED)?;
+        stream.flush()?;
+        return Err(auth_error("Wrong Password"));
+    }
+    stream.write_all(ACCEPTED)?;
+    stream.flush()?;
+    Ok(transcript.session_keys(Role::Responder))
+}
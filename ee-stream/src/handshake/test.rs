@@ -0,0 +1,133 @@
+use std::{
+    io::{self, Read, Write},
+    thread,
+};
+
+use crate::{
+    e_stream::{EStreamSync, SessionKeys},
+    handshake::{initiate_sync, respond_sync},
+    test_util::duplex,
+};
+
+/// Result of one side of a handshake and the bytes it wrote.
+type Side = (io::Result<SessionKeys>, Vec<u8>);
+
+/// Run both sides of the handshake.
+fn run(initiator_key: [u8; 32], responder_key: [u8; 32]) -> (Side, Side) {
+    let (mut a, mut b) = duplex();
+    let t = thread::spawn(move || {
+        let r = respond_sync(&mut b, &responder_key);
+        (r, b.sent)
+    });
+    let r = initiate_sync(&mut a, &initiator_key);
+    let sent = std::mem::take(&mut a.sent);
+    // unblock the responder if the initiator gave up
+    drop(a);
+    ((r, sent), t.join().unwrap())
+}
+
+#[test]
+fn test_handshake_same_key() {
+    let (mut a, mut b) = duplex();
+    let t = thread::spawn(move || respond_sync(&mut b, &[3; 32]).map(|k| (k, b)));
+    let ka = initiate_sync(&mut a, &[3; 32]).unwrap();
+    let (kb, b) = t.join().unwrap().unwrap();
+    assert_eq!(ka.write_key(), kb.read_key());
+    assert_eq!(ka.read_key(), kb.write_key());
+    assert_ne!(ka.write_key(), ka.read_key());
+
+    // the session keys work end to end
+    let mut a = EStreamSync::builder().keys(ka).inner(a).build().unwrap();
+    let mut b = EStreamSync::builder().keys(kb).inner(b).build().unwrap();
+    a.write_all(b"ping").unwrap();
+    a.flush().unwrap();
+    let mut buf = [0; 4];
+    b.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+    b.write_all(b"pong").unwrap();
+    b.flush().unwrap();
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+}
+
+#[test]
+fn test_handshake_fresh_keys_per_session() {
+    let ((k1, _), _) = run([3; 32], [3; 32]);
+    let ((k2, _), _) = run([3; 32], [3; 32]);
+    assert_ne!(k1.unwrap(), k2.unwrap());
+}
+
+#[test]
+fn test_handshake_wrong_key() {
+    let ((ka, _), (kb, _)) = run([3; 32], [4; 32]);
+    assert_eq!(ka.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    // the initiator stops before sending its proof, so the responder sees EOF
+    assert!(kb.is_err());
+}
+
+#[test]
+fn test_handshake_device_key_never_sent() {
+    let key = [0x5a; 32];
+    let ((_, a), (_, b)) = run(key, key);
+    for sent in [&a, &b] {
+        assert!(!sent.windows(32).any(|v| v == key));
+    }
+    // e_i, mac_i and e_r, mac_r, status
+    assert_eq!(a.len(), 64);
+    assert_eq!(b.len(), 67);
+}
+
+#[test]
+fn test_handshake_tampered_public_key() {
+    // initiator <-> (b, m) <-> responder, the test relays and flips one bit of e_i
+    let (mut a, mut b) = duplex();
+    let (mut m, mut c) = duplex();
+    let responder = thread::spawn(move || respond_sync(&mut c, &[3; 32]));
+    let initiator = thread::spawn(move || initiate_sync(&mut a, &[3; 32]));
+    let mut e_i = [0u8; 32];
+    b.read_exact(&mut e_i).unwrap();
+    e_i[0] ^= 1;
+    m.write_all(&e_i).unwrap();
+    let mut reply = [0u8; 64];
+    m.read_exact(&mut reply).unwrap();
+    b.write_all(&reply).unwrap();
+    assert_eq!(
+        initiator.join().unwrap().unwrap_err().kind(),
+        io::ErrorKind::PermissionDenied
+    );
+    drop(m);
+    assert!(responder.join().unwrap().is_err());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn test_handshake_async_with_sync_peer() {
+    use crate::handshake::{initiate_async, respond_async};
+
+    // sync initiator, async responder
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let t = thread::spawn(move || {
+        let mut s = std::net::TcpStream::connect(addr).unwrap();
+        initiate_sync(&mut s, &[3; 32])
+    });
+    let (mut s, _) = listener.accept().await.unwrap();
+    let kb = respond_async(&mut s, &[3; 32]).await.unwrap();
+    let ka = t.join().unwrap().unwrap();
+    assert_eq!(ka.write_key(), kb.read_key());
+
+    // async initiator, sync responder with the wrong key
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let responder = thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        respond_sync(&mut s, &[4; 32])
+    });
+    let mut s = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    let err = initiate_async(&mut s, &[3; 32]).await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    drop(s);
+    assert!(responder.join().unwrap().is_err());
+}
@@ -1,5 +1,10 @@
 pub mod buffer;
 pub mod e_stream;
+pub mod handshake;
+pub mod pairing;
+
+#[cfg(test)]
+mod test_util;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,155 @@
+//! One-time code pairing (CPace over ristretto255).
+//!
+//! The receiver shows a short [`PairingCode`], the sender types it in and
+//! both sides run a balanced PAKE. A passive observer learns nothing about
+//! the code and an active attacker gets exactly one guess per code, so the
+//! receiver must throw the code away after a single attempt.
+//!
+//! ```text
+//! initiator                                  responder
+//!     <sid: 16><y_i: 32>         ------->
+//!                                <-------    <y_r: 32><mac_r: 32>
+//!     <mac_i: 32>                ------->
+//!                                <-------    :0: | :1:
+//! ```
+//!
+//! `g = map(SHA512(protocol || code || sid))`, every side sends `y = s * g`
+//! and computes `k = s * y_peer`. The confirmation and session keys are
+//! derived from `k` the same way as in [`crate::handshake`].
+
+mod sync;
+
+use std::{fmt, io, str::FromStr};
+
+use curve25519_dalek::{RistrettoPoint, Scalar, ristretto::CompressedRistretto};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{e_stream::Role, handshake::Transcript};
+
+pub use sync::{initiate_sync, respond_sync};
+
+pub const SID_LEN: usize = 16;
+pub const POINT_LEN: usize = 32;
+
+/// Number of digits of a [`PairingCode`].
+pub const CODE_DIGITS: usize = 8;
+
+const PROTOCOL: &[u8] = b"eagle-eye pairing v1";
+
+/// Short numeric code shown by the receiver, e.g. `1234-5678`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PairingCode([u8; CODE_DIGITS]);
+
+impl PairingCode {
+    pub fn generate() -> Self {
+        let mut v = [0u8; CODE_DIGITS];
+        for d in v.iter_mut() {
+            *d = b'0' + (rand::random::<u32>() % 10) as u8;
+        }
+        Self(v)
+    }
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for PairingCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b) = self.0.split_at(CODE_DIGITS / 2);
+        // only ascii digits are stored
+        write!(
+            f,
+            "{}-{}",
+            std::str::from_utf8(a).unwrap(),
+            std::str::from_utf8(b).unwrap()
+        )
+    }
+}
+
+impl fmt::Debug for PairingCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PairingCode(..)")
+    }
+}
+
+impl FromStr for PairingCode {
+    type Err = io::Error;
+    /// Accepts the digits with or without separators (`-` and spaces).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut v = [0u8; CODE_DIGITS];
+        let mut n = 0;
+        for c in s.bytes().filter(|c| *c != b'-' && !c.is_ascii_whitespace()) {
+            if !c.is_ascii_digit() || n == CODE_DIGITS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid pairing code",
+                ));
+            }
+            v[n] = c;
+            n += 1;
+        }
+        if n != CODE_DIGITS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid pairing code",
+            ));
+        }
+        Ok(Self(v))
+    }
+}
+
+/// Secret scalar and public share of one side of a pairing.
+pub(crate) struct Share {
+    secret: Scalar,
+    public: [u8; POINT_LEN],
+}
+
+impl Share {
+    pub(crate) fn generate(code: &PairingCode, sid: &[u8; SID_LEN]) -> Self {
+        let hash: [u8; 64] = Sha512::new()
+            .chain_update(PROTOCOL)
+            .chain_update([CODE_DIGITS as u8])
+            .chain_update(code.as_bytes())
+            .chain_update(sid)
+            .finalize()
+            .into();
+        let generator = RistrettoPoint::from_uniform_bytes(&hash);
+        let secret = Scalar::from_bytes_mod_order_wide(&rand::random::<[u8; 64]>());
+        let public = (secret * generator).compress().to_bytes();
+        Self { secret, public }
+    }
+    pub(crate) fn public(&self) -> [u8; POINT_LEN] {
+        self.public
+    }
+    pub(crate) fn transcript(
+        self,
+        sid: &[u8; SID_LEN],
+        peer: [u8; POINT_LEN],
+        role: Role,
+    ) -> io::Result<Transcript> {
+        let (y_i, y_r) = match role {
+            Role::Initiator => (self.public, peer),
+            Role::Responder => (peer, self.public),
+        };
+        let k = CompressedRistretto(peer)
+            .decompress()
+            .map(|p| self.secret * p)
+            .filter(|k| *k != RistrettoPoint::default())
+            .ok_or_else(|| pairing_error("invalid public share"))?;
+        let hash: [u8; 32] = Sha256::new()
+            .chain_update(PROTOCOL)
+            .chain_update(sid)
+            .chain_update(y_i)
+            .chain_update(y_r)
+            .finalize()
+            .into();
+        Ok(Transcript::derive(sid, k.compress().as_bytes(), hash))
+    }
+}
+
+pub(crate) fn pairing_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, msg)
+}
+
+#[cfg(test)]
+mod test;
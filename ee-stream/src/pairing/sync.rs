@@ -0,0 +1,68 @@
+use std::io;
+
+use crate::{
+    e_stream::{Role, SessionKeys},
+    handshake::{ACCEPTED, MAC_LEN, REJECTED},
+    pairing::{POINT_LEN, PairingCode, SID_LEN, Share, pairing_error},
+};
+
+/// Pair as the side that typed the code in (ee-sender).
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the peer showed a
+/// different code.
+pub fn initiate_sync<S: io::Read + io::Write>(
+    mut stream: S,
+    code: &PairingCode,
+) -> io::Result<SessionKeys> {
+    let sid = rand::random::<[u8; SID_LEN]>();
+    let own = Share::generate(code, &sid);
+    stream.write_all(&sid)?;
+    stream.write_all(&own.public())?;
+    stream.flush()?;
+    let mut peer = [0u8; POINT_LEN];
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut peer)?;
+    stream.read_exact(&mut tag)?;
+    let transcript = own.transcript(&sid, peer, Role::Initiator)?;
+    if !transcript.verify(Role::Responder, &tag) {
+        return Err(pairing_error("wrong pairing code"));
+    }
+    stream.write_all(&transcript.confirm(Role::Initiator))?;
+    stream.flush()?;
+    let mut status = [0u8; 3];
+    stream.read_exact(&mut status)?;
+    if &status != ACCEPTED {
+        return Err(pairing_error("wrong pairing code"));
+    }
+    Ok(transcript.session_keys(Role::Initiator))
+}
+
+/// Pair as the side that shows the code (ee-receiver).
+///
+/// Fails with [`io::ErrorKind::PermissionDenied`] if the peer typed a
+/// different code. Whatever the result, `code` must not be used again.
+pub fn respond_sync<S: io::Read + io::Write>(
+    mut stream: S,
+    code: &PairingCode,
+) -> io::Result<SessionKeys> {
+    let mut sid = [0u8; SID_LEN];
+    let mut peer = [0u8; POINT_LEN];
+    stream.read_exact(&mut sid)?;
+    stream.read_exact(&mut peer)?;
+    let own = Share::generate(code, &sid);
+    let public = own.public();
+    let transcript = own.transcript(&sid, peer, Role::Responder)?;
+    stream.write_all(&public)?;
+    stream.write_all(&transcript.confirm(Role::Responder))?;
+    stream.flush()?;
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut tag)?;
+    if !transcript.verify(Role::Initiator, &tag) {
+        stream.write_all(REJECTED)?;
+        stream.flush()?;
+        return Err(pairing_error("wrong pairing code"));
+    }
+    stream.write_all(ACCEPTED)?;
+    stream.flush()?;
+    Ok(transcript.session_keys(Role::Responder))
+}
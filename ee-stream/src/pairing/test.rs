@@ -0,0 +1,102 @@
+use std::{
+    io::{self, Read, Write},
+    str::FromStr,
+    thread,
+};
+
+use crate::{
+    e_stream::{EStreamSync, SessionKeys},
+    pairing::{PairingCode, initiate_sync, respond_sync},
+    test_util::duplex,
+};
+
+type Side = (io::Result<SessionKeys>, Vec<u8>);
+
+fn run(initiator_code: &str, responder_code: &str) -> (Side, Side) {
+    let i = PairingCode::from_str(initiator_code).unwrap();
+    let r = PairingCode::from_str(responder_code).unwrap();
+    let (mut a, mut b) = duplex();
+    let t = thread::spawn(move || {
+        let v = respond_sync(&mut b, &r);
+        (v, b.sent)
+    });
+    let v = initiate_sync(&mut a, &i);
+    let sent = std::mem::take(&mut a.sent);
+    drop(a);
+    ((v, sent), t.join().unwrap())
+}
+
+#[test]
+fn test_pairing_code_parse() {
+    let code = PairingCode::from_str("1234-5678").unwrap();
+    assert_eq!(code.to_string(), "1234-5678");
+    assert_eq!(PairingCode::from_str(" 1234 5678\n").unwrap(), code);
+    assert_eq!(PairingCode::from_str("12345678").unwrap(), code);
+    for bad in ["", "1234-567", "1234-56789", "1234-567a"] {
+        assert_eq!(
+            PairingCode::from_str(bad).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+    let code = PairingCode::generate();
+    assert_eq!(PairingCode::from_str(&code.to_string()).unwrap(), code);
+    assert_eq!(format!("{code:?}"), "PairingCode(..)");
+}
+
+#[test]
+fn test_pairing_same_code() {
+    let code = PairingCode::from_str("0000-0001").unwrap();
+    let (mut a, mut b) = duplex();
+    let c = code.clone();
+    let t = thread::spawn(move || respond_sync(&mut b, &c).map(|k| (k, b)));
+    let ka = initiate_sync(&mut a, &code).unwrap();
+    let (kb, b) = t.join().unwrap().unwrap();
+    assert_eq!(ka.write_key(), kb.read_key());
+    assert_eq!(ka.read_key(), kb.write_key());
+
+    let mut a = EStreamSync::builder().keys(ka).inner(a).build().unwrap();
+    let mut b = EStreamSync::builder().keys(kb).inner(b).build().unwrap();
+    b.write_all(b"identity").unwrap();
+    b.flush().unwrap();
+    let mut buf = [0; 8];
+    a.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"identity");
+}
+
+#[test]
+fn test_pairing_fresh_keys_per_session() {
+    let ((k1, _), _) = run("1111-2222", "1111-2222");
+    let ((k2, _), _) = run("1111-2222", "1111-2222");
+    assert_ne!(k1.unwrap(), k2.unwrap());
+}
+
+#[test]
+fn test_pairing_wrong_code() {
+    let ((ka, _), (kb, _)) = run("1111-2222", "1111-2223");
+    assert_eq!(ka.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    assert!(kb.is_err());
+}
+
+#[test]
+fn test_pairing_code_never_sent() {
+    let ((_, a), (_, b)) = run("1357-9246", "1357-9246");
+    for sent in [&a, &b] {
+        assert!(!sent.windows(8).any(|v| v == b"13579246"));
+    }
+    // sid, y_i, mac_i and y_r, mac_r, status
+    assert_eq!(a.len(), 16 + 32 + 32);
+    assert_eq!(b.len(), 32 + 32 + 3);
+}
+
+#[test]
+fn test_pairing_identity_share_rejected() {
+    // an all zero share is the identity point
+    let code = PairingCode::from_str("1111-2222").unwrap();
+    let (mut a, mut b) = duplex();
+    let t = thread::spawn(move || respond_sync(&mut b, &code));
+    a.write_all(&[0; 16 + 32]).unwrap();
+    assert_eq!(
+        t.join().unwrap().unwrap_err().kind(),
+        io::ErrorKind::PermissionDenied
+    );
+}
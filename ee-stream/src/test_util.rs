@@ -0,0 +1,58 @@
+use std::{
+    io,
+    sync::mpsc::{Receiver, Sender, channel},
+};
+
+/// One end of an in-memory, full duplex byte stream.
+pub(crate) struct Duplex {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    pos: usize,
+    pub(crate) sent: Vec<u8>,
+}
+
+pub(crate) fn duplex() -> (Duplex, Duplex) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+    let end = |tx, rx| Duplex {
+        tx,
+        rx,
+        pending: Vec::new(),
+        pos: 0,
+        sent: Vec::new(),
+    };
+    (end(a_tx, a_rx), end(b_tx, b_rx))
+}
+
+impl io::Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.pending.len() {
+            match self.rx.recv() {
+                Ok(v) => {
+                    self.pending = v;
+                    self.pos = 0;
+                }
+                // peer dropped
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl io::Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.extend_from_slice(buf);
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
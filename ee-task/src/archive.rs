@@ -0,0 +1,438 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use ee_http::{ChunkedWriter, HttpRequest, HttpResponse};
+use ee_proto::{Decode, Encode, Message, Response, codec};
+use flate2::{Compression, write::GzEncoder};
+use globset::GlobSet;
+use serde::Deserialize;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use zip::{
+    CompressionMethod, ZipWriter,
+    write::{SimpleFileOptions, StreamWriter},
+};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    file::{CHUNK_SIZE, attachment, send_failed},
+    glob,
+    sandbox::Sandbox,
+};
+
+/// Most include and exclude patterns of an [`ArchiveSync`].
+pub const MAX_ARCHIVE_PATTERNS: usize = 1024;
+
+/// How an [`ArchiveSync`] packs the directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Tar => "tar",
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+            Self::TarZst => "tar.zst",
+        }
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Tar => "application/x-tar",
+            Self::TarGz => "application/gzip",
+            Self::Zip => "application/zip",
+            Self::TarZst => "application/zstd",
+        }
+    }
+}
+
+/// Streams a directory of the receiver into the http response as an
+/// archive, made on the fly.
+///
+/// The receiver answers with a [`Response`] and the name of the directory,
+/// the top directory of the archive. Then the archive as chunks of at most
+/// [`CHUNK_SIZE`] (`<u32 len><data>`) up to an empty one, then a trailing
+/// [`Response`]. The sender ends the chunked http body only if it is ok, so
+/// a broken archive never looks complete.
+///
+/// Directories are walked in name order, symlinks are stored as symlinks.
+/// Paths are matched relative to the directory: only files that match one
+/// of `include` (every file if it is empty) and nothing under a match of
+/// `exclude`, see the glob patterns of [`crate::sync::SyncDirSync`].
+/// Directories are stored on their own only without `include`.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct ArchiveSync {
+    path: PathBuf,
+    #[serde(default)]
+    format: ArchiveFormat,
+    #[serde(default)]
+    #[message(max_len = MAX_ARCHIVE_PATTERNS)]
+    include: Vec<String>,
+    #[serde(default)]
+    #[message(max_len = MAX_ARCHIVE_PATTERNS)]
+    exclude: Vec<String>,
+}
+
+impl ArchiveSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            format: ArchiveFormat::Tar,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+    pub fn format(mut self, value: ArchiveFormat) -> Self {
+        self.format = value;
+        self
+    }
+    pub fn include<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+    pub fn exclude<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+    /// The name of the directory at `path`, which has to be one.
+    fn name(path: &Path) -> io::Result<String> {
+        if !fs::metadata(path)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            ));
+        }
+        let path = fs::canonicalize(path)?;
+        Ok(path
+            .file_name()
+            .map_or("archive".into(), |v| v.to_string_lossy().into_owned()))
+    }
+    /// The archive of `root` to `out`, its entries under `name`.
+    fn write<W: Write>(&self, root: &Path, name: &str, filter: &Filter, out: W) -> io::Result<()> {
+        match self.format {
+            ArchiveFormat::Tar => {
+                let mut tar = tar::Builder::new(out);
+                filter.add_dir(&mut tar, root, name, "")?;
+                tar.into_inner()?;
+            }
+            ArchiveFormat::TarGz => {
+                let gz = GzEncoder::new(out, Compression::default());
+                let mut tar = tar::Builder::new(gz);
+                filter.add_dir(&mut tar, root, name, "")?;
+                tar.into_inner()?.finish()?;
+            }
+            ArchiveFormat::TarZst => {
+                let mut tar = tar::Builder::new(zstd::Encoder::new(out, 0)?);
+                filter.add_dir(&mut tar, root, name, "")?;
+                tar.into_inner()?.finish()?;
+            }
+            ArchiveFormat::Zip => {
+                let mut zip = ZipWriter::new_stream(out);
+                filter.add_dir(&mut zip, root, name, "")?;
+                zip.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The compiled patterns of an [`ArchiveSync`].
+struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    fn new(req: &ArchiveSync) -> io::Result<Self> {
+        Ok(Self {
+            include: glob::set(&req.include)?,
+            exclude: glob::set(&req.exclude)?,
+        })
+    }
+    /// The content of `root/rel`, `rel` is empty or ends with a `/`.
+    fn add_dir<A: Archive>(
+        &self,
+        archive: &mut A,
+        root: &Path,
+        name: &str,
+        rel: &str,
+    ) -> io::Result<()> {
+        if self.include.is_empty() {
+            let meta = fs::metadata(root.join(rel))?;
+            archive.dir(&format!("{name}/{rel}"), &meta)?;
+        }
+        let mut entries = fs::read_dir(root.join(rel))?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|v| v.file_name());
+        for entry in entries {
+            let path = format!("{rel}{}", entry.file_name().to_string_lossy());
+            if self.exclude.is_match(&path) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                self.add_dir(archive, root, name, &format!("{path}/"))?;
+                continue;
+            }
+            if !self.include.is_empty() && !self.include.is_match(&path) {
+                continue;
+            }
+            let full = format!("{name}/{path}");
+            if meta.is_symlink() {
+                let target = fs::read_link(entry.path())?;
+                archive.symlink(&full, &meta, &target.to_string_lossy())?;
+            } else if meta.is_file() {
+                archive.file(&full, &meta, File::open(entry.path())?)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The entries of an archive being written.
+trait Archive {
+    /// `name` ends with a `/`.
+    fn dir(&mut self, name: &str, meta: &fs::Metadata) -> io::Result<()>;
+    fn file(&mut self, name: &str, meta: &fs::Metadata, file: File) -> io::Result<()>;
+    fn symlink(&mut self, name: &str, meta: &fs::Metadata, target: &str) -> io::Result<()>;
+}
+
+/// Unix permission bits, the usual ones on other platforms.
+fn mode(meta: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        if meta.is_dir() { 0o755 } else { 0o644 }
+    }
+}
+
+/// The `len` bytes of `file` the header announced, an error if it shrank
+/// while reading.
+struct Exact {
+    file: io::Take<File>,
+}
+
+impl Exact {
+    fn new(file: File, len: u64) -> Self {
+        Self {
+            file: file.take(len),
+        }
+    }
+}
+
+impl Read for Exact {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read(buf)?;
+        if n == 0 && !buf.is_empty() && self.file.limit() > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file shrank while reading",
+            ));
+        }
+        Ok(n)
+    }
+}
+
+impl<W: Write> Archive for tar::Builder<W> {
+    fn dir(&mut self, name: &str, meta: &fs::Metadata) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(meta);
+        header.set_size(0);
+        self.append_data(&mut header, name, io::empty())
+    }
+    fn file(&mut self, name: &str, meta: &fs::Metadata, file: File) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(meta);
+        self.append_data(&mut header, name, Exact::new(file, meta.len()))
+    }
+    fn symlink(&mut self, name: &str, meta: &fs::Metadata, target: &str) -> io::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(meta);
+        header.set_size(0);
+        self.append_link(&mut header, name, target)
+    }
+}
+
+/// Deflated files, the modification time in UTC, 1980 at the earliest.
+fn zip_options(meta: &fs::Metadata) -> SimpleFileOptions {
+    let time = meta
+        .modified()
+        .ok()
+        .map(OffsetDateTime::from)
+        .and_then(|v| zip::DateTime::try_from(PrimitiveDateTime::new(v.date(), v.time())).ok())
+        .unwrap_or_default();
+    SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(mode(meta))
+        .last_modified_time(time)
+        .large_file(meta.len() >= u32::MAX as u64)
+}
+
+impl<W: Write> Archive for ZipWriter<StreamWriter<W>> {
+    fn dir(&mut self, name: &str, meta: &fs::Metadata) -> io::Result<()> {
+        Ok(self.add_directory(name, zip_options(meta))?)
+    }
+    fn file(&mut self, name: &str, meta: &fs::Metadata, file: File) -> io::Result<()> {
+        self.start_file(name, zip_options(meta))?;
+        io::copy(&mut Exact::new(file, meta.len()), self)?;
+        Ok(())
+    }
+    fn symlink(&mut self, name: &str, meta: &fs::Metadata, target: &str) -> io::Result<()> {
+        Ok(self.add_symlink(name, target, zip_options(meta).unix_permissions(0o777))?)
+    }
+}
+
+/// The archive as `<u32 len><data>` chunks of [`CHUNK_SIZE`], remembering
+/// if the stream failed to tell it from the errors of the files.
+struct Chunks<S> {
+    stream: S,
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl<S: Write> Chunks<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            failed: false,
+        }
+    }
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let res = codec::write_len(&mut self.stream, self.buf.len(), CHUNK_SIZE)
+            .and_then(|_| self.stream.write_all(&self.buf));
+        self.failed |= res.is_err();
+        self.buf.clear();
+        res
+    }
+    /// What is left and the empty chunk.
+    fn finish(mut self) -> io::Result<S> {
+        self.send()?;
+        codec::write_len(&mut self.stream, 0, CHUNK_SIZE)?;
+        Ok(self.stream)
+    }
+}
+
+impl<S: Write> Write for Chunks<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        if self.buf.len() == CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl GetId for ArchiveSync {
+    fn id() -> &'static str {
+        "archive"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for ArchiveSync {
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        req: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        let name = String::decode(&mut stream)?;
+        let file = format!("{name}.{}", self.format.extension());
+        HttpResponse::new()
+            .content_type(self.format.content_type())
+            .push_header("Content-Disposition", attachment(Path::new(&file)))
+            .push_header("Transfer-Encoding", "chunked")
+            .send(req, &mut http)?;
+        let mut body = ChunkedWriter::new(&mut http);
+        let mut buf = Vec::new();
+        // keep reading after the http side fails, the receiver sends the
+        // whole archive either way
+        let mut http_err = None;
+        loop {
+            let n = codec::read_len(&mut stream, CHUNK_SIZE)?;
+            if n == 0 {
+                break;
+            }
+            buf.resize(n, 0);
+            stream.read_exact(&mut buf)?;
+            if http_err.is_none()
+                && let Err(err) = body.write_all(&buf)
+            {
+                http_err = Some(err);
+            }
+        }
+        Response::read_from(&mut stream)?.into_result()?;
+        if let Some(err) = http_err {
+            return Err(err);
+        }
+        body.finish()?.flush()?;
+        Ok(ExecuteResult::Ok)
+    }
+}
+
+impl ExeReceiverSync for ArchiveSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let (root, name, filter) = match Filter::new(&req).and_then(|filter| {
+            let path = sandbox.read(&req.path)?;
+            Ok((Self::name(&path)?, path, filter))
+        }) {
+            Ok((name, path, filter)) => (path, name, filter),
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        name.encode(&mut stream)?;
+
+        let mut out = Chunks::new(&mut stream);
+        let res = match req.write(&root, &name, &filter, &mut out) {
+            Err(err) if out.failed => return Err(err),
+            Err(err) => Response::from(&err),
+            Ok(()) => Response::ok(),
+        };
+        out.finish()?;
+        res.write_to(&mut stream)?;
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
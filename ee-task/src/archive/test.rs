@@ -0,0 +1,185 @@
+use std::{
+    io::{self, Cursor},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+};
+
+use ee_http::HttpRequest;
+use ee_proto::{Decode, Response, codec};
+use ee_test_util::TempDir;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    archive::{ArchiveFormat, ArchiveSync},
+    file::CHUNK_SIZE,
+    test_util::{content, dechunk, exchange, receive},
+};
+
+/// `photos/` with files, a long name, an empty directory, a file to
+/// exclude and on unix a symlink.
+fn archive_fixture(name: &str) -> (TempDir, PathBuf) {
+    let dir = TempDir::new(name)
+        .dir("photos/empty")
+        .file("photos/a.txt", b"hello")
+        .file("photos/big.bin", content(300_000))
+        .file("photos/skip.tmp", b"x")
+        .file(format!("photos/sub/deeper/{}", "n".repeat(150)), b"long");
+    #[cfg(unix)]
+    let dir = dir.symlink("photos/sub/link", "../a.txt");
+    let root = dir.path().join("photos");
+    (dir, root)
+}
+
+/// The archive the receiver sends for `task`.
+fn archive(task: &ArchiveSync) -> Vec<u8> {
+    let output = receive(task);
+    let mut r = output.as_slice();
+    assert!(Response::read_from(&mut r).unwrap().is_ok());
+    assert_eq!(String::decode(&mut r).unwrap(), "photos");
+    let mut data = Vec::new();
+    loop {
+        let n = codec::read_len(&mut r, CHUNK_SIZE).unwrap();
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&r[..n]);
+        r = &r[n..];
+    }
+    assert!(Response::read_from(&mut r).unwrap().is_ok());
+    data
+}
+
+/// Name, type and data of the entries of a tar, the target of symlinks.
+fn tar_entries(data: &[u8]) -> Vec<(String, u8, Vec<u8>)> {
+    let mut archive = tar::Archive::new(data);
+    let mut entries = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = String::from_utf8(entry.path_bytes().into_owned()).unwrap();
+        let kind = entry.header().entry_type().as_byte();
+        let mut data = Vec::new();
+        match entry.link_name_bytes() {
+            Some(v) => data.extend_from_slice(&v),
+            None => _ = io::Read::read_to_end(&mut entry, &mut data).unwrap(),
+        }
+        entries.push((name, kind, data));
+    }
+    entries
+}
+
+/// Name and data of the entries of a zip, CRCs checked while reading.
+fn zip_entries(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i).unwrap();
+            let mut data = Vec::new();
+            io::Read::read_to_end(&mut file, &mut data).unwrap();
+            (file.name().to_owned(), data)
+        })
+        .collect()
+}
+
+#[test]
+fn test_archive_tar() {
+    let (_dir, root) = archive_fixture("archive-tar");
+    let entries = tar_entries(&archive(&ArchiveSync::new(&root).exclude("*.tmp")));
+    let names: Vec<&str> = entries.iter().map(|v| v.0.as_str()).collect();
+    let long = format!("photos/sub/deeper/{}", "n".repeat(150));
+    let mut expected = vec![
+        "photos/",
+        "photos/a.txt",
+        "photos/big.bin",
+        "photos/empty/",
+        "photos/sub/",
+        "photos/sub/deeper/",
+        &long,
+    ];
+    #[cfg(unix)]
+    expected.push("photos/sub/link");
+    assert_eq!(names, expected);
+    assert_eq!(entries[1], ("photos/a.txt".into(), b'0', b"hello".to_vec()));
+    assert_eq!(entries[2].2, content(300_000));
+    assert_eq!(entries[3].1, b'5');
+    assert_eq!(entries[6].2, b"long");
+    #[cfg(unix)]
+    assert_eq!(
+        entries[7],
+        ("photos/sub/link".into(), b'2', b"../a.txt".to_vec())
+    );
+
+    // only the files that match, no directories on their own
+    let task = ArchiveSync::new(&root).include("*.txt").include("sub/**");
+    let entries = tar_entries(&archive(&task));
+    let names: Vec<&str> = entries.iter().map(|v| v.0.as_str()).collect();
+    let mut expected = vec!["photos/a.txt", &long];
+    #[cfg(unix)]
+    expected.push("photos/sub/link");
+    assert_eq!(names, expected);
+}
+
+#[test]
+fn test_archive_compressed() {
+    let (_dir, root) = archive_fixture("archive-compressed");
+    let tar = archive(&ArchiveSync::new(&root));
+    let gz = archive(&ArchiveSync::new(&root).format(ArchiveFormat::TarGz));
+    assert_eq!(gz[..3], [0x1f, 0x8b, 8]);
+    assert!(gz.len() < tar.len() / 4, "{} of {}", gz.len(), tar.len());
+    let mut data = Vec::new();
+    io::Read::read_to_end(&mut flate2::read::GzDecoder::new(gz.as_slice()), &mut data).unwrap();
+    assert_eq!(data, tar);
+
+    let zst = archive(&ArchiveSync::new(&root).format(ArchiveFormat::TarZst));
+    assert_eq!(zst[..4], [0x28, 0xb5, 0x2f, 0xfd]);
+    assert!(zst.len() < tar.len() / 4, "{} of {}", zst.len(), tar.len());
+    assert_eq!(zstd::decode_all(zst.as_slice()).unwrap(), tar);
+
+    let zip = zip_entries(&archive(
+        &ArchiveSync::new(&root).format(ArchiveFormat::Zip),
+    ));
+    let tar = tar_entries(&tar);
+    assert_eq!(zip.len(), tar.len());
+    for ((name, data), (tar_name, _, tar_data)) in zip.iter().zip(&tar) {
+        assert_eq!(name, tar_name);
+        assert_eq!(data, tar_data, "{name}");
+    }
+}
+
+#[test]
+fn test_archive_on_sender() {
+    let (_dir, root) = archive_fixture("archive-sender");
+    let task = ArchiveSync::new(&root).format(ArchiveFormat::TarGz);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        ArchiveSync::execute_on_receiver(stream).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut http = Vec::new();
+    let r = task.execute_on_sender(&mut stream, &mut HttpRequest::default(), &mut http);
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    receiver.join().unwrap().unwrap();
+    let (head, body) = http.split_at(http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4);
+    let head = String::from_utf8_lossy(head);
+    assert!(head.contains("application/gzip"), "{head}");
+    assert!(
+        head.contains("attachment; filename=\"photos.tar.gz\""),
+        "{head}"
+    );
+    assert_eq!(dechunk(body), archive(&task));
+
+    let (r, json) = exchange(
+        &ArchiveSync::new(root.join("a.txt")),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::InvalidPath);
+    assert_eq!(json["kind"], "not_a_directory");
+    let (r, json) = exchange(
+        &ArchiveSync::new(root.join("missing")),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["kind"], "not_found");
+}
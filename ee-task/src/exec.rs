@@ -0,0 +1,449 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ee_http::{ChunkedWriter, HttpRequest, HttpResponse};
+use ee_proto::{Bytes, Decode, Encode, Message, Response};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    file::{CHUNK_SIZE, send_failed},
+    sandbox::{self, Sandbox},
+};
+
+/// Most arguments and environment variables of an [`ExecSync`].
+pub const MAX_EXEC_ARGS: usize = 1024;
+
+/// How long the receiver collects output before it waits for the sender.
+pub(crate) const TURN: Duration = Duration::from_millis(50);
+
+/// Most output the receiver sends before it waits for the sender.
+pub(crate) const MAX_TURN_LEN: usize = 4 * CHUNK_SIZE;
+
+/// Runs a program on the receiver and streams its output back.
+///
+/// The receiver answers with a [`Response`], then sends [`ExecOutput`]s
+/// while the process runs: stdout and stderr as they come, in turns that end
+/// with [`ExecOutput::Wait`]. After every turn the sender answers with an
+/// [`ExecInput`], to feed stdin or to cancel, so a slow sender slows the
+/// process down instead of filling the memory of the receiver. The last
+/// output is [`ExecOutput::Exit`].
+///
+/// The process runs in a process group of its own, a timeout or a cancel
+/// kills the whole group. Senders limited to shared directories can not run
+/// anything.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct ExecSync {
+    program: String,
+    #[serde(default)]
+    #[message(max_len = MAX_EXEC_ARGS)]
+    args: Vec<String>,
+    /// Added to the environment of the receiver.
+    #[serde(default, deserialize_with = "env")]
+    #[message(max_len = MAX_EXEC_ARGS)]
+    env: Vec<(String, String)>,
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+    /// Milliseconds, no limit if `None`.
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+/// `env` as a JSON object.
+fn env<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<(String, String)>, D::Error> {
+    BTreeMap::<String, String>::deserialize(d).map(|v| v.into_iter().collect())
+}
+
+impl ExecSync {
+    pub fn new<T: Into<String>>(program: T) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+    pub fn arg<T: Into<String>>(mut self, value: T) -> Self {
+        self.args.push(value.into());
+        self
+    }
+    pub fn args<I: IntoIterator<Item = T>, T: Into<String>>(mut self, values: I) -> Self {
+        self.args.extend(values.into_iter().map(Into::into));
+        self
+    }
+    pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+    pub fn cwd<T: Into<PathBuf>>(mut self, value: T) -> Self {
+        self.cwd = Some(value.into());
+        self
+    }
+    pub fn timeout(mut self, value: Duration) -> Self {
+        self.timeout = Some(value.as_millis().try_into().unwrap_or(u64::MAX));
+        self
+    }
+    /// Read the task out of the JSON body of an http request of the sender,
+    /// and leave only its `stdin` field in the body.
+    pub fn from_http(req: &mut HttpRequest) -> io::Result<Self> {
+        #[derive(Deserialize)]
+        struct Body {
+            #[serde(flatten)]
+            task: ExecSync,
+            #[serde(default)]
+            stdin: String,
+        }
+        let body = req
+            .get_body()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "body not found"))?;
+        let body: Body = serde_json::from_slice(body)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        req.set_body(body.stdin.into_bytes());
+        Ok(body.task)
+    }
+    fn spawn(&self) -> io::Result<Process> {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = &self.cwd {
+            cmd.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut child = cmd.spawn()?;
+
+        let (tx, events) = mpsc::sync_channel(16);
+        if let Some(v) = child.stdout.take() {
+            pipe(v, tx.clone(), ExecOutput::Stdout);
+        }
+        if let Some(v) = child.stderr.take() {
+            pipe(v, tx, ExecOutput::Stderr);
+        }
+        let stdin = child.stdin.take().map(|mut v| {
+            let (tx, rx) = mpsc::channel::<Vec<u8>>();
+            // a process that does not read its stdin blocks this thread
+            // instead of the connection
+            thread::spawn(move || {
+                for data in rx {
+                    if v.write_all(&data).is_err() {
+                        break;
+                    }
+                }
+            });
+            tx
+        });
+        Ok(Process {
+            child,
+            events,
+            open: 2,
+            stdin,
+            exited: false,
+        })
+    }
+}
+
+/// Send what `r` reads to `tx` as `output`, then `None`.
+pub(crate) fn pipe<R: Read + Send + 'static, T: Send + 'static>(
+    mut r: R,
+    tx: SyncSender<Option<T>>,
+    output: fn(Bytes) -> T,
+) {
+    thread::spawn(move || {
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let n = match r.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            if tx.send(Some(output(Bytes(buf[..n].to_vec())))).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(None);
+    });
+}
+
+/// A running [`ExecSync`], killed on drop if it did not exit.
+struct Process {
+    child: Child,
+    /// Output of stdout and stderr, `None` when one of them closes.
+    events: Receiver<Option<ExecOutput>>,
+    /// Pipes still open.
+    open: usize,
+    stdin: Option<mpsc::Sender<Vec<u8>>>,
+    exited: bool,
+}
+
+impl Process {
+    /// Kill the process group.
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        signal_group(&self.child, libc::SIGKILL);
+        let _ = self.child.kill();
+    }
+    /// Stream the output to `stream` up to the exit of the process.
+    fn run<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        let deadline = timeout.map(|v| Instant::now() + v);
+        let mut status = ExitStatus::default();
+        loop {
+            let turn_end = Instant::now() + TURN;
+            let mut sent = 0;
+            while self.open > 0 && sent < MAX_TURN_LEN {
+                let wait = turn_end.saturating_duration_since(Instant::now());
+                match self.events.recv_timeout(wait) {
+                    Ok(Some(v)) => {
+                        if let ExecOutput::Stdout(data) | ExecOutput::Stderr(data) = &v {
+                            sent += data.0.len();
+                        }
+                        v.encode(&mut *stream)?;
+                    }
+                    Ok(None) => self.open -= 1,
+                    Err(_) => break,
+                }
+            }
+            if self.open == 0 {
+                // all output is out, the process is about to exit
+                match self.child.try_wait()? {
+                    Some(v) => {
+                        self.exited = true;
+                        status.set(v);
+                        ExecOutput::Exit(status).encode(&mut *stream)?;
+                        return stream.flush();
+                    }
+                    None => thread::sleep(turn_end.saturating_duration_since(Instant::now())),
+                }
+            }
+            if !status.timed_out && deadline.is_some_and(|v| Instant::now() >= v) {
+                self.kill();
+                status.timed_out = true;
+            }
+            ExecOutput::Wait.encode(&mut *stream)?;
+            stream.flush()?;
+            match ExecInput::decode(&mut *stream)? {
+                ExecInput::Continue => {}
+                ExecInput::Stdin(v) => {
+                    // stdin closed by the process is not an error
+                    if let Some(tx) = &self.stdin {
+                        let _ = tx.send(v.0);
+                    }
+                }
+                ExecInput::CloseStdin => self.stdin = None,
+                ExecInput::Cancel if !status.cancelled => {
+                    self.kill();
+                    status.cancelled = true;
+                }
+                ExecInput::Cancel => {}
+            }
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if !self.exited {
+            self.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Send `signal` to the process group `child` leads.
+#[cfg(unix)]
+pub(crate) fn signal_group(child: &Child, signal: i32) {
+    if let Ok(pid) = i32::try_from(child.id()) {
+        // SAFETY: only sends a signal, to a group the child was put in
+        unsafe {
+            libc::kill(-pid, signal);
+        }
+    }
+}
+
+/// What the receiver sends while an [`ExecSync`] runs.
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum ExecOutput {
+    Stdout(#[message(max_len = CHUNK_SIZE)] Bytes),
+    Stderr(#[message(max_len = CHUNK_SIZE)] Bytes),
+    /// End of a turn, the receiver waits for an [`ExecInput`].
+    Wait,
+    Exit(ExitStatus),
+}
+
+/// What the sender answers [`ExecOutput::Wait`] with.
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum ExecInput {
+    Continue,
+    Stdin(#[message(max_len = CHUNK_SIZE)] Bytes),
+    /// No more stdin, the process reads the end of the file.
+    CloseStdin,
+    /// Kill the process group.
+    Cancel,
+}
+
+/// How an [`ExecSync`] ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Serialize)]
+pub struct ExitStatus {
+    /// `None` if it was killed by a signal.
+    pub code: Option<i32>,
+    /// The signal that killed it, unix only.
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub cancelled: bool,
+}
+
+impl ExitStatus {
+    pub(crate) fn set(&mut self, v: std::process::ExitStatus) {
+        self.code = v.code();
+        #[cfg(unix)]
+        {
+            self.signal = std::os::unix::process::ExitStatusExt::signal(&v);
+        }
+    }
+}
+
+/// Output of stdout or stderr as text, holding back a character split
+/// across chunks.
+#[derive(Default)]
+struct Text(Vec<u8>);
+
+impl Text {
+    fn push(&mut self, data: &[u8]) -> String {
+        self.0.extend_from_slice(data);
+        let valid = match std::str::from_utf8(&self.0) {
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            _ => self.0.len(),
+        };
+        let rest = self.0.split_off(valid);
+        let text = String::from_utf8_lossy(&self.0).into_owned();
+        self.0 = rest;
+        text
+    }
+}
+
+impl GetId for ExecSync {
+    fn id() -> &'static str {
+        "exec"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for ExecSync {
+    /// Answers with newline delimited JSON as the output comes, sent with
+    /// `Transfer-Encoding: chunked`. The body of `req` is fed to stdin. If
+    /// the http client goes away the process is cancelled.
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        req: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        HttpResponse::new()
+            .content_type("application/x-ndjson")
+            .push_header("Transfer-Encoding", "chunked")
+            .send(req, &mut http)?;
+        let mut body = ChunkedWriter::new(&mut http);
+        let mut stdin = req.get_body().unwrap_or_default();
+        let mut stdin_closed = false;
+        let mut text = [Text::default(), Text::default()];
+        // keep reading after a failed write, to cancel at the next turn
+        let mut http_err = None;
+        let mut cancelled = false;
+        loop {
+            let (name, data, text) = match ExecOutput::decode(&mut stream)? {
+                ExecOutput::Stdout(v) => ("stdout", v, &mut text[0]),
+                ExecOutput::Stderr(v) => ("stderr", v, &mut text[1]),
+                ExecOutput::Wait => {
+                    let input = if http_err.is_some() && !cancelled {
+                        cancelled = true;
+                        ExecInput::Cancel
+                    } else if !stdin.is_empty() {
+                        let (data, rest) = stdin.split_at(stdin.len().min(CHUNK_SIZE));
+                        stdin = rest;
+                        ExecInput::Stdin(Bytes(data.to_vec()))
+                    } else if !stdin_closed {
+                        stdin_closed = true;
+                        ExecInput::CloseStdin
+                    } else {
+                        ExecInput::Continue
+                    };
+                    input.encode(&mut stream)?;
+                    stream.flush()?;
+                    continue;
+                }
+                ExecOutput::Exit(status) => {
+                    if let Some(err) = http_err {
+                        return Err(err);
+                    }
+                    let mut line = serde_json::json!({ "exit": status }).to_string();
+                    line.push('\n');
+                    body.write_all(line.as_bytes())?;
+                    body.finish()?;
+                    return Ok(ExecuteResult::Ok);
+                }
+            };
+            let data = text.push(&data.0);
+            if http_err.is_some() || data.is_empty() {
+                continue;
+            }
+            let mut line = serde_json::json!({ "stream": name, "data": data }).to_string();
+            line.push('\n');
+            if let Err(err) = body.write_all(line.as_bytes()).and_then(|_| body.flush()) {
+                http_err = Some(err);
+            }
+        }
+    }
+}
+
+impl ExeReceiverSync for ExecSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let process = if sandbox.is_unrestricted() {
+            req.spawn()
+        } else {
+            Err(sandbox::forbidden(
+                "a sandboxed sender can not run programs",
+            ))
+        };
+        let mut process = match process {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        process.run(&mut stream, req.timeout.map(Duration::from_millis))?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,162 @@
+use std::{
+    fs, io,
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use ee_http::HttpRequest;
+use ee_test_util::TempDir;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    exec::ExecSync,
+    file::CHUNK_SIZE,
+    test_util::{Broken, dechunk, exchange, exchange_sandboxed, sandbox_fixture},
+};
+
+/// Run `task` with `stdin`, the result and the JSON lines of the answer.
+fn exec(task: &ExecSync, stdin: &str) -> (io::Result<ExecuteResult>, Vec<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        ExecSync::execute_on_receiver(stream).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut req = HttpRequest::default().body(Some(stdin.as_bytes().to_vec()));
+    let mut http = Vec::new();
+    let r = task.execute_on_sender(&mut stream, &mut req, &mut http);
+    receiver.join().unwrap().unwrap();
+    let (head, body) = http.split_at(http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4);
+    assert!(String::from_utf8_lossy(head).contains("Transfer-Encoding: chunked"));
+    let lines = String::from_utf8(dechunk(body))
+        .unwrap()
+        .lines()
+        .map(|v| serde_json::from_str(v).unwrap())
+        .collect();
+    (r, lines)
+}
+
+/// Everything the process wrote to `stream`.
+fn output(lines: &[serde_json::Value], stream: &str) -> String {
+    lines
+        .iter()
+        .filter(|v| v["stream"] == stream)
+        .map(|v| v["data"].as_str().unwrap())
+        .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_output() {
+    let task = ExecSync::new("sh")
+        .args(["-c", "echo out; echo err >&2; printf \"$GREETING\"; exit 3"])
+        .env("GREETING", "héllo");
+    let (r, lines) = exec(&task, "");
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(output(&lines, "stdout"), "out\nhéllo");
+    assert_eq!(output(&lines, "stderr"), "err\n");
+    let exit = &lines.last().unwrap()["exit"];
+    assert_eq!(exit["code"], 3);
+    assert_eq!(exit["signal"], serde_json::Value::Null);
+    assert_eq!(exit["timed_out"], false);
+
+    // stdin from the sender, larger than a chunk
+    let input = "0123456789".repeat(CHUNK_SIZE / 4);
+    let (r, lines) = exec(&ExecSync::new("cat"), &input);
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(output(&lines, "stdout"), input);
+    assert_eq!(lines.last().unwrap()["exit"]["code"], 0);
+
+    let dir = TempDir::new("exec-cwd");
+    let (_, lines) = exec(&ExecSync::new("pwd").cwd(dir.path()), "");
+    assert_eq!(
+        output(&lines, "stdout").trim_end(),
+        fs::canonicalize(dir.path()).unwrap().to_str().unwrap()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_timeout() {
+    let start = std::time::Instant::now();
+    let task = ExecSync::new("sh")
+        .args(["-c", "echo started; sleep 10"])
+        .timeout(Duration::from_millis(200));
+    let (r, lines) = exec(&task, "");
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(output(&lines, "stdout"), "started\n");
+    let exit = &lines.last().unwrap()["exit"];
+    assert_eq!(exit["timed_out"], true);
+    assert_eq!(exit["code"], serde_json::Value::Null);
+    assert_eq!(exit["signal"], 9);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_exec_cancel() {
+    let dir = TempDir::new("exec-cancel");
+    let pid_file = dir.path().join("pid");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        ExecSync::execute_on_receiver(stream).map(|_| ())
+    });
+    let start = std::time::Instant::now();
+    // a child in the background, killed with its process group
+    let script = format!(
+        "sleep 10 & echo $! > {}; echo started; wait",
+        pid_file.display()
+    );
+    let task = ExecSync::new("sh").args(["-c", &script]);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut http = Broken::default();
+    let err = task
+        .execute_on_sender(&mut stream, &mut HttpRequest::default(), &mut http)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    receiver.join().unwrap().unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    let pid = fs::read_to_string(&pid_file).unwrap();
+    // gone, or a zombie waiting for init, once the signal is delivered
+    let deadline = std::time::Instant::now() + Duration::from_secs(2);
+    loop {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        if stat.is_empty() || stat.contains(") Z ") {
+            break;
+        }
+        assert!(std::time::Instant::now() < deadline, "{stat}");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_exec_failed() {
+    let (r, json) = exchange(
+        &ExecSync::new("ee-task-no-such-program"),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["status"], "faild");
+
+    let (dir, sandbox) = sandbox_fixture("exec-sandboxed");
+    let task = ExecSync::new("sh").cwd(dir.path().join("rw"));
+    let (r, json) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox);
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert_eq!(json["kind"], "forbidden");
+}
+
+#[test]
+fn test_exec_from_http() {
+    let body = br#"{"program": "ls", "args": ["-l"], "env": {"A": "1"}, "stdin": "input"}"#;
+    let mut req = HttpRequest::default().body(Some(body.to_vec()));
+    let task = ExecSync::from_http(&mut req).unwrap();
+    assert_eq!(task, ExecSync::new("ls").arg("-l").env("A", "1"));
+    assert_eq!(req.get_body(), Some(&b"input"[..]));
+    let mut req = HttpRequest::default().body(Some(b"{}".to_vec()));
+    assert!(ExecSync::from_http(&mut req).is_err());
+}
@@ -1,22 +1,97 @@
 use std::{
+    cmp::Ordering,
+    collections::HashMap,
     ffi::OsString,
-    io::{self, Read, Write},
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use ee_http::{HttpRequest, HttpResponse};
-use ee_stream::EStreamSync;
+use ee_http::{
+    HttpRequest, HttpResponse, Status, content_type,
+    multipart::{self, Multipart},
+};
+use ee_proto::{Bytes, Decode, Encode, ErrorKind, Message, Response, codec};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId, sandbox::Sandbox};
+
+/// Answer the http request with the failure the receiver reported.
+pub(crate) fn send_failed<W: io::Write>(http: W, res: &Response) -> io::Result<ExecuteResult> {
+    let data = serde_json::json!({
+        "status": "faild",
+        "kind": res.get_kind().as_str(),
+        "error": res.get_message().unwrap_or_default(),
+    });
+    HttpResponse::new().send_json_str(http, data.to_string())?;
+    Ok(match res.get_kind() {
+        ErrorKind::NotFound => ExecuteResult::NotFound,
+        ErrorKind::PermissionDenied => ExecuteResult::PermissionDenied,
+        ErrorKind::AlreadyExists => ExecuteResult::AlreadyExists,
+        ErrorKind::NotEmpty => ExecuteResult::NotEmpty,
+        ErrorKind::Forbidden => ExecuteResult::Forbidden,
+        ErrorKind::InvalidInput | ErrorKind::IsADirectory | ErrorKind::NotADirectory => {
+            ExecuteResult::InvalidPath
+        }
+        _ => ExecuteResult::Faild,
+    })
+}
+
+/// Send `task`, and answer the http request with `json` of what the
+/// receiver answers and `"status": "ok"`.
+pub(crate) fn request<T, W, A>(
+    task: &impl Encode,
+    mut stream: T,
+    http: W,
+    json: impl FnOnce(A) -> serde_json::Value,
+) -> io::Result<ExecuteResult>
+where
+    T: io::Read + io::Write,
+    W: io::Write,
+    A: Decode,
+{
+    task.encode(&mut stream)?;
+    stream.flush()?;
+    let res = Response::read_from(&mut stream)?;
+    if !res.is_ok() {
+        return send_failed(http, &res);
+    }
+    let mut data = json(A::decode(&mut stream)?);
+    data["status"] = "ok".into();
+    HttpResponse::new().send_json_str(http, data.to_string())?;
+    Ok(ExecuteResult::Ok)
+}
 
-use crate::{ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId};
+/// The receiver side of [`request`], [`Response::ok`] and the answer, or
+/// the error.
+pub(crate) fn respond<S: io::Write, A: Encode>(
+    mut stream: S,
+    answer: io::Result<A>,
+) -> io::Result<S> {
+    match answer {
+        Ok(v) => {
+            Response::ok().write_to(&mut stream)?;
+            v.encode(&mut stream)?;
+        }
+        Err(err) => Response::from(&err).write_to(&mut stream)?,
+    }
+    stream.flush()?;
+    Ok(stream)
+}
 
+#[derive(Deserialize)]
 pub struct RemoveFileSync {
-    path: OsString,
+    path: PathBuf,
 }
 
 impl<T: Into<OsString>> From<T> for RemoveFileSync {
     fn from(value: T) -> Self {
-        Self { path: value.into() }
+        Self {
+            path: value.into().into(),
+        }
     }
 }
 
@@ -24,6 +99,9 @@ impl GetId for RemoveFileSync {
     fn id() -> &'static str {
         "remove-file"
     }
+    fn version() -> u32 {
+        2
+    }
 }
 
 impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for RemoveFileSync {
@@ -33,63 +111,42 @@ impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for RemoveFileSy
         _req: &mut HttpRequest,
         http: W,
     ) -> std::io::Result<ExecuteResult> {
-        let mut buf = [0; 1];
-        let path = self.path.to_string_lossy();
-        let bytes = path.as_bytes();
-        let len = bytes.len() as u16;
-        stream.write_all(&len.to_be_bytes())?;
-        stream.write_all(bytes)?;
+        self.path.encode(&mut stream)?;
         stream.flush()?;
-        stream.read_exact(&mut buf)?;
-        let res = HttpResponse::new();
-        let r = ExecuteResult::try_from(buf);
-        if r.is_err() {
-            res.send_str(http, "ERROR: faild to remove file")?;
-            return Err(io::Error::other("Invalid Execution Result"));
-        } else {
-            match r.unwrap() {
-                ExecuteResult::Ok => {
-                    HttpResponse::new().send_json_str(http, r#"{"status":"ok"}"#)?
-                }
-                _ => HttpResponse::new().send_json_str(http, r#"{"status":"faild"}"#)?,
+        let res = match Response::read_from(&mut stream) {
+            Ok(v) => v,
+            Err(err) => {
+                HttpResponse::new().send_str(http, "ERROR: faild to remove file")?;
+                return Err(err);
             }
+        };
+        if !res.is_ok() {
+            return send_failed(http, &res);
         }
-        Ok(r.unwrap())
+        HttpResponse::new().send_json_str(http, r#"{"status":"ok"}"#)?;
+        Ok(ExecuteResult::Ok)
     }
 }
 
 impl ExeReceiverSync for RemoveFileSync {
-    fn execute_on_receiver(mut stream: EStreamSync) -> io::Result<EStreamSync> {
-        let mut buf = [0; 8];
-        stream.read_exact(&mut buf[0..2])?;
-        let mut len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
-        let mut path = Vec::new();
-        loop {
-            let read_len = std::cmp::min(len, buf.len());
-            let n = stream.read(&mut buf[0..read_len])?;
-            if n == 0 {
-                break;
-            }
-            path.extend_from_slice(&buf[0..n]);
-            len -= n;
-            if len == 0 {
-                break;
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let res = match PathBuf::decode(&mut stream) {
+            Ok(path) => match sandbox.write(&path).and_then(std::fs::remove_file) {
+                Ok(()) => Response::ok(),
+                Err(err) => Response::from(&err),
+            },
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Response::error(ErrorKind::InvalidInput, "invalid path")
             }
-        }
-        let s = String::from_utf8_lossy(&path);
-        let path = OsString::from_str(&s);
-        if path.is_err() {
-            stream.write_all(&ExecuteResult::InvalidPath.to_be_bytes())?;
-            stream.flush()?;
-            return Ok(stream);
-        }
-        let path = path.unwrap();
-        if std::fs::remove_file(path).is_err() {
-            stream.write_all(&ExecuteResult::Faild.to_be_bytes())?;
-            stream.flush()?;
-            return Ok(stream);
-        }
-        stream.write_all(&ExecuteResult::Ok.to_be_bytes())?;
+            Err(err) => return Err(err),
+        };
+        res.write_to(&mut stream)?;
         stream.flush()?;
         Ok(stream)
     }
@@ -97,22 +154,228 @@ impl ExeReceiverSync for RemoveFileSync {
 
 impl RemoveFileSync {
     pub fn new(path: OsString) -> Self {
-        Self { path }
+        Self { path: path.into() }
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Entries [`LsSync`] returns per page unless asked otherwise.
+pub const DEFAULT_LS_LIMIT: u32 = 100;
+
+/// Most entries [`LsSync`] returns per page.
+pub const MAX_LS_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    /// Directories first, then files, symlinks and the rest.
+    Kind,
+}
+
+/// Lists a directory of the receiver, one page at a time.
+///
+/// The receiver reads the whole directory to sort it, and only sends the
+/// page between `offset` and `offset + limit`.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+#[serde(default)]
 pub struct LsSync {
     path: PathBuf,
     show_hidden: bool,
+    sort: SortBy,
+    reverse: bool,
+    offset: u64,
+    limit: u32,
+}
+
+impl Default for LsSync {
+    fn default() -> Self {
+        Self::home()
+    }
 }
 
 impl LsSync {
+    /// List the home directory of the receiver.
     pub fn home() -> Self {
-        todo!()
+        Self::new(PathBuf::new())
     }
+    /// List `path` on the receiver, an empty path is its home directory.
     pub fn new<T: Into<PathBuf>>(path: T) -> Self {
-        todo!()
+        Self {
+            path: path.into(),
+            show_hidden: false,
+            sort: SortBy::Name,
+            reverse: false,
+            offset: 0,
+            limit: DEFAULT_LS_LIMIT,
+        }
+    }
+    pub fn show_hidden(mut self, value: bool) -> Self {
+        self.show_hidden = value;
+        self
+    }
+    pub fn sort(mut self, value: SortBy) -> Self {
+        self.sort = value;
+        self
+    }
+    pub fn reverse(mut self, value: bool) -> Self {
+        self.reverse = value;
+        self
+    }
+    pub fn offset(mut self, value: u64) -> Self {
+        self.offset = value;
+        self
+    }
+    /// Entries per page, at most [`MAX_LS_LIMIT`].
+    pub fn limit(mut self, value: u32) -> Self {
+        self.limit = value;
+        self
+    }
+    /// Read the requested page, runs on the receiver.
+    pub fn list(&self) -> io::Result<Listing> {
+        let path = if self.path.as_os_str().is_empty() {
+            home_dir()?
+        } else {
+            self.path.clone()
+        };
+        let path = fs::canonicalize(path)?;
+        let mut entries = Vec::new();
+        for v in fs::read_dir(&path)? {
+            // entries removed or unreadable while listing are left out
+            let Some(entry) = v.ok().and_then(|v| Entry::read(&v).ok()) else {
+                continue;
+            };
+            if self.show_hidden || !entry.hidden {
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a, b| {
+            let order = match self.sort {
+                SortBy::Name => Ordering::Equal,
+                SortBy::Size => a.size.cmp(&b.size),
+                SortBy::Modified => a.modified.cmp(&b.modified),
+                SortBy::Kind => a.kind.cmp(&b.kind),
+            };
+            order.then_with(|| cmp_name(&a.name, &b.name))
+        });
+        if self.reverse {
+            entries.reverse();
+        }
+        let total = entries.len() as u64;
+        let offset = usize::try_from(self.offset).unwrap_or(usize::MAX);
+        let limit = self.limit.min(MAX_LS_LIMIT) as usize;
+        Ok(Listing {
+            path,
+            total,
+            entries: entries.into_iter().skip(offset).take(limit).collect(),
+        })
+    }
+}
+
+fn cmp_name(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+fn home_dir() -> io::Result<PathBuf> {
+    std::env::home_dir()
+        .filter(|v| !v.as_os_str().is_empty())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "home directory not found"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Message, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+    Other,
+}
+
+/// One entry of a [`Listing`]. Symlinks are not followed.
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Milliseconds since the unix epoch, `None` where the platform or the
+    /// file system does not record it.
+    pub modified: Option<u64>,
+    pub created: Option<u64>,
+    pub readonly: bool,
+    /// Unix permission bits, `None` on other platforms.
+    pub mode: Option<u32>,
+    /// Starts with a `.`, or has the hidden attribute on windows.
+    pub hidden: bool,
+}
+
+impl Entry {
+    fn read(entry: &fs::DirEntry) -> io::Result<Self> {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        Ok(Self::new(name, &entry.metadata()?))
+    }
+    fn new(name: String, meta: &fs::Metadata) -> Self {
+        let kind = if meta.is_dir() {
+            EntryKind::Dir
+        } else if meta.is_file() {
+            EntryKind::File
+        } else if meta.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::Other
+        };
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(meta.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        #[cfg(windows)]
+        let hidden = {
+            use std::os::windows::fs::MetadataExt;
+            const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+            meta.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+        };
+        #[cfg(not(windows))]
+        let hidden = false;
+        Self {
+            hidden: hidden || name.starts_with('.'),
+            name,
+            kind,
+            size: meta.len(),
+            modified: meta.modified().ok().and_then(unix_millis),
+            created: meta.created().ok().and_then(unix_millis),
+            readonly: meta.permissions().readonly(),
+            mode,
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> Option<u64> {
+    let v = time.duration_since(UNIX_EPOCH).ok()?;
+    u64::try_from(v.as_millis()).ok()
+}
+
+/// A page of a directory, the answer to [`LsSync`].
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+pub struct Listing {
+    /// Absolute path of the directory.
+    pub path: PathBuf,
+    /// Entries in the whole directory, hidden ones only if they were asked
+    /// for.
+    pub total: u64,
+    #[message(max_len = MAX_LS_LIMIT as usize)]
+    pub entries: Vec<Entry>,
+}
+
+impl LsSync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.path = sandbox.read(&self.path)?;
+        Ok(self)
     }
 }
 
@@ -123,74 +386,1220 @@ impl GetId for LsSync {
 }
 
 impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for LsSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(self, stream, http, |listing: Listing| {
+            serde_json::json!({
+                "path": listing.path,
+                "total": listing.total,
+                "offset": self.offset,
+                "entries": listing.entries,
+            })
+        })
+    }
+}
+
+impl ExeReceiverSync for LsSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.list()))
+    }
+}
+
+/// Largest chunk of a [`DownloadFileSync`].
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+const DIGEST_LEN: usize = 32;
+
+/// Streams a file of the receiver into the http response.
+///
+/// The receiver answers with a [`Response`] carrying the length it sends
+/// and the size of the whole file, then the bytes as chunks of at most
+/// [`CHUNK_SIZE`] (`<u32 len><data>`) up to an empty one, then a trailing
+/// [`Response`] followed by the SHA-256 of the data. The sender holds the
+/// last chunk back until the digest checks out, so a corrupt download never
+/// looks complete.
+///
+/// `offset` and `len` pick a part of the file, to resume an interrupted
+/// transfer. A `Range: bytes=a-b` (or `bytes=a-`) header on the http
+/// request takes their place.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct DownloadFileSync {
+    path: PathBuf,
+    #[serde(default)]
+    offset: u64,
+    /// Up to the end of the file if `None`.
+    #[serde(default)]
+    len: Option<u64>,
+}
+
+impl DownloadFileSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            offset: 0,
+            len: None,
+        }
+    }
+    pub fn offset(mut self, value: u64) -> Self {
+        self.offset = value;
+        self
+    }
+    pub fn len(mut self, value: u64) -> Self {
+        self.len = Some(value);
+        self
+    }
+    /// The file at the requested offset, its size and the length to send,
+    /// `0` past the end.
+    fn open(&self) -> io::Result<(fs::File, u64, u64)> {
+        let mut file = fs::File::open(&self.path)?;
+        let meta = file.metadata()?;
+        if meta.is_dir() {
+            return Err(is_a_directory());
+        }
+        if !meta.is_file() {
+            return Err(invalid_input("not a file"));
+        }
+        let total = meta.len();
+        file.seek(SeekFrom::Start(self.offset))?;
+        let rest = total.saturating_sub(self.offset);
+        Ok((file, total, self.len.map_or(rest, |v| v.min(rest))))
+    }
+}
+
+/// `bytes=a-b` or `bytes=a-` as an offset and a length. Other ranges
+/// (suffixes, several ranges) are not supported, the whole file is sent
+/// for them.
+pub(crate) fn parse_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let start: u64 = start.trim().parse().ok()?;
+    let end = end.trim();
+    if end.is_empty() {
+        return Some((start, None));
+    }
+    let end: u64 = end.parse().ok()?;
+    (end >= start).then(|| (start, Some(end - start + 1)))
+}
+
+/// `attachment; filename="..."` with the characters that would break the
+/// header replaced.
+pub(crate) fn attachment(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    format!("attachment; filename=\"{name}\"")
+}
+
+impl GetId for DownloadFileSync {
+    fn id() -> &'static str {
+        "download-file"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for DownloadFileSync {
     fn execute_on_sender(
         &self,
         mut stream: T,
         req: &mut HttpRequest,
-        http: W,
+        mut http: W,
     ) -> io::Result<ExecuteResult> {
-        let path = self
-            .path
-            .as_os_str()
-            .to_string_lossy()
-            .encode_utf16()
-            .collect::<Vec<u16>>();
-        let len = if let Some(v) = (path.len() as u16).checked_mul(2) {
-            v
+        let task = match req.get_header("Range").and_then(parse_range) {
+            Some((offset, len)) => Self {
+                path: self.path.clone(),
+                offset,
+                len,
+            },
+            None => self.clone(),
+        };
+        task.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            // the range error carries the size of the file
+            return match res.get_payload_len() {
+                Some(total) if res.get_kind() == ErrorKind::InvalidInput => {
+                    HttpResponse::new()
+                        .status(Status::RangeNotSatisfiable)
+                        .content_length(0)
+                        .push_header("Content-Range", format!("bytes */{total}"))
+                        .send(req, &mut http)?;
+                    Ok(ExecuteResult::InvalidRequest)
+                }
+                _ => send_failed(http, &res),
+            };
+        }
+        let len = res.get_payload_len().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing the payload length")
+        })?;
+        let total = u64::decode(&mut stream)?;
+        let mut head = HttpResponse::new()
+            .content_type(content_type(&task.path))
+            .content_length(
+                usize::try_from(len)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Unsupported, "file is too big"))?,
+            )
+            .push_header("Accept-Ranges", "bytes")
+            .push_header("Content-Disposition", attachment(&task.path));
+        if len > 0 && len < total {
+            head = head.status(Status::PartialContent).push_header(
+                "Content-Range",
+                format!("bytes {}-{}/{total}", task.offset, task.offset + len - 1),
+            );
+        }
+        head.send(req, &mut http)?;
+
+        let mut hasher = Sha256::new();
+        let mut received = 0;
+        let mut last = Vec::new();
+        // keep reading after the http side fails, the receiver sends the
+        // whole file either way
+        let mut http_err = None;
+        loop {
+            let n = codec::read_len(&mut stream, CHUNK_SIZE)?;
+            if n == 0 {
+                break;
+            }
+            if http_err.is_none()
+                && let Err(err) = http.write_all(&last)
+            {
+                http_err = Some(err);
+            }
+            last.resize(n, 0);
+            stream.read_exact(&mut last)?;
+            hasher.update(&last);
+            received += n as u64;
+        }
+        Response::read_from(&mut stream)?.into_result()?;
+        let digest = Bytes::decode_max(&mut stream, DIGEST_LEN)?;
+        if let Some(err) = http_err {
+            return Err(err);
+        }
+        if received != len || digest.0 != hasher.finalize().as_slice() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "download does not match its digest",
+            ));
+        }
+        http.write_all(&last)?;
+        http.flush()?;
+        Ok(ExecuteResult::Ok)
+    }
+}
+
+impl ExeReceiverSync for DownloadFileSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let mut req = Self::decode(&mut stream)?;
+        let (file, total, len) = match sandbox.read(&req.path).and_then(|path| {
+            req.path = path;
+            req.open()
+        }) {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        // nothing is left to send from an offset at or past the end, unlike
+        // the whole of an empty file
+        if req.offset > 0 && req.offset >= total {
+            Response::error(
+                ErrorKind::InvalidInput,
+                "offset is past the end of the file",
+            )
+            .payload_len(total)
+            .write_to(&mut stream)?;
+            stream.flush()?;
+            return Ok(stream);
+        }
+        Response::ok().payload_len(len).write_to(&mut stream)?;
+        total.encode(&mut stream)?;
+
+        let mut file = file.take(len);
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut sent = 0;
+        let res = loop {
+            let n = match file.read(&mut buf) {
+                Ok(0) if sent < len => {
+                    break Response::error(ErrorKind::UnexpectedEof, "file shrank while reading");
+                }
+                Ok(0) => break Response::ok(),
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => break Response::from(&err),
+            };
+            hasher.update(&buf[..n]);
+            codec::write_len(&mut stream, n, CHUNK_SIZE)?;
+            stream.write_all(&buf[..n])?;
+            sent += n as u64;
+        };
+        codec::write_len(&mut stream, 0, CHUNK_SIZE)?;
+        res.write_to(&mut stream)?;
+        if res.is_ok() {
+            Bytes(hasher.finalize().to_vec()).encode(&mut stream)?;
+        }
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+/// Longest form field of an upload.
+const MAX_FIELD_LEN: usize = 4096;
+
+/// The content of the file in the body of an upload, see
+/// [`UploadFileSync::from_http`].
+pub enum UploadBody<R: io::Read> {
+    Raw(R),
+    Multipart(Multipart<R>),
+}
+
+impl<R: io::Read> io::Read for UploadBody<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(v) => v.read(buf),
+            Self::Multipart(v) => v.read(buf),
+        }
+    }
+}
+
+/// Read until `buf` is full or `r` ends, the number of bytes read.
+fn read_full<R: io::Read>(mut r: R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(v) => n += v,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(n)
+}
+
+/// What [`UploadFileSync`] does when the destination already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message)]
+pub enum Conflict {
+    /// Replace the existing file.
+    Overwrite,
+    /// Keep the existing file and drop the upload.
+    Skip,
+    /// Place the upload next to it, as `name (1).ext`, `name (2).ext`, ...
+    #[default]
+    Rename,
+}
+
+impl FromStr for Conflict {
+    type Err = io::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(Self::Overwrite),
+            "skip" => Ok(Self::Skip),
+            "rename" => Ok(Self::Rename),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "conflict should be `overwrite`, `skip` or `rename`",
+            )),
+        }
+    }
+}
+
+/// Writes the body of the http request to a file of the receiver.
+///
+/// The receiver first answers with a [`Response`] and whether it skips the
+/// upload. Otherwise the sender sends the data as chunks like
+/// [`DownloadFileSync`], up to an empty one and the SHA-256 of the data.
+/// The receiver writes them to a temporary file in the destination
+/// directory, checks the digest and only then moves the file into place,
+/// so the destination never holds a partial upload. It answers with a
+/// [`Response`] and the path the file ended up at (`None` if it was
+/// skipped).
+#[derive(Debug, Clone, PartialEq, Message)]
+pub struct UploadFileSync {
+    path: PathBuf,
+    conflict: Conflict,
+    /// Unix permission bits to set, ignored on other platforms.
+    mode: Option<u32>,
+    /// Modification time to set, milliseconds since the unix epoch.
+    modified: Option<u64>,
+}
+
+impl UploadFileSync {
+    /// Upload to `path` on the receiver, its directory has to exist.
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            conflict: Conflict::default(),
+            mode: None,
+            modified: None,
+        }
+    }
+    pub fn conflict(mut self, value: Conflict) -> Self {
+        self.conflict = value;
+        self
+    }
+    pub fn mode(mut self, value: u32) -> Self {
+        self.mode = Some(value);
+        self
+    }
+    pub fn modified(mut self, value: u64) -> Self {
+        self.modified = Some(value);
+        self
+    }
+    /// Read the upload out of an http request of the sender and its `body`,
+    /// and return it with the content of the file.
+    ///
+    /// The body is either the raw file with the options in the `Path`,
+    /// `Conflict`, `Mode` and `Modified` headers, or `multipart/form-data`
+    /// with the fields `path`, `conflict`, `mode`, `modified` and last
+    /// `file`. `mode` is octal, like `755`. Only the fields in front of the
+    /// file are read.
+    pub fn from_http<R: io::Read>(req: &HttpRequest, body: R) -> io::Result<(Self, UploadBody<R>)> {
+        let mut fields = HashMap::new();
+        let body = match req.get_header("Content-Type").and_then(multipart::boundary) {
+            Some(boundary) => {
+                let mut form = Multipart::new(body, boundary);
+                loop {
+                    let part = form
+                        .next_part()?
+                        .ok_or_else(|| invalid_input("file not found"))?;
+                    if part.name == "file" {
+                        break;
+                    }
+                    let mut value = Vec::new();
+                    (&mut form)
+                        .take(MAX_FIELD_LEN as u64 + 1)
+                        .read_to_end(&mut value)?;
+                    if value.len() > MAX_FIELD_LEN {
+                        return Err(invalid_input("field is too long"));
+                    }
+                    let value = String::from_utf8_lossy(&value).into_owned();
+                    fields.insert(part.name, value);
+                }
+                UploadBody::Multipart(form)
+            }
+            None => {
+                for (field, header) in [
+                    ("path", "Path"),
+                    ("conflict", "Conflict"),
+                    ("mode", "Mode"),
+                    ("modified", "Modified"),
+                ] {
+                    if let Some(v) = req.get_header(header) {
+                        fields.insert(field.to_owned(), v.to_owned());
+                    }
+                }
+                UploadBody::Raw(body)
+            }
+        };
+        let path = fields
+            .remove("path")
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| invalid_input("path not found"))?;
+        let mut task = Self::new(path);
+        if let Some(v) = fields.get("conflict") {
+            task.conflict = v.parse()?;
+        }
+        if let Some(v) = fields.get("mode") {
+            let v = u32::from_str_radix(v.trim_start_matches("0o"), 8)
+                .map_err(|_| invalid_input("mode should be octal"))?;
+            task.mode = Some(v);
+        }
+        if let Some(v) = fields.get("modified") {
+            let v = v
+                .parse()
+                .map_err(|_| invalid_input("invalid modified time"))?;
+            task.modified = Some(v);
+        }
+        Ok((task, body))
+    }
+    /// The temporary file to write to, `None` if the upload is skipped.
+    fn prepare(&self) -> io::Result<Option<PartFile>> {
+        if self.path.file_name().is_none() {
+            return Err(invalid_input("not a file path"));
+        }
+        match fs::symlink_metadata(&self.path) {
+            Ok(meta) if meta.is_dir() => return Err(is_a_directory()),
+            Ok(_) if self.conflict == Conflict::Skip => return Ok(None),
+            _ => {}
+        }
+        PartFile::create(&self.path).map(Some)
+    }
+    /// Set the times and mode of `part` and move it into place.
+    fn finish(&self, mut part: PartFile) -> io::Result<Option<PathBuf>> {
+        if let Some(v) = self.modified {
+            part.file
+                .set_modified(UNIX_EPOCH + Duration::from_millis(v))?;
+        }
+        #[cfg(unix)]
+        if let Some(v) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            part.file
+                .set_permissions(fs::Permissions::from_mode(v & 0o7777))?;
+        }
+        part.file.sync_all()?;
+        let placed = place(&part.path, &self.path, self.conflict)?;
+        part.placed = placed.is_some();
+        Ok(placed)
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn is_a_directory() -> io::Error {
+    io::Error::new(io::ErrorKind::IsADirectory, "is a directory")
+}
+
+/// An upload in progress, next to its destination. Removed on drop unless
+/// it was placed.
+pub(crate) struct PartFile {
+    pub(crate) path: PathBuf,
+    pub(crate) file: fs::File,
+    pub(crate) placed: bool,
+}
+
+impl PartFile {
+    pub(crate) fn create(dest: &Path) -> io::Result<Self> {
+        let dir = dest.parent().unwrap_or(Path::new(""));
+        let name = dest.file_name().unwrap_or_default().to_string_lossy();
+        let mut n = 0;
+        loop {
+            let path = dir.join(format!(".{name}.{}-{n}.part", std::process::id()));
+            match fs::File::create_new(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        file,
+                        placed: false,
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for PartFile {
+    fn drop(&mut self) {
+        if !self.placed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Move `from` to `dest` following `conflict`, returns where it ended up or
+/// `None` if it was skipped.
+fn place(from: &Path, dest: &Path, conflict: Conflict) -> io::Result<Option<PathBuf>> {
+    if conflict == Conflict::Overwrite {
+        fs::rename(from, dest)?;
+        return Ok(Some(dest.to_owned()));
+    }
+    let mut n = 0;
+    loop {
+        let path = if n == 0 {
+            dest.to_owned()
         } else {
-            return Err(io::Error::other("Path length is too large."));
+            numbered(dest, n)
         };
-        stream.write_all(&len.to_be_bytes())?;
-        for v in path {
-            stream.write_all(&v.to_be_bytes())?;
+        match rename_new(from, &path) {
+            Ok(()) => return Ok(Some(path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                if conflict == Conflict::Skip {
+                    return Ok(None);
+                }
+                n += 1;
+            }
+            Err(err) => return Err(err),
         }
-        todo!()
     }
 }
 
-impl LsSync {
-    pub fn execute_on_server<T: io::Read + io::Write>(mut stream: T) -> io::Result<T> {
-        let mut buf = [0u8; 2];
-        stream.read_exact(&mut buf)?;
-        let mut len = u16::from_be_bytes(buf) as usize;
-        if len % 2 != 0 {
-            return Err(io::Error::other("invalid path length"));
-        }
-        let mut v: Vec<u16> = Vec::new();
+/// `name (n).ext` next to `path`.
+fn numbered(path: &Path, n: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem} ({n}).{}", ext.to_string_lossy()),
+        None => format!("{stem} ({n})"),
+    };
+    path.with_file_name(name)
+}
+
+/// Move `from` to `to` unless `to` exists. A hard link makes the check and
+/// the move one step, file systems without hard links check first.
+fn rename_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => fs::remove_file(from),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Err(err),
+        Err(_) if fs::symlink_metadata(to).is_ok() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "file already exists",
+        )),
+        Err(_) => fs::rename(from, to),
+    }
+}
+
+impl GetId for UploadFileSync {
+    fn id() -> &'static str {
+        "upload-file"
+    }
+}
+
+/// The file is read from `http`, the connection of the request, up to its
+/// end, see [`UploadFileSync::from_http`].
+impl<T: io::Read + io::Write, W: io::Read + io::Write> ExeSenderSync<T, W> for UploadFileSync {
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        _: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        let placed = if bool::decode(&mut stream)? {
+            None
+        } else {
+            let mut hasher = Sha256::new();
+            let mut buf = vec![0; CHUNK_SIZE];
+            loop {
+                let n = read_full(&mut http, &mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                codec::write_len(&mut stream, n, CHUNK_SIZE)?;
+                stream.write_all(&buf[..n])?;
+                hasher.update(&buf[..n]);
+            }
+            codec::write_len(&mut stream, 0, CHUNK_SIZE)?;
+            Bytes(hasher.finalize().to_vec()).encode(&mut stream)?;
+            stream.flush()?;
+            let res = Response::read_from(&mut stream)?;
+            if !res.is_ok() {
+                return send_failed(http, &res);
+            }
+            Option::<PathBuf>::decode(&mut stream)?
+        };
+        let data = serde_json::json!({
+            "status": "ok",
+            "skipped": placed.is_none(),
+            "path": placed.as_ref().unwrap_or(&self.path),
+        });
+        HttpResponse::new().send_json_str(http, data.to_string())?;
+        Ok(ExecuteResult::Ok)
+    }
+}
+
+impl ExeReceiverSync for UploadFileSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let mut req = Self::decode(&mut stream)?;
+        let part = match sandbox.write(&req.path).and_then(|path| {
+            req.path = path;
+            req.prepare()
+        }) {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        part.is_none().encode(&mut stream)?;
+        stream.flush()?;
+        let Some(mut part) = part else {
+            return Ok(stream);
+        };
+
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; CHUNK_SIZE];
+        // keep reading after a failed write, the sender sends the whole
+        // file either way
+        let mut write_err = None;
         loop {
-            if len == 0 {
+            let n = codec::read_len(&mut stream, CHUNK_SIZE)?;
+            if n == 0 {
                 break;
             }
-            stream.read_exact(&mut buf)?;
-            v.push(u16::from_be_bytes(buf));
-            len -= buf.len();
+            stream.read_exact(&mut buf[..n])?;
+            hasher.update(&buf[..n]);
+            if write_err.is_none()
+                && let Err(err) = part.file.write_all(&buf[..n])
+            {
+                write_err = Some(err);
+            }
+        }
+        let digest = Bytes::decode_max(&mut stream, DIGEST_LEN)?;
+        let placed = match write_err {
+            Some(err) => Err(err),
+            None if digest.0 != hasher.finalize().as_slice() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "upload does not match its digest",
+            )),
+            None => req.finish(part),
+        };
+        match placed {
+            Ok(path) => {
+                Response::ok().write_to(&mut stream)?;
+                path.encode(&mut stream)?;
+            }
+            Err(err) => Response::from(&err).write_to(&mut stream)?,
+        }
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+/// Metadata of a path on the receiver, symlinks are not followed.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct StatSync {
+    path: PathBuf,
+}
+
+impl StatSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self { path: path.into() }
+    }
+    /// The absolute path and its entry, runs on the receiver.
+    pub fn stat(&self) -> io::Result<(PathBuf, Entry)> {
+        let meta = fs::symlink_metadata(&self.path)?;
+        let path = std::path::absolute(&self.path)?;
+        let name = match path.file_name() {
+            Some(v) => v.to_string_lossy().into_owned(),
+            None => path.to_string_lossy().into_owned(),
+        };
+        Ok((path, Entry::new(name, &meta)))
+    }
+}
+
+impl StatSync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.path = sandbox.read(&self.path)?;
+        Ok(self)
+    }
+}
+
+impl GetId for StatSync {
+    fn id() -> &'static str {
+        "stat"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for StatSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(
+            self,
+            stream,
+            http,
+            |(path, entry): (PathBuf, Entry)| serde_json::json!({ "path": path, "entry": entry }),
+        )
+    }
+}
+
+impl ExeReceiverSync for StatSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.stat()))
+    }
+}
+
+/// Creates a directory on the receiver, with its missing parents if
+/// `parents` is set (`mkdir -p`).
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct MkdirSync {
+    path: PathBuf,
+    #[serde(default)]
+    parents: bool,
+}
+
+impl MkdirSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            parents: false,
+        }
+    }
+    pub fn parents(mut self, value: bool) -> Self {
+        self.parents = value;
+        self
+    }
+    /// Create the directory and return its absolute path, runs on the
+    /// receiver. With `parents` an existing directory is not an error.
+    pub fn mkdir(&self) -> io::Result<PathBuf> {
+        if self.parents {
+            fs::create_dir_all(&self.path)?;
+        } else {
+            fs::create_dir(&self.path)?;
+        }
+        fs::canonicalize(&self.path)
+    }
+}
+
+impl MkdirSync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.path = sandbox.write(&self.path)?;
+        Ok(self)
+    }
+}
+
+impl GetId for MkdirSync {
+    fn id() -> &'static str {
+        "mkdir"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for MkdirSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(
+            self,
+            stream,
+            http,
+            |path: PathBuf| serde_json::json!({ "path": path }),
+        )
+    }
+}
+
+impl ExeReceiverSync for MkdirSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.mkdir()))
+    }
+}
+
+/// Renames or moves a file or a directory on the receiver.
+///
+/// An existing `to` is an error unless `overwrite` is set. Moves to another
+/// file system copy and then remove.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct RenameSync {
+    from: PathBuf,
+    to: PathBuf,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+impl RenameSync {
+    pub fn new<T: Into<PathBuf>, U: Into<PathBuf>>(from: T, to: U) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+            overwrite: false,
+        }
+    }
+    pub fn overwrite(mut self, value: bool) -> Self {
+        self.overwrite = value;
+        self
+    }
+    /// Move `from` and return the absolute path it ended up at, runs on the
+    /// receiver.
+    pub fn rename(&self) -> io::Result<PathBuf> {
+        fs::symlink_metadata(&self.from)?;
+        let moved = if self.overwrite {
+            fs::rename(&self.from, &self.to)
+        } else {
+            rename_new(&self.from, &self.to)
+        };
+        match moved {
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                CopySync::new(&self.from, &self.to)
+                    .overwrite(self.overwrite)
+                    .copy()?;
+                remove(&self.from)?;
+            }
+            v => v?,
         }
-        let s = String::from_utf16(&v).map_err(|v| io::Error::other(v.to_string()))?;
-        for t in std::fs::read_dir(s)? {
-            let entry = t?;
-            let m = entry.metadata()?;
+        std::path::absolute(&self.to)
+    }
+}
+
+/// Remove a file, a symlink or a directory tree.
+fn remove(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+impl RenameSync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.from = sandbox.write(&self.from)?;
+        self.to = sandbox.write(&self.to)?;
+        Ok(self)
+    }
+}
+
+impl GetId for RenameSync {
+    fn id() -> &'static str {
+        "rename"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for RenameSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(
+            self,
+            stream,
+            http,
+            |path: PathBuf| serde_json::json!({ "path": path }),
+        )
+    }
+}
+
+impl ExeReceiverSync for RenameSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.rename()))
+    }
+}
+
+/// What [`CopySync`] copied or [`RemoveDirSync`] removes. Symlinks count
+/// as files of size 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Serialize)]
+pub struct Summary {
+    pub files: u64,
+    pub dirs: u64,
+    /// Bytes in the files.
+    pub size: u64,
+}
+
+impl Summary {
+    fn add(&mut self, meta: &fs::Metadata) {
+        if meta.is_dir() {
+            self.dirs += 1;
+        } else {
+            self.files += 1;
+            if meta.is_file() {
+                self.size += meta.len();
+            }
+        }
+    }
+}
+
+/// Copies a file or a directory tree on the receiver, without sending it
+/// through the sender.
+///
+/// An existing `to` is an error unless `overwrite` is set, then files are
+/// replaced and directories merged. A file is copied next to `to` first and
+/// moved into place, a directory is copied in place.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct CopySync {
+    from: PathBuf,
+    to: PathBuf,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+impl CopySync {
+    pub fn new<T: Into<PathBuf>, U: Into<PathBuf>>(from: T, to: U) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+            overwrite: false,
+        }
+    }
+    pub fn overwrite(mut self, value: bool) -> Self {
+        self.overwrite = value;
+        self
+    }
+    /// Copy `from` and return the absolute path of the copy, runs on the
+    /// receiver.
+    pub fn copy(&self) -> io::Result<(PathBuf, Summary)> {
+        let meta = fs::symlink_metadata(&self.from)?;
+        let to = std::path::absolute(&self.to)?;
+        if !self.overwrite && fs::symlink_metadata(&to).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "destination already exists",
+            ));
         }
-        todo!()
+        let mut summary = Summary::default();
+        if meta.is_dir() {
+            let from = fs::canonicalize(&self.from)?;
+            let parent = to.parent().map(fs::canonicalize).transpose()?;
+            if parent.is_some_and(|v| v.starts_with(&from)) {
+                return Err(invalid_input("can not copy a directory into itself"));
+            }
+            copy_tree(&from, &to, &mut summary)?;
+        } else if meta.is_file() {
+            let mut part = PartFile::create(&to)?;
+            io::copy(&mut fs::File::open(&self.from)?, &mut part.file)?;
+            part.file.set_permissions(meta.permissions())?;
+            let conflict = if self.overwrite {
+                Conflict::Overwrite
+            } else {
+                Conflict::Skip
+            };
+            part.placed = place(&part.path, &to, conflict)?.is_some();
+            if !part.placed {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "destination already exists",
+                ));
+            }
+            summary.add(&meta);
+        } else {
+            copy_tree(&self.from, &to, &mut summary)?;
+        }
+        Ok((to, summary))
     }
 }
 
-fn to_cross_vec_u8(path: &Path) -> Vec<u8> {
-    let mut v = Vec::new();
-    for i in path.to_string_lossy().encode_utf16() {
-        v.extend_from_slice(&i.to_be_bytes());
+/// Copy `from` to `to`, replacing files and merging directories that exist.
+fn copy_tree(from: &Path, to: &Path, summary: &mut Summary) -> io::Result<()> {
+    let meta = fs::symlink_metadata(from)?;
+    if meta.is_dir() {
+        match fs::create_dir(to) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && to.is_dir() => {}
+            v => v?,
+        }
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()), summary)?;
+        }
+    } else if meta.is_symlink() {
+        #[cfg(unix)]
+        {
+            let target = fs::read_link(from)?;
+            if fs::symlink_metadata(to).is_ok() {
+                fs::remove_file(to)?;
+            }
+            std::os::unix::fs::symlink(target, to)?;
+        }
+        // the target is copied where symlinks need privileges
+        #[cfg(not(unix))]
+        fs::copy(from, to)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+    summary.add(&meta);
+    Ok(())
+}
+
+impl CopySync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.from = sandbox.read(&self.from)?;
+        self.to = sandbox.write(&self.to)?;
+        Ok(self)
     }
-    v
 }
 
-fn from_cros_vec_u8(value: Vec<u8>) -> io::Result<PathBuf> {
-    let mut v: Vec<u16> = Vec::with_capacity(value.len() / 2);
-    let mut i = 0;
-    while i + 1 < value.len() {
-        let n = u16::from_be_bytes([value[i], value[i + 1]]);
-        v.push(n);
-        i += 2;
+impl GetId for CopySync {
+    fn id() -> &'static str {
+        "copy"
     }
-    let s = String::from_utf16(&v).map_err(|v| io::Error::other(v.to_string()))?;
-    let path = PathBuf::from_str(s.as_str()).map_err(|v| io::Error::other(v.to_string()))?;
-    Ok(path)
 }
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for CopySync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(self, stream, http, |(path, summary): (PathBuf, Summary)| {
+            let mut data = serde_json::json!(summary);
+            data["path"] = serde_json::json!(path);
+            data
+        })
+    }
+}
+
+impl ExeReceiverSync for CopySync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.copy()))
+    }
+}
+
+/// Removes a directory on the receiver, with everything in it if
+/// `recursive` is set. With `dry_run` nothing is removed, the answer is what
+/// would be.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct RemoveDirSync {
+    path: PathBuf,
+    #[serde(default)]
+    recursive: bool,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl RemoveDirSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            recursive: false,
+            dry_run: false,
+        }
+    }
+    pub fn recursive(mut self, value: bool) -> Self {
+        self.recursive = value;
+        self
+    }
+    pub fn dry_run(mut self, value: bool) -> Self {
+        self.dry_run = value;
+        self
+    }
+    /// Remove the directory, runs on the receiver. Symlinks in it are
+    /// removed, not followed. Root directories are never removed.
+    pub fn remove(&self) -> io::Result<Summary> {
+        if !fs::symlink_metadata(&self.path)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            ));
+        }
+        let path = fs::canonicalize(&self.path)?;
+        if path.parent().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "refusing to remove a root directory",
+            ));
+        }
+        let mut summary = Summary::default();
+        if !self.recursive {
+            if fs::read_dir(&path)?.next().is_some() {
+                return Err(io::Error::new(
+                    io::ErrorKind::DirectoryNotEmpty,
+                    "directory not empty",
+                ));
+            }
+            summary.dirs = 1;
+        } else {
+            count(&path, &mut summary)?;
+        }
+        if !self.dry_run {
+            fs::remove_dir_all(&path)?;
+        }
+        Ok(summary)
+    }
+}
+
+fn count(path: &Path, summary: &mut Summary) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            count(&entry?.path(), summary)?;
+        }
+    }
+    summary.add(&meta);
+    Ok(())
+}
+
+impl RemoveDirSync {
+    fn sandboxed(mut self, sandbox: &Sandbox) -> io::Result<Self> {
+        self.path = sandbox.write(&self.path)?;
+        Ok(self)
+    }
+}
+
+impl GetId for RemoveDirSync {
+    fn id() -> &'static str {
+        "remove-dir"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for RemoveDirSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(self, stream, http, |summary: Summary| {
+            let mut data = serde_json::json!(summary);
+            data["dry_run"] = self.dry_run.into();
+            data
+        })
+    }
+}
+
+impl ExeReceiverSync for RemoveDirSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, req.sandboxed(sandbox).and_then(|v| v.remove()))
+    }
+}
+
+#[cfg(test)]
+mod test;
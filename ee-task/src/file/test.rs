@@ -0,0 +1,637 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ee_http::{HttpRequest, Method};
+use ee_proto::{Bytes, Decode, Encode, ErrorKind, Response, codec};
+use ee_test_util::{Pipe, TempDir};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    file::{
+        CHUNK_SIZE, Conflict, CopySync, DownloadFileSync, EntryKind, Listing, LsSync, MkdirSync,
+        RemoveDirSync, RenameSync, SortBy, StatSync, Summary, UploadFileSync, parse_range,
+    },
+    test_util::{content, exchange, receive},
+};
+
+/// `A.txt` (1 byte), `b.txt` (10 bytes), `c/`, `.hidden` and on unix the
+/// symlink `link` to `b.txt`.
+fn fixture(name: &str) -> TempDir {
+    let dir = TempDir::new(name)
+        .file("A.txt", b"a")
+        .file("b.txt", b"0123456789")
+        .dir("c")
+        .file(".hidden", b"");
+    #[cfg(unix)]
+    let dir = dir.symlink("link", "b.txt");
+    dir
+}
+
+fn names(listing: &Listing) -> Vec<&str> {
+    listing.entries.iter().map(|v| v.name.as_str()).collect()
+}
+
+#[cfg(unix)]
+const ALL: [&str; 4] = ["A.txt", "b.txt", "c", "link"];
+#[cfg(not(unix))]
+const ALL: [&str; 3] = ["A.txt", "b.txt", "c"];
+
+#[test]
+fn test_ls_hidden() {
+    let dir = fixture("ls-hidden");
+    let listing = LsSync::new(dir.path()).list().unwrap();
+    assert_eq!(names(&listing), ALL);
+    assert_eq!(listing.total, ALL.len() as u64);
+    assert_eq!(listing.path, fs::canonicalize(dir.path()).unwrap());
+
+    let listing = LsSync::new(dir.path()).show_hidden(true).list().unwrap();
+    assert_eq!(listing.entries[0].name, ".hidden");
+    assert!(listing.entries[0].hidden);
+    assert_eq!(listing.total, ALL.len() as u64 + 1);
+}
+
+#[test]
+fn test_ls_entries() {
+    let dir = fixture("ls-entries");
+    let listing = LsSync::new(dir.path()).list().unwrap();
+    let b = &listing.entries[1];
+    assert_eq!(b.kind, EntryKind::File);
+    assert_eq!(b.size, 10);
+    assert!(b.modified.is_some());
+    assert!(!b.readonly && !b.hidden);
+    assert_eq!(listing.entries[2].kind, EntryKind::Dir);
+    #[cfg(unix)]
+    {
+        assert!(b.mode.is_some());
+        // symlinks are not followed
+        assert_eq!(listing.entries[3].kind, EntryKind::Symlink);
+    }
+}
+
+#[test]
+fn test_ls_sort() {
+    let dir = fixture("ls-sort");
+    let ls = || LsSync::new(dir.path());
+    let listing = ls().reverse(true).list().unwrap();
+    let mut all = ALL.to_vec();
+    all.reverse();
+    assert_eq!(names(&listing), all);
+
+    let listing = ls().sort(SortBy::Kind).list().unwrap();
+    assert_eq!(listing.entries[0].name, "c");
+
+    let listing = ls().sort(SortBy::Size).reverse(true).list().unwrap();
+    // the directory and the symlink may be bigger than the files
+    let sizes = listing.entries.iter().map(|v| v.size).collect::<Vec<_>>();
+    assert!(sizes.is_sorted_by(|a, b| a >= b));
+}
+
+#[test]
+fn test_ls_pages() {
+    let dir = fixture("ls-pages");
+    let listing = LsSync::new(dir.path()).offset(1).limit(2).list().unwrap();
+    assert_eq!(names(&listing), ALL[1..3]);
+    assert_eq!(listing.total, ALL.len() as u64);
+
+    let listing = LsSync::new(dir.path()).offset(10).list().unwrap();
+    assert!(listing.entries.is_empty());
+    assert_eq!(listing.total, ALL.len() as u64);
+
+    let listing = LsSync::new(dir.path()).limit(0).list().unwrap();
+    assert!(listing.entries.is_empty());
+}
+
+#[test]
+fn test_ls_on_receiver() {
+    let dir = fixture("ls-receiver");
+    let req = LsSync::new(dir.path()).limit(2);
+    let output = receive(&req);
+    let mut r = output.as_slice();
+    assert!(Response::read_from(&mut r).unwrap().is_ok());
+    assert_eq!(Listing::decode(&mut r).unwrap(), req.list().unwrap());
+    assert!(r.is_empty());
+
+    let output = receive(&LsSync::new(dir.path().join("missing")));
+    let res = Response::read_from(output.as_slice()).unwrap();
+    assert_eq!(res.get_kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn test_ls_on_sender() {
+    let dir = fixture("ls-sender");
+    let req = LsSync::new(dir.path()).offset(1);
+    let mut http = Vec::new();
+    let r = req
+        .execute_on_sender(
+            Pipe::new(receive(&req)),
+            &mut HttpRequest::default(),
+            &mut http,
+        )
+        .unwrap();
+    assert_eq!(r, ExecuteResult::Ok);
+    let http = String::from_utf8(http).unwrap();
+    let (_, body) = http.split_once("\r\n\r\n").unwrap();
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["offset"], 1);
+    assert_eq!(json["total"], ALL.len());
+    assert_eq!(json["entries"][0]["name"], "b.txt");
+    assert_eq!(json["entries"][0]["kind"], "file");
+    assert_eq!(json["entries"][0]["size"], 10);
+
+    let req = LsSync::new(dir.path().join("missing"));
+    let mut http = Vec::new();
+    let r = req
+        .execute_on_sender(
+            Pipe::new(receive(&req)),
+            &mut HttpRequest::default(),
+            &mut http,
+        )
+        .unwrap();
+    assert_eq!(r, ExecuteResult::NotFound);
+    assert!(
+        String::from_utf8(http)
+            .unwrap()
+            .contains(r#""status":"faild""#)
+    );
+}
+
+#[test]
+fn test_ls_from_json() {
+    let v: LsSync = serde_json::from_str(r#"{"path":"/tmp","sort":"size","limit":5}"#).unwrap();
+    assert_eq!(v, LsSync::new("/tmp").sort(SortBy::Size).limit(5));
+    let v: LsSync = serde_json::from_str("{}").unwrap();
+    assert_eq!(v, LsSync::home());
+}
+
+/// Head and body of the http response the sender writes for `req`, with
+/// `output` as the answer of the receiver.
+fn download(
+    task: &DownloadFileSync,
+    req: &mut HttpRequest,
+    output: Vec<u8>,
+) -> (io::Result<ExecuteResult>, String, Vec<u8>) {
+    let mut http = Vec::new();
+    let r = task.execute_on_sender(Pipe::new(output), req, &mut http);
+    let end = http
+        .windows(4)
+        .position(|v| v == b"\r\n\r\n")
+        .map_or(http.len(), |i| i + 4);
+    let body = http.split_off(end);
+    (r, String::from_utf8(http).unwrap(), body)
+}
+
+#[test]
+fn test_download_whole_file() {
+    let dir = TempDir::new("download-whole");
+    let path = dir.path().join("data.txt");
+    let data = content(2 * CHUNK_SIZE + 100);
+    fs::write(&path, &data).unwrap();
+    let task = DownloadFileSync::new(&path);
+    let (r, head, body) = download(&task, &mut HttpRequest::default(), receive(&task));
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert!(head.contains("Content-Type: text/plain\r\n"));
+    assert!(head.contains(&format!("Content-Length: {}\r\n", data.len())));
+    assert!(head.contains("Content-Disposition: attachment; filename=\"data.txt\"\r\n"));
+    assert_eq!(body, data);
+
+    let path = dir.path().join("empty");
+    fs::write(&path, b"").unwrap();
+    let task = DownloadFileSync::new(&path);
+    let (r, head, body) = download(&task, &mut HttpRequest::default(), receive(&task));
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(head.contains("Content-Length: 0\r\n"));
+    assert!(body.is_empty());
+}
+
+#[test]
+fn test_download_range() {
+    let dir = TempDir::new("download-range");
+    let path = dir.path().join("data.bin");
+    let data = content(CHUNK_SIZE + 10);
+    fs::write(&path, &data).unwrap();
+
+    let task = DownloadFileSync::new(&path)
+        .offset(CHUNK_SIZE as u64)
+        .len(100);
+    let (r, head, body) = download(&task, &mut HttpRequest::default(), receive(&task));
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(head.starts_with("HTTP/1.1 206"), "{head}");
+    assert!(head.contains("Content-Type: application/octet-stream\r\n"));
+    assert!(head.contains(&format!(
+        "Content-Range: bytes {}-{}/{}\r\n",
+        CHUNK_SIZE,
+        data.len() - 1,
+        data.len()
+    )));
+    assert_eq!(body, data[CHUNK_SIZE..]);
+
+    // the receiver gets the range of the http request
+    let task = DownloadFileSync::new(&path);
+    let mut req = HttpRequest::default().header("Range", "bytes=10-19");
+    let ranged = DownloadFileSync::new(&path).offset(10).len(10);
+    let (r, head, body) = download(&task, &mut req, receive(&ranged));
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(head.contains("Content-Range: bytes 10-19/"));
+    assert_eq!(body, data[10..20]);
+}
+
+#[test]
+fn test_download_tampered() {
+    let dir = TempDir::new("download-tampered");
+    let path = dir.path().join("data");
+    let data = content(CHUNK_SIZE + 1);
+    fs::write(&path, &data).unwrap();
+    let task = DownloadFileSync::new(&path);
+    let mut output = receive(&task);
+    // response, file size and the length of the first chunk come first
+    output[11 + 8 + 4] ^= 1;
+    let (r, _, body) = download(&task, &mut HttpRequest::default(), output);
+    assert_eq!(r.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    // the last chunk is held back
+    assert_eq!(body.len(), CHUNK_SIZE);
+}
+
+#[test]
+fn test_download_failed() {
+    let dir = TempDir::new("download-failed");
+    let path = dir.path().join("data");
+    fs::write(&path, b"0123456789").unwrap();
+    for (task, result) in [
+        (
+            DownloadFileSync::new(dir.path().join("missing")),
+            ExecuteResult::NotFound,
+        ),
+        (
+            DownloadFileSync::new(dir.path()),
+            ExecuteResult::InvalidPath,
+        ),
+    ] {
+        let output = receive(&task);
+        assert!(!Response::read_from(output.as_slice()).unwrap().is_ok());
+        let (r, head, body) = download(&task, &mut HttpRequest::default(), output);
+        assert_eq!(r.unwrap(), result, "{task:?}");
+        assert!(head.contains("application/json"));
+        assert!(
+            String::from_utf8(body)
+                .unwrap()
+                .contains(r#""status":"faild""#)
+        );
+    }
+}
+
+#[test]
+fn test_download_unsatisfiable() {
+    let dir = TempDir::new("download-unsatisfiable");
+    let path = dir.path().join("data");
+    fs::write(&path, b"0123456789").unwrap();
+    for offset in [10, 11] {
+        let task = DownloadFileSync::new(&path).offset(offset);
+        let (r, head, body) = download(&task, &mut HttpRequest::default(), receive(&task));
+        assert_eq!(r.unwrap(), ExecuteResult::InvalidRequest);
+        assert!(head.starts_with("HTTP/1.1 416"), "{head}");
+        assert!(head.contains("Content-Range: bytes */10\r\n"), "{head}");
+        assert!(head.contains("Content-Length: 0\r\n"));
+        assert!(body.is_empty());
+    }
+
+    // from the range of the http request too
+    let task = DownloadFileSync::new(&path);
+    let mut req = HttpRequest::default().header("Range", "bytes=10-");
+    let ranged = DownloadFileSync::new(&path).offset(10);
+    let (r, head, _) = download(&task, &mut req, receive(&ranged));
+    assert_eq!(r.unwrap(), ExecuteResult::InvalidRequest);
+    assert!(head.contains("Content-Range: bytes */10\r\n"), "{head}");
+}
+
+#[test]
+fn test_download_parse_range() {
+    assert_eq!(parse_range("bytes=0-9"), Some((0, Some(10))));
+    assert_eq!(parse_range("bytes=5-"), Some((5, None)));
+    assert_eq!(parse_range("bytes=5-5"), Some((5, Some(1))));
+    assert_eq!(parse_range("bytes=9-0"), None);
+    assert_eq!(parse_range("bytes=-5"), None);
+    assert_eq!(parse_range("bytes=0-1,4-5"), None);
+    assert_eq!(parse_range("items=0-1"), None);
+}
+
+/// Names in `dir`, sorted.
+fn dir_names(dir: &Path) -> Vec<String> {
+    let mut v = fs::read_dir(dir)
+        .unwrap()
+        .map(|v| v.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    v.sort();
+    v
+}
+
+#[test]
+fn test_upload_raw() {
+    let dir = TempDir::new("upload-raw");
+    let path = dir.path().join("app.bin");
+    let data = content(CHUNK_SIZE * 2 + 3);
+    let mut req = HttpRequest::default()
+        .method(Method::Put)
+        .header("Path", path.to_str().unwrap())
+        .header("Mode", "750")
+        .header("Modified", "1700000000000")
+        .body(Some(data.clone()));
+    let (task, mut body) = UploadFileSync::from_http(&req, data.as_slice()).unwrap();
+    assert_eq!(
+        task,
+        UploadFileSync::new(&path)
+            .mode(0o750)
+            .modified(1_700_000_000_000)
+    );
+    let mut file = Vec::new();
+    body.read_to_end(&mut file).unwrap();
+    assert_eq!(file, data);
+    let (r, json) = exchange(&task, &mut req);
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["skipped"], false);
+    assert_eq!(json["path"], path.to_str().unwrap());
+    assert_eq!(fs::read(&path).unwrap(), data);
+    let meta = fs::metadata(&path).unwrap();
+    assert_eq!(
+        meta.modified().unwrap(),
+        UNIX_EPOCH + Duration::from_millis(1_700_000_000_000)
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o750);
+    }
+    assert_eq!(dir_names(dir.path()), ["app.bin"]);
+}
+
+#[test]
+fn test_upload_multipart() {
+    let body = b"--b\r\n\
+Content-Disposition: form-data; name=\"path\"\r\n\r\n/tmp/x.txt\r\n\
+--b\r\n\
+Content-Disposition: form-data; name=\"conflict\"\r\n\r\noverwrite\r\n\
+--b\r\n\
+Content-Disposition: form-data; name=\"file\"; filename=\"x.txt\"\r\n\r\nhello\r\n\
+--b--\r\n";
+    let req = HttpRequest::default()
+        .method(Method::Post)
+        .header("Content-Type", "multipart/form-data; boundary=b");
+    let (task, mut file) = UploadFileSync::from_http(&req, &body[..]).unwrap();
+    assert_eq!(
+        task,
+        UploadFileSync::new("/tmp/x.txt").conflict(Conflict::Overwrite)
+    );
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello");
+
+    // the fields come before the file
+    let body = b"--b\r\n\
+Content-Disposition: form-data; name=\"file\"\r\n\r\nhello\r\n\
+--b\r\n\
+Content-Disposition: form-data; name=\"path\"\r\n\r\n/tmp/x.txt\r\n\
+--b--\r\n";
+    assert!(UploadFileSync::from_http(&req, &body[..]).is_err());
+    assert!(UploadFileSync::from_http(&req, &b"--b--\r\n"[..]).is_err());
+
+    let req = HttpRequest::default().header("Conflict", "skip");
+    assert!(UploadFileSync::from_http(&req, io::empty()).is_err());
+    let req = HttpRequest::default()
+        .header("Path", "/tmp/x")
+        .header("Conflict", "merge");
+    assert!(UploadFileSync::from_http(&req, io::empty()).is_err());
+}
+
+#[test]
+fn test_upload_conflict() {
+    let dir = TempDir::new("upload-conflict");
+    let path = dir.path().join("a.txt");
+    fs::write(&path, b"old").unwrap();
+    let upload = |conflict| {
+        let mut req = HttpRequest::default().body(Some(b"new".to_vec()));
+        let task = UploadFileSync::new(&path).conflict(conflict);
+        let (r, json) = exchange(&task, &mut req);
+        assert_eq!(r.unwrap(), ExecuteResult::Ok);
+        json
+    };
+
+    let json = upload(Conflict::Skip);
+    assert_eq!(json["skipped"], true);
+    assert_eq!(fs::read(&path).unwrap(), b"old");
+
+    let json = upload(Conflict::Rename);
+    let renamed = dir.path().join("a (1).txt");
+    assert_eq!(json["skipped"], false);
+    assert_eq!(json["path"], renamed.to_str().unwrap());
+    assert_eq!(fs::read(&renamed).unwrap(), b"new");
+    upload(Conflict::Rename);
+    assert_eq!(dir_names(dir.path()), ["a (1).txt", "a (2).txt", "a.txt"]);
+
+    upload(Conflict::Overwrite);
+    assert_eq!(fs::read(&path).unwrap(), b"new");
+    assert_eq!(dir_names(dir.path()).len(), 3);
+}
+
+#[test]
+fn test_upload_tampered() {
+    let dir = TempDir::new("upload-tampered");
+    let path = dir.path().join("a.txt");
+    let mut input = Vec::new();
+    UploadFileSync::new(&path).encode(&mut input).unwrap();
+    codec::write_len(&mut input, 5, CHUNK_SIZE).unwrap();
+    input.extend_from_slice(b"hello");
+    codec::write_len(&mut input, 0, CHUNK_SIZE).unwrap();
+    Bytes(vec![0; 32]).encode(&mut input).unwrap();
+    let output = UploadFileSync::execute_on_receiver(Pipe::new(input))
+        .unwrap()
+        .output;
+    let mut r = output.as_slice();
+    assert!(Response::read_from(&mut r).unwrap().is_ok());
+    assert!(!bool::decode(&mut r).unwrap());
+    let res = Response::read_from(&mut r).unwrap();
+    assert_eq!(res.get_kind(), ErrorKind::InvalidData);
+    assert!(r.is_empty());
+    // neither the file nor the temporary one are left
+    assert!(dir_names(dir.path()).is_empty());
+}
+
+#[test]
+fn test_upload_failed() {
+    let dir = TempDir::new("upload-failed");
+    for (path, result, kind) in [
+        (
+            dir.path().join("missing/a.txt"),
+            ExecuteResult::NotFound,
+            "not_found",
+        ),
+        (
+            dir.path().to_owned(),
+            ExecuteResult::InvalidPath,
+            "is_a_directory",
+        ),
+    ] {
+        let mut req = HttpRequest::default().body(Some(b"new".to_vec()));
+        let (r, json) = exchange(&UploadFileSync::new(&path), &mut req);
+        assert_eq!(r.unwrap(), result, "{path:?}");
+        assert_eq!(json["status"], "faild");
+        assert_eq!(json["kind"], kind);
+    }
+}
+
+#[test]
+fn test_stat() {
+    let dir = fixture("stat");
+    let (path, entry) = StatSync::new(dir.path().join("b.txt")).stat().unwrap();
+    assert!(path.is_absolute());
+    assert_eq!(entry.name, "b.txt");
+    assert_eq!(entry.kind, EntryKind::File);
+    assert_eq!(entry.size, 10);
+    #[cfg(unix)]
+    {
+        let (_, entry) = StatSync::new(dir.path().join("link")).stat().unwrap();
+        assert_eq!(entry.kind, EntryKind::Symlink);
+    }
+
+    let (r, json) = exchange(&StatSync::new(dir.path()), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["entry"]["kind"], "dir");
+
+    let task = StatSync::new(dir.path().join("missing"));
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["kind"], "not_found");
+}
+
+#[test]
+fn test_mkdir() {
+    let dir = TempDir::new("mkdir");
+    let nested = dir.path().join("a/b");
+    let err = MkdirSync::new(&nested).mkdir().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let path = MkdirSync::new(&nested).parents(true).mkdir().unwrap();
+    assert_eq!(path, fs::canonicalize(&nested).unwrap());
+    // an existing directory is fine with parents
+    MkdirSync::new(&nested).parents(true).mkdir().unwrap();
+
+    let (r, json) = exchange(&MkdirSync::new(&nested), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::AlreadyExists);
+    assert_eq!(json["kind"], "already_exists");
+    let (r, json) = exchange(
+        &MkdirSync::new(dir.path().join("c")),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(Path::new(json["path"].as_str().unwrap()).is_dir());
+}
+
+#[test]
+fn test_rename() {
+    let dir = fixture("rename");
+    let a = dir.path().join("A.txt");
+    let b = dir.path().join("b.txt");
+    let err = RenameSync::new(&a, &b).rename().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    assert!(a.exists());
+
+    let moved = dir.path().join("c/a.txt");
+    let (r, json) = exchange(&RenameSync::new(&a, &moved), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["path"], moved.to_str().unwrap());
+    assert!(!a.exists());
+    assert_eq!(fs::read(&moved).unwrap(), b"a");
+
+    RenameSync::new(&moved, &b)
+        .overwrite(true)
+        .rename()
+        .unwrap();
+    assert_eq!(fs::read(&b).unwrap(), b"a");
+    RenameSync::new(dir.path().join("c"), dir.path().join("d"))
+        .rename()
+        .unwrap();
+    assert!(dir.path().join("d").is_dir());
+
+    let task = RenameSync::new(dir.path().join("missing"), &a);
+    let (r, _) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+}
+
+#[test]
+fn test_copy() {
+    let dir = fixture("copy");
+    let b = dir.path().join("b.txt");
+    let copy = dir.path().join("c/b.txt");
+    let (path, summary) = CopySync::new(&b, &copy).copy().unwrap();
+    assert_eq!(path, copy);
+    assert_eq!(
+        summary,
+        Summary {
+            files: 1,
+            dirs: 0,
+            size: 10
+        }
+    );
+    assert_eq!(fs::read(&copy).unwrap(), b"0123456789");
+    assert_eq!(dir_names(&dir.path().join("c")), ["b.txt"]);
+
+    let a = dir.path().join("A.txt");
+    let (r, json) = exchange(&CopySync::new(&a, &copy), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::AlreadyExists);
+    assert_eq!(json["kind"], "already_exists");
+    CopySync::new(&a, &copy).overwrite(true).copy().unwrap();
+    assert_eq!(fs::read(&copy).unwrap(), b"a");
+
+    let tree = dir.path().join("tree");
+    let (r, json) = exchange(
+        &CopySync::new(dir.path(), &tree),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::InvalidPath);
+    assert_eq!(json["kind"], "invalid_input");
+
+    let (r, json) = exchange(
+        &CopySync::new(dir.path().join("c"), &tree),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["files"], 1);
+    assert_eq!(json["dirs"], 1);
+    assert_eq!(json["size"], 1);
+    assert_eq!(fs::read(tree.join("b.txt")).unwrap(), b"a");
+}
+
+#[test]
+fn test_remove_dir() {
+    let dir = fixture("remove-dir");
+    let (r, json) = exchange(&RemoveDirSync::new(dir.path()), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::NotEmpty);
+    assert_eq!(json["kind"], "not_empty");
+
+    let task = RemoveDirSync::new(dir.path()).recursive(true).dry_run(true);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["dry_run"], true);
+    assert_eq!(json["dirs"], 2);
+    // the directory `c` is left out of `ALL`, `.hidden` is not in it
+    assert_eq!(json["files"], ALL.len());
+    assert_eq!(json["size"], 11);
+    assert!(dir.path().join("b.txt").exists());
+
+    let err = RemoveDirSync::new(dir.path().join("b.txt"))
+        .remove()
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotADirectory);
+    RemoveDirSync::new(dir.path().join("c")).remove().unwrap();
+    assert!(!dir.path().join("c").exists());
+    RemoveDirSync::new(dir.path())
+        .recursive(true)
+        .remove()
+        .unwrap();
+    assert!(!dir.path().exists());
+}
@@ -0,0 +1,635 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ee_http::{ChunkedWriter, HttpRequest, HttpResponse};
+use ee_proto::{Decode, Encode, Message, Response};
+use globset::GlobSet;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    exec::TURN,
+    file::{EntryKind, send_failed},
+    glob,
+    sandbox::Sandbox,
+};
+
+/// Most name and exclude patterns of a [`FindSync`].
+pub const MAX_FIND_PATTERNS: usize = 1024;
+
+/// Most matching lines of a file a [`Found`] carries.
+pub const MAX_FIND_MATCHES: u32 = 1000;
+
+/// Bytes of a line searched for the content, the rest of a longer line is
+/// skipped.
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Bytes of a matching line sent back.
+const MAX_MATCH_TEXT: usize = 1024;
+
+/// Most results the receiver sends before it waits for the sender.
+const MAX_TURN_FOUND: usize = 256;
+
+/// Lines of a file searched between two looks at the turn.
+const LINES_PER_TURN: u64 = 4096;
+
+/// Search the files and directories under a directory of the receiver.
+///
+/// An entry is found if it passes every filter given. The receiver answers
+/// with a [`Response`], then sends a [`FindOutput::Found`] for each entry
+/// as it walks, and a [`FindOutput::Wait`] at least every
+/// [`TURN`](crate::exec::TURN) that the sender answers with a
+/// [`FindInput`], up to [`FindOutput::End`]. Symlinks are not followed,
+/// so the search stays under the directory.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct FindSync {
+    /// The directory searched.
+    path: PathBuf,
+    /// Glob patterns of paths relative to `path`, an entry has to match one.
+    #[serde(default)]
+    #[message(max_len = MAX_FIND_PATTERNS)]
+    name: Vec<String>,
+    /// Matched anywhere in the name of an entry.
+    #[serde(default)]
+    regex: Option<String>,
+    /// Matched anywhere in a line, only files with such a line are found.
+    #[serde(default)]
+    content: Option<String>,
+    /// For `regex` and `content`.
+    #[serde(default)]
+    ignore_case: bool,
+    #[serde(default)]
+    kind: Option<EntryKind>,
+    /// Sizes in bytes, only files are found with them.
+    #[serde(default)]
+    min_size: Option<u64>,
+    #[serde(default)]
+    max_size: Option<u64>,
+    /// Modified at or after, in milliseconds since the unix epoch.
+    #[serde(default)]
+    newer: Option<u64>,
+    /// Modified before, in milliseconds since the unix epoch.
+    #[serde(default)]
+    older: Option<u64>,
+    /// Glob patterns of paths left out, directories with what they have.
+    #[serde(default)]
+    #[message(max_len = MAX_FIND_PATTERNS)]
+    exclude: Vec<String>,
+    /// Levels searched below `path`, `1` is only its entries.
+    #[serde(default)]
+    max_depth: Option<u32>,
+    /// Matching lines sent of each file, a file with more is found all the
+    /// same.
+    #[serde(default = "default_max_matches")]
+    max_matches: u32,
+    /// Entries found before the search stops.
+    #[serde(default)]
+    limit: Option<u64>,
+}
+
+fn default_max_matches() -> u32 {
+    10
+}
+
+/// An entry found by a [`FindSync`].
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct Found {
+    /// Relative to the searched directory, `/` separated.
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Milliseconds since the unix epoch.
+    pub modified: Option<u64>,
+    /// The first lines matching the content, up to `max_matches`.
+    pub matches: Vec<LineMatch>,
+}
+
+/// A line of a file matching the content of a [`FindSync`].
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct LineMatch {
+    /// Starts at 1.
+    pub line: u64,
+    /// Without the line break, cut at [`MAX_MATCH_TEXT`] bytes. Text that
+    /// is not valid UTF-8 gets replacement characters.
+    pub text: String,
+}
+
+/// How a [`FindSync`] went.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Message, Serialize)]
+pub struct FindSummary {
+    /// Entries looked at.
+    pub scanned: u64,
+    pub found: u64,
+    /// Directories and files that could not be read.
+    pub unreadable: u64,
+    /// The search stopped at `limit`.
+    pub truncated: bool,
+}
+
+/// What the receiver sends while a [`FindSync`] runs.
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum FindOutput {
+    Found(Found),
+    /// The receiver waits for a [`FindInput`] after it.
+    Wait,
+    End(FindSummary),
+}
+
+/// What the sender answers [`FindOutput::Wait`] with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Message)]
+pub enum FindInput {
+    Continue,
+    /// Stop the search, the receiver goes on with the next task.
+    Cancel,
+}
+
+impl FindSync {
+    pub fn new<T: Into<PathBuf>>(path: T) -> Self {
+        Self {
+            path: path.into(),
+            name: Vec::new(),
+            regex: None,
+            content: None,
+            ignore_case: false,
+            kind: None,
+            min_size: None,
+            max_size: None,
+            newer: None,
+            older: None,
+            exclude: Vec::new(),
+            max_depth: None,
+            max_matches: default_max_matches(),
+            limit: None,
+        }
+    }
+    pub fn name<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.name.push(pattern.into());
+        self
+    }
+    pub fn regex<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.regex = Some(pattern.into());
+        self
+    }
+    pub fn content<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.content = Some(pattern.into());
+        self
+    }
+    pub fn ignore_case(mut self, value: bool) -> Self {
+        self.ignore_case = value;
+        self
+    }
+    pub fn kind(mut self, value: EntryKind) -> Self {
+        self.kind = Some(value);
+        self
+    }
+    pub fn min_size(mut self, value: u64) -> Self {
+        self.min_size = Some(value);
+        self
+    }
+    pub fn max_size(mut self, value: u64) -> Self {
+        self.max_size = Some(value);
+        self
+    }
+    pub fn newer(mut self, value: SystemTime) -> Self {
+        self.newer = Some(millis(value));
+        self
+    }
+    pub fn older(mut self, value: SystemTime) -> Self {
+        self.older = Some(millis(value));
+        self
+    }
+    pub fn exclude<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+    pub fn max_depth(mut self, value: u32) -> Self {
+        self.max_depth = Some(value);
+        self
+    }
+    pub fn max_matches(mut self, value: u32) -> Self {
+        self.max_matches = value;
+        self
+    }
+    pub fn limit(mut self, value: u64) -> Self {
+        self.limit = Some(value);
+        self
+    }
+    /// The compiled patterns and the directory searched.
+    fn prepare(&self, sandbox: &Sandbox) -> io::Result<(Filter<'_>, PathBuf)> {
+        if self.max_matches > MAX_FIND_MATCHES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("max_matches cannot be greater than {MAX_FIND_MATCHES}"),
+            ));
+        }
+        let compile = |v: &Option<String>| {
+            v.as_deref()
+                .map(|v| {
+                    RegexBuilder::new(v)
+                        .case_insensitive(self.ignore_case)
+                        .build()
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+                })
+                .transpose()
+        };
+        let filter = Filter {
+            req: self,
+            name: glob::set(&self.name)?,
+            exclude: glob::set(&self.exclude)?,
+            regex: compile(&self.regex)?,
+            content: compile(&self.content)?,
+        };
+        let root = sandbox.read(&self.path)?;
+        if !fs::metadata(&root)?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            ));
+        }
+        Ok((filter, root))
+    }
+    /// Walk `root` depth first, sending what passes `filter`. Returns
+    /// `false` if the sender cancelled.
+    fn run<S: io::Read + io::Write>(
+        &self,
+        root: &Path,
+        filter: &Filter,
+        search: &mut Search<S>,
+    ) -> io::Result<bool> {
+        let mut dirs = vec![(String::new(), 0)];
+        while let Some((dir, depth)) = dirs.pop() {
+            let entries = match read_dir(&root.join(&dir), &mut search.summary) {
+                Some(v) => v,
+                None => continue,
+            };
+            let mut subdirs = Vec::new();
+            for (name, meta) in entries {
+                if !search.turn()? {
+                    return Ok(false);
+                }
+                let path = if dir.is_empty() {
+                    name
+                } else {
+                    format!("{dir}/{name}")
+                };
+                if filter.exclude.is_match(&path) {
+                    continue;
+                }
+                search.summary.scanned += 1;
+                // not followed, symlinks are not directories
+                if meta.is_dir() && self.max_depth.is_none_or(|v| depth + 1 < v) {
+                    subdirs.push((path.clone(), depth + 1));
+                }
+                let Some(mut found) = filter.check(path, &meta) else {
+                    continue;
+                };
+                if let Some(content) = &filter.content {
+                    match self.grep(&root.join(&found.path), content, search)? {
+                        Grep::Matches(v) => found.matches = v,
+                        Grep::NoMatch => continue,
+                        Grep::Cancelled => return Ok(false),
+                    }
+                }
+                if !search.found(found)? {
+                    return Ok(false);
+                }
+                if self.limit.is_some_and(|v| search.summary.found >= v) {
+                    search.summary.truncated = true;
+                    return Ok(true);
+                }
+            }
+            // the first entries come out first
+            dirs.extend(subdirs.into_iter().rev());
+        }
+        Ok(true)
+    }
+    /// The lines of the file at `path` matching `content`, up to
+    /// `max_matches`. Files that can not be read and binary files do not
+    /// match.
+    fn grep<S: io::Read + io::Write>(
+        &self,
+        path: &Path,
+        content: &Regex,
+        search: &mut Search<S>,
+    ) -> io::Result<Grep> {
+        let mut file = match fs::File::open(path) {
+            Ok(v) => BufReader::new(v),
+            Err(_) => {
+                search.summary.unreadable += 1;
+                return Ok(Grep::NoMatch);
+            }
+        };
+        let max = self.max_matches as usize;
+        let mut matches = Vec::new();
+        let mut matched = false;
+        let mut line = Vec::new();
+        let mut number = 0;
+        loop {
+            match read_line(&mut file, &mut line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(_) => {
+                    search.summary.unreadable += 1;
+                    break;
+                }
+            }
+            number += 1;
+            if line.contains(&0) {
+                return Ok(Grep::NoMatch);
+            }
+            let text = String::from_utf8_lossy(&line);
+            if content.is_match(&text) {
+                matched = true;
+                if matches.len() < max {
+                    matches.push(LineMatch {
+                        line: number,
+                        text: truncate(&text, MAX_MATCH_TEXT).to_string(),
+                    });
+                }
+                if matches.len() >= max {
+                    break;
+                }
+            }
+            if number % LINES_PER_TURN == 0 && !search.turn()? {
+                return Ok(Grep::Cancelled);
+            }
+        }
+        Ok(if matched {
+            Grep::Matches(matches)
+        } else {
+            Grep::NoMatch
+        })
+    }
+}
+
+enum Grep {
+    Matches(Vec<LineMatch>),
+    NoMatch,
+    Cancelled,
+}
+
+/// The compiled patterns of a [`FindSync`].
+struct Filter<'a> {
+    req: &'a FindSync,
+    name: GlobSet,
+    exclude: GlobSet,
+    regex: Option<Regex>,
+    content: Option<Regex>,
+}
+
+impl Filter<'_> {
+    /// The entry at `path` if it passes all but the content.
+    fn check(&self, path: String, meta: &fs::Metadata) -> Option<Found> {
+        let req = self.req;
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        if !self.name.is_empty() && !self.name.is_match(&path) {
+            return None;
+        }
+        if self.regex.as_ref().is_some_and(|v| !v.is_match(name)) {
+            return None;
+        }
+        let kind = if meta.is_dir() {
+            EntryKind::Dir
+        } else if meta.is_file() {
+            EntryKind::File
+        } else if meta.is_symlink() {
+            EntryKind::Symlink
+        } else {
+            EntryKind::Other
+        };
+        if req.kind.is_some_and(|v| v != kind) {
+            return None;
+        }
+        let sized = req.min_size.is_some() || req.max_size.is_some() || self.content.is_some();
+        if sized && kind != EntryKind::File {
+            return None;
+        }
+        let size = meta.len();
+        if req.min_size.is_some_and(|v| size < v) || req.max_size.is_some_and(|v| size > v) {
+            return None;
+        }
+        let modified = meta.modified().ok().map(millis);
+        if req.newer.is_some() || req.older.is_some() {
+            let time = modified?;
+            if req.newer.is_some_and(|v| time < v) || req.older.is_some_and(|v| time >= v) {
+                return None;
+            }
+        }
+        Some(Found {
+            path,
+            kind,
+            size,
+            modified,
+            matches: Vec::new(),
+        })
+    }
+}
+
+/// The receiver's side of a running [`FindSync`].
+struct Search<'a, S> {
+    stream: &'a mut S,
+    turn_end: Instant,
+    sent: usize,
+    summary: FindSummary,
+}
+
+impl<S: io::Read + io::Write> Search<'_, S> {
+    fn found(&mut self, found: Found) -> io::Result<bool> {
+        FindOutput::Found(found).encode(&mut *self.stream)?;
+        self.sent += 1;
+        self.summary.found += 1;
+        self.turn()
+    }
+    /// Wait for the sender once the turn is over, `false` if it cancelled.
+    fn turn(&mut self) -> io::Result<bool> {
+        if self.sent < MAX_TURN_FOUND && Instant::now() < self.turn_end {
+            return Ok(true);
+        }
+        FindOutput::Wait.encode(&mut *self.stream)?;
+        self.stream.flush()?;
+        let input = FindInput::decode(&mut *self.stream)?;
+        self.sent = 0;
+        self.turn_end = Instant::now() + TURN;
+        Ok(input == FindInput::Continue)
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// The entries of `dir` sorted by name, `None` if it can not be read.
+fn read_dir(dir: &Path, summary: &mut FindSummary) -> Option<Vec<(String, fs::Metadata)>> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        summary.unreadable += 1;
+        return None;
+    };
+    let mut out = Vec::new();
+    for entry in entries {
+        match entry.and_then(|v| Ok((v.file_name(), v.metadata()?))) {
+            Ok((name, meta)) => out.push((name.to_string_lossy().into_owned(), meta)),
+            Err(_) => summary.unreadable += 1,
+        }
+    }
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    Some(out)
+}
+
+/// Read a line without its line break into `line`, at most
+/// [`MAX_LINE_LEN`] bytes of it. `false` at the end of the file.
+fn read_line<R: BufRead>(r: &mut R, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    let mut read = false;
+    loop {
+        let buf = match r.fill_buf() {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        if buf.is_empty() {
+            break;
+        }
+        read = true;
+        let (n, end) = match buf.iter().position(|&v| v == b'\n') {
+            Some(v) => (v + 1, true),
+            None => (buf.len(), false),
+        };
+        let room = MAX_LINE_LEN.saturating_sub(line.len());
+        line.extend_from_slice(&buf[..n.min(room)]);
+        r.consume(n);
+        if end {
+            break;
+        }
+    }
+    if line.ends_with(b"\n") {
+        line.pop();
+    }
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(read)
+}
+
+/// `text` cut at a character boundary to at most `max` bytes.
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+impl GetId for FindSync {
+    fn id() -> &'static str {
+        "find"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for FindSync {
+    /// Answers with newline delimited JSON as entries are found, sent with
+    /// `Transfer-Encoding: chunked`, one [`Found`] per line and the
+    /// [`FindSummary`] last. If the http client goes away the search is
+    /// cancelled.
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        req: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        HttpResponse::new()
+            .content_type("application/x-ndjson")
+            .push_header("Transfer-Encoding", "chunked")
+            .send(req, &mut http)?;
+        let mut body = ChunkedWriter::new(&mut http);
+        // keep reading after a failed write, to cancel at the next turn
+        let mut http_err = None;
+        loop {
+            match FindOutput::decode(&mut stream)? {
+                FindOutput::Found(found) => {
+                    if http_err.is_some() {
+                        continue;
+                    }
+                    let mut line = serde_json::to_string(&found).unwrap();
+                    line.push('\n');
+                    if let Err(err) = body.write_all(line.as_bytes()) {
+                        http_err = Some(err);
+                    }
+                }
+                FindOutput::Wait => {
+                    if http_err.is_none()
+                        && let Err(err) = body.flush()
+                    {
+                        http_err = Some(err);
+                    }
+                    if let Some(err) = http_err {
+                        FindInput::Cancel.encode(&mut stream)?;
+                        stream.flush()?;
+                        return Err(err);
+                    }
+                    FindInput::Continue.encode(&mut stream)?;
+                    stream.flush()?;
+                }
+                FindOutput::End(summary) => {
+                    if let Some(err) = http_err {
+                        return Err(err);
+                    }
+                    let mut line = serde_json::json!({ "end": summary }).to_string();
+                    line.push('\n');
+                    body.write_all(line.as_bytes())?;
+                    body.finish()?;
+                    return Ok(ExecuteResult::Ok);
+                }
+            }
+        }
+    }
+}
+
+impl ExeReceiverSync for FindSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let (filter, root) = match req.prepare(sandbox) {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        let mut search = Search {
+            stream: &mut stream,
+            turn_end: Instant::now() + TURN,
+            sent: 0,
+            summary: FindSummary::default(),
+        };
+        if req.run(&root, &filter, &mut search)? {
+            FindOutput::End(search.summary).encode(&mut stream)?;
+        }
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
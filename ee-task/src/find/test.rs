@@ -0,0 +1,205 @@
+use std::{
+    fs, io,
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ee_http::HttpRequest;
+use ee_test_util::TempDir;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    file::EntryKind,
+    find::FindSync,
+    ping_pong::Ping,
+    sandbox::Sandbox,
+    test_util::{Broken, dechunk, exchange, exchange_sandboxed, sandbox_fixture},
+};
+
+/// A tree to search: `notes.txt`, `src/main.rs`, `src/lib.rs`,
+/// `src/deep/mod.rs`, `target/out.bin` (binary) and `big.log` (10 KiB).
+fn find_fixture(name: &str) -> TempDir {
+    TempDir::new(name)
+        .file("notes.txt", "TODO: call\nnothing\ntodo later\n")
+        .file("src/main.rs", "fn main() {\n    // TODO\n}\n")
+        .file("src/lib.rs", "pub mod deep;\n")
+        .file("src/deep/mod.rs", "// TODO one\r\n// TODO two\r\n")
+        .file("target/out.bin", b"TODO\0\x01")
+        .file("big.log", vec![b'x'; 10 * 1024])
+}
+
+/// Run `task` on a receiver limited to `sandbox`, writing the answer to
+/// `http`.
+fn find_on<W: io::Write>(
+    task: &FindSync,
+    sandbox: Sandbox,
+    mut http: W,
+) -> io::Result<ExecuteResult> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let stream = FindSync::execute_sandboxed(stream, &sandbox)?;
+        Ping::execute_on_receiver(stream).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let r = task.execute_on_sender(&mut stream, &mut HttpRequest::default(), &mut http);
+    // the connection is still in step
+    let pong = Ping::new().execute_on_sender(&mut stream, &mut HttpRequest::default(), Vec::new());
+    assert_eq!(pong.unwrap(), ExecuteResult::Ok);
+    receiver.join().unwrap().unwrap();
+    r
+}
+
+/// The found paths of a search and its summary.
+fn find(task: &FindSync) -> (Vec<serde_json::Value>, serde_json::Value) {
+    let mut http = Vec::new();
+    assert_eq!(
+        find_on(task, Sandbox::unrestricted(), &mut http).unwrap(),
+        ExecuteResult::Ok
+    );
+    let (head, body) = http.split_at(http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4);
+    assert!(String::from_utf8_lossy(head).contains("application/x-ndjson"));
+    let mut lines: Vec<serde_json::Value> = String::from_utf8(dechunk(body))
+        .unwrap()
+        .lines()
+        .map(|v| serde_json::from_str(v).unwrap())
+        .collect();
+    let end = lines.pop().unwrap()["end"].clone();
+    assert_eq!(end["found"], lines.len());
+    (lines, end)
+}
+
+fn paths(found: &[serde_json::Value]) -> Vec<&str> {
+    found.iter().map(|v| v["path"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn test_find_names() {
+    let dir = find_fixture("find-names");
+    let (found, end) = find(&FindSync::new(dir.path()).name("*.rs"));
+    assert_eq!(
+        paths(&found),
+        ["src/lib.rs", "src/main.rs", "src/deep/mod.rs"]
+    );
+    assert_eq!(found[0]["kind"], "file");
+    assert_eq!(found[0]["size"], 14);
+    assert!(found[0]["modified"].is_u64());
+    assert_eq!(end["scanned"], 9);
+    assert_eq!(end["truncated"], false);
+
+    let (found, _) = find(&FindSync::new(dir.path()).regex("^(main|mod)\\."));
+    assert_eq!(paths(&found), ["src/main.rs", "src/deep/mod.rs"]);
+    let task = FindSync::new(dir.path())
+        .kind(EntryKind::Dir)
+        .exclude("target");
+    let (found, end) = find(&task);
+    assert_eq!(paths(&found), ["src", "src/deep"]);
+    assert_eq!(end["scanned"], 7);
+    let (found, _) = find(&FindSync::new(dir.path()).name("*.rs").max_depth(2));
+    assert_eq!(paths(&found), ["src/lib.rs", "src/main.rs"]);
+    let (found, end) = find(&FindSync::new(dir.path()).limit(2));
+    assert_eq!(paths(&found), ["big.log", "notes.txt"]);
+    assert_eq!(end["truncated"], true);
+}
+
+#[test]
+fn test_find_size_and_time() {
+    let dir = find_fixture("find-size");
+    let (found, _) = find(&FindSync::new(dir.path()).min_size(1024));
+    assert_eq!(paths(&found), ["big.log"]);
+    let (found, _) = find(&FindSync::new(dir.path()).max_size(6));
+    assert_eq!(paths(&found), ["target/out.bin"]);
+
+    let old = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::File::options()
+        .write(true)
+        .open(dir.path().join("notes.txt"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    let cut = old + Duration::from_secs(1);
+    let (found, _) = find(&FindSync::new(dir.path()).kind(EntryKind::File).older(cut));
+    assert_eq!(paths(&found), ["notes.txt"]);
+    let (found, _) = find(&FindSync::new(dir.path()).name("*.txt").newer(cut));
+    assert!(found.is_empty());
+}
+
+#[test]
+fn test_find_content() {
+    let dir = find_fixture("find-content");
+    let (found, _) = find(&FindSync::new(dir.path()).content("TODO"));
+    // not the binary file
+    assert_eq!(
+        paths(&found),
+        ["notes.txt", "src/main.rs", "src/deep/mod.rs"]
+    );
+    assert_eq!(
+        found[0]["matches"],
+        serde_json::json!([{ "line": 1, "text": "TODO: call" }])
+    );
+    assert_eq!(found[2]["matches"][1]["text"], "// TODO two");
+
+    let task = FindSync::new(dir.path())
+        .name("*.txt")
+        .content("^todo")
+        .ignore_case(true);
+    let (found, _) = find(&task);
+    assert_eq!(found[0]["matches"].as_array().unwrap().len(), 2);
+    assert_eq!(found[0]["matches"][1]["line"], 3);
+    // capped, still found
+    let (found, _) = find(&task.clone().max_matches(1));
+    assert_eq!(found[0]["matches"].as_array().unwrap().len(), 1);
+    let (found, _) = find(&task.max_matches(0));
+    assert_eq!(paths(&found), ["notes.txt"]);
+    assert_eq!(found[0]["matches"], serde_json::json!([]));
+}
+
+#[test]
+fn test_find_cancel() {
+    let dir = TempDir::new("find-cancel");
+    for i in 0..1000 {
+        fs::write(dir.path().join(format!("{i:04}.txt")), b"").unwrap();
+    }
+    let err = find_on(
+        &FindSync::new(dir.path()),
+        Sandbox::unrestricted(),
+        Broken::default(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn test_find_failed() {
+    let dir = find_fixture("find-failed");
+    for task in [
+        FindSync::new(dir.path()).content("(unclosed"),
+        FindSync::new(dir.path()).name("[unclosed"),
+    ] {
+        let (r, json) = exchange(&task, &mut HttpRequest::default());
+        assert_eq!(r.unwrap(), ExecuteResult::InvalidPath);
+        assert_eq!(json["kind"], "invalid_input");
+    }
+    let task = FindSync::new(dir.path().join("notes.txt"));
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::InvalidPath);
+    assert_eq!(json["kind"], "not_a_directory");
+
+    let (dir, sandbox) = sandbox_fixture("find-sandboxed");
+    let task = FindSync::new(dir.path().join("outside"));
+    let (r, json) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert_eq!(json["kind"], "forbidden");
+    // symlinks out of a shared directory are not followed
+    let mut http = Vec::new();
+    let task = FindSync::new(dir.path().join("rw")).content("data");
+    assert_eq!(
+        find_on(&task, sandbox, &mut http).unwrap(),
+        ExecuteResult::Ok
+    );
+    let http = String::from_utf8(http).unwrap();
+    assert!(http.contains("\"a.txt\""), "{http}");
+    assert!(!http.contains("secret"), "{http}");
+}
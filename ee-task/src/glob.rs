@@ -0,0 +1,29 @@
+//! Glob patterns for relative paths with `/` separators.
+//!
+//! `*` matches anything but `/`, `?` one character but `/`, and `**`
+//! anything, `/` included, `**/` also nothing. A pattern without a `/`
+//! matches the name of an entry wherever it is, like in `.gitignore`.
+
+use std::io;
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// `patterns` compiled, an [`io::ErrorKind::InvalidInput`] for a bad one.
+pub(crate) fn set(patterns: &[String]) -> io::Result<GlobSet> {
+    let invalid = |err: globset::Error| io::Error::new(io::ErrorKind::InvalidInput, err);
+    let mut set = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = if pattern.contains('/') {
+            pattern.strip_prefix('/').unwrap_or(pattern).to_owned()
+        } else {
+            format!("**/{pattern}")
+        };
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()
+            .map_err(invalid)?;
+        set.add(glob);
+    }
+    set.build().map_err(invalid)
+}
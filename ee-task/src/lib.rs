@@ -1,14 +1,56 @@
 use std::io;
 
 use ee_http::HttpRequest;
-use ee_stream::EStreamSync;
 
+use crate::sandbox::Sandbox;
+
+pub mod archive;
+pub mod exec;
 pub mod file;
+pub mod find;
+mod glob;
+pub mod metrics;
 pub mod ping_pong;
 pub mod prelude;
+pub mod process;
+pub mod sandbox;
+pub mod shell;
+pub mod sync;
+pub mod system;
+
+#[cfg(test)]
+mod test_util;
 
 pub trait GetId {
     fn id() -> &'static str;
+    /// Version of the task's wire format, bump it on breaking changes so
+    /// senders can tell an outdated receiver apart.
+    fn version() -> u32 {
+        1
+    }
+}
+
+/// Longest task id the receiver reads.
+pub const MAX_ID_LEN: usize = 100;
+
+/// Rules every registered task id has to follow.
+///
+/// Ids that start and end with `:` (`:break:`, `:stop-server:`, ...) are
+/// reserved for the connection itself, and an id is sent as one line.
+pub fn validate_id(id: &str) -> Result<(), &'static str> {
+    if id.is_empty() {
+        return Err("ID can not be empty");
+    }
+    if id.starts_with(':') && id.ends_with(':') {
+        return Err("ID should not start and end with `:`");
+    }
+    if id.len() > MAX_ID_LEN {
+        return Err("ID can not be greater then 100");
+    }
+    if id.contains('\n') {
+        return Err("ID can not contain a new line");
+    }
+    Ok(())
 }
 
 pub trait ExeSenderSync<T: io::Read + io::Write, W: io::Write>: GetId {
@@ -21,7 +63,13 @@ pub trait ExeSenderSync<T: io::Read + io::Write, W: io::Write>: GetId {
 }
 
 pub trait ExeReceiverSync: GetId {
-    fn execute_on_receiver(stream: EStreamSync) -> io::Result<EStreamSync>;
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S>;
+    /// [`ExeReceiverSync::execute_on_receiver`] for a sender limited to
+    /// `sandbox`. Tasks that take paths resolve them with it, the others
+    /// ignore it.
+    fn execute_sandboxed<S: io::Read + io::Write>(stream: S, _sandbox: &Sandbox) -> io::Result<S> {
+        Self::execute_on_receiver(stream)
+    }
 }
 
 #[repr(u8)]
@@ -32,6 +80,13 @@ pub enum ExecuteResult {
     UnknownTask = 2,
     InvalidPath = 3,
     Faild = 4,
+    NotFound = 5,
+    PermissionDenied = 6,
+    AlreadyExists = 7,
+    /// A directory that has to be empty is not.
+    NotEmpty = 8,
+    /// Outside of the directories the receiver shares with this sender.
+    Forbidden = 9,
 }
 
 impl TryFrom<[u8; 1]> for ExecuteResult {
@@ -43,6 +98,11 @@ impl TryFrom<[u8; 1]> for ExecuteResult {
             [2] => Ok(Self::UnknownTask),
             [3] => Ok(Self::InvalidPath),
             [4] => Ok(Self::Faild),
+            [5] => Ok(Self::NotFound),
+            [6] => Ok(Self::PermissionDenied),
+            [7] => Ok(Self::AlreadyExists),
+            [8] => Ok(Self::NotEmpty),
+            [9] => Ok(Self::Forbidden),
             _ => Err(()),
         }
     }
@@ -84,13 +144,12 @@ macro_rules! create_task_registery {
             /// # Panics
             ///
             /// Panics if:
-            /// - the ID starts and ends with `:`
+            /// - the ID breaks the rules of `ee_task::validate_id`
             /// - a task with the same ID already exists
             $v fn register(&mut self, id: &'static str, f: $t) {
-                assert!(
-                    !(id.starts_with(':') && id.ends_with(':')),
-                    "ID should not start and end with `:`"
-                );
+                if let Err(err) = $crate::validate_id(id) {
+                    panic!("{err}");
+                }
                 for &(v, _) in self.inner.iter() {
                     assert_ne!(id, v, "already exists with this ID...");
                 }
@@ -0,0 +1,346 @@
+use std::{
+    io::{self, Write},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ee_http::{ChunkedWriter, HttpRequest, HttpResponse};
+use ee_proto::{Decode, Encode, Message, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId, file::send_failed};
+
+/// Shortest interval between two [`Metrics`].
+pub const MIN_METRICS_INTERVAL: u64 = 100;
+
+/// Longest interval between two [`Metrics`], an hour.
+pub const MAX_METRICS_INTERVAL: u64 = 60 * 60 * 1000;
+
+/// Live resource usage of the receiver (Linux only).
+///
+/// The receiver answers with a [`Response`], then samples the counters of
+/// the system every `interval` and sends a [`MetricsOutput::Frame`] with
+/// the usage since the last one. The sender answers every frame with a
+/// [`MetricsInput`], so the stream goes on until it cancels, or up to
+/// [`MetricsOutput::End`] after `count` frames.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct MetricsSync {
+    /// Milliseconds between two frames.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    /// Frames to send, no limit if `None`.
+    #[serde(default)]
+    count: Option<u32>,
+}
+
+fn default_interval() -> u64 {
+    1000
+}
+
+impl Default for MetricsSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resource usage since the previous frame of a [`MetricsSync`].
+#[derive(Debug, Clone, PartialEq, Message, Serialize)]
+pub struct Metrics {
+    /// Milliseconds since the unix epoch.
+    pub time: u64,
+    /// Percent of the time all CPUs were busy.
+    pub cpu: f64,
+    /// Percent of the time each CPU was busy.
+    pub cpus: Vec<f64>,
+    /// Memory in use, in bytes.
+    pub memory_used: u64,
+    pub memory_total: u64,
+    /// Bytes per second read from and written to the disks.
+    pub disk_read: u64,
+    pub disk_write: u64,
+    /// Bytes per second received and sent, loopback left out.
+    pub net_rx: u64,
+    pub net_tx: u64,
+}
+
+/// What the receiver sends while a [`MetricsSync`] runs.
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum MetricsOutput {
+    /// The receiver waits for a [`MetricsInput`] after it.
+    Frame(Metrics),
+    /// `count` frames are out.
+    End,
+}
+
+/// What the sender answers [`MetricsOutput::Frame`] with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Message)]
+pub enum MetricsInput {
+    Continue,
+    /// No more frames, the receiver goes on with the next task.
+    Cancel,
+}
+
+impl MetricsSync {
+    pub fn new() -> Self {
+        Self {
+            interval: default_interval(),
+            count: None,
+        }
+    }
+    pub fn interval(mut self, value: Duration) -> Self {
+        self.interval = value.as_millis().try_into().unwrap_or(u64::MAX);
+        self
+    }
+    pub fn count(mut self, value: u32) -> Self {
+        self.count = Some(value);
+        self
+    }
+    fn validate(&self) -> io::Result<()> {
+        if !(MIN_METRICS_INTERVAL..=MAX_METRICS_INTERVAL).contains(&self.interval) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "interval has to be {MIN_METRICS_INTERVAL} to {MAX_METRICS_INTERVAL} \
+                     milliseconds"
+                ),
+            ));
+        }
+        Ok(())
+    }
+    /// Stream frames to `stream` up to the cancel of the sender, or `count`.
+    fn run<S: io::Read + io::Write>(&self, stream: &mut S, mut last: Counters) -> io::Result<()> {
+        let mut sent = 0;
+        while self.count.is_none_or(|v| sent < v) {
+            thread::sleep(Duration::from_millis(self.interval));
+            let now = Counters::read()?;
+            MetricsOutput::Frame(now.since(&last)).encode(&mut *stream)?;
+            stream.flush()?;
+            last = now;
+            sent += 1;
+            if MetricsInput::decode(&mut *stream)? == MetricsInput::Cancel {
+                return Ok(());
+            }
+        }
+        MetricsOutput::End.encode(&mut *stream)?;
+        stream.flush()
+    }
+}
+
+/// Counters of the system at one point in time, [`Metrics`] are the
+/// difference of two.
+#[derive(Debug, Clone)]
+struct Counters {
+    at: SystemTime,
+    /// Busy and total time of all CPUs, then of each.
+    cpu: (u64, u64),
+    cpus: Vec<(u64, u64)>,
+    memory_used: u64,
+    memory_total: u64,
+    /// Bytes since the boot.
+    disk_read: u64,
+    disk_write: u64,
+    net_rx: u64,
+    net_tx: u64,
+}
+
+impl Counters {
+    fn since(&self, last: &Self) -> Metrics {
+        let secs = self
+            .at
+            .duration_since(last.at)
+            .unwrap_or_default()
+            .as_secs_f64()
+            .max(0.001);
+        let percent = |(busy, total): (u64, u64), (last_busy, last_total): (u64, u64)| {
+            let total = total.saturating_sub(last_total);
+            if total == 0 {
+                return 0.0;
+            }
+            busy.saturating_sub(last_busy) as f64 * 100.0 / total as f64
+        };
+        let rate = |now: u64, last: u64| (now.saturating_sub(last) as f64 / secs) as u64;
+        Metrics {
+            time: self
+                .at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            cpu: percent(self.cpu, last.cpu),
+            cpus: self
+                .cpus
+                .iter()
+                .zip(&last.cpus)
+                .map(|(&now, &last)| percent(now, last))
+                .collect(),
+            memory_used: self.memory_used,
+            memory_total: self.memory_total,
+            disk_read: rate(self.disk_read, last.disk_read),
+            disk_write: rate(self.disk_write, last.disk_write),
+            net_rx: rate(self.net_rx, last.net_rx),
+            net_tx: rate(self.net_tx, last.net_tx),
+        }
+    }
+    #[cfg(target_os = "linux")]
+    fn read() -> io::Result<Self> {
+        use std::fs;
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid /proc/stat");
+        let mut cpu = None;
+        let mut cpus = Vec::new();
+        for line in fs::read_to_string("/proc/stat")?.lines() {
+            let Some(rest) = line.strip_prefix("cpu") else {
+                continue;
+            };
+            let mut fields = rest.split_whitespace();
+            let name = if rest.starts_with(' ') {
+                None
+            } else {
+                fields.next()
+            };
+            // user nice system idle iowait irq softirq steal, guests are in
+            // user and nice already
+            let times: Vec<u64> = fields.take(8).filter_map(|v| v.parse().ok()).collect();
+            if times.len() < 5 {
+                return Err(invalid());
+            }
+            let total: u64 = times.iter().sum();
+            let busy = total - times[3] - times[4];
+            match name {
+                None => cpu = Some((busy, total)),
+                Some(_) => cpus.push((busy, total)),
+            }
+        }
+
+        // whole disks only, their partitions are in them
+        let disks: Vec<String> = fs::read_dir("/sys/block")?
+            .filter_map(|v| v.ok()?.file_name().into_string().ok())
+            .filter(|v| !v.starts_with("loop") && !v.starts_with("ram"))
+            .collect();
+        let (mut disk_read, mut disk_write) = (0, 0);
+        for line in fs::read_to_string("/proc/diskstats")?.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || !disks.iter().any(|v| v == fields[2]) {
+                continue;
+            }
+            // in sectors of 512 bytes, whatever the disk uses
+            let sectors = |i: usize| fields[i].parse::<u64>().unwrap_or(0) * 512;
+            disk_read += sectors(5);
+            disk_write += sectors(9);
+        }
+
+        let (mut net_rx, mut net_tx) = (0, 0);
+        for line in fs::read_to_string("/proc/net/dev")?.lines().skip(2) {
+            let Some((name, rest)) = line.split_once(':') else {
+                continue;
+            };
+            if name.trim() == "lo" {
+                continue;
+            }
+            let fields: Vec<u64> = rest
+                .split_whitespace()
+                .filter_map(|v| v.parse().ok())
+                .collect();
+            if let (Some(rx), Some(tx)) = (fields.first(), fields.get(8)) {
+                net_rx += rx;
+                net_tx += tx;
+            }
+        }
+
+        let (memory, _) = crate::system::linux::memory();
+        let memory = memory.unwrap_or(crate::system::Memory {
+            total: 0,
+            available: 0,
+        });
+        Ok(Self {
+            at: SystemTime::now(),
+            cpu: cpu.ok_or_else(invalid)?,
+            cpus,
+            memory_used: memory.total.saturating_sub(memory.available),
+            memory_total: memory.total,
+            disk_read,
+            disk_write,
+            net_rx,
+            net_tx,
+        })
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn read() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no metrics on this receiver",
+        ))
+    }
+}
+
+impl GetId for MetricsSync {
+    fn id() -> &'static str {
+        "metrics"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for MetricsSync {
+    /// Answers with Server-Sent Events, one `metrics` event per frame with
+    /// the [`Metrics`] as JSON, sent with `Transfer-Encoding: chunked`. If
+    /// the http client goes away the stream is cancelled.
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        req: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        HttpResponse::new()
+            .content_type("text/event-stream")
+            .push_header("Cache-Control", "no-cache")
+            .push_header("Transfer-Encoding", "chunked")
+            .send(req, &mut http)?;
+        let mut body = ChunkedWriter::new(&mut http);
+        loop {
+            let metrics = match MetricsOutput::decode(&mut stream)? {
+                MetricsOutput::Frame(v) => v,
+                MetricsOutput::End => {
+                    body.write_all(b"event: end\ndata: {}\n\n")?;
+                    body.finish()?;
+                    return Ok(ExecuteResult::Ok);
+                }
+            };
+            let event = format!(
+                "event: metrics\ndata: {}\n\n",
+                serde_json::to_string(&metrics).unwrap()
+            );
+            if let Err(err) = body.write_all(event.as_bytes()).and_then(|_| body.flush()) {
+                MetricsInput::Cancel.encode(&mut stream)?;
+                stream.flush()?;
+                return Err(err);
+            }
+            MetricsInput::Continue.encode(&mut stream)?;
+            stream.flush()?;
+        }
+    }
+}
+
+impl ExeReceiverSync for MetricsSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(mut stream: S) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let first = req.validate().and_then(|_| Counters::read());
+        let first = match first {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        req.run(&mut stream, first)?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
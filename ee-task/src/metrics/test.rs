@@ -0,0 +1,81 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use ee_http::HttpRequest;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    metrics::MetricsSync,
+    ping_pong::Ping,
+    test_util::{Broken, dechunk, exchange},
+};
+
+/// Run `task` on a receiver that answers a [`Ping`] after it.
+fn metrics<W: io::Write>(task: &MetricsSync, mut http: W) -> io::Result<ExecuteResult> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let stream = MetricsSync::execute_on_receiver(stream)?;
+        Ping::execute_on_receiver(stream).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let r = task.execute_on_sender(&mut stream, &mut HttpRequest::default(), &mut http);
+    // the connection is still in step
+    let pong = Ping::new().execute_on_sender(&mut stream, &mut HttpRequest::default(), Vec::new());
+    assert_eq!(pong.unwrap(), ExecuteResult::Ok);
+    receiver.join().unwrap().unwrap();
+    r
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_metrics_events() {
+    let task = MetricsSync::new()
+        .interval(Duration::from_millis(100))
+        .count(2);
+    let mut http = Vec::new();
+    assert_eq!(metrics(&task, &mut http).unwrap(), ExecuteResult::Ok);
+    let (head, body) = http.split_at(http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4);
+    assert!(String::from_utf8_lossy(head).contains("text/event-stream"));
+    let body = String::from_utf8(dechunk(body)).unwrap();
+    let events: Vec<(&str, serde_json::Value)> = body
+        .split_terminator("\n\n")
+        .map(|v| {
+            let (event, data) = v.split_once('\n').unwrap();
+            let data = data.strip_prefix("data: ").unwrap();
+            (event, serde_json::from_str(data).unwrap())
+        })
+        .collect();
+    assert_eq!(events.len(), 3);
+    for (event, data) in &events[..2] {
+        assert_eq!(*event, "event: metrics");
+        let cpu = data["cpu"].as_f64().unwrap();
+        assert!((0.0..=100.0).contains(&cpu), "{cpu}");
+        assert!(!data["cpus"].as_array().unwrap().is_empty());
+        assert!(data["memory_used"].as_u64().unwrap() <= data["memory_total"].as_u64().unwrap());
+    }
+    assert!(events[1].1["time"].as_u64() > events[0].1["time"].as_u64());
+    assert_eq!(events[2].0, "event: end");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_metrics_cancel() {
+    // no count, only the client going away ends it
+    let task = MetricsSync::new().interval(Duration::from_millis(100));
+    let err = metrics(&task, Broken::default()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn test_metrics_invalid() {
+    let task = MetricsSync::new().interval(Duration::from_millis(1));
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::InvalidPath);
+    assert_eq!(json["kind"], "invalid_input");
+}
@@ -1,7 +1,7 @@
-use std::io::{self, Read, Write};
+use std::io;
 
 use ee_http::{HttpRequest, HttpResponse};
-use ee_stream::EStreamSync;
+use ee_proto::{Decode, Encode};
 
 use crate::{ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId};
 
@@ -33,7 +33,7 @@ impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for Ping {
 }
 
 impl ExeReceiverSync for Ping {
-    fn execute_on_receiver(mut stream: EStreamSync) -> io::Result<EStreamSync> {
+    fn execute_on_receiver<S: io::Read + io::Write>(mut stream: S) -> io::Result<S> {
         let mut buf = [0; 4];
         stream.read_exact(&mut buf)?;
         if *b"ping" == buf {
@@ -58,6 +58,12 @@ impl Ping {
 
 pub struct DeviceInfo {}
 
+impl Default for DeviceInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl DeviceInfo {
     pub fn new() -> Self {
         Self {}
@@ -68,6 +74,9 @@ impl GetId for DeviceInfo {
     fn id() -> &'static str {
         "dv-id"
     }
+    fn version() -> u32 {
+        3
+    }
 }
 
 impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for DeviceInfo {
@@ -77,39 +86,16 @@ impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for DeviceInfo {
         _req: &mut HttpRequest,
         http: W,
     ) -> io::Result<ExecuteResult> {
-        let mut buf = [0u8; 8];
-        stream.read_exact(&mut buf[0..1])?;
-        let mut user_len = u8::from_be_bytes([buf[0]]) as usize;
-        stream.read_exact(&mut buf[0..1])?;
-        let mut os_len = u8::from_be_bytes([buf[0]]) as usize;
-        let mut user = Vec::new();
-        loop {
-            if user_len == 0 {
-                break;
-            }
-            let n = stream.read(&mut buf[0..std::cmp::min(user_len, 8)])?;
-            user_len -= n;
-            user.extend_from_slice(&buf[0..n]);
-        }
-        let mut os = Vec::new();
-        loop {
-            if os_len == 0 {
-                break;
-            }
-            let n = stream.read(&mut buf[0..std::cmp::min(os_len, 8)])?;
-            os_len -= n;
-            os.extend_from_slice(&buf[0..n]);
-        }
-        let user = unsafe { String::from_utf8_unchecked(user) };
-        let os = unsafe { String::from_utf8_unchecked(os) };
-        let data = format!("{{\"user\":\"{}\",\"os\":{}}}", user, os);
-        HttpResponse::new().send_json_str(http, data)?;
+        let os = String::decode_max(&mut stream, u8::MAX as usize)?;
+        let user = String::decode_max(&mut stream, u8::MAX as usize)?;
+        let data = serde_json::json!({ "os": os, "user": user });
+        HttpResponse::new().send_json_str(http, data.to_string())?;
         Ok(ExecuteResult::Ok)
     }
 }
 
 impl ExeReceiverSync for DeviceInfo {
-    fn execute_on_receiver(mut stream: EStreamSync) -> io::Result<EStreamSync> {
+    fn execute_on_receiver<S: io::Read + io::Write>(mut stream: S) -> io::Result<S> {
         let user = std::env::var(if cfg!(windows) { "USERNAME" } else { "USER" })
             .unwrap_or("unknown".to_owned());
         let os = if cfg!(target_os = "windows") {
@@ -119,12 +105,13 @@ impl ExeReceiverSync for DeviceInfo {
         } else {
             "unknown"
         };
-        stream.write_all(&(user.len() as u8).to_be_bytes())?;
-        stream.write_all(&(os.len() as u8).to_be_bytes())?;
-        stream.write_all(user.as_bytes())?;
-        stream.write_all(os.as_bytes())?;
-        stream.write_all(&ExecuteResult::Ok.to_be_bytes())?;
+        // in the order of `ee_device::Device`
+        os.encode_max(&mut stream, u8::MAX as usize)?;
+        user.encode_max(&mut stream, u8::MAX as usize)?;
         stream.flush()?;
         Ok(stream)
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,11 @@
+use ee_http::HttpRequest;
+
+use crate::{ExecuteResult, ping_pong::DeviceInfo, test_util::exchange};
+
+#[test]
+fn test_device_info() {
+    let (r, json) = exchange(&DeviceInfo::new(), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(json["os"].is_string());
+    assert!(json["user"].is_string());
+}
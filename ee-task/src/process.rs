@@ -0,0 +1,360 @@
+use std::io;
+
+use ee_http::HttpRequest;
+use ee_proto::{Decode, Message};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    file::{request, respond},
+    sandbox::{self, Sandbox},
+};
+
+/// The processes running on the receiver, read from `/proc` (Linux only).
+///
+/// The filters run on the receiver, so a large process table is not sent
+/// for a few processes. Senders limited to shared directories can not see
+/// the processes.
+#[derive(Debug, Clone, Default, PartialEq, Message, Deserialize)]
+pub struct ProcessListSync {
+    /// Only processes with it in their name, ignoring case.
+    #[serde(default)]
+    name: Option<String>,
+    /// Only processes of this user, a name or a uid.
+    #[serde(default)]
+    user: Option<String>,
+}
+
+/// A process of a [`ProcessListSync`].
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct Process {
+    pub pid: u32,
+    /// `0` for the processes the kernel started.
+    pub ppid: u32,
+    pub name: String,
+    /// Empty for kernel threads.
+    pub cmdline: Vec<String>,
+    pub uid: u32,
+    /// `None` if the uid has no user.
+    pub user: Option<String>,
+    /// Milliseconds on a CPU, user and system.
+    pub cpu_time: u64,
+    /// Resident memory in bytes.
+    pub rss: u64,
+    /// Seconds since the unix epoch.
+    pub start_time: u64,
+}
+
+impl ProcessListSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn name<T: Into<String>>(mut self, value: T) -> Self {
+        self.name = Some(value.into());
+        self
+    }
+    pub fn user<T: Into<String>>(mut self, value: T) -> Self {
+        self.user = Some(value.into());
+        self
+    }
+    fn matches(&self, process: &Process) -> bool {
+        let name = self
+            .name
+            .as_ref()
+            .is_none_or(|v| process.name.to_lowercase().contains(&v.to_lowercase()));
+        let user = self
+            .user
+            .as_ref()
+            .is_none_or(|v| process.user.as_ref() == Some(v) || process.uid.to_string() == *v);
+        name && user
+    }
+    /// The matching processes sorted by pid, runs on the receiver.
+    #[cfg(target_os = "linux")]
+    pub fn list(&self) -> io::Result<Vec<Process>> {
+        let proc = linux::Proc::new()?;
+        let mut processes: Vec<Process> = std::fs::read_dir("/proc")?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            // a process may exit while it is read
+            .filter_map(|pid| proc.process(pid).ok())
+            .filter(|v| self.matches(v))
+            .collect();
+        processes.sort_by_key(|v| v.pid);
+        Ok(processes)
+    }
+    #[cfg(not(target_os = "linux"))]
+    pub fn list(&self) -> io::Result<Vec<Process>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no process list on this receiver",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{collections::HashMap, fs, io};
+
+    use super::Process;
+
+    /// What every process of `/proc` needs.
+    pub(super) struct Proc {
+        /// Clock ticks per second.
+        ticks: u64,
+        page_size: u64,
+        /// Seconds since the unix epoch.
+        boot_time: u64,
+        users: HashMap<u32, String>,
+    }
+
+    impl Proc {
+        pub(super) fn new() -> io::Result<Self> {
+            // SAFETY: `sysconf` only reads a setting
+            let (ticks, page_size) = unsafe {
+                (
+                    libc::sysconf(libc::_SC_CLK_TCK),
+                    libc::sysconf(libc::_SC_PAGESIZE),
+                )
+            };
+            let boot_time = fs::read_to_string("/proc/stat")?
+                .lines()
+                .find_map(|v| v.strip_prefix("btime ")?.trim().parse().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "boot time not found"))?;
+            Ok(Self {
+                ticks: ticks.max(1) as u64,
+                page_size: page_size.max(1) as u64,
+                boot_time,
+                users: users(),
+            })
+        }
+        pub(super) fn process(&self, pid: u32) -> io::Result<Process> {
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid /proc entry");
+            let stat = fs::read_to_string(format!("/proc/{pid}/stat"))?;
+            // the name is in parentheses and may have any of them in it
+            let (name, rest) = stat
+                .split_once(" (")
+                .and_then(|(_, v)| v.rsplit_once(") "))
+                .ok_or_else(invalid)?;
+            // fields from the third one, the state, on
+            let fields: Vec<&str> = rest.split_whitespace().collect();
+            let field = |n: usize| -> io::Result<u64> {
+                fields
+                    .get(n - 3)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(invalid)
+            };
+            let cmdline = fs::read(format!("/proc/{pid}/cmdline"))?
+                .split(|&v| v == 0)
+                .filter(|v| !v.is_empty())
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+                .collect();
+            let uid = fs::read_to_string(format!("/proc/{pid}/status"))?
+                .lines()
+                .find_map(|v| {
+                    v.strip_prefix("Uid:")?
+                        .split_whitespace()
+                        .next()?
+                        .parse()
+                        .ok()
+                })
+                .ok_or_else(invalid)?;
+            Ok(Process {
+                pid,
+                ppid: field(4)? as u32,
+                name: name.to_owned(),
+                cmdline,
+                uid,
+                user: self.users.get(&uid).cloned(),
+                cpu_time: (field(14)? + field(15)?) * 1000 / self.ticks,
+                rss: field(24)? * self.page_size,
+                start_time: self.boot_time + field(22)? / self.ticks,
+            })
+        }
+    }
+
+    /// Names of the uids in `/etc/passwd`.
+    fn users() -> HashMap<u32, String> {
+        fs::read_to_string("/etc/passwd")
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(':');
+                let name = fields.next()?;
+                let uid = fields.nth(1)?.parse().ok()?;
+                Some((uid, name.to_owned()))
+            })
+            .collect()
+    }
+}
+
+impl GetId for ProcessListSync {
+    fn id() -> &'static str {
+        "process-list"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for ProcessListSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(
+            self,
+            stream,
+            http,
+            |processes: Vec<Process>| serde_json::json!({ "processes": processes }),
+        )
+    }
+}
+
+impl ExeReceiverSync for ProcessListSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let answer = if sandbox.is_unrestricted() {
+            req.list()
+        } else {
+            Err(sandbox::forbidden(
+                "a sandboxed sender can not see the processes",
+            ))
+        };
+        respond(stream, answer)
+    }
+}
+
+/// A signal a [`KillProcessSync`] sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signal {
+    /// Ask the process to exit.
+    #[default]
+    Term,
+    Kill,
+    Int,
+    Hup,
+    Quit,
+    Stop,
+    Cont,
+    Usr1,
+    Usr2,
+}
+
+#[cfg(unix)]
+impl Signal {
+    fn as_raw(self) -> i32 {
+        match self {
+            Self::Term => libc::SIGTERM,
+            Self::Kill => libc::SIGKILL,
+            Self::Int => libc::SIGINT,
+            Self::Hup => libc::SIGHUP,
+            Self::Quit => libc::SIGQUIT,
+            Self::Stop => libc::SIGSTOP,
+            Self::Cont => libc::SIGCONT,
+            Self::Usr1 => libc::SIGUSR1,
+            Self::Usr2 => libc::SIGUSR2,
+        }
+    }
+}
+
+/// Sends a signal to a process of the receiver (unix only). Senders
+/// limited to shared directories can not signal processes.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct KillProcessSync {
+    pid: u32,
+    #[serde(default)]
+    signal: Signal,
+}
+
+impl KillProcessSync {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            signal: Signal::default(),
+        }
+    }
+    pub fn signal(mut self, value: Signal) -> Self {
+        self.signal = value;
+        self
+    }
+    /// Send the signal, runs on the receiver.
+    #[cfg(unix)]
+    pub fn kill(&self) -> io::Result<()> {
+        // 0 and negative pids are process groups
+        let pid = match i32::try_from(self.pid) {
+            Ok(v) if v > 0 => v,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "not a process id",
+                ));
+            }
+        };
+        // SAFETY: only sends a signal
+        if unsafe { libc::kill(pid, self.signal.as_raw()) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ESRCH) => Err(io::Error::new(io::ErrorKind::NotFound, "no such process")),
+            _ => Err(err),
+        }
+    }
+    #[cfg(not(unix))]
+    pub fn kill(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no signals on this receiver",
+        ))
+    }
+}
+
+impl GetId for KillProcessSync {
+    fn id() -> &'static str {
+        "kill-process"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for KillProcessSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(
+            self,
+            stream,
+            http,
+            |(): ()| serde_json::json!({ "pid": self.pid, "signal": self.signal }),
+        )
+    }
+}
+
+impl ExeReceiverSync for KillProcessSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let answer = if sandbox.is_unrestricted() {
+            req.kill()
+        } else {
+            Err(sandbox::forbidden(
+                "a sandboxed sender can not signal processes",
+            ))
+        };
+        respond(stream, answer)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,73 @@
+use ee_http::HttpRequest;
+
+use crate::{
+    ExecuteResult,
+    process::{KillProcessSync, ProcessListSync, Signal},
+    test_util::{exchange, exchange_sandboxed, sandbox_fixture},
+};
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_process_list() {
+    let me = std::process::id();
+    let all = ProcessListSync::new().list().unwrap();
+    let process = all.iter().find(|v| v.pid == me).unwrap();
+    assert!(all.is_sorted_by_key(|v| v.pid));
+    assert!(!process.cmdline.is_empty());
+    assert!(process.rss > 0);
+    assert!(process.start_time > 0);
+
+    // the filters run on the receiver
+    let task = ProcessListSync::new()
+        .name(process.name.to_uppercase())
+        .user(process.uid.to_string());
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    let processes = json["processes"].as_array().unwrap();
+    assert!(processes.iter().any(|v| v["pid"] == me));
+    assert!(
+        processes
+            .iter()
+            .all(|v| v["name"] == process.name.as_str() && v["uid"] == process.uid)
+    );
+    let task = ProcessListSync::new().name("ee-task-no-such-process");
+    let (_, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(json["processes"], serde_json::json!([]));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_process() {
+    use std::os::unix::process::ExitStatusExt;
+
+    let mut child = std::process::Command::new("sleep")
+        .arg("10")
+        .spawn()
+        .unwrap();
+    let task = KillProcessSync::new(child.id()).signal(Signal::Kill);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["signal"], "kill");
+    assert_eq!(child.wait().unwrap().signal(), Some(9));
+
+    let (r, json) = exchange(
+        &KillProcessSync::new(child.id()),
+        &mut HttpRequest::default(),
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["kind"], "not_found");
+    // 0 would be the process group of the receiver
+    let (_, json) = exchange(&KillProcessSync::new(0), &mut HttpRequest::default());
+    assert_eq!(json["kind"], "invalid_input");
+
+    let (_, sandbox) = sandbox_fixture("kill-sandboxed");
+    let task = KillProcessSync::new(std::process::id());
+    let (r, _) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    let (r, _) = exchange_sandboxed(
+        &ProcessListSync::new(),
+        &mut HttpRequest::default(),
+        sandbox,
+    );
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+}
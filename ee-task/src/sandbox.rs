@@ -0,0 +1,161 @@
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use ee_proto::{ErrorKind, response};
+use serde::Deserialize;
+
+/// What a sender may do under a [`Root`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    #[default]
+    ReadOnly,
+    ReadWrite,
+}
+
+/// A directory of the receiver shared with a sender.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Root {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub access: Access,
+}
+
+/// The directories of the receiver a sender can reach.
+///
+/// Tasks that take paths resolve them with [`Sandbox::read`] or
+/// [`Sandbox::write`] before touching them. A resolved path has its
+/// directories canonicalized, so `..` and symlinks can not lead out of the
+/// roots, and a symlink as the last component has to point inside them too.
+/// Relative paths are relative to the first root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Every path is allowed if `None`.
+    roots: Option<Vec<Root>>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sandbox {
+    /// No path is allowed until roots are added.
+    pub fn new() -> Self {
+        Self {
+            roots: Some(Vec::new()),
+        }
+    }
+    /// Every path is allowed, as it is.
+    pub fn unrestricted() -> Self {
+        Self { roots: None }
+    }
+    pub fn root<T: Into<PathBuf>>(mut self, path: T, access: Access) -> Self {
+        self.roots.get_or_insert_with(Vec::new).push(Root {
+            path: path.into(),
+            access,
+        });
+        self
+    }
+    pub fn roots<I: IntoIterator<Item = Root>>(mut self, roots: I) -> Self {
+        self.roots.get_or_insert_with(Vec::new).extend(roots);
+        self
+    }
+    pub fn is_unrestricted(&self) -> bool {
+        self.roots.is_none()
+    }
+    /// `path` resolved for reading.
+    pub fn read(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve(path, Access::ReadOnly)
+    }
+    /// `path` resolved for changing it, under a [`Access::ReadWrite`] root
+    /// and not a root itself.
+    pub fn write(&self, path: &Path) -> io::Result<PathBuf> {
+        self.resolve(path, Access::ReadWrite)
+    }
+    fn resolve(&self, path: &Path, access: Access) -> io::Result<PathBuf> {
+        let Some(roots) = &self.roots else {
+            return Ok(path.to_path_buf());
+        };
+        // roots that do not exist (yet) share nothing
+        let roots: Vec<(PathBuf, Access)> = roots
+            .iter()
+            .filter_map(|v| Some((fs::canonicalize(&v.path).ok()?, v.access)))
+            .collect();
+        let path = if path.is_absolute() {
+            canonical_parent(path)?
+        } else {
+            match roots.first() {
+                Some((root, _)) => canonical_parent(&root.join(path))?,
+                None => return Err(forbidden("nothing is shared")),
+            }
+        };
+        let Some(&(ref root, root_access)) = find(&roots, &path) else {
+            return Err(forbidden("outside of the shared directories"));
+        };
+        if access == Access::ReadWrite {
+            if root_access == Access::ReadOnly {
+                return Err(forbidden("shared read-only"));
+            }
+            if &path == root {
+                return Err(forbidden("can not change a shared directory itself"));
+            }
+        }
+        if fs::symlink_metadata(&path).is_ok_and(|v| v.is_symlink()) {
+            let target = fs::canonicalize(&path).map_err(|_| forbidden("symlink to nowhere"))?;
+            if find(&roots, &target).is_none() {
+                return Err(forbidden("symlink to outside of the shared directories"));
+            }
+        }
+        Ok(path)
+    }
+}
+
+pub(crate) fn forbidden(msg: &str) -> io::Error {
+    response::Error::new(ErrorKind::Forbidden, msg).into()
+}
+
+/// The deepest root `path` is in.
+fn find<'a>(roots: &'a [(PathBuf, Access)], path: &Path) -> Option<&'a (PathBuf, Access)> {
+    roots
+        .iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+}
+
+/// `path` with its directories canonicalized, the last component is kept
+/// so a symlink there is not followed.
+fn canonical_parent(path: &Path) -> io::Result<PathBuf> {
+    let mut components = path.components();
+    match components.next_back() {
+        Some(Component::Normal(name)) => Ok(canonical_dir(components.as_path())?.join(name)),
+        _ => fs::canonicalize(path),
+    }
+}
+
+/// `dir` canonicalized, the directories missing at its end are appended as
+/// they are (`mkdir -p` creates them).
+fn canonical_dir(dir: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(dir) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            // creating through it would create the target, wherever it is
+            if fs::symlink_metadata(dir).is_ok() {
+                return Err(forbidden("symlink to nowhere"));
+            }
+            let mut components = dir.components();
+            match components.next_back() {
+                Some(Component::Normal(name)) => {
+                    Ok(canonical_dir(components.as_path())?.join(name))
+                }
+                _ => Err(err),
+            }
+        }
+        v => v,
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,113 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use ee_http::HttpRequest;
+use ee_proto::{Decode, Encode, ErrorKind, Response, response};
+use ee_test_util::Pipe;
+
+use crate::{
+    ExeReceiverSync, ExecuteResult,
+    file::{CopySync, Listing, LsSync, RemoveDirSync, RemoveFileSync},
+    sandbox::Sandbox,
+    test_util::{exchange_sandboxed, sandbox_fixture},
+};
+
+fn assert_forbidden(v: io::Result<PathBuf>) {
+    match v {
+        Ok(v) => panic!("{} is allowed", v.display()),
+        Err(err) => assert_eq!(
+            response::Error::kind_of(&err),
+            ErrorKind::Forbidden,
+            "{err}"
+        ),
+    }
+}
+
+#[test]
+fn test_sandbox_resolve() {
+    let (dir, sandbox) = sandbox_fixture("sandbox-resolve");
+    let rw = fs::canonicalize(dir.path().join("rw")).unwrap();
+    let ro = dir.path().join("ro");
+    let outside = dir.path().join("outside");
+
+    assert_eq!(sandbox.read(&rw.join("a.txt")).unwrap(), rw.join("a.txt"));
+    // relative to the first root
+    assert_eq!(sandbox.read(Path::new("a.txt")).unwrap(), rw.join("a.txt"));
+    assert_eq!(sandbox.read(Path::new("")).unwrap(), rw);
+    // missing directories are fine, `mkdir -p` creates them
+    assert_eq!(
+        sandbox.write(&rw.join("new/deeper")).unwrap(),
+        rw.join("new/deeper")
+    );
+    assert!(sandbox.read(&ro.join("b.txt")).is_ok());
+
+    assert_forbidden(sandbox.read(&outside.join("secret.txt")));
+    assert_forbidden(sandbox.read(&rw.join("../outside/secret.txt")));
+    assert_forbidden(sandbox.read(Path::new("../outside/secret.txt")));
+    assert_forbidden(sandbox.write(&ro.join("b.txt")));
+    assert_forbidden(sandbox.write(&ro.join("new")));
+    // the roots themselves can not be removed or renamed
+    assert_forbidden(sandbox.write(&rw));
+    assert!(sandbox.write(&rw.join("missing/../../outside/x")).is_err());
+
+    assert_forbidden(Sandbox::new().read(&rw.join("a.txt")));
+    assert_eq!(
+        Sandbox::unrestricted().read(&outside).unwrap(),
+        outside.as_path()
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sandbox_symlinks() {
+    let (dir, sandbox) = sandbox_fixture("sandbox-symlinks");
+    let rw = fs::canonicalize(dir.path().join("rw")).unwrap();
+
+    assert_eq!(sandbox.read(&rw.join("inner")).unwrap(), rw.join("inner"));
+    assert_forbidden(sandbox.read(&rw.join("escape")));
+    assert_forbidden(sandbox.read(&rw.join("escape/secret.txt")));
+    assert_forbidden(sandbox.write(&rw.join("escape/new.txt")));
+    assert_forbidden(sandbox.read(&rw.join("dangling")));
+    assert_forbidden(sandbox.write(&rw.join("dangling/new.txt")));
+}
+
+#[test]
+fn test_sandbox_tasks() {
+    let (dir, sandbox) = sandbox_fixture("sandbox-tasks");
+    let secret = dir.path().join("outside/secret.txt");
+
+    let task = RemoveFileSync::from(secret.as_os_str());
+    let (r, json) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert_eq!(json["kind"], "forbidden");
+    assert!(secret.exists());
+
+    let task = RemoveDirSync::new(dir.path().join("rw/../outside")).recursive(true);
+    let (r, _) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert!(secret.exists());
+
+    let task = CopySync::new(dir.path().join("ro/b.txt"), dir.path().join("ro/c.txt"));
+    let (r, _) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    let task = CopySync::new(dir.path().join("ro/b.txt"), "b.txt");
+    let (r, _) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert!(dir.path().join("rw/b.txt").exists());
+
+    // an empty path lists the first root instead of the home directory
+    let mut input = Vec::new();
+    LsSync::home().encode(&mut input).unwrap();
+    let output = LsSync::execute_sandboxed(Pipe::new(input), &sandbox)
+        .unwrap()
+        .output;
+    let mut r = output.as_slice();
+    Response::read_from(&mut r).unwrap().into_result().unwrap();
+    let listing = Listing::decode(&mut r).unwrap();
+    assert_eq!(
+        listing.path,
+        fs::canonicalize(dir.path().join("rw")).unwrap()
+    );
+}
@@ -0,0 +1,467 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::Child,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ee_http::{
+    HttpRequest,
+    websocket::{self, WebSocket},
+};
+use ee_proto::{Bytes, Decode, Encode, Message, Response};
+use serde::Deserialize;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    exec::{ExitStatus, MAX_TURN_LEN, TURN, pipe},
+    file::{CHUNK_SIZE, send_failed},
+    sandbox::{self, Sandbox},
+};
+
+/// How long a shell has to exit after [`ShellInput::Close`] before it is
+/// killed.
+const HANG_UP_GRACE: Duration = Duration::from_secs(1);
+
+/// An interactive shell on the receiver, in a pseudo terminal.
+///
+/// The receiver answers with a [`Response`], then sends the output of the
+/// terminal as [`ShellOutput::Data`] in turns that end with
+/// [`ShellOutput::Wait`], the same way [`crate::exec::ExecSync`] does. A turn
+/// ends as soon as there is output, so typing stays responsive. The sender
+/// answers every turn with [`ShellInput`]s, the last one
+/// [`ShellInput::Continue`]. The last output is [`ShellOutput::Exit`].
+///
+/// On the sender the http request has to be a WebSocket upgrade, the output
+/// goes out as binary messages and the input comes in as binary messages or
+/// JSON text messages (`{"type": "input", "data": ...}`,
+/// `{"type": "resize", "cols": ..., "rows": ...}`). The http stream needs a
+/// short read timeout, it is polled for input after every turn.
+///
+/// Unix only. Senders limited to shared directories can not open a shell.
+#[derive(Debug, Clone, PartialEq, Message, Deserialize)]
+pub struct ShellSync {
+    /// `$SHELL` of the receiver, or `/bin/sh`, if `None`.
+    #[serde(default)]
+    shell: Option<String>,
+    #[serde(default = "default_cols")]
+    cols: u16,
+    #[serde(default = "default_rows")]
+    rows: u16,
+    /// The home directory of the receiver if `None`.
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+fn default_cols() -> u16 {
+    80
+}
+
+fn default_rows() -> u16 {
+    24
+}
+
+impl Default for ShellSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShellSync {
+    pub fn new() -> Self {
+        Self {
+            shell: None,
+            cols: default_cols(),
+            rows: default_rows(),
+            cwd: None,
+        }
+    }
+    pub fn shell<T: Into<String>>(mut self, value: T) -> Self {
+        self.shell = Some(value.into());
+        self
+    }
+    pub fn size(mut self, cols: u16, rows: u16) -> Self {
+        self.cols = cols;
+        self.rows = rows;
+        self
+    }
+    pub fn cwd<T: Into<PathBuf>>(mut self, value: T) -> Self {
+        self.cwd = Some(value.into());
+        self
+    }
+    #[cfg(unix)]
+    fn spawn(&self) -> io::Result<Shell> {
+        use std::{
+            os::{
+                fd::{AsRawFd, FromRawFd, OwnedFd},
+                unix::process::CommandExt,
+            },
+            process::{Command, Stdio},
+        };
+
+        let size = winsize(self.cols, self.rows);
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: `openpty` writes the two descriptors, they are owned below
+        let (master, slave) = unsafe {
+            if libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &size,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+        };
+        for fd in [&master, &slave] {
+            // SAFETY: sets a flag of a descriptor owned here
+            if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let shell = match &self.shell {
+            Some(v) => v.clone(),
+            None => std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned()),
+        };
+        let mut cmd = Command::new(shell);
+        cmd.env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        match &self.cwd {
+            Some(v) => {
+                cmd.current_dir(v);
+            }
+            None => {
+                if let Some(home) = std::env::var_os("HOME") {
+                    cmd.current_dir(home);
+                }
+            }
+        }
+        // SAFETY: only async-signal-safe calls between fork and exec
+        unsafe {
+            cmd.pre_exec(|| {
+                // a session of its own, with the terminal as its controlling
+                // terminal, so job control and hang ups work
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        // the copies of the slave in `cmd` have to be closed, or reading the
+        // master never ends
+        let child = cmd.spawn()?;
+        drop(cmd);
+
+        let master = File::from(master);
+        let (tx, events) = mpsc::sync_channel(16);
+        pipe(master.try_clone()?, tx, ShellOutput::Data);
+        let (input, rx) = mpsc::channel::<Vec<u8>>();
+        let mut writer = master.try_clone()?;
+        // a shell that does not read its input blocks this thread instead
+        // of the connection
+        thread::spawn(move || {
+            for data in rx {
+                if writer.write_all(&data).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Shell {
+            child,
+            master,
+            events,
+            open: true,
+            input,
+            exited: false,
+        })
+    }
+    #[cfg(not(unix))]
+    fn spawn(&self) -> io::Result<Shell> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no pseudo terminals on this receiver",
+        ))
+    }
+}
+
+#[cfg(unix)]
+fn winsize(cols: u16, rows: u16) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// A running [`ShellSync`], killed on drop if it did not exit.
+struct Shell {
+    child: Child,
+    /// The terminal end of the receiver.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    master: File,
+    /// Output of the terminal, `None` when it closes.
+    events: Receiver<Option<ShellOutput>>,
+    open: bool,
+    input: mpsc::Sender<Vec<u8>>,
+    exited: bool,
+}
+
+impl Shell {
+    fn resize(&self, cols: u16, rows: u16) {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+
+            let size = winsize(cols, rows);
+            // SAFETY: the terminal answers with SIGWINCH to the shell
+            unsafe {
+                libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ, &size);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = (cols, rows);
+    }
+    fn kill(&mut self) {
+        #[cfg(unix)]
+        crate::exec::signal_group(&self.child, libc::SIGKILL);
+        let _ = self.child.kill();
+    }
+    /// Ask the shell and its jobs to exit, like closing a terminal window.
+    fn hang_up(&mut self) {
+        #[cfg(unix)]
+        crate::exec::signal_group(&self.child, libc::SIGHUP);
+        #[cfg(not(unix))]
+        self.kill();
+    }
+    /// Stream the terminal to `stream` up to the exit of the shell.
+    fn run<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+        let mut status = ExitStatus::default();
+        let mut hung_up = None;
+        loop {
+            let turn_end = Instant::now() + TURN;
+            let mut sent = 0;
+            while self.open && sent < MAX_TURN_LEN {
+                // wait for the first output, then take what is there
+                let event = if sent == 0 {
+                    self.events
+                        .recv_timeout(turn_end.saturating_duration_since(Instant::now()))
+                        .ok()
+                } else {
+                    self.events.try_recv().ok()
+                };
+                match event {
+                    Some(Some(v)) => {
+                        if let ShellOutput::Data(data) = &v {
+                            sent += data.0.len();
+                        }
+                        v.encode(&mut *stream)?;
+                    }
+                    Some(None) => self.open = false,
+                    None => break,
+                }
+            }
+            if !self.exited
+                && let Some(v) = self.child.try_wait()?
+            {
+                self.exited = true;
+                status.set(v);
+            }
+            // jobs left behind may keep the terminal open, a quiet turn
+            // after the exit is the end
+            if self.exited && (!self.open || sent == 0) {
+                ShellOutput::Exit(status).encode(&mut *stream)?;
+                return stream.flush();
+            }
+            if hung_up.is_some_and(|v: Instant| v.elapsed() >= HANG_UP_GRACE) {
+                hung_up = None;
+                self.kill();
+            }
+            if !self.open {
+                thread::sleep(turn_end.saturating_duration_since(Instant::now()));
+            }
+            ShellOutput::Wait.encode(&mut *stream)?;
+            stream.flush()?;
+            loop {
+                match ShellInput::decode(&mut *stream)? {
+                    ShellInput::Continue => break,
+                    ShellInput::Data(v) => {
+                        let _ = self.input.send(v.0);
+                    }
+                    ShellInput::Resize { cols, rows } => self.resize(cols, rows),
+                    ShellInput::Close => {
+                        if !status.cancelled {
+                            status.cancelled = true;
+                            hung_up = Some(Instant::now());
+                            self.hang_up();
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Shell {
+    fn drop(&mut self) {
+        if !self.exited {
+            self.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// What the receiver sends while a [`ShellSync`] runs.
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum ShellOutput {
+    Data(#[message(max_len = CHUNK_SIZE)] Bytes),
+    /// End of a turn, the receiver waits for [`ShellInput`]s.
+    Wait,
+    /// `cancelled` if it exited after [`ShellInput::Close`].
+    Exit(ExitStatus),
+}
+
+/// What the sender answers [`ShellOutput::Wait`] with, any number of them
+/// and then [`ShellInput::Continue`].
+#[derive(Debug, Clone, PartialEq, Message)]
+pub enum ShellInput {
+    Continue,
+    /// Typed into the terminal.
+    Data(#[message(max_len = CHUNK_SIZE)] Bytes),
+    Resize {
+        cols: u16,
+        rows: u16,
+    },
+    /// Hang up, the shell is killed if it does not exit.
+    Close,
+}
+
+/// A text message of the WebSocket.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum Control {
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+}
+
+impl GetId for ShellSync {
+    fn id() -> &'static str {
+        "shell"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Read + io::Write> ExeSenderSync<T, W> for ShellSync {
+    /// Switches `http` to WebSocket and bridges it to the terminal. When the
+    /// shell exits a text message `{"type": "exit", ...}` with its
+    /// [`ExitStatus`] is sent and the WebSocket closed. When the client
+    /// closes the WebSocket the shell is hung up.
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        req: &mut HttpRequest,
+        mut http: W,
+    ) -> io::Result<ExecuteResult> {
+        self.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        websocket::accept(req, &mut http)?;
+        let mut ws = WebSocket::new(http);
+        // keep going after the client is gone, up to the exit of the shell
+        let mut closed = false;
+        let mut hung_up = false;
+        loop {
+            match ShellOutput::decode(&mut stream)? {
+                ShellOutput::Data(v) => {
+                    if !closed && ws.send_binary(&v.0).is_err() {
+                        closed = true;
+                    }
+                }
+                ShellOutput::Wait => {
+                    while !closed {
+                        let data = match ws.read() {
+                            Ok(None) => break,
+                            Ok(Some(websocket::Message::Binary(v))) => v,
+                            Ok(Some(websocket::Message::Text(v))) => {
+                                match serde_json::from_str(&v) {
+                                    Ok(Control::Input { data }) => data.into_bytes(),
+                                    Ok(Control::Resize { cols, rows }) => {
+                                        ShellInput::Resize { cols, rows }.encode(&mut stream)?;
+                                        continue;
+                                    }
+                                    // a terminal does not fail on a bad message
+                                    Err(_) => continue,
+                                }
+                            }
+                            Ok(Some(websocket::Message::Close)) | Err(_) => {
+                                closed = true;
+                                break;
+                            }
+                        };
+                        for v in data.chunks(CHUNK_SIZE) {
+                            ShellInput::Data(Bytes(v.to_vec())).encode(&mut stream)?;
+                        }
+                    }
+                    if closed && !hung_up {
+                        hung_up = true;
+                        ShellInput::Close.encode(&mut stream)?;
+                    }
+                    ShellInput::Continue.encode(&mut stream)?;
+                    stream.flush()?;
+                }
+                ShellOutput::Exit(status) => {
+                    if !closed {
+                        let mut exit = serde_json::to_value(status).unwrap();
+                        exit["type"] = "exit".into();
+                        ws.send_text(&exit.to_string())?;
+                        ws.close()?;
+                    }
+                    return Ok(ExecuteResult::Ok);
+                }
+            }
+        }
+    }
+}
+
+impl ExeReceiverSync for ShellSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        let shell = if sandbox.is_unrestricted() {
+            req.spawn()
+        } else {
+            Err(sandbox::forbidden(
+                "a sandboxed sender can not open a shell",
+            ))
+        };
+        let mut shell = match shell {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        shell.run(&mut stream)?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,149 @@
+use std::{
+    io::{self, Cursor},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use ee_http::HttpRequest;
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, sandbox::Sandbox, shell::ShellSync,
+    test_util::sandbox_fixture,
+};
+
+/// The browser end of a WebSocket: reads `input`, then times out.
+struct Browser {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl io::Read for Browser {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.input.read(buf)? {
+            0 => Err(io::ErrorKind::WouldBlock.into()),
+            n => Ok(n),
+        }
+    }
+}
+
+impl io::Write for Browser {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A masked frame, the way a browser sends it.
+fn ws_frame(opcode: u8, data: &[u8]) -> Vec<u8> {
+    assert!(data.len() < 126);
+    let mask = [7, 1, 9, 3];
+    let mut v = vec![0x80 | opcode, 0x80 | data.len() as u8];
+    v.extend_from_slice(&mask);
+    v.extend(data.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    v
+}
+
+/// The frames the sender answered with, as opcode and data.
+fn ws_frames(mut data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    while !data.is_empty() {
+        let (len, at) = match data[1] {
+            126 => (u16::from_be_bytes([data[2], data[3]]) as usize, 4),
+            127 => (
+                u64::from_be_bytes(data[2..10].try_into().unwrap()) as usize,
+                10,
+            ),
+            v => (v as usize, 2),
+        };
+        frames.push((data[0] & 0x0f, data[at..at + len].to_vec()));
+        data = &data[at + len..];
+    }
+    frames
+}
+
+/// Run `task` with `input` from the browser, the result and the answer.
+fn shell(
+    task: &ShellSync,
+    input: Vec<u8>,
+    sandbox: Sandbox,
+) -> (io::Result<ExecuteResult>, Vec<u8>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        ShellSync::execute_sandboxed(stream, &sandbox).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut req = HttpRequest::default()
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+    let mut http = Browser {
+        input: Cursor::new(input),
+        output: Vec::new(),
+    };
+    let r = task.execute_on_sender(&mut stream, &mut req, &mut http);
+    receiver.join().unwrap().unwrap();
+    (r, http.output)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shell_session() {
+    let mut input = ws_frame(0x1, br#"{"type": "resize", "cols": 100, "rows": 30}"#);
+    input.extend(ws_frame(0x2, b"stty size; exit 3\n"));
+    let task = ShellSync::new().shell("/bin/sh").size(80, 24);
+    let (r, http) = shell(&task, input, Sandbox::unrestricted());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    let at = http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4;
+    assert!(http.starts_with(b"HTTP/1.1 101 "));
+    let frames = ws_frames(&http[at..]);
+    let terminal: Vec<u8> = frames
+        .iter()
+        .filter(|(opcode, _)| *opcode == 0x2)
+        .flat_map(|(_, v)| v.clone())
+        .collect();
+    // the size after the resize
+    assert!(String::from_utf8_lossy(&terminal).contains("30 100"));
+    let (opcode, exit) = &frames[frames.len() - 2];
+    assert_eq!(*opcode, 0x1);
+    let exit: serde_json::Value = serde_json::from_slice(exit).unwrap();
+    assert_eq!(exit["type"], "exit");
+    assert_eq!(exit["code"], 3);
+    assert_eq!(exit["cancelled"], false);
+    assert_eq!(frames.last().unwrap().0, 0x8);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_shell_close() {
+    // a shell the browser leaves is hung up
+    let input = ws_frame(0x8, &1000u16.to_be_bytes());
+    let task = ShellSync::new().shell("/bin/sh");
+    let (r, http) = shell(&task, input, Sandbox::unrestricted());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    let at = http.windows(4).position(|v| v == b"\r\n\r\n").unwrap() + 4;
+    // only the answer to the close
+    assert_eq!(ws_frames(&http[at..]).last().unwrap().0, 0x8);
+}
+
+#[test]
+fn test_shell_failed() {
+    let (dir, sandbox) = sandbox_fixture("shell-sandboxed");
+    let task = ShellSync::new().cwd(dir.path().join("rw"));
+    let (r, http) = shell(&task, Vec::new(), sandbox);
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert!(
+        String::from_utf8(http)
+            .unwrap()
+            .contains(r#""kind":"forbidden""#)
+    );
+
+    let task = ShellSync::new().shell("ee-task-no-such-shell");
+    let (r, _) = shell(&task, Vec::new(), Sandbox::unrestricted());
+    #[cfg(unix)]
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    #[cfg(not(unix))]
+    assert_eq!(r.unwrap(), ExecuteResult::Faild);
+}
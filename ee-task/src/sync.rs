@@ -0,0 +1,927 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ee_http::{HttpRequest, HttpResponse};
+use ee_proto::{Bytes, Decode, Encode, Message, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    file::{CHUNK_SIZE, EntryKind, PartFile, send_failed},
+    glob,
+    sandbox::Sandbox,
+};
+
+/// Most exclude patterns of a [`SyncDirSync`].
+pub const MAX_SYNC_EXCLUDES: usize = 1024;
+
+/// Smallest block of a [`ManifestEntry`].
+const MIN_BLOCK_SIZE: u64 = 1024;
+
+/// Most blocks of a [`ManifestEntry`], larger files get larger blocks.
+const MAX_BLOCKS: u64 = 1 << 16;
+
+const DIGEST_LEN: usize = 32;
+
+/// Which way a [`SyncDirSync`] goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Message, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// From the sender to the receiver.
+    #[default]
+    Push,
+    /// From the receiver to the sender.
+    Pull,
+}
+
+/// Make a directory of one side like the one of the other, sending only the
+/// blocks of the files that changed.
+///
+/// The destination sends a manifest of its files with the weak (rolling)
+/// and strong checksums of their blocks, the source answers with the
+/// directories and files that are new or changed, a file as the runs of
+/// old blocks it reuses and the bytes it does not. A file is written next
+/// to its destination and renamed over it once its SHA-256 matches, so it
+/// is either the old or the new one. Files of the same size and
+/// modification time are left alone, symlinks are skipped on both sides.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SyncDirSync {
+    /// The directory of the sender.
+    local: PathBuf,
+    /// The directory of the receiver.
+    remote: PathBuf,
+    #[serde(default)]
+    mode: SyncMode,
+    /// Glob patterns of paths to leave alone on both sides, relative to the
+    /// directories.
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// Remove what the destination has and the source does not.
+    #[serde(default)]
+    delete: bool,
+    /// Only report what would change.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// A [`SyncDirSync`] as the receiver gets it, the local directory stays
+/// with the sender.
+#[derive(Debug, Clone, PartialEq, Message)]
+struct SyncRequest {
+    mode: SyncMode,
+    path: PathBuf,
+    #[message(max_len = MAX_SYNC_EXCLUDES)]
+    exclude: Vec<String>,
+    delete: bool,
+    dry_run: bool,
+}
+
+/// A file or directory of the destination of a [`SyncDirSync`].
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+pub struct ManifestEntry {
+    /// Relative to the synchronized directory, `/` separated.
+    pub path: String,
+    /// [`EntryKind::Dir`] or [`EntryKind::File`].
+    pub kind: EntryKind,
+    pub size: u64,
+    /// Milliseconds since the unix epoch.
+    pub mtime: u64,
+    /// Bytes of each of `blocks` but the last, 0 for directories.
+    pub block_size: u32,
+    pub blocks: Vec<Block>,
+}
+
+/// Checksums of a block of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Message)]
+pub struct Block {
+    /// The rolling checksum, cheap to slide over the new file.
+    pub weak: u32,
+    /// The first 8 bytes of the SHA-256, checked when `weak` matches.
+    pub strong: u64,
+}
+
+/// A change the source of a [`SyncDirSync`] sends, up to [`SyncItem::Done`].
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+pub enum SyncItem {
+    Dir {
+        path: String,
+    },
+    /// Followed by [`Delta`]s up to [`Delta::End`] or [`Delta::Abort`].
+    File {
+        path: String,
+        size: u64,
+        mtime: u64,
+    },
+    Delete {
+        path: String,
+    },
+    Done,
+}
+
+/// A piece of a new file.
+#[derive(Debug, Clone, PartialEq, Eq, Message)]
+pub enum Delta {
+    /// `count` blocks of the old file, from block `index` on.
+    Copy {
+        index: u32,
+        count: u32,
+    },
+    Data(#[message(max_len = CHUNK_SIZE)] Bytes),
+    /// The SHA-256 of the new file.
+    End(#[message(max_len = DIGEST_LEN)] Bytes),
+    /// The source could not read the file, the old one stays.
+    Abort,
+}
+
+/// What a [`SyncDirSync`] changed, or would change on a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Message, Serialize)]
+pub struct SyncReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Files left alone.
+    pub unchanged: u64,
+    /// Bytes of the new files sent as they are.
+    pub literal_bytes: u64,
+    /// Bytes of the new files the destination had already.
+    pub matched_bytes: u64,
+    pub dry_run: bool,
+}
+
+impl SyncDirSync {
+    pub fn new<T: Into<PathBuf>, U: Into<PathBuf>>(local: T, remote: U) -> Self {
+        Self {
+            local: local.into(),
+            remote: remote.into(),
+            mode: SyncMode::Push,
+            exclude: Vec::new(),
+            delete: false,
+            dry_run: false,
+        }
+    }
+    pub fn mode(mut self, value: SyncMode) -> Self {
+        self.mode = value;
+        self
+    }
+    pub fn exclude<T: Into<String>>(mut self, pattern: T) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+    pub fn delete(mut self, value: bool) -> Self {
+        self.delete = value;
+        self
+    }
+    pub fn dry_run(mut self, value: bool) -> Self {
+        self.dry_run = value;
+        self
+    }
+    fn request(&self) -> SyncRequest {
+        SyncRequest {
+            mode: self.mode,
+            path: self.remote.clone(),
+            exclude: self.exclude.clone(),
+            delete: self.delete,
+            dry_run: self.dry_run,
+        }
+    }
+}
+
+impl SyncRequest {
+    /// The directory on the receiver. The destination of a push is created
+    /// if it is missing, the source of a pull has to be a directory.
+    fn root(&self, sandbox: &Sandbox) -> io::Result<PathBuf> {
+        let root = sandbox.read(&self.path)?;
+        match fs::metadata(&root) {
+            Ok(v) if v.is_dir() => Ok(root),
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            )),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && self.mode == SyncMode::Push => {
+                sandbox.write(&self.path)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// The rolling checksum of rsync, two 16 bit sums of a window of bytes.
+#[derive(Debug, Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(data: &[u8]) -> Self {
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, &v) in data.iter().enumerate() {
+            a = a.wrapping_add(v as u32);
+            b = b.wrapping_add(((data.len() - i) as u32).wrapping_mul(v as u32));
+        }
+        Self {
+            a,
+            b,
+            len: data.len() as u32,
+        }
+    }
+    /// Slide the window one byte, `out` leaves it and `next` comes in.
+    fn roll(&mut self, out: u8, next: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(next as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+fn strong(data: &[u8]) -> u64 {
+    let digest = Sha256::digest(data);
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+fn block_size(len: u64) -> u64 {
+    len.isqrt()
+        .max(MIN_BLOCK_SIZE)
+        .max(len.div_ceil(MAX_BLOCKS))
+}
+
+fn mtime(meta: &fs::Metadata) -> u64 {
+    meta.modified()
+        .ok()
+        .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |v| v.as_millis() as u64)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Read up to `buf.len()` bytes, less only at the end of the file.
+fn read_full(r: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(v) => n += v,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(n)
+}
+
+/// The directories and files under `root` but the excluded ones, parents
+/// before their children. A missing `root` is empty.
+fn walk(root: &Path, exclude: &[String]) -> io::Result<Vec<(String, fs::Metadata)>> {
+    let exclude = glob::set(exclude)?;
+    let mut out = Vec::new();
+    let mut dirs = vec![String::new()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(root.join(&dir)) {
+            Ok(v) => v,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && dir.is_empty() => break,
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|_| invalid_data("file name is not valid UTF-8"))?;
+            let path = if dir.is_empty() {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            if exclude.is_match(&path) {
+                continue;
+            }
+            // not followed, symlinks are neither
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                dirs.push(path.clone());
+            } else if !meta.is_file() {
+                continue;
+            }
+            out.push((path, meta));
+        }
+    }
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(out)
+}
+
+/// Send the manifest of `root` as `Some` entries up to `None`, and a
+/// [`Response`] if it is complete. Returns the error that cut it short.
+fn write_manifest<S: Write>(
+    root: &Path,
+    exclude: &[String],
+    stream: &mut S,
+) -> io::Result<Option<io::Error>> {
+    let entries = match walk(root, exclude) {
+        Ok(v) => v,
+        Err(err) => return cut_manifest(err, stream),
+    };
+    for (path, meta) in entries {
+        let (block_size, blocks) = if meta.is_dir() {
+            (0, Vec::new())
+        } else {
+            match signature(&root.join(&path), meta.len()) {
+                Ok(v) => v,
+                Err(err) => return cut_manifest(err, stream),
+            }
+        };
+        Some(ManifestEntry {
+            path,
+            kind: if meta.is_dir() {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            },
+            size: meta.len(),
+            mtime: mtime(&meta),
+            block_size,
+            blocks,
+        })
+        .encode(&mut *stream)?;
+    }
+    None::<ManifestEntry>.encode(&mut *stream)?;
+    Response::ok().write_to(&mut *stream)?;
+    stream.flush()?;
+    Ok(None)
+}
+
+fn cut_manifest<S: Write>(err: io::Error, stream: &mut S) -> io::Result<Option<io::Error>> {
+    None::<ManifestEntry>.encode(&mut *stream)?;
+    Response::from(&err).write_to(&mut *stream)?;
+    stream.flush()?;
+    Ok(Some(err))
+}
+
+/// The other side of [`write_manifest`], the entries or the error.
+fn read_manifest<S: Read>(stream: &mut S) -> io::Result<Result<Vec<ManifestEntry>, Response>> {
+    let mut entries = Vec::new();
+    while let Some(v) = Option::<ManifestEntry>::decode(&mut *stream)? {
+        entries.push(v);
+    }
+    let res = Response::read_from(&mut *stream)?;
+    Ok(if res.is_ok() { Ok(entries) } else { Err(res) })
+}
+
+fn signature(path: &Path, len: u64) -> io::Result<(u32, Vec<Block>)> {
+    let size = block_size(len);
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; size as usize];
+    let mut blocks = Vec::new();
+    loop {
+        let n = read_full(&mut file, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        blocks.push(Block {
+            weak: Rolling::new(&buf[..n]).digest(),
+            strong: strong(&buf[..n]),
+        });
+        if n < buf.len() {
+            break;
+        }
+    }
+    Ok((size as u32, blocks))
+}
+
+/// A writer that remembers if it failed, to tell the errors of the stream
+/// from the ones of the files.
+struct Watched<'a, W> {
+    inner: &'a mut W,
+    failed: bool,
+}
+
+impl<W: Write> Write for Watched<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let res = self.inner.write(buf);
+        self.failed |= res.is_err();
+        res
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let res = self.inner.flush();
+        self.failed |= res.is_err();
+        res
+    }
+}
+
+/// Send what the destination of `dest` lacks of `root`, up to
+/// [`SyncItem::Done`]. An error of the source stops it early and is
+/// returned with the report, errors of the stream are the `Err`.
+fn send_changes<S: Write>(
+    root: &Path,
+    dest: Vec<ManifestEntry>,
+    req: &SyncRequest,
+    stream: &mut S,
+) -> io::Result<(SyncReport, Option<io::Error>)> {
+    let mut out = Watched {
+        inner: stream,
+        failed: false,
+    };
+    let mut report = SyncReport {
+        dry_run: req.dry_run,
+        ..Default::default()
+    };
+    let failed = match changes(root, dest, req, &mut out, &mut report) {
+        Ok(()) => None,
+        Err(err) if out.failed => return Err(err),
+        Err(err) => Some(err),
+    };
+    SyncItem::Done.encode(&mut out)?;
+    out.flush()?;
+    Ok((report, failed))
+}
+
+fn changes<S: Write>(
+    root: &Path,
+    dest: Vec<ManifestEntry>,
+    req: &SyncRequest,
+    out: &mut Watched<S>,
+    report: &mut SyncReport,
+) -> io::Result<()> {
+    // unlike the destination, the source has to be there
+    if !fs::metadata(root)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            "not a directory",
+        ));
+    }
+    let entries = walk(root, &req.exclude)?;
+    let send = !req.dry_run;
+    let old: HashMap<&str, &ManifestEntry> = dest.iter().map(|v| (v.path.as_str(), v)).collect();
+    for (path, meta) in &entries {
+        let old = old.get(path.as_str()).copied();
+        if meta.is_dir() {
+            if old.is_none_or(|v| v.kind != EntryKind::Dir) {
+                report.created.push(path.clone());
+                if send {
+                    SyncItem::Dir { path: path.clone() }.encode(&mut *out)?;
+                }
+            }
+            continue;
+        }
+        let mtime = mtime(meta);
+        let old = old.filter(|v| v.kind == EntryKind::File);
+        if old.is_some_and(|v| v.size == meta.len() && v.mtime == mtime) {
+            report.unchanged += 1;
+            continue;
+        }
+        match old {
+            Some(_) => report.updated.push(path.clone()),
+            None => report.created.push(path.clone()),
+        }
+        let file = root.join(path);
+        if !send {
+            delta(&file, old, &mut io::sink(), report)?;
+            continue;
+        }
+        SyncItem::File {
+            path: path.clone(),
+            size: meta.len(),
+            mtime,
+        }
+        .encode(&mut *out)?;
+        match delta(&file, old, &mut *out, report) {
+            Ok(digest) => Delta::End(Bytes(digest.to_vec())).encode(&mut *out)?,
+            Err(err) => {
+                if !out.failed {
+                    Delta::Abort.encode(&mut *out)?;
+                }
+                return Err(err);
+            }
+        }
+    }
+
+    if req.delete {
+        let source: HashSet<&str> = entries.iter().map(|(v, _)| v.as_str()).collect();
+        let mut deleted: Vec<&str> = Vec::new();
+        for v in &dest {
+            // a replaced directory goes with its content
+            let replaced = source.contains(v.path.as_str());
+            if replaced || deleted.iter().any(|d| is_under(&v.path, d)) {
+                continue;
+            }
+            deleted.push(&v.path);
+            report.deleted.push(v.path.clone());
+            if send {
+                SyncItem::Delete {
+                    path: v.path.clone(),
+                }
+                .encode(&mut *out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_under(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir).is_some_and(|v| v.starts_with('/'))
+}
+
+/// Write `path` to `out` as [`Delta`]s against the blocks of `old`, and
+/// return its SHA-256. [`Delta::End`] is left to the caller.
+fn delta<W: Write>(
+    path: &Path,
+    old: Option<&ManifestEntry>,
+    out: &mut W,
+    report: &mut SyncReport,
+) -> io::Result<[u8; DIGEST_LEN]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let blocks = old.map_or(&[][..], |v| &v.blocks);
+    let size = old.map_or(0, |v| v.block_size as usize);
+    if blocks.is_empty() || size == 0 {
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let n = read_full(&mut file, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            Delta::Data(Bytes(buf[..n].to_vec())).encode(&mut *out)?;
+            report.literal_bytes += n as u64;
+        }
+        return Ok(hasher.finalize().into());
+    }
+
+    let mut table: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (i, v) in blocks.iter().enumerate() {
+        table.entry(v.weak).or_default().push(i as u32);
+    }
+    // the bytes read and not sent yet, `start..pos` is literal and the
+    // window is `pos..pos + size`
+    let mut buf: Vec<u8> = Vec::new();
+    let (mut start, mut pos) = (0, 0);
+    let mut eof = false;
+    let mut rolling: Option<Rolling> = None;
+    // a run of old blocks, sent when the next one does not follow
+    let mut run: Option<(u32, u32)> = None;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        while !eof && buf.len() < pos + size + 1 {
+            let n = file.read(&mut chunk)?;
+            if n == 0 {
+                eof = true;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        if buf.len() < pos + size {
+            break;
+        }
+        let window = &buf[pos..pos + size];
+        let weak = rolling.get_or_insert_with(|| Rolling::new(window)).digest();
+        let found = table.get(&weak).and_then(|v| {
+            let strong = strong(window);
+            v.iter()
+                .copied()
+                .find(|&i| blocks[i as usize].strong == strong)
+        });
+        if let Some(i) = found {
+            if start < pos {
+                send_run(&mut run, out)?;
+                send_literal(&buf[start..pos], &mut hasher, out, report)?;
+            }
+            hasher.update(window);
+            report.matched_bytes += size as u64;
+            run = match run {
+                Some((index, count)) if index + count == i => Some((index, count + 1)),
+                _ => {
+                    send_run(&mut run, out)?;
+                    Some((i, 1))
+                }
+            };
+            pos += size;
+            start = pos;
+            rolling = None;
+            if start >= CHUNK_SIZE {
+                buf.drain(..start);
+                (start, pos) = (0, 0);
+            }
+            continue;
+        }
+        match (rolling.as_mut(), buf.get(pos + size)) {
+            (Some(v), Some(&next)) => v.roll(buf[pos], next),
+            _ => rolling = None,
+        }
+        pos += 1;
+        if pos - start >= CHUNK_SIZE {
+            send_run(&mut run, out)?;
+            send_literal(&buf[start..pos], &mut hasher, out, report)?;
+            buf.drain(..pos);
+            (start, pos) = (0, 0);
+        }
+    }
+    send_run(&mut run, out)?;
+    send_literal(&buf[start..], &mut hasher, out, report)?;
+    Ok(hasher.finalize().into())
+}
+
+fn send_run<W: Write>(run: &mut Option<(u32, u32)>, out: &mut W) -> io::Result<()> {
+    if let Some((index, count)) = run.take() {
+        Delta::Copy { index, count }.encode(out)?;
+    }
+    Ok(())
+}
+
+fn send_literal<W: Write>(
+    data: &[u8],
+    hasher: &mut Sha256,
+    out: &mut W,
+    report: &mut SyncReport,
+) -> io::Result<()> {
+    for v in data.chunks(CHUNK_SIZE) {
+        hasher.update(v);
+        Delta::Data(Bytes(v.to_vec())).encode(&mut *out)?;
+        report.literal_bytes += v.len() as u64;
+    }
+    Ok(())
+}
+
+/// `rel` under `root`, if it is a plain relative path.
+fn join(root: &Path, rel: &str) -> io::Result<PathBuf> {
+    let path = Path::new(rel);
+    if rel.is_empty() || !path.components().all(|v| matches!(v, Component::Normal(_))) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a relative path",
+        ));
+    }
+    Ok(root.join(path))
+}
+
+/// Remove `path` whatever it is, a missing one included.
+fn remove(path: &Path) -> io::Result<()> {
+    let res = match fs::symlink_metadata(path) {
+        Ok(v) if v.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match res {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// A file of a [`SyncItem::File`] being rebuilt.
+struct Incoming {
+    dest: PathBuf,
+    /// The old file and the size of its blocks.
+    basis: Option<(File, u64)>,
+    part: PartFile,
+    hasher: Sha256,
+}
+
+impl Incoming {
+    fn open(dest: PathBuf) -> io::Result<Self> {
+        let basis = match fs::symlink_metadata(&dest) {
+            Ok(v) if v.is_dir() => {
+                fs::remove_dir_all(&dest)?;
+                None
+            }
+            Ok(v) if v.is_file() => Some((File::open(&dest)?, block_size(v.len()))),
+            _ => None,
+        };
+        Ok(Self {
+            part: PartFile::create(&dest)?,
+            dest,
+            basis,
+            hasher: Sha256::new(),
+        })
+    }
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.hasher.update(data);
+        self.part.file.write_all(data)
+    }
+    fn copy(&mut self, index: u32, count: u32) -> io::Result<()> {
+        let Some((file, size)) = &mut self.basis else {
+            return Err(invalid_data("no old file to copy from"));
+        };
+        let size = *size;
+        file.seek(SeekFrom::Start(index as u64 * size))?;
+        let mut left = count as u64 * size;
+        let mut buf = vec![0; CHUNK_SIZE];
+        while left > 0 {
+            let n = file.read(&mut buf[..left.min(CHUNK_SIZE as u64) as usize])?;
+            if n == 0 {
+                break;
+            }
+            left -= n as u64;
+            self.hasher.update(&buf[..n]);
+            self.part.file.write_all(&buf[..n])?;
+        }
+        Ok(())
+    }
+    fn finish(mut self, digest: &[u8], mtime: u64) -> io::Result<()> {
+        if self.hasher.finalize().as_slice() != digest {
+            return Err(invalid_data("file changed while it was synchronized"));
+        }
+        self.part
+            .file
+            .set_modified(UNIX_EPOCH + Duration::from_millis(mtime))?;
+        self.part.file.sync_all()?;
+        fs::rename(&self.part.path, &self.dest)?;
+        self.part.placed = true;
+        Ok(())
+    }
+}
+
+/// Apply the changes of `stream` under `root`, up to [`SyncItem::Done`].
+/// `resolve` checks every path before it is touched. The first error stops
+/// the changes, the rest is read and dropped, and is returned.
+fn apply<S: Read>(
+    root: &Path,
+    stream: &mut S,
+    resolve: &dyn Fn(&Path) -> io::Result<PathBuf>,
+) -> io::Result<Option<io::Error>> {
+    let mut failed: Option<io::Error> = None;
+    let mut created_root = false;
+    let mut target = |rel: &str| -> io::Result<PathBuf> {
+        if !created_root {
+            fs::create_dir_all(root)?;
+            created_root = true;
+        }
+        resolve(&join(root, rel)?)
+    };
+    loop {
+        match SyncItem::decode(&mut *stream)? {
+            SyncItem::Done => return Ok(failed),
+            SyncItem::Dir { path } => {
+                if failed.is_none()
+                    && let Err(err) = target(&path).and_then(|path| {
+                        if fs::symlink_metadata(&path).is_ok_and(|v| !v.is_dir()) {
+                            fs::remove_file(&path)?;
+                        }
+                        fs::create_dir_all(path)
+                    })
+                {
+                    failed = Some(err);
+                }
+            }
+            SyncItem::Delete { path } => {
+                if failed.is_none()
+                    && let Err(err) = target(&path).and_then(|path| remove(&path))
+                {
+                    failed = Some(err);
+                }
+            }
+            SyncItem::File { path, mtime, .. } => {
+                let mut file = None;
+                if failed.is_none() {
+                    match target(&path).and_then(Incoming::open) {
+                        Ok(v) => file = Some(v),
+                        Err(err) => failed = Some(err),
+                    }
+                }
+                loop {
+                    let res = match (Delta::decode(&mut *stream)?, file.as_mut()) {
+                        (Delta::Copy { index, count }, Some(v)) => v.copy(index, count),
+                        (Delta::Data(data), Some(v)) => v.write(&data.0),
+                        (Delta::End(digest), _) => {
+                            if let Some(v) = file.take()
+                                && let Err(err) = v.finish(&digest.0, mtime)
+                            {
+                                failed = Some(err);
+                            }
+                            break;
+                        }
+                        (Delta::Abort, _) => break,
+                        (_, None) => Ok(()),
+                    };
+                    if let Err(err) = res {
+                        file = None;
+                        failed = Some(err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GetId for SyncDirSync {
+    fn id() -> &'static str {
+        "sync-dir"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for SyncDirSync {
+    /// Answers with the [`SyncReport`] as JSON.
+    fn execute_on_sender(
+        &self,
+        mut stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        let req = self.request();
+        req.encode(&mut stream)?;
+        stream.flush()?;
+        let res = Response::read_from(&mut stream)?;
+        if !res.is_ok() {
+            return send_failed(http, &res);
+        }
+        let report = match self.mode {
+            SyncMode::Push => {
+                let dest = match read_manifest(&mut stream)? {
+                    Ok(v) => v,
+                    Err(res) => return send_failed(http, &res),
+                };
+                let (report, failed) = send_changes(&self.local, dest, &req, &mut stream)?;
+                let res = Response::read_from(&mut stream)?;
+                if let Some(err) = failed {
+                    return send_failed(http, &Response::from(&err));
+                }
+                if !res.is_ok() {
+                    return send_failed(http, &res);
+                }
+                report
+            }
+            SyncMode::Pull => {
+                // the receiver stops too if the manifest is incomplete
+                if let Some(err) = write_manifest(&self.local, &self.exclude, &mut stream)? {
+                    return send_failed(http, &Response::from(&err));
+                }
+                let failed = apply(&self.local, &mut stream, &|v| Ok(v.to_path_buf()))?;
+                let res = Response::read_from(&mut stream)?;
+                if !res.is_ok() {
+                    return send_failed(http, &res);
+                }
+                let report = SyncReport::decode(&mut stream)?;
+                if let Some(err) = failed {
+                    return send_failed(http, &Response::from(&err));
+                }
+                report
+            }
+        };
+        let mut data = serde_json::to_value(report).unwrap();
+        data["status"] = "ok".into();
+        HttpResponse::new().send_json_str(http, data.to_string())?;
+        Ok(ExecuteResult::Ok)
+    }
+}
+
+impl ExeReceiverSync for SyncDirSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(stream: S) -> io::Result<S> {
+        Self::execute_sandboxed(stream, &Sandbox::unrestricted())
+    }
+    fn execute_sandboxed<S: io::Read + io::Write>(
+        mut stream: S,
+        sandbox: &Sandbox,
+    ) -> io::Result<S> {
+        let req = SyncRequest::decode(&mut stream)?;
+        let root = match req.root(sandbox) {
+            Ok(v) => v,
+            Err(err) => {
+                Response::from(&err).write_to(&mut stream)?;
+                stream.flush()?;
+                return Ok(stream);
+            }
+        };
+        Response::ok().write_to(&mut stream)?;
+        match req.mode {
+            SyncMode::Push => {
+                // the sender stops too if the manifest is incomplete
+                if write_manifest(&root, &req.exclude, &mut stream)?.is_some() {
+                    return Ok(stream);
+                }
+                let failed = apply(&root, &mut stream, &|v| sandbox.write(v))?;
+                match failed {
+                    Some(err) => Response::from(&err).write_to(&mut stream)?,
+                    None => Response::ok().write_to(&mut stream)?,
+                }
+            }
+            SyncMode::Pull => {
+                stream.flush()?;
+                let dest = match read_manifest(&mut stream)? {
+                    Ok(v) => v,
+                    Err(_) => return Ok(stream),
+                };
+                let (report, failed) = send_changes(&root, dest, &req, &mut stream)?;
+                match failed {
+                    Some(err) => Response::from(&err).write_to(&mut stream)?,
+                    None => {
+                        Response::ok().write_to(&mut stream)?;
+                        report.encode(&mut stream)?;
+                    }
+                }
+            }
+        }
+        stream.flush()?;
+        Ok(stream)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,159 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use ee_http::HttpRequest;
+use ee_test_util::TempDir;
+
+use crate::{
+    ExecuteResult,
+    sandbox::{Access, Sandbox},
+    sync::{SyncDirSync, SyncMode},
+    test_util::{content, exchange, exchange_sandboxed},
+};
+
+/// `a.txt`, `dir/b.txt` and `big.bin`, what the destination of
+/// [`sync_dest`] is synchronized to.
+fn sync_source(name: &str) -> TempDir {
+    let old = content(300_000);
+    let mut big = b"inserted".to_vec();
+    big.extend_from_slice(&old[..150_000]);
+    big.extend_from_slice(b"changed");
+    big.extend_from_slice(&old[150_003..]);
+    TempDir::new(name)
+        .file("a.txt", b"new a")
+        .file("dir/b.txt", b"b")
+        .file("big.bin", big)
+}
+
+/// The old `big.bin` and `a.txt`, a file the source does not have and an
+/// excluded one.
+fn sync_dest(name: &str) -> TempDir {
+    let dir = TempDir::new(name)
+        .file("a.txt", b"old a")
+        .file("big.bin", content(300_000))
+        .file("extra.txt", b"extra")
+        .file("keep.log", b"log");
+    // older than the source, `a.txt` has the same size
+    for name in ["a.txt", "big.bin"] {
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.path().join(name))
+            .unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(1_000_000))
+            .unwrap();
+    }
+    dir
+}
+
+fn assert_synced(source: &Path, dest: &Path) {
+    for name in ["a.txt", "dir/b.txt", "big.bin"] {
+        let (a, b) = (source.join(name), dest.join(name));
+        assert_eq!(fs::read(&a).unwrap(), fs::read(&b).unwrap(), "{name}");
+        let mtime = |v: &Path| fs::metadata(v).unwrap().modified().unwrap();
+        let (a, b) = (mtime(&a), mtime(&b));
+        let diff = a.duration_since(b).unwrap_or_else(|v| v.duration());
+        assert!(diff < Duration::from_millis(1), "{name}");
+    }
+}
+
+#[test]
+fn test_sync_push() {
+    let (source, dest) = (sync_source("sync-push-src"), sync_dest("sync-push-dest"));
+    let task = SyncDirSync::new(source.path(), dest.path())
+        .exclude("*.log")
+        .delete(true);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["created"], serde_json::json!(["dir", "dir/b.txt"]));
+    assert_eq!(json["updated"], serde_json::json!(["a.txt", "big.bin"]));
+    assert_eq!(json["deleted"], serde_json::json!(["extra.txt"]));
+    // only the changed blocks of big.bin went over
+    assert!(json["matched_bytes"].as_u64().unwrap() > 280_000, "{json}");
+    assert!(json["literal_bytes"].as_u64().unwrap() < 20_000, "{json}");
+    assert_synced(source.path(), dest.path());
+    assert!(!dest.path().join("extra.txt").exists());
+    assert_eq!(fs::read(dest.path().join("keep.log")).unwrap(), b"log");
+
+    // nothing left to do
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["unchanged"], 3);
+    assert_eq!(json["created"], serde_json::json!([]));
+    assert_eq!(json["literal_bytes"], 0);
+}
+
+#[test]
+fn test_sync_pull() {
+    let (source, dest) = (sync_source("sync-pull-src"), sync_dest("sync-pull-dest"));
+    let task = SyncDirSync::new(dest.path(), source.path()).mode(SyncMode::Pull);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["updated"], serde_json::json!(["a.txt", "big.bin"]));
+    assert!(json["matched_bytes"].as_u64().unwrap() > 0);
+    assert_synced(source.path(), dest.path());
+    // no delete
+    assert!(dest.path().join("extra.txt").exists());
+
+    // into a directory that does not exist yet
+    let new = dest.path().join("new");
+    let task = SyncDirSync::new(&new, source.path()).mode(SyncMode::Pull);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["matched_bytes"], 0);
+    assert_synced(source.path(), &new);
+}
+
+#[test]
+fn test_sync_dry_run() {
+    let (source, dest) = (sync_source("sync-dry-src"), sync_dest("sync-dry-dest"));
+    let task = SyncDirSync::new(source.path(), dest.path())
+        .delete(true)
+        .dry_run(true);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["dry_run"], true);
+    assert_eq!(json["updated"], serde_json::json!(["a.txt", "big.bin"]));
+    assert_eq!(
+        json["deleted"],
+        serde_json::json!(["extra.txt", "keep.log"])
+    );
+    assert!(json["matched_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(fs::read(dest.path().join("a.txt")).unwrap(), b"old a");
+    assert!(dest.path().join("extra.txt").exists());
+    assert!(!dest.path().join("dir").exists());
+}
+
+#[test]
+fn test_sync_failed() {
+    let (source, dest) = (
+        sync_source("sync-failed-src"),
+        sync_dest("sync-failed-dest"),
+    );
+    let sandbox = Sandbox::new().root(dest.path(), Access::ReadOnly);
+    let task = SyncDirSync::new(source.path(), dest.path().join("sub"));
+    let (r, json) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox.clone());
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert_eq!(json["kind"], "forbidden");
+
+    // the manifest is read, the changes are not allowed
+    let task = SyncDirSync::new(source.path(), dest.path());
+    let (r, json) = exchange_sandboxed(&task, &mut HttpRequest::default(), sandbox);
+    assert_eq!(r.unwrap(), ExecuteResult::Forbidden);
+    assert_eq!(json["kind"], "forbidden");
+    assert_eq!(fs::read(dest.path().join("a.txt")).unwrap(), b"old a");
+
+    let task = SyncDirSync::new(source.path(), dest.path().join("missing")).mode(SyncMode::Pull);
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["kind"], "not_found");
+
+    // a missing local directory fails after the receiver answered
+    let task = SyncDirSync::new(source.path().join("missing"), dest.path());
+    let (r, json) = exchange(&task, &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::NotFound);
+    assert_eq!(json["kind"], "not_found");
+}
@@ -0,0 +1,335 @@
+use std::{io, path::PathBuf};
+
+use ee_http::HttpRequest;
+use ee_proto::{Decode, Message};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult, GetId,
+    file::{request, respond},
+};
+
+/// A snapshot of the receiver's system and its health.
+///
+/// What the platform of the receiver does not tell is left out, most of it
+/// comes from `/proc` on Linux.
+#[derive(Debug, Clone, Default, PartialEq, Message, Deserialize)]
+pub struct SystemInfoSync {}
+
+/// The answer to [`SystemInfoSync`].
+#[derive(Debug, Clone, PartialEq, Message, Serialize)]
+pub struct SystemInfo {
+    pub hostname: String,
+    /// `linux`, `windows`, `macos`, ...
+    pub os: String,
+    /// Name and version of the distribution.
+    pub os_version: Option<String>,
+    /// Release of the kernel, unix only.
+    pub kernel: Option<String>,
+    pub arch: String,
+    /// Seconds since the boot.
+    pub uptime: Option<u64>,
+    /// Over 1, 5 and 15 minutes, unix only.
+    pub load_average: Option<(f64, f64, f64)>,
+    pub cpus: u32,
+    pub memory: Option<Memory>,
+    pub swap: Option<Memory>,
+    pub disks: Vec<Disk>,
+    pub interfaces: Vec<Interface>,
+}
+
+/// Memory or swap, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Message, Serialize)]
+pub struct Memory {
+    pub total: u64,
+    pub available: u64,
+}
+
+/// A mounted file system, sizes in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct Disk {
+    pub mount: PathBuf,
+    pub device: String,
+    pub fs_type: String,
+    pub total: u64,
+    /// Free for unprivileged users.
+    pub available: u64,
+}
+
+/// A network interface with its IPv4 and IPv6 addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Message, Serialize)]
+pub struct Interface {
+    pub name: String,
+    pub addresses: Vec<String>,
+}
+
+impl SystemInfoSync {
+    pub fn new() -> Self {
+        Self {}
+    }
+    /// The snapshot, runs on the receiver.
+    pub fn snapshot(&self) -> SystemInfo {
+        let mut info = SystemInfo {
+            hostname: "unknown".to_owned(),
+            os: std::env::consts::OS.to_owned(),
+            os_version: None,
+            kernel: None,
+            arch: std::env::consts::ARCH.to_owned(),
+            uptime: None,
+            load_average: None,
+            cpus: std::thread::available_parallelism().map_or(1, |v| v.get() as u32),
+            memory: None,
+            swap: None,
+            disks: Vec::new(),
+            interfaces: Vec::new(),
+        };
+        #[cfg(unix)]
+        unix::fill(&mut info);
+        #[cfg(target_os = "linux")]
+        linux::fill(&mut info);
+        #[cfg(windows)]
+        if let Ok(v) = std::env::var("COMPUTERNAME") {
+            info.hostname = v;
+        }
+        info
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        ffi::CStr,
+        net::{Ipv4Addr, Ipv6Addr},
+    };
+
+    use super::{Interface, SystemInfo};
+
+    pub(super) fn fill(info: &mut SystemInfo) {
+        let mut buf = [0u8; 256];
+        // SAFETY: `gethostname` writes at most `buf.len()` bytes
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0
+            && let Ok(v) = CStr::from_bytes_until_nul(&buf)
+        {
+            info.hostname = v.to_string_lossy().into_owned();
+        }
+        // SAFETY: `uname` fills the struct
+        unsafe {
+            let mut name: libc::utsname = std::mem::zeroed();
+            if libc::uname(&mut name) == 0 {
+                let release = CStr::from_ptr(name.release.as_ptr());
+                info.kernel = Some(release.to_string_lossy().into_owned());
+            }
+        }
+        let mut load = [0f64; 3];
+        // SAFETY: `getloadavg` writes at most 3 values
+        if unsafe { libc::getloadavg(load.as_mut_ptr(), 3) } == 3 {
+            info.load_average = Some((load[0], load[1], load[2]));
+        }
+        info.interfaces = interfaces();
+    }
+
+    /// Every interface, in the order of `getifaddrs`.
+    fn interfaces() -> Vec<Interface> {
+        let mut interfaces: Vec<Interface> = Vec::new();
+        let mut addrs = std::ptr::null_mut();
+        // SAFETY: the list is only read up to `freeifaddrs`, the addresses
+        // are read as the type their family says
+        unsafe {
+            if libc::getifaddrs(&mut addrs) != 0 {
+                return interfaces;
+            }
+            let mut next = addrs;
+            while let Some(v) = next.as_ref() {
+                next = v.ifa_next;
+                let name = CStr::from_ptr(v.ifa_name).to_string_lossy();
+                let address =
+                    v.ifa_addr
+                        .as_ref()
+                        .and_then(|addr| match i32::from(addr.sa_family) {
+                            libc::AF_INET => {
+                                let addr = &*v.ifa_addr.cast::<libc::sockaddr_in>();
+                                Some(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).to_string())
+                            }
+                            libc::AF_INET6 => {
+                                let addr = &*v.ifa_addr.cast::<libc::sockaddr_in6>();
+                                Some(Ipv6Addr::from(addr.sin6_addr.s6_addr).to_string())
+                            }
+                            _ => None,
+                        });
+                let i = match interfaces.iter().position(|v| v.name == name) {
+                    Some(i) => i,
+                    None => {
+                        interfaces.push(Interface {
+                            name: name.into_owned(),
+                            addresses: Vec::new(),
+                        });
+                        interfaces.len() - 1
+                    }
+                };
+                interfaces[i].addresses.extend(address);
+            }
+            libc::freeifaddrs(addrs);
+        }
+        interfaces
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod linux {
+    use std::{
+        ffi::CString,
+        fs,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+    };
+
+    use super::{Disk, Memory, SystemInfo};
+
+    /// File systems that are not disks.
+    const VIRTUAL: &[&str] = &["tmpfs", "devtmpfs", "squashfs", "ramfs"];
+
+    pub(super) fn fill(info: &mut SystemInfo) {
+        info.os_version = fs::read_to_string("/etc/os-release").ok().and_then(|v| {
+            v.lines()
+                .find_map(|v| v.strip_prefix("PRETTY_NAME="))
+                .map(|v| v.trim_matches('"').to_owned())
+        });
+        info.uptime = fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|v| v.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|v| v as u64);
+        (info.memory, info.swap) = memory();
+        info.disks = disks();
+    }
+
+    /// Memory and swap from `/proc/meminfo`.
+    pub(crate) fn memory() -> (Option<Memory>, Option<Memory>) {
+        let Ok(v) = fs::read_to_string("/proc/meminfo") else {
+            return (None, None);
+        };
+        // in kB
+        let value = |key: &str| -> Option<u64> {
+            v.lines()
+                .find_map(|v| v.strip_prefix(key)?.strip_prefix(':'))?
+                .split_whitespace()
+                .next()?
+                .parse::<u64>()
+                .ok()
+                .map(|v| v * 1024)
+        };
+        let memory = |total: &str, available: &str| {
+            Some(Memory {
+                total: value(total)?,
+                available: value(available)?,
+            })
+        };
+        (
+            memory("MemTotal", "MemAvailable"),
+            memory("SwapTotal", "SwapFree"),
+        )
+    }
+
+    /// The mounts of `/proc/mounts` that have a size, the last one of a
+    /// mount point.
+    fn disks() -> Vec<Disk> {
+        let mut disks: Vec<Disk> = Vec::new();
+        let mounts = fs::read_to_string("/proc/mounts").unwrap_or_default();
+        for line in mounts.lines() {
+            let mut fields = line.split_whitespace().map(unescape);
+            let (Some(device), Some(mount), Some(fs_type)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let mount = PathBuf::from(mount);
+            if VIRTUAL.contains(&fs_type.as_str()) {
+                continue;
+            }
+            let Some((total, available)) = statvfs(&mount) else {
+                continue;
+            };
+            // pseudo file systems like proc have no blocks
+            if total == 0 {
+                continue;
+            }
+            disks.retain(|v| v.mount != mount);
+            disks.push(Disk {
+                mount,
+                device,
+                fs_type,
+                total,
+                available,
+            });
+        }
+        disks
+    }
+
+    /// Total and available bytes.
+    #[allow(clippy::unnecessary_cast, reason = "the types differ between targets")]
+    fn statvfs(path: &Path) -> Option<(u64, u64)> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `statvfs` fills the struct
+        unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+                return None;
+            }
+            let size = stat.f_frsize as u64;
+            Some((stat.f_blocks as u64 * size, stat.f_bavail as u64 * size))
+        }
+    }
+
+    /// A field of `/proc/mounts`, with spaces and the like escaped in octal
+    /// (`\040`).
+    fn unescape(v: &str) -> String {
+        let bytes = v.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes
+                .get(i + 1..i + 4)
+                .filter(|_| bytes[i] == b'\\')
+                .and_then(|v| u8::from_str_radix(std::str::from_utf8(v).ok()?, 8).ok());
+            match octal {
+                Some(v) => {
+                    out.push(v);
+                    i += 4;
+                }
+                None => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+impl GetId for SystemInfoSync {
+    fn id() -> &'static str {
+        "system-info"
+    }
+}
+
+impl<T: io::Read + io::Write, W: io::Write> ExeSenderSync<T, W> for SystemInfoSync {
+    fn execute_on_sender(
+        &self,
+        stream: T,
+        _req: &mut HttpRequest,
+        http: W,
+    ) -> io::Result<ExecuteResult> {
+        request(self, stream, http, |info: SystemInfo| {
+            serde_json::to_value(info).unwrap()
+        })
+    }
+}
+
+impl ExeReceiverSync for SystemInfoSync {
+    fn execute_on_receiver<S: io::Read + io::Write>(mut stream: S) -> io::Result<S> {
+        let req = Self::decode(&mut stream)?;
+        respond(stream, Ok(req.snapshot()))
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,27 @@
+use ee_http::HttpRequest;
+
+use crate::{ExecuteResult, system::SystemInfoSync, test_util::exchange};
+
+#[test]
+fn test_system_info() {
+    let info = SystemInfoSync::new().snapshot();
+    assert_eq!(info.os, std::env::consts::OS);
+    assert!(info.cpus >= 1);
+    #[cfg(target_os = "linux")]
+    {
+        assert!(info.kernel.is_some());
+        assert!(info.uptime.is_some());
+        let memory = info.memory.unwrap();
+        assert!(memory.available <= memory.total);
+        assert!(info.disks.iter().all(|v| v.total > 0));
+        assert!(info.interfaces.iter().any(|v| v.name == "lo"));
+    }
+
+    let (r, json) = exchange(&SystemInfoSync::new(), &mut HttpRequest::default());
+    assert_eq!(r.unwrap(), ExecuteResult::Ok);
+    assert_eq!(json["status"], "ok");
+    assert_eq!(json["hostname"], info.hostname.as_str());
+    assert_eq!(json["arch"], std::env::consts::ARCH);
+    #[cfg(unix)]
+    assert_eq!(json["load_average"].as_array().unwrap().len(), 3);
+}
@@ -0,0 +1,112 @@
+use std::{
+    io,
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use ee_http::HttpRequest;
+use ee_proto::Encode;
+use ee_test_util::{Pipe, TempDir};
+
+use crate::{
+    ExeReceiverSync, ExeSenderSync, ExecuteResult,
+    sandbox::{Access, Sandbox},
+};
+
+/// What the receiver writes for `req`.
+pub(crate) fn receive<E: Encode + ExeReceiverSync>(req: &E) -> Vec<u8> {
+    let mut input = Vec::new();
+    req.encode(&mut input).unwrap();
+    E::execute_on_receiver(Pipe::new(input)).unwrap().output
+}
+
+/// `len` bytes that do not repeat at chunk boundaries.
+pub(crate) fn content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Run `task` between a sender and a receiver connected over tcp, returns
+/// what the sender returns and the body of its http response. The http
+/// connection carries the body of `req` after the request.
+pub(crate) fn exchange<E>(
+    task: &E,
+    req: &mut HttpRequest,
+) -> (io::Result<ExecuteResult>, serde_json::Value)
+where
+    E: ExeReceiverSync + for<'a> ExeSenderSync<&'a mut TcpStream, &'a mut Pipe>,
+{
+    exchange_sandboxed(task, req, Sandbox::unrestricted())
+}
+
+/// [`exchange`] with a receiver limited to `sandbox`.
+pub(crate) fn exchange_sandboxed<E>(
+    task: &E,
+    req: &mut HttpRequest,
+    sandbox: Sandbox,
+) -> (io::Result<ExecuteResult>, serde_json::Value)
+where
+    E: ExeReceiverSync + for<'a> ExeSenderSync<&'a mut TcpStream, &'a mut Pipe>,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let receiver = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        E::execute_sandboxed(stream, &sandbox).map(|_| ())
+    });
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut http = Pipe::new(req.get_body().unwrap_or_default());
+    let r = task.execute_on_sender(&mut stream, req, &mut http);
+    receiver.join().unwrap().unwrap();
+    let http = String::from_utf8(http.output).unwrap();
+    let (_, body) = http.split_once("\r\n\r\n").unwrap();
+    (r, serde_json::from_str(body).unwrap())
+}
+
+/// `rw/a.txt` shared read-write, `ro/b.txt` shared read-only and the
+/// unshared `outside/secret.txt`. On unix `rw/escape` links to `outside`,
+/// `rw/inner` to `a.txt` and `rw/dangling` to nothing.
+pub(crate) fn sandbox_fixture(name: &str) -> (TempDir, Sandbox) {
+    let dir = TempDir::new(name)
+        .file("rw/a.txt", b"data")
+        .file("ro/b.txt", b"data")
+        .file("outside/secret.txt", b"data");
+    #[cfg(unix)]
+    let dir = dir
+        .symlink("rw/escape", "../outside")
+        .symlink("rw/inner", "a.txt")
+        .symlink("rw/dangling", "missing");
+    let sandbox = Sandbox::new()
+        .root(dir.path().join("rw"), Access::ReadWrite)
+        .root(dir.path().join("ro"), Access::ReadOnly);
+    (dir, sandbox)
+}
+
+/// Body of a `Transfer-Encoding: chunked` response.
+pub(crate) fn dechunk(mut body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let end = body.windows(2).position(|v| v == b"\r\n").unwrap();
+        let len = usize::from_str_radix(std::str::from_utf8(&body[..end]).unwrap(), 16).unwrap();
+        if len == 0 {
+            return data;
+        }
+        data.extend_from_slice(&body[end + 2..end + 2 + len]);
+        body = &body[end + 4 + len..];
+    }
+}
+
+/// Takes the headers of a response, fails every write after them.
+#[derive(Default)]
+pub(crate) struct Broken(Vec<u8>);
+
+impl io::Write for Broken {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.0.windows(4).any(|v| v == b"\r\n\r\n") {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        self.0.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! What the tests of the workspace share, a stream in memory and
+//! temporary directories.
+
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Reads from `input`, collects everything written into `output`.
+///
+/// `session` is whatever else the connection carries, like the sandbox
+/// of a receiver session.
+pub struct Pipe<T = ()> {
+    pub input: Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+    pub session: T,
+}
+
+impl Pipe {
+    pub fn new<I: Into<Vec<u8>>>(input: I) -> Self {
+        Self {
+            input: Cursor::new(input.into()),
+            output: Vec::new(),
+            session: (),
+        }
+    }
+}
+
+impl<T> Pipe<T> {
+    pub fn session<U>(self, value: U) -> Pipe<U> {
+        Pipe {
+            input: self.input,
+            output: self.output,
+            session: value,
+        }
+    }
+}
+
+impl<T> io::Read for Pipe<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl<T> io::Write for Pipe<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Unpin> tokio::io::AsyncRead for Pipe<T> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        let n = io::Read::read(&mut self.get_mut().input, buf.initialize_unfilled())?;
+        buf.advance(n);
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Unpin> tokio::io::AsyncWrite for Pipe<T> {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<io::Result<usize>> {
+        self.get_mut().output.extend_from_slice(buf);
+        std::task::Poll::Ready(Ok(buf.len()))
+    }
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// Directory removed on drop, filled with [`file`](Self::file),
+/// [`dir`](Self::dir) and [`symlink`](Self::symlink). Parents are created
+/// as needed.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "ee-{name}-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+    pub fn file<P: AsRef<Path>, C: AsRef<[u8]>>(self, path: P, content: C) -> Self {
+        let path = self.parent_of(path);
+        fs::write(path, content).unwrap();
+        self
+    }
+    pub fn dir<P: AsRef<Path>>(self, path: P) -> Self {
+        fs::create_dir_all(self.0.join(path)).unwrap();
+        self
+    }
+    /// A symlink at `path` to `target`, as it is given.
+    #[cfg(unix)]
+    pub fn symlink<P: AsRef<Path>, T: AsRef<Path>>(self, path: P, target: T) -> Self {
+        let path = self.parent_of(path);
+        std::os::unix::fs::symlink(target, path).unwrap();
+        self
+    }
+    /// `path` in the directory, its parent created.
+    fn parent_of<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}